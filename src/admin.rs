@@ -0,0 +1,688 @@
+use std::borrow::Cow;
+
+use itertools::Itertools;
+use mongodb::bson::DateTime;
+use serenity::model::channel::{Attachment, AttachmentType};
+use serenity::model::guild::Role;
+use serenity::model::user::User;
+use serenity::prelude::Mentionable;
+
+use crate::charts::{self, ChartPeriod};
+use crate::classes::{Class, Server};
+use crate::courses::{CatalogSource, Course};
+use crate::enrollment::check_assignable;
+use crate::jobs::{Job, JobKind, JobState};
+use crate::ordering::CategoryOrder;
+use crate::tempgrants::{self, TempGrant};
+use crate::templates::ServerTemplate;
+use crate::trash::TrashedClass;
+use crate::{audit, diag, dispatch, grader, grants, jobs, latency, lookup, policy, scheduler, secrets, selfcheck, ClassError, Context, Error};
+
+/// How many jobs `/admin jobs list` shows.
+const RECENT_JOBS: i64 = 15;
+/// Embed field values can be at most 1024 characters.
+const FIELD_LIMIT: usize = 1024;
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub(crate) enum ExportFormat {
+    /// One row per member, one column per class
+    #[name = "Matrix"]
+    Matrix,
+    /// One row per member per class
+    #[name = "Long"]
+    Long,
+}
+
+/// Quote a CSV field if it contains anything that would otherwise break the row.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_row<'a>(fields: impl IntoIterator<Item = &'a str>) -> String {
+    fields.into_iter().map(csv_field).join(",") + "\n"
+}
+
+#[poise::command(slash_command, subcommands("AdminCommand::catalog", "AdminCommand::chart", "AdminCommand::diag", "AdminCommand::grader", "AdminCommand::grant", "AdminCommand::grant_temp", "AdminCommand::jobs", "AdminCommand::memberships", "AdminCommand::metrics", "AdminCommand::order", "AdminCommand::selfcheck", "AdminCommand::template", "AdminCommand::trash", "AdminCommand::webhook"))]
+pub(crate) async fn admin(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct AdminCommand;
+impl AdminCommand {
+    #[poise::command(slash_command, subcommands("AdminMembershipsCommand::export"))]
+    async fn memberships(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("AdminChartCommand::enrollment"))]
+    async fn chart(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("AdminTemplateCommand::export", "AdminTemplateCommand::apply"))]
+    async fn template(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("AdminTrashCommand::list"))]
+    async fn trash(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("AdminCatalogCommand::source", "AdminCatalogCommand::import"))]
+    async fn catalog(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("AdminOrderCommand::categories"))]
+    async fn order(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("AdminJobsCommand::list", "AdminJobsCommand::status", "AdminJobsCommand::cancel"))]
+    async fn jobs(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Give a role to every user ID in a CSV file, in the background.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        required_bot_permissions = "MANAGE_ROLES",
+    )]
+    async fn grant(
+        ctx: Context<'_>,
+        role: Role,
+        #[description = "A CSV file of user IDs"] users: Attachment,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        check_assignable(ctx.discord(), server_id, &[role.id])?;
+        let users = grants::parse_users(&String::from_utf8_lossy(&users.download().await?));
+        if users.is_empty() {
+            Err(ClassError::NoGrantUsers)?;
+        }
+        let count = users.len();
+
+        let id = jobs::start(ctx, JobKind::GrantRole { role: role.id, users, granted_by: ctx.author().id }).await?;
+
+        ctx.say(format!(
+            "Granting {} to {} users as job `{}`. Its progress is shown in this channel, and users who \
+            aren't in the server yet will get the role when they join.",
+            role.mention(),
+            count,
+            id,
+        )).await?;
+
+        Ok(())
+    }
+
+    /// Let a member run a command or group of commands for a while, like `3d` or `1w 2d`.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        rename = "grant-temp",
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn grant_temp(
+        ctx: Context<'_>,
+        user: User,
+        #[autocomplete = "policy::autocomplete_command"] command: String,
+        duration: String,
+    ) -> Result<(), Error> {
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let command = command.trim().to_lowercase();
+        if !policy::command_names(&ctx.framework().options().commands).contains(&command) {
+            Err(ClassError::InvalidCommand)?;
+        }
+        if !tempgrants::grantable(&command) {
+            Err(ClassError::UngrantableCommand)?;
+        }
+        let duration = scheduler::parse_duration(&duration)
+            .filter(|d| *d <= tempgrants::MAX_DURATION)
+            .ok_or(ClassError::InvalidDuration)?;
+        let expires_at = DateTime::from_millis(DateTime::now().timestamp_millis() + duration.as_millis() as i64);
+
+        TempGrant::give(server_id, user.id, &command, ctx.author().id, expires_at).await?;
+
+        ctx.say(format!(
+            "{} can run `/{}` until <t:{}:f>.",
+            user.mention(),
+            command,
+            expires_at.timestamp_millis() / 1000,
+        )).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn metrics(ctx: Context<'_>) -> Result<(), Error> {
+        let metrics = dispatch::metrics();
+        let latency = latency::metrics();
+
+        ctx.say(format!(
+            "**Interaction queue:**\nQueued: {}\nHandled: {}\nIn flight: {}\nWaiting: {}\nShed: {}\n\n\
+            **Latency:**\nTimed: {}\nSlower than {}s: {}",
+            metrics.queued,
+            metrics.handled,
+            metrics.in_flight,
+            metrics.waiting,
+            metrics.shed,
+            latency.timed,
+            latency::SLOW.as_secs(),
+            latency.slow,
+        )).await?;
+
+        Ok(())
+    }
+
+    /// Show the bot's version, uptime, latency and recent errors.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn diag(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let latencies = diag::gateway_latencies(ctx.framework().shard_manager).await;
+        let mongo = match diag::mongo_ping().await {
+            Ok(ping) => format!("{}ms", ping.as_millis()),
+            Err(e) => format!("failed: {}", e),
+        };
+        let (guilds, channels, users) = diag::cache_sizes(ctx.discord());
+        let queue = dispatch::metrics();
+        let scheduler = match scheduler::last_tick() {
+            Some((at, took)) => format!("Last tick <t:{}:R>, took {}ms", at, took.as_millis()),
+            None => "Hasn't ticked yet".to_string(),
+        };
+        let errors = audit::recent_errors(5).await?;
+
+        ctx.send(|m| m.embed(|e| e
+            .title("Diagnostics")
+            .field(
+                "Version",
+                format!("{} ({})", env!("CARGO_PKG_VERSION"), env!("GIT_COMMIT")),
+                true,
+            )
+            .field("Uptime", diag::format_duration(diag::uptime()), true)
+            .field(
+                "Gateway latency",
+                latencies.iter()
+                    .map(|(shard, latency)| format!(
+                        "Shard {}: {}",
+                        shard,
+                        latency.map(|l| format!("{}ms", l.as_millis())).unwrap_or_else(|| "no heartbeat yet".to_string()),
+                    ))
+                    .join("\n"),
+                true,
+            )
+            .field("Database ping", mongo, true)
+            .field("Cache", format!("{} servers, {} channels, {} users", guilds, channels, users), true)
+            .field(
+                "Interaction queue",
+                format!("{} waiting, {} in flight, {} shed", queue.waiting, queue.in_flight, queue.shed),
+                true,
+            )
+            .field("Scheduler", scheduler, true)
+            .field(
+                "Recent errors",
+                if errors.is_empty() {
+                    "None".to_string()
+                } else {
+                    errors.iter()
+                        .map(|(context, error, at)| format!(
+                            "<t:{}:R> **{}**: {}",
+                            at.timestamp_millis() / 1000,
+                            context,
+                            error.chars().take(150).collect::<String>(),
+                        ))
+                        .join("\n")
+                },
+                false,
+            )
+        )).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn selfcheck(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let problems = selfcheck::check(ctx.discord(), ctx.guild_id().ok_or(ClassError::NoServer)?).await?;
+
+        if problems.is_empty() {
+            ctx.say("No problems found.").await?;
+        } else {
+            ctx.say(format!(
+                "Found {} problems:\n{}",
+                problems.len(),
+                problems.iter().map(|p| format!("• {}", p)).join("\n"),
+            )).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the URL of a class's webhook, for course automation to post with.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn webhook(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let url = secrets::open(class.webhook.as_deref().ok_or(ClassError::NoClassWebhook)?)?;
+
+        ctx.say(format!("The webhook for \"{}\" is <{}>. Keep it secret.", class.name, url)).await?;
+
+        Ok(())
+    }
+
+    /// Make a new token for an autograder to post results for a class, replacing any earlier one.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn grader(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let (token, hash) = grader::new_token();
+        class.set_grader_token(Some(hash)).await?;
+
+        ctx.say(format!(
+            "The grader token for \"{}\" is `{}`. It won't be shown again, so store it in your grader now. \
+            Post results to `/results` with the header `Authorization: Bearer <token>` and a JSON body with \
+            `class`, `student`, `assignment`, and optionally `score`, `max_score` and `details`.",
+            class.name,
+            token,
+        )).await?;
+
+        Ok(())
+    }
+}
+
+struct AdminTemplateCommand;
+impl AdminTemplateCommand {
+    /// Save the server's classes and settings, without members, to set up another server with.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn export(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let guild = lookup::current_guild(ctx).await?;
+        let template = ServerTemplate::export(&guild).await?;
+        let data = serde_json::to_vec_pretty(&template)?;
+
+        ctx.send(|m| m
+            .content(format!("Here is the template for {}.", guild.name))
+            .attachment(AttachmentType::Bytes {
+                data: Cow::Owned(data),
+                filename: "server-template.json".to_string(),
+            })
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Create the classes and settings from a template exported from another server.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        required_bot_permissions = "MANAGE_ROLES | MANAGE_CHANNELS",
+    )]
+    async fn apply(ctx: Context<'_>, template: Attachment) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let template = ServerTemplate::parse(&template.download().await?)?;
+        let count = template.class_count();
+        let id = jobs::start(ctx, JobKind::ApplyTemplate { template }).await?;
+
+        ctx.say(format!(
+            "Applying the template with {} classes as job `{}`. This can take a while, and its progress is shown in this channel.",
+            count,
+            id,
+        )).await?;
+
+        Ok(())
+    }
+}
+
+struct AdminChartCommand;
+impl AdminChartCommand {
+    /// Chart how many members were enrolled in a class, or in every class if none is given.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn enrollment(ctx: Context<'_>, class: Option<Role>, period: ChartPeriod) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let guild = lookup::current_guild(ctx).await?;
+        let (roles, title) = match class {
+            Some(role) => {
+                let class = Class::find_by_role(role.id).await?.ok_or(ClassError::InvalidClass)?;
+                (vec![class.role], format!("{} enrollment", class.name))
+            }
+            None => (
+                Class::list(guild.id).await?.into_iter().map(|c| c.role).collect(),
+                "Total enrollment".to_string(),
+            ),
+        };
+        let chart = charts::enrollment(&guild, &roles, &title, period).await?;
+
+        ctx.send(|m| m
+            .attachment(AttachmentType::Bytes {
+                data: Cow::Owned(chart),
+                filename: "enrollment.png".to_string(),
+            })
+        ).await?;
+
+        Ok(())
+    }
+}
+
+struct AdminMembershipsCommand;
+impl AdminMembershipsCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn export(ctx: Context<'_>, format: Option<ExportFormat>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let guild = lookup::current_guild(ctx).await?;
+        let classes = Class::list(guild.id).await?
+            .into_iter()
+            .sorted_by(|c1, c2| human_sort::compare(&c1.name, &c2.name))
+            .collect::<Vec<_>>();
+        let members = guild.members.values()
+            .filter(|m| !m.user.bot)
+            .sorted_by_key(|m| m.user.tag())
+            .collect::<Vec<_>>();
+
+        let mut csv = String::new();
+        match format.unwrap_or(ExportFormat::Matrix) {
+            ExportFormat::Matrix => {
+                csv += &csv_row(
+                    ["user_id", "username", "display_name"].into_iter()
+                        .chain(classes.iter().map(|c| c.name.as_str()))
+                );
+                for member in &members {
+                    let id = member.user.id.to_string();
+                    let tag = member.user.tag();
+                    let enrolled = classes.iter()
+                        .map(|c| if member.roles.contains(&c.role) { "1" } else { "0" })
+                        .collect::<Vec<_>>();
+                    csv += &csv_row(
+                        [id.as_str(), tag.as_str(), member.display_name().as_str()].into_iter()
+                            .chain(enrolled)
+                    );
+                }
+            }
+            ExportFormat::Long => {
+                csv += &csv_row(["user_id", "username", "display_name", "class", "short_name"]);
+                for member in &members {
+                    let id = member.user.id.to_string();
+                    let tag = member.user.tag();
+                    for class in classes.iter().filter(|c| member.roles.contains(&c.role)) {
+                        csv += &csv_row([
+                            id.as_str(),
+                            tag.as_str(),
+                            member.display_name().as_str(),
+                            class.name.as_str(),
+                            class.short_name.as_str(),
+                        ]);
+                    }
+                }
+            }
+        }
+
+        ctx.send(|m| m
+            .content(format!(
+                "Memberships of {} members across {} classes.",
+                members.len(),
+                classes.len(),
+            ))
+            .attachment(AttachmentType::Bytes {
+                data: Cow::Owned(csv.into_bytes()),
+                filename: "memberships.csv".to_string(),
+            })
+        ).await?;
+
+        Ok(())
+    }
+}
+
+struct AdminTrashCommand;
+impl AdminTrashCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn list(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let trashed = TrashedClass::list(ctx.guild_id().ok_or(ClassError::NoServer)?).await?;
+
+        if trashed.is_empty() {
+            ctx.say("There are no deleted classes that can be restored.").await?;
+        } else {
+            ctx.say(format!(
+                "Deleted classes, restorable with `/class restore`:\n{}",
+                trashed.iter()
+                    .map(|t| format!(
+                        "**{}**: deleted <t:{}:R>, restorable until <t:{}:f>",
+                        t.class.name,
+                        t.deleted_at.timestamp_millis() / 1000,
+                        t.expires_at(),
+                    ))
+                    .join("\n"),
+            )).await?;
+        }
+
+        Ok(())
+    }
+}
+
+struct AdminOrderCommand;
+impl AdminOrderCommand {
+    /// Move class categories into order, leaving other categories where they are.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        required_bot_permissions = "MANAGE_CHANNELS",
+    )]
+    async fn categories(ctx: Context<'_>, by: CategoryOrder) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let id = jobs::start(ctx, JobKind::OrderCategories { by }).await?;
+
+        ctx.say(format!("Started job `{}`. Its progress is shown in this channel.", id)).await?;
+
+        Ok(())
+    }
+}
+
+struct AdminCatalogCommand;
+impl AdminCatalogCommand {
+    /// Set where the official course catalog is imported from, as JSON.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn source(
+        ctx: Context<'_>,
+        #[description = "The URL of the catalog's JSON"] url: String,
+        #[description = "Dotted path to the list of courses, like data.courses, if not the top level"]
+        courses_path: Option<String>,
+        #[description = "The field with each course's code, like code"] code_field: String,
+        #[description = "The field with each course's title, like title"] title_field: String,
+        #[description = "The field with each course's description"] description_field: Option<String>,
+        #[description = "The field with each course's department, or taken from its code if left out"]
+        department_field: Option<String>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?).await?;
+        server.set_catalog_source(Some(CatalogSource {
+            url: url.trim().to_string(),
+            courses_path: courses_path.unwrap_or_default().trim().to_string(),
+            code_field: code_field.trim().to_string(),
+            title_field: title_field.trim().to_string(),
+            description_field: description_field.map(|f| f.trim().to_string()),
+            department_field: department_field.map(|f| f.trim().to_string()),
+        })).await?;
+
+        ctx.say("Saved the catalog source. Import it with `/admin catalog import`.").await?;
+
+        Ok(())
+    }
+
+    /// Import the official course catalog, describing classes and checking their names against it.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn import(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let source = Server::get_or_create(server_id).await?
+            .catalog_source
+            .ok_or(ClassError::NoCatalogSource)?;
+        let report = Course::import(server_id, &source).await?;
+
+        let list = |names: &[String]| if names.is_empty() {
+            "None".to_string()
+        } else {
+            names.join("\n").chars().take(FIELD_LIMIT).collect()
+        };
+        let drifted = report.drifted.iter()
+            .map(|(name, title)| format!("{} (officially \"{}\")", name, title))
+            .collect::<Vec<_>>();
+        ctx.send(|m| m.embed(|e| e
+            .title(format!("Imported {} courses", report.courses))
+            .field("Descriptions filled in", list(&report.described), false)
+            .field("Names that differ from the catalog", list(&drifted), false)
+            .field("Not in the catalog", list(&report.unmatched), false)
+        )).await?;
+
+        Ok(())
+    }
+}
+
+struct AdminJobsCommand;
+impl AdminJobsCommand {
+    /// List the server's most recent background jobs.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn list(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let jobs = Job::list(ctx.guild_id().ok_or(ClassError::NoServer)?, RECENT_JOBS).await?;
+
+        if jobs.is_empty() {
+            ctx.say("There are no background jobs.").await?;
+        } else {
+            ctx.say(format!(
+                "Recent background jobs, newest first:\n{}",
+                jobs.iter()
+                    .map(|j| format!(
+                        "`{}` {}: {}, started <t:{}:R>",
+                        j.id.to_hex(),
+                        j.job.describe(),
+                        j.state.describe(),
+                        j.created_at.timestamp_millis() / 1000,
+                    ))
+                    .join("\n"),
+            )).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Show a background job's progress, or the error it failed with.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn status(ctx: Context<'_>, #[description = "The job's ID, from /admin jobs list"] id: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let job = Job::find(ctx.guild_id().ok_or(ClassError::NoServer)?, &id).await?;
+
+        ctx.send(|m| m.embed(|e| {
+            e.title(job.job.describe())
+                .description(job.state.describe())
+                .field("ID", format!("`{}`", job.id.to_hex()), true)
+                .field("Attempts", job.attempts, true)
+                .field(
+                    "Started",
+                    match job.started_by {
+                        Some(user) => format!("<t:{}:f> by {}", job.created_at.timestamp_millis() / 1000, user.mention()),
+                        None => format!("<t:{}:f> automatically", job.created_at.timestamp_millis() / 1000),
+                    },
+                    false,
+                );
+            if let Some(finished_at) = job.finished_at {
+                e.field("Finished", format!("<t:{}:f>", finished_at.timestamp_millis() / 1000), true);
+            }
+            if let Some(progress) = &job.progress {
+                e.field("Progress", progress, false);
+            }
+            if let Some(result) = &job.result {
+                let label = if job.state == JobState::Finished { "Result" } else { "Last error" };
+                e.field(label, result.chars().take(FIELD_LIMIT).collect::<String>(), false);
+            }
+            e
+        })).await?;
+
+        Ok(())
+    }
+
+    /// Stop a background job that hasn't finished. Work it has already done is not undone.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn cancel(ctx: Context<'_>, #[description = "The job's ID, from /admin jobs list"] id: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let job = Job::find(ctx.guild_id().ok_or(ClassError::NoServer)?, &id).await?;
+        job.cancel(ctx.discord()).await?;
+
+        ctx.say(format!("Cancelled job `{}` ({}).", job.id.to_hex(), job.job.describe().to_lowercase())).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use chrono::{TimeZone, Utc};
+use futures::TryStreamExt;
+use itertools::Itertools;
+use mongodb::bson::{doc, DateTime};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use tokio::sync::OnceCell;
+
+use crate::classes::Class;
+use crate::mentions;
+use crate::notifications;
+use crate::{class_members, get_conn, lookup, ClassError, ClassResult, Context, Error, ENV};
+
+/// How much of each announcement is included in a digest.
+const ANNOUNCEMENT_LENGTH: usize = 200;
+/// Discord's limit on the length of a message.
+const MESSAGE_LIMIT: usize = 2000;
+
+/// An announcement waiting to be sent to a member in their daily digest.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PendingNotification {
+    server_id: GuildId,
+    user: UserId,
+    role: RoleId,
+    class_name: String,
+    content: String,
+    link: String,
+    created_at: DateTime,
+}
+
+impl PendingNotification {
+    fn line(&self) -> String {
+        let mut content = self.content.chars().take(ANNOUNCEMENT_LENGTH).collect::<String>();
+        if content.chars().count() < self.content.chars().count() {
+            content += "…";
+        }
+        format!("**{}**: {} ({})", self.class_name, content, self.link)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static PENDING: OnceCell<Collection<PendingNotification>> = OnceCell::const_new();
+
+        PENDING
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("pending_notifications")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Split digest lines into messages that fit within Discord's limit.
+pub(crate) fn digest_messages(lines: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut messages = vec!["Announcements from your classes:".to_string()];
+    for line in lines {
+        // Unwrapping because the list starts with the heading
+        let last = messages.last_mut().unwrap();
+        if last.len() + line.len() + 2 > MESSAGE_LIMIT {
+            messages.push(line);
+        } else {
+            *last += "\n\n";
+            *last += &line;
+        }
+    }
+    messages
+}
+
+/// DM every member their digest of announcements from before today, then drop them from the
+/// queue. Members with DMs disabled miss their digest rather than holding up the queue.
+pub(crate) async fn tick(ctx: &SContext) -> ClassResult<()> {
+    // Unwrapping because midnight always exists in UTC
+    let today = Utc.from_utc_datetime(&Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap());
+    let cutoff = DateTime::from_millis(today.timestamp_millis());
+
+    let collection = PendingNotification::get_collection().await;
+    let due = collection
+        .find(doc! { "created_at": { "$lt": cutoff } }, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let digests = due.into_iter()
+        .sorted_by_key(|p| (p.user, p.created_at))
+        .group_by(|p| p.user)
+        .into_iter()
+        .map(|(user, pending)| (user, digest_messages(pending.map(|p| p.line()))))
+        .collect::<Vec<_>>();
+
+    for (user, messages) in digests {
+        // Throwing away the result as the member may have DMs disabled
+        if let Ok(channel) = user.create_dm_channel(ctx).await {
+            for message in messages {
+                channel.say(ctx, message).await.ok();
+            }
+        }
+
+        collection
+            .delete_many(doc! { "user": user.to_string(), "created_at": { "$lt": cutoff } }, None)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Post an announcement to a class, notifying each member the way they chose.
+#[poise::command(slash_command, ephemeral, check = "crate::manages_class")]
+pub(crate) async fn announce(ctx: Context<'_>, class: Role, message: String) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+    let channel = *class.text_channels.first().ok_or(ClassError::NoClassChannels)?;
+    let guild = lookup::current_guild(ctx).await?;
+    let members = class_members(&guild, class.role);
+
+    let (immediate, digest) = notifications::announcement_recipients(class.role, members).await?;
+    let posted = mentions::announce(ctx.discord(), channel, class.role, message.clone(), immediate.clone()).await?;
+
+    if !digest.is_empty() {
+        let link = posted.link();
+        let created_at = DateTime::now();
+        PendingNotification::get_collection().await
+            .insert_many(
+                digest.iter().map(|&user| PendingNotification {
+                    server_id: class.server_id,
+                    user,
+                    role: class.role,
+                    class_name: class.name.clone(),
+                    content: message.clone(),
+                    link: link.clone(),
+                    created_at,
+                }),
+                None,
+            )
+            .await?;
+    }
+
+    ctx.say(format!(
+        "Posted the announcement to \"{}\". Pinged {} members, and {} will get it in their daily digest.",
+        class.name,
+        immediate.len(),
+        digest.len(),
+    )).await?;
+
+    Ok(())
+}
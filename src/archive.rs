@@ -0,0 +1,237 @@
+use chrono::{Duration, Utc};
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::Context as SContext;
+use serenity::model::channel::{ChannelType, Message};
+use serenity::model::event::MessageUpdateEvent;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+use serenity::prelude::EventHandler;
+use tokio::sync::OnceCell;
+
+use crate::classes::{Class, Server};
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, ClassResult, ENV};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ArchivedAttachment {
+    pub(crate) filename: String,
+    pub(crate) url: String,
+    pub(crate) size: u64,
+    pub(crate) content_type: Option<String>,
+}
+
+/// A copy of a message sent in a class channel, kept for classes that have opted in to archiving.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ArchivedMessage {
+    pub(crate) server_id: GuildId,
+    pub(crate) role: RoleId,
+    pub(crate) channel_id: ChannelId,
+    pub(crate) message_id: MessageId,
+    pub(crate) author: UserId,
+    pub(crate) content: String,
+    pub(crate) attachments: Vec<ArchivedAttachment>,
+    pub(crate) at: DateTime,
+    pub(crate) edited_at: Option<DateTime>,
+    pub(crate) deleted_at: Option<DateTime>,
+}
+
+impl ArchivedMessage {
+    /// A link to the message, which only works while it hasn't been deleted.
+    pub(crate) fn link(&self) -> String {
+        self.message_id.link(self.channel_id, Some(self.server_id))
+    }
+
+    /// The archived messages in a class best matching a query, leaving out deleted ones.
+    pub(crate) async fn search(role: RoleId, query: &str, limit: i64) -> ClassResult<Vec<ArchivedMessage>> {
+        Ok(
+            Self::get_collection().await
+                .find(
+                    doc! {
+                        "role": role.to_string(),
+                        "deleted_at": null,
+                        "$text": { "$search": query },
+                    },
+                    FindOptions::builder()
+                        .sort(doc! { "score": { "$meta": "textScore" } })
+                        .limit(limit)
+                        .build(),
+                )
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    /// The most recently deleted messages in a class, optionally only those by one member.
+    pub(crate) async fn deleted(role: RoleId, author: Option<UserId>, limit: i64) -> ClassResult<Vec<ArchivedMessage>> {
+        let mut filter = doc! { "role": role.to_string(), "deleted_at": { "$ne": null } };
+        if let Some(author) = author {
+            filter.insert("author", author.to_string());
+        }
+
+        Ok(
+            Self::get_collection().await
+                .find(filter, FindOptions::builder().sort(doc! { "deleted_at": -1 }).limit(limit).build())
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    pub(crate) async fn get_collection() -> Collection<Self> {
+        static ARCHIVE: OnceCell<Collection<ArchivedMessage>> = OnceCell::const_new();
+
+        ARCHIVE
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("message_archive")
+            })
+            .await
+            .clone()
+    }
+}
+
+async fn archive_message(ctx: &SContext, message: &Message) -> ClassResult<()> {
+    let server_id = match message.guild_id {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+
+    // Messages in threads are archived along with the channel the thread belongs to
+    let channel = ctx.cache
+        .guild_channel_field(message.channel_id, |c| match c.kind {
+            ChannelType::PublicThread | ChannelType::PrivateThread => c.parent_id,
+            _ => Some(c.id),
+        })
+        .flatten()
+        .unwrap_or(message.channel_id);
+    let class = match Class::find_by_text_channel(channel).await? {
+        Some(c) if c.archive_messages => c,
+        _ => return Ok(()),
+    };
+
+    ArchivedMessage::get_collection().await
+        .insert_one(ArchivedMessage {
+            server_id,
+            role: class.role,
+            channel_id: message.channel_id,
+            message_id: message.id,
+            author: message.author.id,
+            content: message.content.clone(),
+            attachments: message.attachments.iter()
+                .map(|a| ArchivedAttachment {
+                    filename: a.filename.clone(),
+                    url: a.url.clone(),
+                    size: a.size,
+                    content_type: a.content_type.clone(),
+                })
+                .collect(),
+            at: DateTime::from_millis(message.timestamp.unix_timestamp() * 1000),
+            edited_at: None,
+            deleted_at: None,
+        }, None)
+        .await?;
+
+    Ok(())
+}
+
+/// Delete archived messages older than each server's retention period.
+pub(crate) async fn tick(ctx: &SContext) -> ClassResult<()> {
+    let collection = ArchivedMessage::get_collection().await;
+    for server_id in ctx.cache.guilds() {
+        let days = match Server::get_or_create(server_id).await?.archive_retention_days {
+            Some(d) => d,
+            None => continue,
+        };
+        let cutoff = DateTime::from_millis((Utc::now() - Duration::days(days.into())).timestamp_millis());
+
+        GuildScoped::new(server_id, collection.clone())
+            .delete_many(doc! { "at": { "$lt": cutoff } }, None)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Archives messages in the text channels of classes with archiving turned on, keeping edits and
+/// deletions so staff can see what a message said after it was removed.
+pub(crate) struct MessageArchiveHandler;
+
+#[async_trait]
+impl EventHandler for MessageArchiveHandler {
+    async fn message(&self, ctx: SContext, message: Message) {
+        if let Err(e) = archive_message(&ctx, &message).await {
+            log_error!("Error archiving message {}: {:?}", message.id, e);
+        }
+    }
+
+    async fn message_update(
+        &self,
+        _ctx: SContext,
+        _old: Option<Message>,
+        _new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        let content = match event.content {
+            Some(c) => c,
+            None => return,
+        };
+
+        let result = ArchivedMessage::get_collection().await
+            .update_one(
+                doc! { "message_id": event.id.to_string() },
+                doc! { "$set": { "content": content, "edited_at": DateTime::now() } },
+                None,
+            )
+            .await;
+        if let Err(e) = result {
+            log_error!("Error archiving edit to message {}: {:?}", event.id, e);
+        }
+    }
+
+    async fn message_delete(
+        &self,
+        _ctx: SContext,
+        _channel_id: ChannelId,
+        deleted_message_id: MessageId,
+        _guild_id: Option<GuildId>,
+    ) {
+        let result = ArchivedMessage::get_collection().await
+            .update_one(
+                doc! { "message_id": deleted_message_id.to_string() },
+                doc! { "$set": { "deleted_at": DateTime::now() } },
+                None,
+            )
+            .await;
+        if let Err(e) = result {
+            log_error!("Error archiving deletion of message {}: {:?}", deleted_message_id, e);
+        }
+    }
+
+    async fn message_delete_bulk(
+        &self,
+        _ctx: SContext,
+        _channel_id: ChannelId,
+        multiple_deleted_messages_ids: Vec<MessageId>,
+        _guild_id: Option<GuildId>,
+    ) {
+        let ids = multiple_deleted_messages_ids.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+        let result = ArchivedMessage::get_collection().await
+            .update_many(
+                doc! { "message_id": { "$in": ids } },
+                doc! { "$set": { "deleted_at": DateTime::now() } },
+                None,
+            )
+            .await;
+        if let Err(e) = result {
+            log_error!("Error archiving bulk deletion of messages: {:?}", e);
+        }
+    }
+}
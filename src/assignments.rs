@@ -0,0 +1,459 @@
+use std::borrow::Cow;
+
+use chrono::{Duration, TimeZone, Utc};
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::builder::CreateEmbed;
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::channel::{AttachmentType, GuildChannel};
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::prelude::Mentionable;
+use tokio::sync::OnceCell;
+
+use crate::canvas::{self, CanvasLink};
+use crate::classes::Class;
+use crate::countdowns;
+use crate::mentions::{self, Pings};
+use crate::scheduler::parse_time;
+use crate::{get_conn, secrets, ClassError, ClassResult, Context, Error, ENV};
+
+/// How long before an assignment is due the class is reminded.
+const REMINDER_LEAD: i64 = 24;
+/// The most assignments shown on a class's board, as embeds can only have 25 fields.
+const BOARD_LIMIT: usize = 25;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Assignment {
+    server_id: GuildId,
+    role: RoleId,
+    title: String,
+    due: DateTime,
+    created_by: UserId,
+    created_at: DateTime,
+    /// Whether this is an exam rather than a regular assignment. Exams drive class countdowns.
+    #[serde(default)]
+    pub(crate) exam: bool,
+    reminded: bool,
+    /// Whether the assignment's due date has passed and it has been taken off the board.
+    past: bool,
+}
+
+impl Assignment {
+    pub(crate) fn timestamp(&self) -> i64 {
+        self.due.timestamp_millis() / 1000
+    }
+
+    pub(crate) fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub(crate) async fn create(
+        class: &Class,
+        title: String,
+        due: chrono::DateTime<Utc>,
+        exam: bool,
+        created_by: UserId,
+    ) -> ClassResult<Assignment> {
+        let collection = Self::get_collection().await;
+        let filter = doc! { "role": class.role.to_string(), "title": &title, "past": false };
+        if collection.find_one(filter, None).await?.is_some() {
+            return Err(ClassError::AssignmentExists);
+        }
+
+        let assignment = Self {
+            server_id: class.server_id,
+            role: class.role,
+            title,
+            due: DateTime::from_millis(due.timestamp_millis()),
+            created_by,
+            created_at: DateTime::now(),
+            exam,
+            reminded: false,
+            past: false,
+        };
+        collection.insert_one(&assignment, None).await?;
+
+        Ok(assignment)
+    }
+
+    pub(crate) async fn delete(class: &Class, title: &str) -> ClassResult<()> {
+        let result = Self::get_collection().await
+            .delete_one(doc! { "role": class.role.to_string(), "title": title.trim(), "past": false }, None)
+            .await?;
+
+        if result.deleted_count == 0 {
+            Err(ClassError::InvalidAssignment)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// A class's assignments, soonest due first.
+    async fn for_class(role: RoleId, upcoming_only: bool) -> ClassResult<Vec<Assignment>> {
+        let mut filter = doc! { "role": role.to_string() };
+        if upcoming_only {
+            filter.insert("past", false);
+        }
+        Self::find(filter).await
+    }
+
+    /// The class's next upcoming exam, or its next upcoming assignment if it has no exams coming up.
+    pub(crate) async fn next(role: RoleId) -> ClassResult<Option<Assignment>> {
+        let upcoming = Self::for_class(role, true).await?;
+        Ok(
+            upcoming.iter().find(|a| a.exam).cloned()
+                .or_else(|| upcoming.into_iter().next())
+        )
+    }
+
+    async fn find(filter: Document) -> ClassResult<Vec<Assignment>> {
+        Ok(
+            Self::get_collection().await
+                .find(filter, FindOptions::builder().sort(doc! { "due": 1 }).build())
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static ASSIGNMENTS: OnceCell<Collection<Assignment>> = OnceCell::const_new();
+
+        ASSIGNMENTS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("assignments")
+            })
+            .await
+            .clone()
+    }
+}
+
+fn render_board<'a>(e: &'a mut CreateEmbed, class: &Class, assignments: &[Assignment]) -> &'a mut CreateEmbed {
+    e.title(format!("Upcoming assignments: {}", class.name));
+    if assignments.is_empty() {
+        e.description("Nothing is due.");
+    }
+    for assignment in assignments.iter().take(BOARD_LIMIT) {
+        let title = if assignment.exam {
+            format!("📝 {}", assignment.title) // U+1F4DD : MEMO
+        } else {
+            assignment.title.clone()
+        };
+        e.field(title, format!("Due <t:{0}:F> (<t:{0}:R>)", assignment.timestamp()), false);
+    }
+    e
+}
+
+/// Update the class's pinned board of upcoming assignments, posting and pinning a new one in its
+/// general channel if it doesn't have one yet or the old one was deleted.
+pub(crate) async fn refresh_board(cache_http: impl CacheHttp, class: &mut Class) -> ClassResult<()> {
+    let http = cache_http.http();
+    let assignments = Assignment::for_class(class.role, true).await?;
+
+    if let Some((channel, message)) = class.assignment_board {
+        if channel.edit_message(http, message, |m| m.embed(|e| render_board(e, class, &assignments))).await.is_ok() {
+            return Ok(());
+        }
+    }
+
+    let channel = *class.text_channels.first().ok_or(ClassError::NoClassChannels)?;
+    let message = channel.send_message(http, |m| m.embed(|e| render_board(e, class, &assignments))).await?;
+    message.pin(http).await?;
+    class.set_assignment_board(Some((channel, message.id))).await
+}
+
+/// Escape text for an iCalendar property value.
+fn ical_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn ical_time(time: DateTime) -> String {
+    Utc.timestamp_millis_opt(time.timestamp_millis())
+        .single()
+        .unwrap_or_else(Utc::now)
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+/// An iCalendar file with every assignment in the class, for importing into calendar apps.
+async fn ical(class: &Class) -> ClassResult<String> {
+    let mut calendar = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//cs_discord_rs//assignments//EN\r\n".to_string();
+    calendar += &format!("X-WR-CALNAME:{}\r\n", ical_text(&class.name));
+    for assignment in Assignment::for_class(class.role, false).await? {
+        calendar += &format!(
+            "BEGIN:VEVENT\r\nUID:{}-{}@cs_discord_rs\r\nDTSTAMP:{}\r\nDTSTART:{}\r\nDTEND:{}\r\nSUMMARY:{}\r\nEND:VEVENT\r\n",
+            assignment.role,
+            assignment.created_at.timestamp_millis(),
+            ical_time(assignment.created_at),
+            ical_time(assignment.due),
+            ical_time(assignment.due),
+            ical_text(&format!("{}: {}", class.short_name, assignment.title)),
+        );
+    }
+    calendar += "END:VCALENDAR\r\n";
+
+    Ok(calendar)
+}
+
+/// Remind classes of assignments due soon, and take assignments off boards once they are due.
+pub(crate) async fn tick(ctx: &SContext) -> ClassResult<()> {
+    let now = Utc::now();
+
+    let due_soon = Assignment::find(doc! {
+        "reminded": false,
+        "past": false,
+        "due": { "$lte": DateTime::from_millis((now + Duration::hours(REMINDER_LEAD)).timestamp_millis()) },
+    }).await?;
+    for assignment in due_soon {
+        let class = match Class::find_by_role(assignment.role).await? {
+            Some(c) => c,
+            None => continue,
+        };
+        if let Some(channel) = class.text_channels.first() {
+            let content = format!("Reminder: **{}** is due <t:{}:R>.", assignment.title, assignment.timestamp());
+            mentions::send(ctx, *channel, class.role, content, Pings::default()).await?;
+        }
+        Assignment::get_collection().await
+            .update_one(
+                doc! { "role": assignment.role.to_string(), "created_at": assignment.created_at },
+                doc! { "$set": { "reminded": true } },
+                None,
+            )
+            .await?;
+    }
+
+    let passed = Assignment::find(doc! {
+        "past": false,
+        "due": { "$lte": DateTime::from_millis(now.timestamp_millis()) },
+    }).await?;
+    let mut roles = passed.iter().map(|a| a.role).collect::<Vec<_>>();
+    roles.sort();
+    roles.dedup();
+    Assignment::get_collection().await
+        .update_many(
+            doc! { "past": false, "due": { "$lte": DateTime::from_millis(now.timestamp_millis()) } },
+            doc! { "$set": { "past": true } },
+            None,
+        )
+        .await?;
+    for role in roles {
+        if let Some(mut class) = Class::find_by_role(role).await? {
+            refresh_board(ctx, &mut class).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    subcommands(
+        "AssignmentCommand::create",
+        "AssignmentCommand::delete",
+        "AssignmentCommand::calendar",
+        "AssignmentCommand::link",
+        "AssignmentCommand::import",
+        "AssignmentCommand::countdown",
+    )
+)]
+pub(crate) async fn assignment(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct AssignmentCommand;
+impl AssignmentCommand {
+    /// Add an assignment to a class's board of upcoming assignments.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        check = "crate::manages_class",
+    )]
+    async fn create(
+        ctx: Context<'_>,
+        class: Role,
+        title: String,
+        due: String,
+        #[description = "Whether this is an exam, which class countdowns count down to"]
+        exam: Option<bool>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let due = parse_time(&due)
+            .filter(|t| *t > Utc::now())
+            .ok_or(ClassError::InvalidTime)?;
+
+        let assignment = Assignment::create(
+            &class,
+            title.trim().to_string(),
+            due,
+            exam.unwrap_or(false),
+            ctx.author().id,
+        ).await?;
+        refresh_board(ctx.discord(), &mut class).await?;
+
+        ctx.say(format!(
+            "Added \"{}\" to \"{}\", due <t:{}:F>.",
+            assignment.title,
+            class.name,
+            assignment.timestamp(),
+        )).await?;
+
+        Ok(())
+    }
+
+    /// Remove an upcoming assignment from a class.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        check = "crate::manages_class",
+    )]
+    async fn delete(ctx: Context<'_>, class: Role, title: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        Assignment::delete(&class, &title).await?;
+        refresh_board(ctx.discord(), &mut class).await?;
+
+        ctx.say(format!("Removed \"{}\" from \"{}\".", title.trim(), class.name)).await?;
+
+        Ok(())
+    }
+
+    /// Get a calendar file of a class's assignments to import into your calendar app.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn calendar(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let calendar = ical(&class).await?;
+
+        ctx.send(|m| m
+            .content(format!("Here are the assignments for {}.", class.role.mention()))
+            .attachment(AttachmentType::Bytes {
+                data: Cow::Owned(calendar.into_bytes()),
+                filename: format!("{}.ics", class.short_name),
+            })
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Link a class to its Canvas course so its assignments can be imported. Leave out the URL to unlink.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        check = "crate::manages_class",
+    )]
+    async fn link(
+        ctx: Context<'_>,
+        class: Role,
+        #[description = "The school's Canvas address, like https://canvas.example.edu"]
+        canvas_url: Option<String>,
+        #[description = "The number after /courses/ in the course's Canvas address"]
+        course_id: Option<u64>,
+        #[description = "A Canvas access token that can read the course"]
+        token: Option<String>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        match (canvas_url, course_id, token) {
+            (Some(base_url), Some(course_id), Some(token)) => {
+                class.set_canvas(Some(CanvasLink {
+                    base_url: base_url.trim().to_string(),
+                    course_id,
+                    token: secrets::seal(token.trim())?,
+                })).await?;
+                ctx.say(format!("Linked \"{}\" to Canvas course {}.", class.name, course_id)).await?;
+            }
+            (None, _, _) => {
+                class.set_canvas(None).await?;
+                ctx.say(format!("Unlinked \"{}\" from Canvas.", class.name)).await?;
+            }
+            _ => Err(ClassError::IncompleteCanvasLink)?,
+        }
+
+        Ok(())
+    }
+
+    /// Add a class's upcoming assignments from its linked Canvas course.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        check = "crate::manages_class",
+    )]
+    async fn import(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let link = class.canvas.clone().ok_or(ClassError::NoCanvasLink)?;
+
+        let now = Utc::now();
+        let (mut added, mut skipped) = (0, 0);
+        for (title, due) in canvas::assignments(&link).await? {
+            if due <= now {
+                continue;
+            }
+            match Assignment::create(&class, title, due, false, ctx.author().id).await {
+                Ok(_) => added += 1,
+                Err(ClassError::AssignmentExists) => skipped += 1,
+                Err(e) => Err(e)?,
+            }
+        }
+        refresh_board(ctx.discord(), &mut class).await?;
+
+        ctx.say(format!(
+            "Imported {} upcoming assignments into \"{}\", skipping {} that were already there.",
+            added,
+            class.name,
+            skipped,
+        )).await?;
+
+        Ok(())
+    }
+
+    /// Rename a voice channel to count down to a class's next exam. Leave out the channel to stop.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        check = "crate::manages_class",
+    )]
+    async fn countdown(
+        ctx: Context<'_>,
+        class: Role,
+        #[channel_types("Voice")] channel: Option<GuildChannel>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        if let Some(channel) = channel {
+            countdowns::enable(&class, channel.id).await?;
+            ctx.say(format!(
+                "{} will count down to the next exam in \"{}\", updating every few minutes.",
+                channel.mention(),
+                class.name,
+            )).await?;
+        } else {
+            countdowns::disable(class.role).await?;
+            ctx.say(format!("Stopped the exam countdown for \"{}\".", class.name)).await?;
+        }
+
+        Ok(())
+    }
+}
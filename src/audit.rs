@@ -0,0 +1,116 @@
+use futures::TryStreamExt;
+use mongodb::bson::{self, doc, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serenity::model::id::GuildId;
+use tokio::sync::OnceCell;
+
+use crate::events::BotEvent;
+use crate::redact::{log_error, log_info};
+use crate::scoped::GuildScoped;
+use crate::{get_conn, ClassResult, ENV};
+
+/// Event bus subscriber logging every event, and keeping it in the audit log collection.
+pub(crate) async fn record(event: BotEvent) {
+    log_info!("[{}] {:?}", event.server_id(), event);
+
+    if let Err(e) = insert(&event).await {
+        log_error!("Error recording audit log entry: {:?}", e);
+    }
+}
+
+async fn insert(event: &BotEvent) -> ClassResult<()> {
+    let mut entry = bson::to_document(event)?;
+    entry.insert("at", DateTime::now());
+    audit_collection().await.insert_one(entry, None).await?;
+
+    Ok(())
+}
+
+/// Record an unexpected error, so it shows up in the staff digest.
+pub(crate) async fn record_error(server_id: Option<GuildId>, context: &str, error: &str) {
+    log_error!("Error in {}: {}", context, error);
+
+    let entry = doc! {
+        "server_id": server_id.map(|s| s.to_string()),
+        "context": context,
+        "error": error,
+        "at": DateTime::now(),
+    };
+    if let Err(e) = error_collection().await.insert_one(entry, None).await {
+        log_error!("Error recording error: {:?}", e);
+    }
+}
+
+/// The names of the classes created in a server since the given time.
+pub(crate) async fn classes_created_since(server_id: GuildId, since: DateTime) -> ClassResult<Vec<String>> {
+    Ok(
+        GuildScoped::new(server_id, audit_collection().await)
+            .find(
+                doc! {
+                    "event": "class_created",
+                    "at": { "$gte": since },
+                },
+                None,
+            )
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+            .iter()
+            .filter_map(|e| e.get_document("class").ok()?.get_str("name").ok())
+            .map(str::to_string)
+            .collect()
+    )
+}
+
+pub(crate) async fn errors_since(server_id: GuildId, since: DateTime) -> ClassResult<u64> {
+    GuildScoped::new(server_id, error_collection().await)
+        .count_documents(doc! { "at": { "$gte": since } }, None)
+        .await
+}
+
+/// The most recent unexpected errors anywhere, newest first, as (context, error, time).
+pub(crate) async fn recent_errors(limit: i64) -> ClassResult<Vec<(String, String, DateTime)>> {
+    Ok(
+        error_collection().await
+            .find(None, FindOptions::builder().sort(doc! { "at": -1 }).limit(limit).build())
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+            .iter()
+            .filter_map(|e| Some((
+                e.get_str("context").ok()?.to_string(),
+                e.get_str("error").ok()?.to_string(),
+                *e.get_datetime("at").ok()?,
+            )))
+            .collect()
+    )
+}
+
+async fn audit_collection() -> Collection<Document> {
+    static AUDIT_LOG: OnceCell<Collection<Document>> = OnceCell::const_new();
+
+    AUDIT_LOG
+        .get_or_init(|| async {
+            get_conn()
+                .await
+                .database(&ENV.mongodb_name)
+                .collection("audit_log")
+        })
+        .await
+        .clone()
+}
+
+async fn error_collection() -> Collection<Document> {
+    static ERROR_LOG: OnceCell<Collection<Document>> = OnceCell::const_new();
+
+    ERROR_LOG
+        .get_or_init(|| async {
+            get_conn()
+                .await
+                .database(&ENV.mongodb_name)
+                .collection("error_log")
+        })
+        .await
+        .clone()
+}
@@ -0,0 +1,345 @@
+use futures::TryStreamExt;
+use itertools::Itertools;
+use mongodb::bson::doc;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::json::{json, Value};
+use serenity::model::channel::ChannelType;
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, RoleId, RuleId};
+use tokio::sync::OnceCell;
+
+use crate::classes::{Class, Server};
+use crate::events::BotEvent;
+use crate::redact::log_error;
+use crate::{get_conn, lookup, ClassError, ClassResult, Context, Error, ENV};
+
+/// Discord allows at most this many exempt channels on a single AutoMod rule.
+const EXEMPT_CHANNEL_LIMIT: usize = 50;
+
+/// A set of AutoMod settings stored on the server, applied to each class as its own rules.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct AutoModTemplate {
+    pub(crate) name: String,
+    /// Words or phrases to block, using Discord's keyword syntax.
+    pub(crate) keywords: Vec<String>,
+    /// The most user and role mentions allowed in one message.
+    pub(crate) mention_limit: Option<u8>,
+}
+
+impl AutoModTemplate {
+    /// The raw rule bodies for this template. Serenity's builder has no mention spam trigger, so
+    /// rules are sent to the API as JSON directly.
+    fn rules(&self, class: &Class, server: &Server, exempt_channels: &[String]) -> Vec<Value> {
+        let mut actions = vec![json!({ "type": 1 })];
+        if let Some(channel) = server.staff_channel {
+            actions.push(json!({ "type": 2, "metadata": { "channel_id": channel.to_string() } }));
+        }
+
+        let mut rules = Vec::new();
+        if !self.keywords.is_empty() {
+            rules.push(json!({
+                "name": format!("{} ({})", self.name, class.short_name),
+                "event_type": 1,
+                "trigger_type": 1,
+                "trigger_metadata": { "keyword_filter": self.keywords },
+                "actions": actions,
+                "enabled": true,
+                "exempt_channels": exempt_channels,
+            }));
+        }
+        if let Some(limit) = self.mention_limit {
+            rules.push(json!({
+                "name": format!("{} mentions ({})", self.name, class.short_name),
+                "event_type": 1,
+                "trigger_type": 5,
+                "trigger_metadata": { "mention_total_limit": limit },
+                "actions": actions,
+                "enabled": true,
+                "exempt_channels": exempt_channels,
+            }));
+        }
+
+        rules
+    }
+}
+
+/// An AutoMod rule created for a class from one of the server's templates.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ClassRule {
+    server_id: GuildId,
+    role: RoleId,
+    template: String,
+    rule_id: RuleId,
+}
+
+impl ClassRule {
+    async fn list(role: RoleId) -> ClassResult<Vec<ClassRule>> {
+        Ok(
+            Self::get_collection().await
+                .find(doc! { "role": role.to_string() }, None)
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static RULES: OnceCell<Collection<ClassRule>> = OnceCell::const_new();
+
+        RULES
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("automod_rules")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// AutoMod rules apply to a whole server, so a rule is limited to a class by exempting every
+/// other text channel.
+async fn exempt_channels(ctx: &SContext, class: &Class) -> ClassResult<Vec<String>> {
+    let guild = lookup::guild(ctx, class.server_id).await?;
+    let class_channels = class.all_channels();
+    let exempt = guild.channels.values()
+        .filter_map(|c| c.clone().guild())
+        .filter(|c| matches!(c.kind, ChannelType::Text | ChannelType::News | ChannelType::Voice))
+        .filter(|c| !class_channels.contains(&c.id))
+        .filter(|c| !c.parent_id.is_some_and(|p| class.categories.contains(&p)))
+        .map(|c| c.id.to_string())
+        .collect::<Vec<_>>();
+
+    if exempt.len() > EXEMPT_CHANNEL_LIMIT {
+        return Err(ClassError::TooManyChannelsForAutoMod);
+    }
+
+    Ok(exempt)
+}
+
+/// Create the rules for a template on a class, replacing any rules already made from it.
+async fn apply(ctx: &SContext, class: &Class, server: &Server, template: &AutoModTemplate) -> ClassResult<()> {
+    remove(ctx, class, Some(&template.name)).await?;
+
+    let exempt = exempt_channels(ctx, class).await?;
+    let collection = ClassRule::get_collection().await;
+    for body in template.rules(class, server, &exempt) {
+        let map = match body {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        };
+        let rule = ctx.http().create_automod_rule(class.server_id.0, &map).await?;
+        collection.insert_one(ClassRule {
+            server_id: class.server_id,
+            role: class.role,
+            template: template.name.clone(),
+            rule_id: rule.id,
+        }, None).await?;
+    }
+
+    Ok(())
+}
+
+/// Delete a class's rules, either all of them or only those made from one template.
+async fn remove(ctx: &SContext, class: &Class, template: Option<&str>) -> ClassResult<()> {
+    let collection = ClassRule::get_collection().await;
+    for rule in ClassRule::list(class.role).await? {
+        if template.is_some_and(|t| t != rule.template) {
+            continue;
+        }
+
+        // Ignoring errors as the rule may already have been deleted by hand
+        ctx.http().delete_automod_rule(class.server_id.0, rule.rule_id.0).await.ok();
+        collection.delete_one(doc! { "rule_id": rule.rule_id.to_string() }, None).await?;
+    }
+
+    Ok(())
+}
+
+/// Event bus subscriber creating AutoMod rules for new classes from the server's templates, and
+/// removing them again when the class is deleted.
+pub(crate) async fn lifecycle(ctx: SContext, event: BotEvent) {
+    let result = match &event {
+        BotEvent::ClassCreated { server_id, class } => match Server::get_or_create(*server_id).await {
+            Ok(server) => {
+                let mut result = Ok(());
+                for template in &server.automod_templates {
+                    result = result.and(apply(&ctx, class, &server, template).await);
+                }
+                result
+            }
+            Err(e) => Err(e),
+        },
+        BotEvent::ClassDeleted { class, .. } => remove(&ctx, class, None).await,
+        _ => return,
+    };
+
+    if let Err(e) = result {
+        log_error!("[{}] Error updating AutoMod rules: {:?}", event.server_id(), e);
+    }
+}
+
+#[poise::command(
+    slash_command,
+    subcommands("AutoModCommand::template", "AutoModCommand::apply", "AutoModCommand::clear")
+)]
+pub(crate) async fn automod(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct AutoModCommand;
+impl AutoModCommand {
+    #[poise::command(
+        slash_command,
+        subcommands("AutoModTemplateCommand::add", "AutoModTemplateCommand::remove", "AutoModTemplateCommand::list")
+    )]
+    async fn template(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Create a class's AutoMod rules from one template, or from all of them.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        required_bot_permissions = "MANAGE_GUILD",
+    )]
+    async fn apply(ctx: Context<'_>, class: Role, template: Option<String>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let server = Server::get_or_create(class.server_id).await?;
+        let templates = match &template {
+            Some(name) => vec![
+                server.automod_templates.iter()
+                    .find(|t| t.name == name.trim())
+                    .ok_or(ClassError::InvalidAutoModTemplate)?
+            ],
+            None => server.automod_templates.iter().collect(),
+        };
+
+        for template in &templates {
+            apply(ctx.discord(), &class, &server, template).await?;
+        }
+
+        ctx.say(format!(
+            "Applied {} AutoMod templates to \"{}\".",
+            templates.len(),
+            class.name,
+        )).await?;
+
+        Ok(())
+    }
+
+    /// Remove all of a class's AutoMod rules.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        required_bot_permissions = "MANAGE_GUILD",
+    )]
+    async fn clear(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        remove(ctx.discord(), &class, None).await?;
+
+        ctx.say(format!("Removed the AutoMod rules for \"{}\".", class.name)).await?;
+
+        Ok(())
+    }
+}
+
+struct AutoModTemplateCommand;
+impl AutoModTemplateCommand {
+    /// Add an AutoMod template. Keywords are comma separated.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn add(
+        ctx: Context<'_>,
+        name: String,
+        keywords: Option<String>,
+        mention_limit: Option<u8>,
+    ) -> Result<(), Error> {
+        let keywords = keywords.iter()
+            .flat_map(|k| k.split(','))
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if keywords.is_empty() && mention_limit.is_none() {
+            Err(ClassError::EmptyAutoModTemplate)?;
+        }
+
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.add_automod_template(AutoModTemplate {
+            name: name.trim().to_string(),
+            keywords,
+            mention_limit,
+        }).await?;
+
+        ctx.say(format!(
+            "Added AutoMod template \"{}\". It will be applied to new classes, use `/automod apply` for existing ones.",
+            name.trim(),
+        )).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn remove(ctx: Context<'_>, name: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.remove_automod_template(name.trim()).await?;
+
+        // Rules already made from the template go with it
+        for class in Class::list(ctx.guild_id().ok_or(ClassError::NoServer)?).await? {
+            remove(ctx.discord(), &class, Some(name.trim())).await?;
+        }
+
+        ctx.say(format!("Removed AutoMod template \"{}\".", name.trim())).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn list(ctx: Context<'_>) -> Result<(), Error> {
+        let server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+
+        if server.automod_templates.is_empty() {
+            ctx.say("There are no AutoMod templates for this server.").await?;
+        } else {
+            ctx.say(format!(
+                "AutoMod templates:\n{}",
+                server.automod_templates.iter()
+                    .map(|t| format!(
+                        "**{}**: {}{}",
+                        t.name,
+                        if t.keywords.is_empty() { "no keywords".to_string() } else { t.keywords.join(", ") },
+                        t.mention_limit.map(|l| format!(", at most {} mentions", l)).unwrap_or_default(),
+                    ))
+                    .join("\n"),
+            )).await?;
+        }
+
+        Ok(())
+    }
+}
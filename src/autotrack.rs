@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serenity::async_trait;
+use serenity::client::Context as SContext;
+use serenity::model::channel::GuildChannel;
+use serenity::model::id::ChannelId;
+use serenity::prelude::EventHandler;
+
+use crate::classes::{Class, Server};
+use crate::ClassResult;
+use crate::redact::log_error;
+
+/// How long to wait before tracking a new channel, so a channel the bot just created for itself
+/// has been excluded by then.
+const SETTLE: Duration = Duration::from_secs(2);
+/// How long excluded channels are remembered, which only has to outlast their create event.
+const REMEMBERED: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref EXCLUDED: Mutex<HashMap<ChannelId, Instant>> = Mutex::new(HashMap::new());
+}
+
+/// Keep a channel the bot created under a class's category out of the class, like a team's
+/// private channels or a study session's voice channel. Tracking it would make it visible to the
+/// whole class.
+pub(crate) fn exclude(channel: ChannelId) {
+    let mut excluded = EXCLUDED.lock().unwrap();
+    excluded.retain(|_, at| at.elapsed() < REMEMBERED);
+    excluded.insert(channel, Instant::now());
+}
+
+async fn track_channel(channel: &GuildChannel) -> ClassResult<()> {
+    let category = match channel.parent_id {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+    tokio::time::sleep(SETTLE).await;
+    if EXCLUDED.lock().unwrap().contains_key(&channel.id) {
+        return Ok(());
+    }
+    let mut class = match Class::find_by_category(category).await? {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+
+    if Server::get_or_create(class.server_id).await?.auto_track_channels {
+        class.add_channel(channel.id, channel.kind).await?;
+    }
+
+    Ok(())
+}
+
+async fn untrack_channel(channel: &GuildChannel) -> ClassResult<()> {
+    let class = match channel.parent_id {
+        Some(category) => Class::find_by_category(category).await?,
+        None => None,
+    };
+
+    if let Some(mut class) = class {
+        class.remove_channel(channel.id).await?;
+    }
+
+    Ok(())
+}
+
+/// Keeps a class's channel lists up to date as channels are added to and removed from its
+/// category by hand.
+pub(crate) struct AutoTrackHandler;
+
+#[async_trait]
+impl EventHandler for AutoTrackHandler {
+    async fn channel_create(&self, _ctx: SContext, channel: &GuildChannel) {
+        if let Err(e) = track_channel(channel).await {
+            log_error!("Error auto-tracking channel {}: {:?}", channel.id, e);
+        }
+    }
+
+    async fn channel_delete(&self, _ctx: SContext, channel: &GuildChannel) {
+        if let Err(e) = untrack_channel(channel).await {
+            log_error!("Error untracking deleted channel {}: {:?}", channel.id, e);
+        }
+    }
+}
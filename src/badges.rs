@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::{Context as SContext, EventHandler};
+use serenity::http::CacheHttp;
+use serenity::model::guild::Member;
+use serenity::model::id::RoleId;
+
+use crate::classes::Class;
+use crate::redact::log_error;
+use crate::users::UserProfile;
+use crate::{ClassError, ClassResult, Context, Error};
+
+/// Discord's limit on the length of a nickname.
+const NICKNAME_LIMIT: usize = 32;
+
+/// A staff tier of a class, like instructor or TA, whose members get a badge added to their
+/// nickname.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct StaffBadge {
+    pub(crate) role: RoleId,
+    /// Shown before the class's name in the badge, like "TA".
+    pub(crate) label: String,
+}
+
+impl StaffBadge {
+    /// The badge's text for a class, like "[TA CS341]".
+    pub(crate) fn text(&self, class: &Class) -> String {
+        format!("[{} {}]", self.label, class.name.split_whitespace().collect::<String>())
+    }
+}
+
+/// Take any of the given badges off the end of a nickname.
+pub(crate) fn strip_badges<'a>(nickname: &'a str, badges: &[String]) -> &'a str {
+    let mut nickname = nickname;
+    while let Some(stripped) = badges.iter()
+        .find_map(|b| nickname.strip_suffix(b.as_str()).and_then(|n| n.strip_suffix(' ')))
+    {
+        nickname = stripped;
+    }
+    nickname
+}
+
+/// A name with as many of the badges added as fit within Discord's nickname limit. Badges that
+/// don't fit are left off rather than cutting the name short.
+pub(crate) fn badged_nickname(name: &str, badges: &[String]) -> String {
+    let mut nickname = name.to_string();
+    for badge in badges {
+        if nickname.chars().count() + badge.chars().count() < NICKNAME_LIMIT {
+            nickname += " ";
+            nickname += badge;
+        }
+    }
+    nickname
+}
+
+/// Set the member's nickname to match the staff tiers they hold, leaving it alone if nothing
+/// changed. `stale` badges are ones no longer in use which should still be taken off.
+pub(crate) async fn refresh(cache_http: impl CacheHttp, member: &Member, stale: &[String]) -> ClassResult<()> {
+    let classes = Class::list(member.guild_id).await?;
+    let all_badges = classes.iter()
+        .flat_map(|c| c.badges.iter().map(move |b| b.text(c)))
+        .chain(stale.iter().cloned())
+        .collect::<Vec<_>>();
+    if all_badges.is_empty() {
+        return Ok(());
+    }
+
+    let current = member.nick.clone().unwrap_or_else(|| member.user.name.clone());
+    let name = strip_badges(&current, &all_badges);
+    let badges = if UserProfile::get(member.user.id).await?.hide_badges {
+        Vec::new()
+    } else {
+        classes.iter()
+            .flat_map(|c| c.badges.iter()
+                .filter(|b| member.roles.contains(&b.role))
+                .map(move |b| b.text(c))
+            )
+            .collect()
+    };
+    let nickname = badged_nickname(name, &badges);
+
+    if nickname != current {
+        // An empty nickname resets it to the member's username
+        let nickname = if nickname == member.user.name { String::new() } else { nickname };
+        member.edit(cache_http.http(), |m| m.nickname(nickname)).await?;
+    }
+
+    Ok(())
+}
+
+/// Adds and removes staff badges as members are given or lose staff tier roles.
+pub(crate) struct StaffBadgeHandler;
+
+#[async_trait]
+impl EventHandler for StaffBadgeHandler {
+    async fn guild_member_update(&self, ctx: SContext, old: Option<Member>, new: Member) {
+        if new.user.bot || old.map(|o| o.roles == new.roles).unwrap_or(false) {
+            return;
+        }
+        if let Err(e) = refresh(&ctx, &new, &[]).await {
+            log_error!("Error updating staff badges: {:?}", e);
+        }
+    }
+}
+
+/// Choose whether staff badges are added to your nickname.
+#[poise::command(slash_command, ephemeral)]
+pub(crate) async fn badges(ctx: Context<'_>, show: bool) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    UserProfile::set_hide_badges(ctx.author().id, !show).await?;
+    let member = ctx.author_member().await.ok_or(ClassError::NoServer)?;
+    refresh(ctx.discord(), &member, &[]).await?;
+
+    if show {
+        ctx.say("Your staff badges will be shown in your nickname.").await?;
+    } else {
+        ctx.say("Your staff badges will no longer be shown in your nickname.").await?;
+    }
+
+    Ok(())
+}
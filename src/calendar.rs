@@ -0,0 +1,44 @@
+use chrono::{DateTime as ChronoDateTime, Duration, NaiveDate, TimeZone, Utc};
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::GuildId;
+
+use crate::classes::Server;
+use crate::{scheduler, ClassResult};
+
+/// A holiday or break in a server's academic calendar, during which there are no classes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct CalendarBreak {
+    pub(crate) name: String,
+    pub(crate) starts: DateTime,
+    pub(crate) ends: DateTime,
+}
+
+impl CalendarBreak {
+    pub(crate) fn contains(&self, time: DateTime) -> bool {
+        self.starts <= time && time < self.ends
+    }
+}
+
+/// Parse the start or end of a break. A bare date like `2024-12-23` covers that whole day in UTC,
+/// so an end date is inclusive. Anything else is read as a time by `scheduler::parse_time`.
+pub(crate) fn parse_break_time(time: &str, end: bool) -> Option<ChronoDateTime<Utc>> {
+    match NaiveDate::parse_from_str(time.trim(), "%Y-%m-%d") {
+        Ok(date) => {
+            let date = if end { date + Duration::days(1) } else { date };
+            Some(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?))
+        }
+        Err(_) => scheduler::parse_time(time),
+    }
+}
+
+/// The break a server is currently on, if any. Scheduled reminders and countdowns for its classes
+/// are held back until it ends.
+pub(crate) async fn current_break(server_id: GuildId) -> ClassResult<Option<CalendarBreak>> {
+    let now = DateTime::now();
+    Ok(Server::get_or_create(server_id).await?.breaks.into_iter().find(|b| b.contains(now)))
+}
+
+pub(crate) async fn on_break(server_id: GuildId) -> ClassResult<bool> {
+    Ok(current_break(server_id).await?.is_some())
+}
@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use reqwest::header::LINK;
+use serde::{Deserialize, Serialize};
+
+use crate::{secrets, ClassResult};
+
+lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::new();
+}
+
+/// The Canvas course a class imports its assignments from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct CanvasLink {
+    /// The school's Canvas address, like `https://canvas.example.edu`.
+    pub(crate) base_url: String,
+    pub(crate) course_id: u64,
+    /// An access token for the course, encrypted with `secrets::seal`.
+    pub(crate) token: String,
+}
+
+#[derive(Deserialize)]
+struct CanvasAssignment {
+    name: String,
+    due_at: Option<DateTime<Utc>>,
+}
+
+/// The URL of the next page of results, from a Canvas `Link` header.
+fn next_page(response: &reqwest::Response) -> Option<String> {
+    response.headers()
+        .get(LINK)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .find(|l| l.contains("rel=\"next\""))?
+        .split(';')
+        .next()
+        .map(|u| u.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+}
+
+/// Every assignment in the course that has a due date, with its name and due date.
+pub(crate) async fn assignments(link: &CanvasLink) -> ClassResult<Vec<(String, DateTime<Utc>)>> {
+    let token = secrets::open(&link.token)?;
+    let mut url = Some(format!(
+        "{}/api/v1/courses/{}/assignments?per_page=100",
+        link.base_url.trim_end_matches('/'),
+        link.course_id,
+    ));
+
+    let mut assignments = Vec::new();
+    while let Some(page) = url {
+        let response = CLIENT.get(&page)
+            .bearer_auth(&token)
+            .send()
+            .await?
+            .error_for_status()?;
+        url = next_page(&response);
+        assignments.extend(
+            response.json::<Vec<CanvasAssignment>>().await?
+                .into_iter()
+                .filter_map(|a| Some((a.name, a.due_at?)))
+        );
+    }
+
+    Ok(assignments)
+}
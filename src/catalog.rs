@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use itertools::Itertools;
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::collector::CollectComponentInteraction;
+use serenity::http::CacheHttp;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::prelude::component::ButtonStyle;
+
+use crate::classes::{Class, Server};
+use crate::departments::department_of;
+use crate::sessions::StudySession;
+use crate::{class_members, lookup, ClassError, Context, Error};
+
+/// How many classes are shown on each page of the catalog.
+const PAGE_SIZE: usize = 10;
+/// How long the page buttons of `/catalog` keep working.
+const PAGE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// How much of a class's description is shown. Embeds can be at most 6000 characters in total,
+/// which a page of classes with names of up to 100 characters and this much description each
+/// stays under.
+const DESCRIPTION_LIMIT: usize = 300;
+
+fn page_buttons(c: &mut CreateComponents, page: usize, pages: usize) -> &mut CreateComponents {
+    c.create_action_row(|r| r
+        .create_button(|b| b
+            .custom_id("catalog_page_prev")
+            .style(ButtonStyle::Secondary)
+            .label("Previous")
+            .disabled(page == 0)
+        )
+        .create_button(|b| b
+            .custom_id("catalog_page_next")
+            .style(ButtonStyle::Secondary)
+            .label("Next")
+            .disabled(page + 1 >= pages)
+        )
+    )
+}
+
+/// Send `pages` one at a time, with buttons to move between them until they time out.
+async fn paginate(ctx: Context<'_>, pages: &[CreateEmbed]) -> Result<(), Error> {
+    let mut page = 0;
+    let reply = ctx
+        .send(|m| m
+            .embed(|e| { *e = pages[page].clone(); e })
+            .components(|c| page_buttons(c, page, pages.len()))
+        )
+        .await?;
+    let message = reply.message().await?.id;
+
+    while let Some(interaction) = CollectComponentInteraction::new(ctx.discord())
+        .message_id(message)
+        .author_id(ctx.author().id)
+        .timeout(PAGE_TIMEOUT)
+        .await
+    {
+        page = match interaction.data.custom_id.as_str() {
+            "catalog_page_prev" => page.saturating_sub(1),
+            "catalog_page_next" => (page + 1).min(pages.len() - 1),
+            _ => page,
+        };
+
+        interaction
+            .create_interaction_response(ctx.discord().http(), |r| r
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|d| d
+                    .set_embed(pages[page].clone())
+                    .components(|c| page_buttons(c, page, pages.len()))
+                )
+            )
+            .await?;
+    }
+
+    reply.edit(ctx, |m| m.components(|c| c)).await?;
+
+    Ok(())
+}
+
+/// Browse every class in the server, grouped by department.
+#[poise::command(slash_command, ephemeral)]
+pub(crate) async fn catalog(
+    ctx: Context<'_>,
+    #[description = "Only show classes in this department, like CS"]
+    department: Option<String>,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+    let guild = lookup::current_guild(ctx).await?;
+    let server = Server::get_or_create(server_id).await?;
+    let next_sessions = StudySession::next_by_class(server_id).await?;
+
+    let departments = Class::list(server_id).await?
+        .into_iter()
+        .into_group_map_by(department_of)
+        .into_iter()
+        .filter(|(d, _)| department.as_ref().map(|f| d.eq_ignore_ascii_case(f.trim())).unwrap_or(true))
+        .sorted_by(|(d1, _), (d2, _)| d1.cmp(d2))
+        .collect::<Vec<_>>();
+
+    let mut pages = Vec::new();
+    for (department, mut classes) in departments {
+        classes.sort_by(|c1, c2| human_sort::compare(&c1.name, &c2.name));
+        let theme = server.department_theme(&department);
+        let chunks = classes.chunks(PAGE_SIZE).collect::<Vec<_>>();
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut embed = CreateEmbed::default();
+            embed.title(match theme.and_then(|t| t.emoji.as_ref()) {
+                Some(emoji) => format!("{} {}", emoji, department),
+                None => department.clone(),
+            });
+            if let Some(colour) = theme.and_then(|t| t.colour) {
+                embed.colour(colour);
+            }
+            if chunks.len() > 1 {
+                embed.description(format!("Part {} of {}", i + 1, chunks.len()));
+            }
+            for class in *chunk {
+                let mut details = match &class.description {
+                    Some(d) if d.chars().count() > DESCRIPTION_LIMIT => {
+                        format!("{}…", d.chars().take(DESCRIPTION_LIMIT - 1).collect::<String>())
+                    }
+                    Some(d) => d.clone(),
+                    None => "No description".to_string(),
+                };
+                if let Some(starts) = next_sessions.get(&class.role) {
+                    details += &format!("\nNext study session <t:{}:R>", starts);
+                }
+                let name = match &class.emoji {
+                    Some(emoji) => format!("{} {}", emoji, class.name),
+                    None => class.name.clone(),
+                };
+                embed.field(
+                    format!("{} ({} members)", name, class_members(&guild, class.role).len()),
+                    details,
+                    false,
+                );
+            }
+            pages.push(embed);
+        }
+    }
+
+    if pages.is_empty() {
+        Err(ClassError::NoCatalogClasses)?;
+    }
+    let page_count = pages.len();
+    for (i, page) in pages.iter_mut().enumerate() {
+        page.footer(|f| f.text(format!("Page {}/{}", i + 1, page_count)));
+    }
+
+    paginate(ctx, &pages).await
+}
@@ -0,0 +1,109 @@
+use chrono::{Duration, Utc};
+use lazy_static::lazy_static;
+use mongodb::bson::DateTime;
+use serde_json::json;
+use serenity::model::guild::Guild;
+use serenity::model::id::RoleId;
+
+use crate::history::EnrollmentEvent;
+use crate::ClassResult;
+
+const QUICKCHART_URL: &str = "https://quickchart.io/chart";
+
+lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::new();
+}
+
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub(crate) enum ChartPeriod {
+    #[name = "Week"]
+    Week,
+    #[name = "Month"]
+    Month,
+    #[name = "Quarter"]
+    Quarter,
+    #[name = "Year"]
+    Year,
+}
+
+impl ChartPeriod {
+    /// How many days each point on the chart covers, and how many points there are.
+    fn buckets(&self) -> (i64, i64) {
+        match self {
+            Self::Week => (1, 7),
+            Self::Month => (1, 30),
+            Self::Quarter => (7, 13),
+            Self::Year => (7, 52),
+        }
+    }
+}
+
+/// Render a line chart of how many members were enrolled in a class, or in every class in total,
+/// over the given period, as a PNG.
+pub(crate) async fn enrollment(
+    guild: &Guild,
+    roles: &[RoleId],
+    title: &str,
+    period: ChartPeriod,
+) -> ClassResult<Vec<u8>> {
+    let (bucket_days, buckets) = period.buckets();
+    let now = Utc::now();
+    let start = now - Duration::days(bucket_days * buckets);
+
+    let role = match roles {
+        [role] => Some(*role),
+        _ => None,
+    };
+    let events = EnrollmentEvent::since(guild.id, role, DateTime::from_millis(start.timestamp_millis()))
+        .await?
+        .into_iter()
+        .filter(|e| roles.contains(&e.role))
+        .collect::<Vec<_>>();
+
+    // Working backwards from current membership, undoing each change made after each point
+    let current = guild.members.values()
+        .map(|m| m.roles.iter().filter(|r| roles.contains(r)).count() as i64)
+        .sum::<i64>();
+    let mut labels = Vec::new();
+    let mut counts = Vec::new();
+    for i in 1..=buckets {
+        let at = start + Duration::days(bucket_days * i);
+        let later = events.iter()
+            .filter(|e| e.at.timestamp_millis() > at.timestamp_millis())
+            .map(|e| if e.joined { 1 } else { -1 })
+            .sum::<i64>();
+        labels.push(at.format("%b %-d").to_string());
+        counts.push(current - later);
+    }
+
+    let body = json!({
+        "version": "2",
+        "width": 800,
+        "height": 400,
+        "format": "png",
+        "backgroundColor": "white",
+        "chart": {
+            "type": "line",
+            "data": {
+                "labels": labels,
+                "datasets": [{ "label": "Enrolled", "data": counts, "fill": false }],
+            },
+            "options": {
+                "title": { "display": true, "text": title },
+                "legend": { "display": false },
+                "scales": { "yAxes": [{ "ticks": { "beginAtZero": true, "precision": 0 } }] },
+            },
+        },
+    });
+
+    Ok(
+        CLIENT.post(QUICKCHART_URL)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec()
+    )
+}
@@ -2,20 +2,106 @@ use std::collections::HashSet;
 
 use futures::future::TryFutureExt;
 use futures::TryStreamExt;
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use mongodb::Collection;
-use mongodb::bson::doc;
-use mongodb::options::{DeleteOptions, FindOneAndReplaceOptions, FindOneOptions, FindOptions, Hint};
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::options::{DeleteOptions, FindOneOptions, FindOptions, Hint, UpdateOptions};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serenity::http::CacheHttp;
-use serenity::model::channel::{Channel, ChannelCategory, ChannelType, GuildChannel, PermissionOverwrite, PermissionOverwriteType};
-use serenity::model::guild::Role;
-use serenity::model::id::{ChannelId, GuildId, RoleId};
-use serenity::model::Permissions;
+use serenity::model::channel::{Channel, ChannelCategory, ChannelType, GuildChannel, PermissionOverwrite, PermissionOverwriteType, ReactionType};
+use serenity::model::guild::{Guild, PremiumTier, Role};
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
 use serenity::prelude::Mentionable;
 use tokio::sync::OnceCell;
 
-use crate::{ClassError, ClassResult, Context, ENV, get_conn};
+use crate::{discord_name, lookup, ClassError, ClassResult, Context, ENV, get_conn};
+use crate::automod::AutoModTemplate;
+use crate::badges::StaffBadge;
+use crate::calendar::CalendarBreak;
+use crate::canvas::CanvasLink;
+use crate::courses::CatalogSource;
+use crate::departments::{DepartmentMenu, DepartmentTheme};
+use crate::enrollment::EnrollmentWindow;
+use crate::events::{self, BotEvent};
+use crate::jobs::JobStatus;
+use crate::policy::CommandPolicy;
+use crate::redact::log_error;
+use crate::renames::RenameSync;
+use crate::scoped::GuildScoped;
+use crate::secrets;
+use crate::trash::TrashedClass;
+use crate::visibility::Visibility;
+
+/// Discord allows at most this many channels in a category.
+const CATEGORY_LIMIT: usize = 50;
+
+/// Discord's limit on the size of a custom emoji's image, in bytes.
+pub(crate) const EMOJI_SIZE_LIMIT: u64 = 256 * 1024;
+
+/// The name given to a class's custom emoji: its short name, limited to the characters and
+/// length Discord allows.
+pub(crate) fn emoji_name(short_name: &str) -> String {
+    let name = short_name.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .take(32)
+        .collect::<String>();
+    if name.len() < 2 {
+        format!("class_{}", name)
+    } else {
+        name
+    }
+}
+
+/// How many static custom emoji a server can have at its boost level.
+pub(crate) fn emoji_limit(tier: PremiumTier) -> usize {
+    match tier {
+        PremiumTier::Tier1 => 100,
+        PremiumTier::Tier2 => 150,
+        PremiumTier::Tier3 => 250,
+        _ => 50,
+    }
+}
+
+/// Every collection field that refers to a class by its role, which has to be updated when a class
+/// moves to a different role.
+const ROLE_REFERENCES: [(&str, &str); 23] = [
+    ("modmail", "class"),
+    ("class_invites", "role"),
+    ("voice_time", "role"),
+    ("study_sessions", "role"),
+    ("enrollment_events", "role"),
+    ("tutors", "role"),
+    ("peer_review_opt_ins", "role"),
+    ("peer_review_rounds", "role"),
+    ("teams", "role"),
+    ("faqs", "role"),
+    ("tags", "role"),
+    ("snippets", "role"),
+    ("federation_mirrors", "role"),
+    ("federation_mirrors", "hub_role"),
+    ("automod_rules", "role"),
+    ("message_archive", "role"),
+    ("icebreakers", "role"),
+    ("help_threads", "role"),
+    ("email_subscriptions", "role"),
+    ("assignments", "role"),
+    ("exam_countdowns", "role"),
+    ("pending_notifications", "role"),
+    ("hand_queues", "role"),
+];
+
+/// Every collection field holding a list of classes by their roles, which has to be updated when
+/// a class moves to a different role.
+const ROLE_LIST_REFERENCES: [(&str, &str); 3] = [
+    ("users", "favorites"),
+    ("notification_prefs", "muted"),
+    ("notification_prefs", "digest"),
+];
+
+/// How many times a write to a server or class is retried when someone else changes it first.
+const WRITE_ATTEMPTS: usize = 3;
 
 lazy_static! {
     static ref SERVER_ID_HINT: Hint = Hint::Name("server_id_1".to_string());
@@ -24,21 +110,107 @@ lazy_static! {
     static ref ROLE_HINT: Hint = Hint::Name("role_1".to_string());
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct Server {
     server_id: GuildId,
     admin_roles: Vec<RoleId>,
-    refrole: Option<RoleId>,
+    pub(crate) refrole: Option<RoleId>,
+    /// Where class events are forwarded, each encrypted with `secrets::seal`.
+    #[serde(default)]
+    pub(crate) webhooks: Vec<String>,
+    #[serde(default)]
+    pub(crate) staff_channel: Option<ChannelId>,
+    #[serde(default)]
+    pub(crate) suggestions_channel: Option<ChannelId>,
+    #[serde(default)]
+    pub(crate) tutoring_channel: Option<ChannelId>,
+    #[serde(default)]
+    pub(crate) enrollment_window: Option<EnrollmentWindow>,
+    /// The server this one mirrors classes from, if it has joined a federation.
+    #[serde(default)]
+    pub(crate) federation_hub: Option<GuildId>,
+    /// The servers allowed to use this one as their federation hub.
+    #[serde(default)]
+    pub(crate) federation_members: Vec<GuildId>,
+    #[serde(default)]
+    pub(crate) rename_sync: RenameSync,
+    #[serde(default)]
+    pub(crate) auto_track_channels: bool,
+    #[serde(default)]
+    pub(crate) menu_group_by_tag: bool,
+    #[serde(default)]
+    pub(crate) automod_templates: Vec<AutoModTemplate>,
+    /// How long archived class messages are kept, or forever if unset.
+    #[serde(default)]
+    pub(crate) archive_retention_days: Option<u32>,
+    /// How long a class whose role or channels were deleted is kept before being cleaned up
+    /// automatically, or never if unset.
+    #[serde(default)]
+    pub(crate) orphan_prune_days: Option<u32>,
+    /// Where members joining and leaving classes are logged.
+    #[serde(default)]
+    pub(crate) log_channel: Option<ChannelId>,
+    /// Whether class roles added or removed by hand are logged too, not just changes made by the bot.
+    #[serde(default)]
+    pub(crate) log_manual_changes: bool,
+    /// Where unexpected errors in the server are posted for staff.
+    #[serde(default)]
+    pub(crate) alert_channel: Option<ChannelId>,
+    /// The most recently posted class menu, linked to from the welcome DM.
+    #[serde(default)]
+    pub(crate) menu_message: Option<(ChannelId, MessageId)>,
+    #[serde(default)]
+    pub(crate) welcome_dm_disabled: bool,
+    /// The welcome DM sent to new members, or the default if unset.
+    #[serde(default)]
+    pub(crate) welcome_template: Option<String>,
+    /// The pool that questions of the day are drawn from.
+    #[serde(default)]
+    pub(crate) icebreaker_prompts: Vec<String>,
+    /// How long a homework-help question can go unanswered before staff are pinged, or never if
+    /// unset.
+    #[serde(default)]
+    pub(crate) escalation_hours: Option<u32>,
+    /// How similar, in percent, a new homework-help question has to be to an earlier one for it
+    /// to be linked as possibly related, or never if unset.
+    #[serde(default)]
+    pub(crate) dedup_threshold: Option<u32>,
+    /// Where the official course catalog is imported from.
+    #[serde(default)]
+    pub(crate) catalog_source: Option<CatalogSource>,
+    /// Where the per-department class menus were last posted.
+    #[serde(default)]
+    pub(crate) menu_channel: Option<ChannelId>,
+    #[serde(default)]
+    pub(crate) department_menus: Vec<DepartmentMenu>,
+    #[serde(default)]
+    pub(crate) department_themes: Vec<DepartmentTheme>,
+    /// Holidays and breaks in the academic calendar.
+    #[serde(default)]
+    pub(crate) breaks: Vec<CalendarBreak>,
+    /// Where newly created classes are announced.
+    #[serde(default)]
+    pub(crate) new_class_channel: Option<ChannelId>,
+    /// Who may run commands, in place of their default permissions.
+    #[serde(default)]
+    pub(crate) command_policies: Vec<CommandPolicy>,
+    /// Bumped whenever classes are added, removed or moved to a different role, so class menus
+    /// built before then can be recognised as expired.
+    #[serde(default)]
+    pub(crate) menu_generation: u64,
+    /// Incremented by every write, so a write made from an out-of-date copy can be caught.
+    #[serde(default)]
+    pub(crate) version: u64,
 }
 
 impl Server {
 
     pub async fn get_or_create(id: GuildId) -> ClassResult<Self> {
-        let servers = Self::get_collection().await;
+        let servers = Self::scoped(id).await;
 
         if let Some(server) = servers
             .find_one(
-                doc! { "server_id": id.to_string() },
+                None,
                 Some(
                     FindOneOptions::builder()
                         .hint(SERVER_ID_HINT.clone())
@@ -54,6 +226,37 @@ impl Server {
             server_id: id,
             admin_roles: Vec::new(),
             refrole: None,
+            webhooks: Vec::new(),
+            staff_channel: None,
+            suggestions_channel: None,
+            tutoring_channel: None,
+            enrollment_window: None,
+            federation_hub: None,
+            federation_members: Vec::new(),
+            rename_sync: RenameSync::default(),
+            auto_track_channels: false,
+            menu_group_by_tag: false,
+            automod_templates: Vec::new(),
+            archive_retention_days: None,
+            orphan_prune_days: None,
+            log_channel: None,
+            log_manual_changes: false,
+            alert_channel: None,
+            menu_message: None,
+            welcome_dm_disabled: false,
+            welcome_template: None,
+            icebreaker_prompts: Vec::new(),
+            escalation_hours: None,
+            dedup_threshold: None,
+            catalog_source: None,
+            menu_channel: None,
+            department_menus: Vec::new(),
+            department_themes: Vec::new(),
+            breaks: Vec::new(),
+            new_class_channel: None,
+            command_policies: Vec::new(),
+            menu_generation: 0,
+            version: 0,
         };
 
         servers.insert_one(&server, None).await?;
@@ -61,31 +264,402 @@ impl Server {
         Ok(server)
     }
 
+    /// Mark every class menu built so far in a server as expired.
+    pub async fn bump_menu_generation(id: GuildId) -> ClassResult<()> {
+        Self::scoped(id).await
+            .update_one(
+                doc! {},
+                doc! { "$inc": { "menu_generation": 1, "version": 1 } },
+                UpdateOptions::builder().hint(SERVER_ID_HINT.clone()).build(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn set_refrole(&mut self, ctx: Context<'_>, role: RoleId) -> ClassResult<()> {
-        if !ctx.guild().ok_or(ClassError::NoServer)?.roles.contains_key(&role) {
+        if lookup::role(ctx.discord(), self.server_id, role).await?.is_none() {
             return Err(ClassError::InvalidRole);
         }
 
-        let new = Self {
-            server_id: self.server_id,
-            admin_roles: self.admin_roles.clone(),
-            refrole: Some(role),
-        };
+        self.replace(
+            Self {
+                refrole: Some(role),
+                ..self.clone()
+            },
+            "refrole",
+        ).await
+    }
 
-        Self::get_collection().await.find_one_and_replace(
-            doc! { "server_id": self.server_id.to_string() },
-            &new,
-            Some(FindOneAndReplaceOptions::builder()
-                .hint(SERVER_ID_HINT.clone())
-                .build()
-            ),
-        ).await?.ok_or(ClassError::NoServer)?;
+    pub async fn set_staff_channel(&mut self, channel: ChannelId) -> ClassResult<()> {
+        self.replace(
+            Self {
+                staff_channel: Some(channel),
+                ..self.clone()
+            },
+            "staff_channel",
+        ).await
+    }
+
+    pub async fn set_suggestions_channel(&mut self, channel: ChannelId) -> ClassResult<()> {
+        self.replace(
+            Self {
+                suggestions_channel: Some(channel),
+                ..self.clone()
+            },
+            "suggestions_channel",
+        ).await
+    }
+
+    pub async fn set_tutoring_channel(&mut self, channel: ChannelId) -> ClassResult<()> {
+        self.replace(
+            Self {
+                tutoring_channel: Some(channel),
+                ..self.clone()
+            },
+            "tutoring_channel",
+        ).await
+    }
+
+    pub async fn set_enrollment_window(&mut self, window: Option<EnrollmentWindow>) -> ClassResult<()> {
+        self.replace(
+            Self {
+                enrollment_window: window,
+                ..self.clone()
+            },
+            "enrollment_window",
+        ).await
+    }
+
+    pub async fn set_federation_hub(&mut self, hub: Option<GuildId>) -> ClassResult<()> {
+        self.replace(
+            Self {
+                federation_hub: hub,
+                ..self.clone()
+            },
+            "federation_hub",
+        ).await
+    }
+
+    /// Whether a server may join this one as its federation hub, and have its mirrors synced.
+    pub(crate) fn allows_federation(&self, member: GuildId) -> bool {
+        self.federation_members.contains(&member)
+    }
+
+    pub async fn set_federation_member(&mut self, member: GuildId, allowed: bool) -> ClassResult<()> {
+        let mut federation_members = self.federation_members.iter()
+            .copied()
+            .filter(|m| *m != member)
+            .collect::<Vec<_>>();
+        if allowed {
+            federation_members.push(member);
+        }
+
+        self.replace(Self { federation_members, ..self.clone() }, "federation_members").await
+    }
+
+    pub async fn set_rename_sync(&mut self, rename_sync: RenameSync) -> ClassResult<()> {
+        self.replace(
+            Self {
+                rename_sync,
+                ..self.clone()
+            },
+            "rename_sync",
+        ).await
+    }
+
+    pub async fn set_auto_track_channels(&mut self, enabled: bool) -> ClassResult<()> {
+        self.replace(
+            Self {
+                auto_track_channels: enabled,
+                ..self.clone()
+            },
+            "auto_track_channels",
+        ).await
+    }
+
+    pub async fn set_menu_group_by_tag(&mut self, enabled: bool) -> ClassResult<()> {
+        self.replace(
+            Self {
+                menu_group_by_tag: enabled,
+                ..self.clone()
+            },
+            "menu_group_by_tag",
+        ).await
+    }
+
+    pub async fn set_archive_retention_days(&mut self, days: Option<u32>) -> ClassResult<()> {
+        self.replace(
+            Self {
+                archive_retention_days: days,
+                ..self.clone()
+            },
+            "archive_retention_days",
+        ).await
+    }
+
+    pub async fn set_orphan_prune_days(&mut self, days: Option<u32>) -> ClassResult<()> {
+        self.replace(
+            Self {
+                orphan_prune_days: days,
+                ..self.clone()
+            },
+            "orphan_prune_days",
+        ).await
+    }
+
+    pub async fn set_escalation_hours(&mut self, hours: Option<u32>) -> ClassResult<()> {
+        self.replace(
+            Self {
+                escalation_hours: hours,
+                ..self.clone()
+            },
+            "escalation_hours",
+        ).await
+    }
+
+    pub async fn set_dedup_threshold(&mut self, threshold: Option<u32>) -> ClassResult<()> {
+        self.replace(Self { dedup_threshold: threshold, ..self.clone() }, "dedup_threshold").await
+    }
+
+    /// Set the roles allowed to run a command, or go back to its default permissions if there are
+    /// none.
+    pub async fn set_command_policy(&mut self, command: &str, roles: Vec<RoleId>) -> ClassResult<()> {
+        let mut command_policies = self.command_policies.iter()
+            .filter(|p| p.command != command)
+            .cloned()
+            .collect::<Vec<_>>();
+        if !roles.is_empty() {
+            command_policies.push(CommandPolicy { command: command.to_string(), roles });
+        }
+
+        self.replace(Self { command_policies, ..self.clone() }, "command_policies").await
+    }
+
+    pub async fn set_catalog_source(&mut self, source: Option<CatalogSource>) -> ClassResult<()> {
+        self.replace(Self { catalog_source: source, ..self.clone() }, "catalog_source").await
+    }
+
+    pub async fn set_alert_channel(&mut self, channel: Option<ChannelId>) -> ClassResult<()> {
+        self.replace(Self { alert_channel: channel, ..self.clone() }, "alert_channel").await
+    }
+
+    pub async fn set_new_class_channel(&mut self, channel: Option<ChannelId>) -> ClassResult<()> {
+        self.replace(Self { new_class_channel: channel, ..self.clone() }, "new_class_channel").await
+    }
+
+    pub async fn set_log_channel(&mut self, channel: Option<ChannelId>, manual_changes: bool) -> ClassResult<()> {
+        self.replace(
+            Self {
+                log_channel: channel,
+                log_manual_changes: manual_changes,
+                ..self.clone()
+            },
+            "log_channel",
+        ).await
+    }
+
+    pub async fn set_menu_message(&mut self, channel: ChannelId, message: MessageId) -> ClassResult<()> {
+        self.replace(
+            Self {
+                menu_message: Some((channel, message)),
+                ..self.clone()
+            },
+            "menu_message",
+        ).await
+    }
+
+    pub async fn set_department_menus(&mut self, channel: ChannelId, menus: Vec<DepartmentMenu>) -> ClassResult<()> {
+        self.replace(
+            Self {
+                menu_channel: Some(channel),
+                department_menus: menus,
+                ..self.clone()
+            },
+            "department_menus",
+        ).await
+    }
+
+    /// Set a department's theme, replacing any it already had. A theme with nothing set is removed.
+    pub async fn set_department_theme(&mut self, theme: DepartmentTheme) -> ClassResult<()> {
+        let mut department_themes = self.department_themes.iter()
+            .filter(|t| t.department != theme.department)
+            .cloned()
+            .collect::<Vec<_>>();
+        if theme.emoji.is_some() || theme.colour.is_some() {
+            department_themes.push(theme);
+        }
+
+        self.replace(Self { department_themes, ..self.clone() }, "department_themes").await
+    }
+
+    pub(crate) fn department_theme(&self, department: &str) -> Option<&DepartmentTheme> {
+        self.department_themes.iter().find(|t| t.department == department)
+    }
+
+    pub async fn set_welcome(&mut self, enabled: bool, template: Option<String>) -> ClassResult<()> {
+        self.replace(
+            Self {
+                welcome_dm_disabled: !enabled,
+                welcome_template: template,
+                ..self.clone()
+            },
+            "welcome",
+        ).await
+    }
+
+    pub async fn add_automod_template(&mut self, template: AutoModTemplate) -> ClassResult<()> {
+        if self.automod_templates.iter().any(|t| t.name == template.name) {
+            return Err(ClassError::AutoModTemplateExists);
+        }
+
+        let mut automod_templates = self.automod_templates.clone();
+        automod_templates.push(template);
+
+        self.replace(Self { automod_templates, ..self.clone() }, "automod_templates").await
+    }
+
+    pub async fn remove_automod_template(&mut self, name: &str) -> ClassResult<()> {
+        if !self.automod_templates.iter().any(|t| t.name == name) {
+            return Err(ClassError::InvalidAutoModTemplate);
+        }
+
+        let automod_templates = self.automod_templates.iter()
+            .filter(|t| t.name != name)
+            .cloned()
+            .collect();
+
+        self.replace(Self { automod_templates, ..self.clone() }, "automod_templates").await
+    }
+
+    /// The server's webhook URLs, decrypted.
+    pub(crate) fn webhook_urls(&self) -> ClassResult<Vec<String>> {
+        self.webhooks.iter().map(|w| secrets::open(w)).collect()
+    }
+
+    pub async fn add_webhook(&mut self, url: &str) -> ClassResult<()> {
+        match reqwest::Url::parse(url) {
+            Ok(u) if u.scheme() == "https" => {}
+            Ok(u) if u.scheme() == "http" => return Err(ClassError::InsecureWebhook),
+            _ => return Err(ClassError::InvalidUrl),
+        }
+        if self.webhook_urls()?.iter().any(|w| w == url) {
+            return Err(ClassError::WebhookExists);
+        }
+
+        let mut webhooks = self.webhooks.clone();
+        webhooks.push(secrets::seal(url)?);
+
+        self.replace(Self { webhooks, ..self.clone() }, "webhooks").await
+    }
+
+    pub async fn remove_webhook(&mut self, url: &str) -> ClassResult<()> {
+        let urls = self.webhook_urls()?;
+        let index = urls.iter().position(|w| w == url).ok_or(ClassError::InvalidWebhook)?;
+
+        let mut webhooks = self.webhooks.clone();
+        webhooks.remove(index);
+
+        self.replace(Self { webhooks, ..self.clone() }, "webhooks").await
+    }
+
+    pub async fn add_icebreaker_prompt(&mut self, prompt: &str) -> ClassResult<()> {
+        if self.icebreaker_prompts.iter().any(|p| p == prompt) {
+            return Err(ClassError::PromptExists);
+        }
+
+        let mut icebreaker_prompts = self.icebreaker_prompts.clone();
+        icebreaker_prompts.push(prompt.to_string());
+
+        self.replace(Self { icebreaker_prompts, ..self.clone() }, "icebreaker_prompts").await
+    }
+
+    /// Remove a prompt by its 1-based number in the pool, returning it.
+    pub async fn remove_icebreaker_prompt(&mut self, number: usize) -> ClassResult<String> {
+        if number == 0 || number > self.icebreaker_prompts.len() {
+            return Err(ClassError::InvalidPrompt);
+        }
+
+        let mut icebreaker_prompts = self.icebreaker_prompts.clone();
+        let prompt = icebreaker_prompts.remove(number - 1);
+
+        self.replace(Self { icebreaker_prompts, ..self.clone() }, "icebreaker_prompts").await?;
+
+        Ok(prompt)
+    }
+
+    pub async fn add_break(&mut self, calendar_break: CalendarBreak) -> ClassResult<()> {
+        if self.breaks.iter().any(|b| b.name.eq_ignore_ascii_case(&calendar_break.name)) {
+            return Err(ClassError::BreakExists);
+        }
+
+        let mut breaks = self.breaks.clone();
+        breaks.push(calendar_break);
+        breaks.sort_by_key(|b| b.starts);
+
+        self.replace(Self { breaks, ..self.clone() }, "breaks").await
+    }
+
+    pub async fn remove_break(&mut self, name: &str) -> ClassResult<CalendarBreak> {
+        let index = self.breaks.iter()
+            .position(|b| b.name.eq_ignore_ascii_case(name.trim()))
+            .ok_or(ClassError::InvalidBreak)?;
+
+        let mut breaks = self.breaks.clone();
+        let removed = breaks.remove(index);
+
+        self.replace(Self { breaks, ..self.clone() }, "breaks").await?;
+
+        Ok(removed)
+    }
+
+    /// Store the server's new settings. Only the settings that changed are written, so settings
+    /// this version of the bot doesn't know about are kept. If the server was changed since it was
+    /// read, the changes are made again on top of the newer copy, unless both changed the same
+    /// setting.
+    async fn replace(&mut self, new: Self, setting: &str) -> ClassResult<()> {
+        let servers = Self::scoped(self.server_id).await;
+        let (mut base, mut new) = (self.clone(), new);
+        for attempt in 1.. {
+            let changed = changed_fields(&base, &new)?;
+            if changed.is_empty() {
+                break;
+            }
+            let updated = servers.update_one(
+                version_filter(base.version),
+                doc! { "$set": changed, "$inc": { "version": 1 } },
+                UpdateOptions::builder().hint(SERVER_ID_HINT.clone()).build(),
+            ).await?;
+            if updated.matched_count > 0 {
+                new.version = base.version + 1;
+                break;
+            }
+
+            let current = servers
+                .find_one(None, FindOneOptions::builder().hint(SERVER_ID_HINT.clone()).build())
+                .await?
+                .ok_or(ClassError::NoServer)?;
+            if attempt == WRITE_ATTEMPTS {
+                return Err(ClassError::EditConflict);
+            }
+            new = merge_concurrent(&base, &new, &current)?;
+            base = current;
+        }
 
         *self = new;
 
+        events::publish(BotEvent::ConfigChanged {
+            server_id: self.server_id,
+            setting: setting.to_string(),
+        });
+
         Ok(())
     }
 
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
     async fn get_collection() -> Collection<Self> {
         static SERVERS: OnceCell<Collection<Server>> = OnceCell::const_new();
 
@@ -101,23 +675,123 @@ impl Server {
     }
 }
 
+/// Matches a document written at the given version. Documents stored before versions were added
+/// don't have one, and count as version 0.
+fn version_filter(version: u64) -> Document {
+    if version == 0 {
+        doc! { "version": { "$in": [0_i64, Bson::Null] } }
+    } else {
+        doc! { "version": version as i64 }
+    }
+}
+
+/// Make the changes from `base` to `ours` on top of `theirs`, a copy someone else changed in the
+/// meantime. Fails if both changed the same field to different values.
+pub(crate) fn merge_concurrent<T: Serialize + DeserializeOwned>(base: &T, ours: &T, theirs: &T) -> ClassResult<T> {
+    let original = bson::to_document(base)?;
+    let mut merged = bson::to_document(theirs)?;
+    for (field, value) in changed_fields(base, ours)? {
+        match merged.get(&field) {
+            Some(theirs) if *theirs != value && original.get(&field) != Some(theirs) => {
+                return Err(ClassError::EditConflict);
+            }
+            _ => {
+                merged.insert(field, value);
+            }
+        }
+    }
+
+    Ok(bson::from_document(merged)?)
+}
+
+/// The fields of `new` that differ from `old`, apart from the version.
+pub(crate) fn changed_fields<T: Serialize>(old: &T, new: &T) -> ClassResult<Document> {
+    let old = bson::to_document(old)?;
+    Ok(
+        bson::to_document(new)?
+            .into_iter()
+            .filter(|(field, value)| field != "version" && old.get(field) != Some(value))
+            .collect()
+    )
+}
+
+/// A channel to create along with a new class.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct NewChannel {
+    pub(crate) name: String,
+    pub(crate) kind: ChannelType,
+    pub(crate) topic: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct Class {
-    server_id: GuildId,
+    pub(crate) server_id: GuildId,
     pub(crate) name: String,
     pub(crate) short_name: String,
     pub(crate) role: RoleId,
-    pub(crate) category: ChannelId,
+    /// The class's categories. Classes start with one, and spill into more as they outgrow
+    /// Discord's per-category channel limit.
+    pub(crate) categories: Vec<ChannelId>,
     pub(crate) text_channels: Vec<ChannelId>,
     pub(crate) voice_channels: Vec<ChannelId>,
+    #[serde(default)]
+    pub(crate) staff_role: Option<RoleId>,
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+    /// Whether messages in the class's text channels are copied into the message archive.
+    #[serde(default)]
+    pub(crate) archive_messages: bool,
+    /// Given to opted-in members who took the class in an earlier term.
+    #[serde(default)]
+    pub(crate) mentor_role: Option<RoleId>,
+    /// Shown under the class's name in the class menu.
+    #[serde(default)]
+    pub(crate) description: Option<String>,
+    /// The URL of the webhook for posting into the class's general channel, encrypted with
+    /// `secrets::seal`.
+    #[serde(default)]
+    pub(crate) webhook: Option<String>,
+    /// The SHA-256 hash of the token graders use to post results for the class.
+    #[serde(default)]
+    pub(crate) grader_token: Option<String>,
+    /// Replaces `grader::DEFAULT_DM_TEMPLATE` for the class.
+    #[serde(default)]
+    pub(crate) grader_dm_template: Option<String>,
+    /// Replaces `grader::DEFAULT_SUMMARY_TEMPLATE` for the class.
+    #[serde(default)]
+    pub(crate) grader_summary_template: Option<String>,
+    /// The pinned message listing the class's upcoming assignments.
+    #[serde(default)]
+    pub(crate) assignment_board: Option<(ChannelId, MessageId)>,
+    /// Where `/assignment import` gets the class's assignments from.
+    #[serde(default)]
+    pub(crate) canvas: Option<CanvasLink>,
+    #[serde(default)]
+    pub(crate) visibility: Visibility,
+    /// The class's custom emoji, formatted like `<:name:id>`. The emoji belongs to the class and
+    /// is removed from the server with it.
+    #[serde(default)]
+    pub(crate) emoji: Option<String>,
+    /// Staff tiers whose members get a badge added to their nickname.
+    #[serde(default)]
+    pub(crate) badges: Vec<StaffBadge>,
+    /// Members who may manage the class without Manage Server.
+    #[serde(default)]
+    pub(crate) managers: Vec<UserId>,
+    /// Roles whose members may manage the class without Manage Server.
+    #[serde(default)]
+    pub(crate) manager_roles: Vec<RoleId>,
+    /// Incremented by every write, so a write made from an out-of-date copy can be caught.
+    #[serde(default)]
+    pub(crate) version: u64,
 }
 
 impl Class {
     pub(crate) async fn list(server_id: GuildId) -> ClassResult<Vec<Class>> {
         Ok(
-            Self::get_collection().await
+            Self::scoped(server_id).await
                 .find(
-                    doc! { "server_id": server_id.to_string() },
+                    None,
                     Some(
                         FindOptions::builder()
                             .hint(SERVER_ID_HINT.clone())
@@ -130,10 +804,36 @@ impl Class {
         )
     }
 
-    pub(crate) async fn create(ctx: Context<'_>, name: &str) -> ClassResult<Class> {
+    /// Create a class with a role, a category, and the usual general, homework help, resources and
+    /// voice channels.
+    pub(crate) async fn create(
+        cache_http: impl CacheHttp,
+        guild: &Guild,
+        name: &str,
+        visibility: Visibility,
+    ) -> ClassResult<Class> {
+        let short_name = name.split_whitespace().collect::<String>().to_lowercase();
+        let channels = [
+            (format!("general—〈{}〉", short_name), ChannelType::Text),
+            (format!("homework-help—〈{}〉", short_name), ChannelType::Text),
+            (format!("resources—〈{}〉", short_name), ChannelType::Text),
+            (format!("General ({})", short_name), ChannelType::Voice),
+        ].into_iter().map(|(name, kind)| NewChannel { name, kind, topic: None }).collect::<Vec<_>>();
+
+        Self::create_with_channels(cache_http, guild, name, visibility, &channels).await
+    }
+
+    /// Create a class with a role, a category, and the given channels in that order.
+    pub(crate) async fn create_with_channels(
+        cache_http: impl CacheHttp,
+        guild: &Guild,
+        name: &str,
+        visibility: Visibility,
+        channels: &[NewChannel],
+    ) -> ClassResult<Class> {
         let name = name.trim();
 
-        let server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?).await?;
+        let server = Server::get_or_create(guild.id).await?;
 
         // Verify the server has a refrole set
         if server.refrole.is_none() {
@@ -144,13 +844,11 @@ impl Class {
             return Err(ClassError::ClassExists);
         }
 
-        let guild = ctx.guild().ok_or(ClassError::NoServer)?;
-
         // Verify the role does not already exist
         if guild
             .roles
-            .iter()
-            .any(|(_, r)| r.name.to_lowercase() == name.to_lowercase())
+            .values()
+            .any(|r| r.name.to_lowercase() == discord_name(name).to_lowercase())
         {
             return Err(ClassError::RoleExists);
         }
@@ -158,13 +856,13 @@ impl Class {
         if guild.channels.iter().any(|(_, c)| {
             matches!(
                 c, Channel::Category(cat)
-                if cat.name.to_lowercase() == name.to_lowercase()
+                if cat.name.to_lowercase() == discord_name(name).to_lowercase()
             )
         }) {
             return Err(ClassError::CategoryExists);
         }
 
-        let http = ctx.discord().http();
+        let http = cache_http.http();
 
         let position = guild
             .roles
@@ -174,74 +872,79 @@ impl Class {
 
         // Create the class role under the server refrole
         let role = guild
-            .create_role(http, |r| r.name(name).mentionable(true).position(position))
+            .create_role(http, |r| r.name(discord_name(name)).mentionable(true).position(position))
             .await?;
 
         // Create the class category
         let category = guild
-            .create_channel(http, |c| {
-                c.name(name).kind(ChannelType::Category).permissions(vec![
-                    PermissionOverwrite {
-                        allow: Permissions::empty(),
-                        deny: Permissions::VIEW_CHANNEL,
-                        kind: PermissionOverwriteType::Role(guild.id.0.into()),
-                    },
-                    PermissionOverwrite {
-                        allow: Permissions::VIEW_CHANNEL,
-                        deny: Permissions::empty(),
-                        kind: PermissionOverwriteType::Role(role.id),
-                    },
-                ])
-            })
+            .create_channel(http, |c| c
+                .name(discord_name(name))
+                .kind(ChannelType::Category)
+                .permissions(visibility.overwrites(guild.id, role.id))
+            )
             .await?;
 
         // Create the class channels
         let short_name = name.split_whitespace().collect::<String>().to_lowercase();
-        let general_channel = guild.create_channel(http, |c| {
-            c.name(format!("general—〈{}〉", short_name))
-                .kind(ChannelType::Text)
-                .category(category.id)
-        });
-        let homework_help_channel = guild.create_channel(http, |c| {
-            c.name(format!("homework-help—〈{}〉", short_name))
-                .kind(ChannelType::Text)
-                .category(category.id)
-        });
-        let resources_channel = guild.create_channel(http, |c| {
-            c.name(format!("resources—〈{}〉", short_name))
-                .kind(ChannelType::Text)
-                .category(category.id)
-        });
-        let voice_channel = guild.create_channel(http, |c| {
-            c.name(format!("General ({})", short_name))
-                .kind(ChannelType::Voice)
-                .category(category.id)
-        });
+        let mut text_channels = Vec::new();
+        let mut voice_channels = Vec::new();
+        for new_channel in channels {
+            let channel = guild
+                .create_channel(http, |c| {
+                    c.name(discord_name(&new_channel.name)).kind(new_channel.kind).category(category.id);
+                    if let Some(topic) = &new_channel.topic {
+                        c.topic(topic);
+                    }
+                    c
+                })
+                .await?;
+            if new_channel.kind == ChannelType::Voice {
+                voice_channels.push(channel.id);
+            } else {
+                text_channels.push(channel.id);
+            }
+        }
 
         // Add the class to the database and return it
         Self {
             server_id: server.server_id,
             name: name.to_string(),
-            short_name: short_name.clone(),
+            short_name,
             role: role.id,
-            category: category.id,
-            text_channels: vec![
-                general_channel.await?.id,
-                homework_help_channel.await?.id,
-                resources_channel.await?.id,
-            ],
-            voice_channels: vec![voice_channel.await?.id],
+            categories: vec![category.id],
+            text_channels,
+            voice_channels,
+            staff_role: None,
+            tags: Vec::new(),
+            archive_messages: false,
+            mentor_role: None,
+            description: None,
+            webhook: None,
+            grader_token: None,
+            grader_dm_template: None,
+            grader_summary_template: None,
+            assignment_board: None,
+            canvas: None,
+            visibility,
+            emoji: None,
+            badges: Vec::new(),
+            managers: Vec::new(),
+            manager_roles: Vec::new(),
+            version: 0,
         }.add_to_db().await
     }
 
+    /// Start tracking an existing role and category as a class. The category's permissions are
+    /// only changed if a visibility is given.
     pub(crate) async fn track(
-        ctx: Context<'_>,
+        cache_http: impl CacheHttp,
+        guild: &Guild,
         name: Option<String>,
         role: Role,
         category: ChannelCategory,
         channels: &[GuildChannel],
+        visibility: Option<Visibility>,
     ) -> ClassResult<Class> {
-        let guild = ctx.guild().ok_or(ClassError::NoServer)?;
         let server = Server::get_or_create(guild.id).await?;
         let name = name.as_ref().map(|s| s.trim()).unwrap_or(&role.name);
 
@@ -259,8 +962,8 @@ impl Class {
         let mut text_channels = HashSet::new();
         let mut voice_channels = HashSet::new();
         for c in channels.iter().chain(
-            guild.channels.iter()
-                .filter_map(|(_, c)| if let Channel::Guild(gc) = c { Some(gc) } else { None })
+            guild.channels.values()
+                .filter_map(|c| if let Channel::Guild(gc) = c { Some(gc) } else { None })
                 .filter(|c| c.parent_id.map(|id| id == category.id).unwrap_or(false))
         ) {
             match c.kind {
@@ -271,44 +974,364 @@ impl Class {
         }
 
         // Add the class to the database and return it
-        Self {
+        let class = Self {
             server_id: server.server_id,
             name: name.to_string(),
             short_name: name.split_whitespace().collect::<String>().to_lowercase(),
             role: role.id,
-            category: category.id,
+            categories: vec![category.id],
             text_channels: text_channels.into_iter().collect(),
             voice_channels: voice_channels.into_iter().collect(),
-        }.add_to_db().await
+            staff_role: None,
+            tags: Vec::new(),
+            archive_messages: false,
+            mentor_role: None,
+            description: None,
+            webhook: None,
+            grader_token: None,
+            grader_dm_template: None,
+            grader_summary_template: None,
+            assignment_board: None,
+            canvas: None,
+            visibility: visibility.unwrap_or_default(),
+            emoji: None,
+            badges: Vec::new(),
+            managers: Vec::new(),
+            manager_roles: Vec::new(),
+            version: 0,
+        }.add_to_db().await?;
+
+        if let Some(visibility) = visibility {
+            visibility.apply(cache_http, &class).await?;
+        }
+
+        Ok(class)
+    }
+
+    pub(crate) async fn set_staff_role(&mut self, role: Option<RoleId>) -> ClassResult<()> {
+        self.replace(Self {
+            staff_role: role,
+            ..self.clone()
+        }).await
+    }
+
+    pub(crate) async fn set_tags<'a>(&mut self, tags: impl IntoIterator<Item = &'a str>) -> ClassResult<()> {
+        let tags = tags.into_iter()
+            .map(|t| t.trim().to_lowercase())
+            .filter(|t| !t.is_empty())
+            .unique()
+            .collect();
+
+        self.replace(Self { tags, ..self.clone() }).await
+    }
+
+    pub(crate) async fn set_description(&mut self, description: Option<String>) -> ClassResult<()> {
+        self.replace(Self { description, ..self.clone() }).await
+    }
+
+    pub(crate) async fn set_webhook(&mut self, webhook: Option<String>) -> ClassResult<()> {
+        self.replace(Self { webhook, ..self.clone() }).await
+    }
+
+    pub(crate) async fn set_grader_token(&mut self, grader_token: Option<String>) -> ClassResult<()> {
+        self.replace(Self { grader_token, ..self.clone() }).await
+    }
+
+    pub(crate) async fn set_grader_templates(&mut self, dm: Option<String>, summary: Option<String>) -> ClassResult<()> {
+        self.replace(Self { grader_dm_template: dm, grader_summary_template: summary, ..self.clone() }).await
+    }
+
+    pub(crate) async fn set_assignment_board(&mut self, board: Option<(ChannelId, MessageId)>) -> ClassResult<()> {
+        self.replace(Self { assignment_board: board, ..self.clone() }).await
+    }
+
+    pub(crate) async fn set_canvas(&mut self, canvas: Option<CanvasLink>) -> ClassResult<()> {
+        self.replace(Self { canvas, ..self.clone() }).await
+    }
+
+    /// Change who can see and post in the class's channels, updating all of their permissions.
+    pub(crate) async fn set_visibility(&mut self, cache_http: impl CacheHttp, visibility: Visibility) -> ClassResult<()> {
+        visibility.apply(cache_http, self).await?;
+        self.replace(Self { visibility, ..self.clone() }).await
+    }
+
+    pub(crate) fn reaction(&self) -> Option<ReactionType> {
+        self.emoji.as_deref().and_then(|e| ReactionType::try_from(e).ok())
+    }
+
+    /// Upload a new custom emoji for the class from a data URI, or remove it if there is no image,
+    /// deleting the emoji it replaces from the server.
+    pub(crate) async fn set_emoji(&mut self, cache_http: impl CacheHttp, image: Option<&str>) -> ClassResult<()> {
+        let old = self.reaction();
+        let emoji = match image {
+            Some(image) => Some(
+                self.server_id.create_emoji(cache_http.http(), &emoji_name(&self.short_name), image).await?.to_string()
+            ),
+            None => None,
+        };
+        self.replace(Self { emoji, ..self.clone() }).await?;
+
+        if let Some(ReactionType::Custom { id, .. }) = old {
+            // Throwing away the result as the emoji may have already been deleted by hand
+            self.server_id.delete_emoji(cache_http.http(), id).await.ok();
+        }
+
+        Ok(())
+    }
+
+    /// Give members of a staff tier role a badge with the label, or stop if there is no label.
+    pub(crate) async fn set_badge(&mut self, role: RoleId, label: Option<String>) -> ClassResult<()> {
+        let mut badges = self.badges.iter()
+            .filter(|b| b.role != role)
+            .cloned()
+            .collect::<Vec<_>>();
+        if let Some(label) = label {
+            badges.push(StaffBadge { role, label });
+        }
+        self.replace(Self { badges, ..self.clone() }).await
+    }
+
+    pub(crate) async fn set_managers(&mut self, managers: Vec<UserId>, manager_roles: Vec<RoleId>) -> ClassResult<()> {
+        self.replace(Self { managers, manager_roles, ..self.clone() }).await
+    }
+
+    /// Whether a member with the given roles is one of the class's managers, or has one of its
+    /// manager roles.
+    pub(crate) fn is_managed_by(&self, user: UserId, roles: &[RoleId]) -> bool {
+        self.managers.contains(&user) || self.manager_roles.iter().any(|r| roles.contains(r))
+    }
+
+    pub(crate) async fn set_mentor_role(&mut self, role: Option<RoleId>) -> ClassResult<()> {
+        self.replace(Self { mentor_role: role, ..self.clone() }).await
+    }
+
+    pub(crate) async fn set_archive_messages(&mut self, enabled: bool) -> ClassResult<()> {
+        self.replace(Self { archive_messages: enabled, ..self.clone() }).await
+    }
+
+    pub(crate) async fn rename(&mut self, name: &str) -> ClassResult<()> {
+        let name = name.trim();
+        self.replace(Self {
+            name: name.to_string(),
+            short_name: name.split_whitespace().collect::<String>().to_lowercase(),
+            ..self.clone()
+        }).await
+    }
+
+    /// Move the class to a different role, repointing everything stored against the old one. The
+    /// class's channels give the new role the same access the old one had, and members of the old
+    /// role are optionally moved to the new one.
+    pub(crate) async fn transfer_role(
+        &mut self,
+        cache_http: impl CacheHttp,
+        guild: &Guild,
+        role: RoleId,
+        migrate_members: bool,
+        status: &JobStatus,
+    ) -> ClassResult<()> {
+        if !guild.roles.contains_key(&role) {
+            return Err(ClassError::InvalidRole);
+        }
+        if let Some(class) = Self::find_by_role(role).await? {
+            return Err(ClassError::RoleInUse(class.name));
+        }
+
+        let http = cache_http.http();
+        let old_role = self.role;
+
+        for channel in self.all_channels() {
+            let overwrite = match guild.channels.get(&channel) {
+                Some(Channel::Guild(c)) => c.permission_overwrites.iter()
+                    .find(|o| o.kind == PermissionOverwriteType::Role(old_role))
+                    .cloned(),
+                Some(Channel::Category(c)) => c.permission_overwrites.iter()
+                    .find(|o| o.kind == PermissionOverwriteType::Role(old_role))
+                    .cloned(),
+                _ => None,
+            };
+            if let Some(overwrite) = overwrite {
+                channel.create_permission(http, &PermissionOverwrite {
+                    kind: PermissionOverwriteType::Role(role),
+                    ..overwrite
+                }).await?;
+                channel.delete_permission(http, PermissionOverwriteType::Role(old_role)).await?;
+            }
+        }
+
+        if migrate_members {
+            let members = guild.members.values().filter(|m| m.roles.contains(&old_role)).collect::<Vec<_>>();
+            for (i, member) in members.iter().enumerate() {
+                let roles = member.roles.iter()
+                    .map(|r| if *r == old_role { role } else { *r })
+                    .collect::<Vec<_>>();
+                guild.id.edit_member(http, member.user.id, |m| m.roles(roles)).await?;
+                status.progress(&cache_http, &format!("Moved {} of {} members.", i + 1, members.len())).await?;
+            }
+        }
+
+        for (collection, field) in ROLE_REFERENCES {
+            get_conn().await
+                .database(&ENV.mongodb_name)
+                .collection::<Document>(collection)
+                .update_many(
+                    doc! { field: old_role.to_string() },
+                    doc! { "$set": { field: role.to_string() } },
+                    None,
+                )
+                .await?;
+        }
+        for (collection, field) in ROLE_LIST_REFERENCES {
+            get_conn().await
+                .database(&ENV.mongodb_name)
+                .collection::<Document>(collection)
+                .update_many(
+                    doc! { field: old_role.to_string() },
+                    doc! { "$set": { format!("{}.$", field): role.to_string() } },
+                    None,
+                )
+                .await?;
+        }
+
+        self.replace(Self { role, ..self.clone() }).await?;
+        Server::bump_menu_generation(self.server_id).await
+    }
+
+    /// Move all of the class's channels under a single different category, copying the first
+    /// category's permission overwrites onto it.
+    pub(crate) async fn transfer_category(
+        &mut self,
+        cache_http: impl CacheHttp,
+        guild: &Guild,
+        category: ChannelId,
+    ) -> ClassResult<()> {
+        if let Some(class) = Self::find_by_category(category).await? {
+            return Err(ClassError::CategoryInUse(class.name));
+        }
+
+        let http = cache_http.http();
+
+        if let Some(Channel::Category(old)) = guild.channels.get(&self.category()) {
+            for overwrite in &old.permission_overwrites {
+                category.create_permission(http, overwrite).await?;
+            }
+        }
+        for channel in self.text_channels.iter().chain(self.voice_channels.iter()) {
+            channel.edit(http, |c| c.category(category)).await?;
+        }
+
+        self.replace(Self { categories: vec![category], ..self.clone() }).await
+    }
+
+    /// Every channel of the class, including its categories.
+    pub(crate) fn all_channels(&self) -> Vec<ChannelId> {
+        self.categories.iter().copied()
+            .chain(self.text_channels.iter().copied())
+            .chain(self.voice_channels.iter().copied())
+            .collect()
+    }
+
+    /// The class's first category, named after the class.
+    pub(crate) fn category(&self) -> ChannelId {
+        // Every class is created with a category, and categories are never removed down to zero
+        self.categories[0]
+    }
+
+    /// Find one of the class's categories with room for `needed` more channels, creating a new
+    /// "Name (n)" category with the same permissions as the first if they are all full.
+    pub(crate) async fn category_with_room(&mut self, cache_http: impl CacheHttp, needed: usize) -> ClassResult<ChannelId> {
+        let guild = lookup::guild(&cache_http, self.server_id).await?;
+
+        let channel_count = |category: &ChannelId| guild.channels.values()
+            .filter(|c| matches!(c, Channel::Guild(gc) if gc.parent_id == Some(*category)))
+            .count();
+        if let Some(category) = self.categories.iter().find(|c| channel_count(c) + needed <= CATEGORY_LIMIT) {
+            return Ok(*category);
+        }
+
+        let permissions = match guild.channels.get(&self.category()) {
+            Some(Channel::Category(c)) => c.permission_overwrites.clone(),
+            _ => Vec::new(),
+        };
+        let category = guild
+            .create_channel(cache_http.http(), |c| c
+                .name(discord_name(&format!("{} ({})", self.name, self.categories.len() + 1)))
+                .kind(ChannelType::Category)
+                .permissions(permissions)
+            )
+            .await?;
+
+        let mut categories = self.categories.clone();
+        categories.push(category.id);
+        self.replace(Self { categories, ..self.clone() }).await?;
+
+        Ok(category.id)
+    }
+
+    /// Start tracking a channel as part of the class. Returns whether the channel was added, or
+    /// was already tracked or of an untrackable type.
+    pub(crate) async fn add_channel(&mut self, channel: ChannelId, kind: ChannelType) -> ClassResult<bool> {
+        if self.text_channels.contains(&channel) || self.voice_channels.contains(&channel) {
+            return Ok(false);
+        }
+
+        let mut text_channels = self.text_channels.clone();
+        let mut voice_channels = self.voice_channels.clone();
+        match kind {
+            ChannelType::Text | ChannelType::News => text_channels.push(channel),
+            ChannelType::Voice | ChannelType::Stage => voice_channels.push(channel),
+            _ => return Ok(false),
+        }
+
+        self.replace(Self { text_channels, voice_channels, ..self.clone() }).await?;
+        Ok(true)
+    }
+
+    /// Stop tracking a channel as part of the class. Returns whether the channel was tracked.
+    pub(crate) async fn remove_channel(&mut self, channel: ChannelId) -> ClassResult<bool> {
+        if !self.text_channels.contains(&channel) && !self.voice_channels.contains(&channel) {
+            return Ok(false);
+        }
+
+        self.replace(Self {
+            text_channels: self.text_channels.iter().filter(|c| **c != channel).copied().collect(),
+            voice_channels: self.voice_channels.iter().filter(|c| **c != channel).copied().collect(),
+            ..self.clone()
+        }).await?;
+        Ok(true)
     }
 
     pub(crate) async fn untrack(self) -> ClassResult<Option<String>> {
-        let deleted_count = Self::get_collection().await
-            .delete_many(
-                doc! { "role": self.role.to_string() },
-                DeleteOptions::builder()
-                    .hint(ROLE_HINT.clone())
-                    .build()
-            ).await?.deleted_count;
+        if !self.remove_from_db().await? {
+            return Ok(None);
+        }
 
-        Ok(
-            if deleted_count > 0 {
-                Some(self.name)
-            } else { None }
-        )
+        events::publish(BotEvent::ClassDeleted {
+            server_id: self.server_id,
+            class: self.clone(),
+            channels_deleted: false,
+        });
+
+        Ok(Some(self.name))
     }
 
     pub(crate) async fn delete(self, ctx: Context<'_>) -> ClassResult<(Option<String>, Vec<ClassError>)> {
-        let mut guild = ctx.guild().ok_or(ClassError::NoServer)?;
+        let mut guild = lookup::current_guild(ctx).await?;
         let http = ctx.discord().http();
 
-        let db_deleted = self.clone().untrack().await?.is_some();
+        let db_deleted = self.remove_from_db().await?;
+
+        // Keep a copy of the class's structure so it can be restored with /class restore
+        if db_deleted {
+            if let Err(e) = TrashedClass::capture(ctx.discord(), &self, &guild).await {
+                log_error!("Error moving class {} to the trash: {:?}", self.name, e);
+            }
+        }
 
         let mut failed = Vec::new();
 
         for c in self.text_channels.iter()
             .chain(self.voice_channels.iter())
-            .chain(std::iter::once(&self.category))
+            .chain(self.categories.iter())
         {
             if let Some(channel) = guild.channels.get(c) {
                 if let Err(e) = channel.delete(http).await {
@@ -329,6 +1352,20 @@ impl Class {
             failed.push(e);
         }
 
+        if let Some(ReactionType::Custom { id, .. }) = self.reaction() {
+            if let Err(e) = self.server_id.delete_emoji(http, id).await {
+                failed.push(ClassError::ApiError(e));
+            }
+        }
+
+        if db_deleted {
+            events::publish(BotEvent::ClassDeleted {
+                server_id: self.server_id,
+                class: self.clone(),
+                channels_deleted: true,
+            });
+        }
+
         Ok((
             if db_deleted {
                 Some(self.name)
@@ -337,6 +1374,10 @@ impl Class {
         ))
     }
 
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
     async fn get_collection() -> Collection<Self> {
         static CLASSES: OnceCell<Collection<Class>> = OnceCell::const_new();
 
@@ -351,25 +1392,105 @@ impl Class {
             .clone()
     }
 
-    async fn class_exists(server_id: GuildId, name: &str) -> ClassResult<bool> {
+    async fn remove_from_db(&self) -> ClassResult<bool> {
+        let deleted_count = Self::get_collection().await
+            .delete_many(
+                doc! { "role": self.role.to_string() },
+                DeleteOptions::builder()
+                    .hint(ROLE_HINT.clone())
+                    .build()
+            ).await?.deleted_count;
+        Server::bump_menu_generation(self.server_id).await?;
+
+        Ok(deleted_count > 0)
+    }
+
+    /// Store the class's changed details, merging them with any changes made since it was read
+    /// like `Server::replace` does.
+    async fn replace(&mut self, new: Self) -> ClassResult<()> {
+        let classes = Self::get_collection().await;
+        let (mut base, mut new) = (self.clone(), new);
+        for attempt in 1.. {
+            let changed = changed_fields(&base, &new)?;
+            if changed.is_empty() {
+                break;
+            }
+            let mut filter = version_filter(base.version);
+            filter.insert("role", self.role.to_string());
+            let updated = classes.update_one(
+                filter,
+                doc! { "$set": changed, "$inc": { "version": 1 } },
+                UpdateOptions::builder().hint(ROLE_HINT.clone()).build(),
+            ).await?;
+            if updated.matched_count > 0 {
+                new.version = base.version + 1;
+                break;
+            }
+
+            let current = Self::find_by_role(self.role).await?.ok_or(ClassError::InvalidClass)?;
+            if attempt == WRITE_ATTEMPTS {
+                return Err(ClassError::EditConflict);
+            }
+            new = merge_concurrent(&base, &new, &current)?;
+            base = current;
+        }
+
+        *self = new;
+
+        Ok(())
+    }
+
+    pub(crate) async fn class_exists(server_id: GuildId, name: &str) -> ClassResult<bool> {
+        Ok(Self::find_by_name(server_id, name).await?.is_some())
+    }
+
+    pub(crate) async fn find_by_name(server_id: GuildId, name: &str) -> ClassResult<Option<Class>> {
+        Self::scoped(server_id).await
+            .find_one(
+                doc! { "name": name },
+                Some(
+                    FindOneOptions::builder()
+                        .hint(SERVER_ID_NAME_HINT.clone())
+                        .build(),
+                ),
+            )
+            .await
+    }
+
+    pub(crate) async fn add_to_db(self) -> ClassResult<Class> {
+        Self::get_collection().await.insert_one(&self, None).await?;
+        Server::bump_menu_generation(self.server_id).await?;
+
+        events::publish(BotEvent::ClassCreated {
+            server_id: self.server_id,
+            class: self.clone(),
+        });
+
+        Ok(self)
+    }
+
+    pub(crate) async fn find_by_category(category: ChannelId) -> ClassResult<Option<Class>> {
         Ok(
             Self::get_collection().await
-                .find_one(
-                    doc! { "server_id": server_id.to_string(), "name": name },
-                    Some(
-                        FindOneOptions::builder()
-                            .hint(SERVER_ID_NAME_HINT.clone())
-                            .build(),
-                    ),
-                )
+                .find_one(doc! { "categories": category.to_string() }, None)
                 .await?
-                .is_some()
         )
     }
 
-    async fn add_to_db(self) -> ClassResult<Class> {
-        Self::get_collection().await.insert_one(&self, None).await?;
-        Ok(self)
+    pub(crate) async fn find_by_text_channel(channel: ChannelId) -> ClassResult<Option<Class>> {
+        Ok(
+            Self::get_collection().await
+                .find_one(doc! { "text_channels": channel.to_string() }, None)
+                .await?
+        )
+    }
+
+    pub(crate) async fn find_by_voice_channel(channel: ChannelId) -> ClassResult<Option<Class>> {
+        Ok(
+            Self::get_collection().await
+                .find_one(doc! { "voice_channels": channel.to_string() }, None)
+                .await?
+        )
     }
 
     pub(crate) async fn find_by_role(role: RoleId) -> ClassResult<Option<Class>> {
@@ -384,6 +1505,11 @@ impl Class {
             ).await?
         )
     }
+
+    /// The class a grader token belongs to, by the token's hash.
+    pub(crate) async fn find_by_grader_token(hash: &str) -> ClassResult<Option<Class>> {
+        Ok(Self::get_collection().await.find_one(doc! { "grader_token": hash }, None).await?)
+    }
 }
 
 
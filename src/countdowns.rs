@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use mongodb::bson::doc;
+use mongodb::options::UpdateOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use tokio::sync::OnceCell;
+
+use crate::assignments::Assignment;
+use crate::calendar;
+use crate::classes::Class;
+use crate::{get_conn, set_all, ClassResult, ENV};
+
+/// How often a countdown channel can be renamed. Discord only allows two renames per channel every
+/// ten minutes, so this leaves room for staff to rename it too.
+const RENAME_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// The longest a channel name can be.
+const NAME_LIMIT: usize = 100;
+
+lazy_static! {
+    static ref LAST_RENAMED: Mutex<HashMap<ChannelId, Instant>> = Mutex::new(HashMap::new());
+}
+
+/// A voice channel whose name counts down to a class's next exam.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Countdown {
+    server_id: GuildId,
+    role: RoleId,
+    channel: ChannelId,
+}
+
+impl Countdown {
+    async fn get_collection() -> Collection<Self> {
+        static COUNTDOWNS: OnceCell<Collection<Countdown>> = OnceCell::const_new();
+
+        COUNTDOWNS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("exam_countdowns")
+            })
+            .await
+            .clone()
+    }
+}
+
+pub(crate) async fn enable(class: &Class, channel: ChannelId) -> ClassResult<()> {
+    Countdown::get_collection().await
+        .update_one(
+            doc! { "role": class.role.to_string() },
+            set_all(&Countdown { server_id: class.server_id, role: class.role, channel })?,
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn disable(role: RoleId) -> ClassResult<bool> {
+    Ok(
+        Countdown::get_collection().await
+            .delete_one(doc! { "role": role.to_string() }, None)
+            .await?
+            .deleted_count > 0
+    )
+}
+
+/// How long until a timestamp, to the nearest minute, like `3d 4h` or `25m`.
+fn remaining(until: i64) -> String {
+    let minutes = ((until - Utc::now().timestamp()) / 60).max(0);
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// The countdown channel name for a class's next exam or assignment.
+fn name(next: Option<&Assignment>) -> String {
+    match next {
+        Some(next) => {
+            let remaining = format!(" in {}", remaining(next.timestamp()));
+            let title = next.title().chars().take(NAME_LIMIT - remaining.chars().count()).collect::<String>();
+            title + &remaining
+        }
+        None => "No upcoming exams".to_string(),
+    }
+}
+
+/// Rename every countdown channel to show the time left, skipping channels renamed too recently
+/// and servers on a break.
+pub(crate) async fn tick(ctx: &SContext) -> ClassResult<()> {
+    let countdowns = Countdown::get_collection().await
+        .find(None, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    for countdown in countdowns {
+        let recently_renamed = LAST_RENAMED.lock().unwrap()
+            .get(&countdown.channel)
+            .is_some_and(|t| t.elapsed() < RENAME_INTERVAL);
+        if recently_renamed || calendar::on_break(countdown.server_id).await? {
+            continue;
+        }
+
+        let name = name(Assignment::next(countdown.role).await?.as_ref());
+        let current = ctx.cache.guild_channel_field(countdown.channel, |c| c.name.clone());
+        if current.as_deref() == Some(name.as_str()) {
+            continue;
+        }
+
+        if let Err(e) = countdown.channel.edit(ctx.http(), |c| c.name(&name)).await {
+            // Stop counting down in channels that were deleted
+            if current.is_none() {
+                disable(countdown.role).await?;
+                continue;
+            }
+            return Err(e.into());
+        }
+        LAST_RENAMED.lock().unwrap().insert(countdown.channel, Instant::now());
+    }
+
+    Ok(())
+}
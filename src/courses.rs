@@ -0,0 +1,239 @@
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use mongodb::bson::doc;
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use poise::AutocompleteChoice;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::model::id::GuildId;
+use tokio::sync::OnceCell;
+
+use crate::classes::Class;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, ClassError, ClassResult, Context, ENV};
+
+lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::new();
+}
+
+/// Discord shows at most 25 autocomplete choices.
+const AUTOCOMPLETE_LIMIT: usize = 25;
+
+/// Where a server's official course catalog is fetched from: a URL returning JSON, and where each
+/// course's details are in it. Fields are dotted paths, like `subject.code`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct CatalogSource {
+    pub(crate) url: String,
+    /// The path to the array of courses, or empty if the response is the array.
+    #[serde(default)]
+    pub(crate) courses_path: String,
+    pub(crate) code_field: String,
+    pub(crate) title_field: String,
+    pub(crate) description_field: Option<String>,
+    /// The field with the course's department code, or taken from the course code if unset.
+    pub(crate) department_field: Option<String>,
+}
+
+/// A course from a server's official catalog.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Course {
+    pub(crate) server_id: GuildId,
+    /// The course's code, like "CS 341".
+    pub(crate) code: String,
+    pub(crate) title: String,
+    pub(crate) description: Option<String>,
+    pub(crate) department: String,
+}
+
+/// A code or name reduced to its uppercase letters and digits, so that "cs 341" and "CS341" match.
+fn key(text: &str) -> String {
+    text.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_uppercase).collect()
+}
+
+/// Look up a dotted path in a JSON value. Numbers are accepted where text is expected, as some
+/// catalogs store course numbers that way.
+fn field(value: &Value, path: &str) -> Option<String> {
+    let found = path.split('.')
+        .filter(|p| !p.is_empty())
+        .try_fold(value, |v, p| v.get(p))?;
+    match found {
+        Value::String(s) => Some(s.trim().to_string()).filter(|s| !s.is_empty()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// The courses in a catalog response. Entries without a code or title are skipped.
+pub(crate) fn parse_courses(server_id: GuildId, source: &CatalogSource, response: &Value) -> ClassResult<Vec<Course>> {
+    let courses = source.courses_path.split('.')
+        .filter(|p| !p.is_empty())
+        .try_fold(response, |v, p| v.get(p))
+        .and_then(Value::as_array)
+        .ok_or(ClassError::InvalidCatalog)?;
+
+    Ok(
+        courses.iter()
+            .filter_map(|c| {
+                let code = field(c, &source.code_field)?;
+                let department = source.department_field.as_deref()
+                    .and_then(|f| field(c, f))
+                    .unwrap_or_else(|| code.chars().take_while(|c| c.is_alphabetic()).collect());
+                Some(Course {
+                    server_id,
+                    title: field(c, &source.title_field)?,
+                    description: source.description_field.as_deref().and_then(|f| field(c, f)),
+                    department: department.to_uppercase(),
+                    code,
+                })
+            })
+            .collect()
+    )
+}
+
+/// The course a class is for, going by the course code its name starts with. A code only matches
+/// if the name doesn't carry on with more digits, so "CS 34" doesn't match "CS 341".
+pub(crate) fn course_for<'a>(class_name: &str, courses: &'a [Course]) -> Option<&'a Course> {
+    let name = key(class_name);
+    courses.iter()
+        .filter(|c| {
+            let code = key(&c.code);
+            !code.is_empty()
+                && name.starts_with(&code)
+                && !name[code.len()..].starts_with(|c: char| c.is_ascii_digit())
+        })
+        .max_by_key(|c| key(&c.code).len())
+}
+
+/// Whether a class's name has a title after its course code that no longer matches the official
+/// one. Names that are only the code don't drift.
+pub(crate) fn title_drifted(class_name: &str, course: &Course) -> bool {
+    let code_len = key(&course.code).chars().count();
+    let mut consumed = 0;
+    let title = class_name.chars()
+        .skip_while(|c| {
+            if consumed == code_len {
+                return false;
+            }
+            if c.is_alphanumeric() {
+                consumed += 1;
+            }
+            true
+        })
+        .collect::<String>();
+    !key(&title).is_empty() && key(&title) != key(&course.title)
+}
+
+/// What an import changed, and the classes whose names no longer match the catalog.
+pub(crate) struct ImportReport {
+    pub(crate) courses: usize,
+    /// Classes whose empty description was filled in from the catalog.
+    pub(crate) described: Vec<String>,
+    /// Classes with the official title each has drifted from.
+    pub(crate) drifted: Vec<(String, String)>,
+    /// Classes with no course in the catalog.
+    pub(crate) unmatched: Vec<String>,
+}
+
+impl Course {
+    /// Fetch the server's catalog, replacing the courses from the last import, and bring tracked
+    /// classes up to date with it.
+    pub(crate) async fn import(server_id: GuildId, source: &CatalogSource) -> ClassResult<ImportReport> {
+        let response = CLIENT.get(&source.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        let courses = parse_courses(server_id, source, &response)?;
+        if courses.is_empty() {
+            Err(ClassError::InvalidCatalog)?;
+        }
+
+        let collection = Self::scoped(server_id).await;
+        collection.delete_many(None, None).await?;
+        collection.insert_many(&courses, None).await?;
+
+        let mut report = ImportReport {
+            courses: courses.len(),
+            described: Vec::new(),
+            drifted: Vec::new(),
+            unmatched: Vec::new(),
+        };
+        for mut class in Class::list(server_id).await? {
+            let course = match course_for(&class.name, &courses) {
+                Some(c) => c,
+                None => {
+                    report.unmatched.push(class.name);
+                    continue;
+                }
+            };
+            if title_drifted(&class.name, course) {
+                report.drifted.push((class.name.clone(), course.title.clone()));
+            }
+            if class.description.is_none() {
+                class.set_description(Some(course.summary())).await?;
+                report.described.push(class.name);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// The course's title, and its description if it has one, for describing a class.
+    pub(crate) fn summary(&self) -> String {
+        match &self.description {
+            Some(d) => format!("{}: {}", self.title, d),
+            None => self.title.clone(),
+        }
+    }
+
+    pub(crate) async fn list(server_id: GuildId) -> ClassResult<Vec<Course>> {
+        Ok(
+            Self::scoped(server_id).await
+                .find(
+                    None,
+                    FindOptions::builder().sort(doc! { "code": 1 }).build(),
+                )
+                .await?
+                .try_collect()
+                .await?
+        )
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static COURSES: OnceCell<Collection<Course>> = OnceCell::const_new();
+
+        COURSES
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("catalog_courses")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Suggest course codes from the server's catalog, for naming new classes.
+pub(crate) async fn autocomplete_code(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice<String>> {
+    let courses = match ctx.guild_id() {
+        Some(server_id) => Course::list(server_id).await.unwrap_or_default(),
+        None => return Vec::new(),
+    };
+    let partial = key(partial);
+
+    courses.into_iter()
+        .filter(|c| key(&c.code).contains(&partial) || key(&c.title).contains(&partial))
+        .take(AUTOCOMPLETE_LIMIT)
+        .map(|c| AutocompleteChoice {
+            name: format!("{} — {}", c.code, c.title).chars().take(100).collect(),
+            value: c.code,
+        })
+        .collect()
+}
@@ -0,0 +1,170 @@
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::{Context as SContext, EventHandler};
+use serenity::http::CacheHttp;
+use serenity::model::application::component::{ButtonStyle, ComponentType};
+use serenity::model::application::interaction::Interaction;
+use serenity::model::channel::ReactionType;
+use serenity::model::id::{ChannelId, GuildId, MessageId};
+
+use crate::build_class_menu;
+use crate::classes::{Class, Server};
+use crate::ClassResult;
+use crate::redact::log_error;
+
+const BUTTON_PREFIX: &str = "class_menu_department:";
+
+/// A persistent class menu message for one department, kept so it can be refreshed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct DepartmentMenu {
+    pub(crate) department: String,
+    pub(crate) message: MessageId,
+}
+
+/// How a department's classes are shown in menus.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct DepartmentTheme {
+    pub(crate) department: String,
+    /// A unicode emoji, or a custom emoji formatted like `<:name:id>`.
+    pub(crate) emoji: Option<String>,
+    pub(crate) colour: Option<u32>,
+}
+
+impl DepartmentTheme {
+    pub(crate) fn reaction(&self) -> Option<ReactionType> {
+        self.emoji.as_deref().and_then(|e| ReactionType::try_from(e).ok())
+    }
+}
+
+/// The department a class belongs to, taken from the letters its name starts with, like "CS" for
+/// "CS 341".
+pub(crate) fn department_of(class: &Class) -> String {
+    let department = class.name.chars()
+        .take_while(|c| c.is_alphabetic())
+        .collect::<String>()
+        .to_uppercase();
+    if department.is_empty() {
+        "Other".to_string()
+    } else {
+        department
+    }
+}
+
+/// Post one menu message per department in the channel, editing the menus already posted there
+/// and deleting those for departments that no longer have classes. Returns how many were posted.
+pub(crate) async fn post_all(cache_http: impl CacheHttp, server_id: GuildId, channel: ChannelId) -> ClassResult<usize> {
+    let http = cache_http.http();
+    let mut server = Server::get_or_create(server_id).await?;
+    let departments = Class::list(server_id).await?
+        .into_iter()
+        .into_group_map_by(department_of)
+        .into_iter()
+        .sorted_by(|(d1, _), (d2, _)| d1.cmp(d2))
+        .collect::<Vec<_>>();
+
+    // Menus posted in a different channel are left alone, as they can't be moved
+    let old_menus = if server.menu_channel == Some(channel) { server.department_menus.clone() } else { Vec::new() };
+
+    let mut menus = Vec::new();
+    for (department, classes) in &departments {
+        let names = classes.iter()
+            .map(|c| c.name.as_str())
+            .sorted_by(|n1, n2| human_sort::compare(n1, n2))
+            .join(", ");
+        let custom_id = format!("{}{}", BUTTON_PREFIX, department);
+        let theme = server.department_theme(department).cloned();
+        let title = match theme.as_ref().and_then(|t| t.emoji.as_ref()) {
+            Some(emoji) => format!("{} {}", emoji, department),
+            None => department.clone(),
+        };
+        let colour = theme.as_ref().and_then(|t| t.colour).unwrap_or(0);
+
+        let existing = old_menus.iter().find(|m| m.department == *department);
+        let message = match existing {
+            Some(menu) => {
+                let edited = channel
+                    .edit_message(http, menu.message, |m| m
+                        .embed(|e| e.title(&title).description(&names).colour(colour))
+                        .components(|c| c.create_action_row(|r| r.create_button(|b| b
+                            .custom_id(&custom_id)
+                            .style(ButtonStyle::Primary)
+                            .label(format!("Choose {} classes", department))
+                        )))
+                    )
+                    .await;
+                match edited {
+                    Ok(m) => Some(m.id),
+                    // The message was deleted by hand, so post it again
+                    Err(_) => None,
+                }
+            }
+            None => None,
+        };
+        let message = match message {
+            Some(m) => m,
+            None => channel
+                .send_message(http, |m| m
+                    .embed(|e| e.title(&title).description(&names).colour(colour))
+                    .components(|c| c.create_action_row(|r| r.create_button(|b| b
+                        .custom_id(&custom_id)
+                        .style(ButtonStyle::Primary)
+                        .label(format!("Choose {} classes", department))
+                    )))
+                )
+                .await?
+                .id,
+        };
+
+        menus.push(DepartmentMenu { department: department.clone(), message });
+    }
+
+    for old in old_menus.iter().filter(|o| !menus.iter().any(|m| m.department == o.department)) {
+        // Throwing away the result as the message may already have been deleted
+        channel.delete_message(http, old.message).await.ok();
+    }
+
+    let posted = menus.len();
+    if let Some(first) = menus.first() {
+        server.set_menu_message(channel, first.message).await?;
+    }
+    server.set_department_menus(channel, menus).await?;
+
+    Ok(posted)
+}
+
+/// Opens the class menu for one department from its persistent menu message.
+pub(crate) struct DepartmentMenuHandler;
+
+#[async_trait]
+impl EventHandler for DepartmentMenuHandler {
+    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
+        let component = match interaction {
+            Interaction::MessageComponent(c) if c.data.component_type == ComponentType::Button => c,
+            _ => return,
+        };
+        let department = match component.data.custom_id.strip_prefix(BUTTON_PREFIX) {
+            Some(d) => d,
+            None => return,
+        };
+        let (member, server_id) = match (&component.member, component.guild_id) {
+            (Some(m), Some(id)) => (m, id),
+            _ => return,
+        };
+
+        let menu = match build_class_menu(server_id, member, None, Some(department)).await {
+            Ok(m) => m,
+            Err(e) => {
+                log_error!("Error handling {}: {:?}", component.data.custom_id, e);
+                return;
+            }
+        };
+
+        if let Err(e) = component.create_interaction_response(ctx.http(), |r| r.interaction_response_data(|d| d
+            .ephemeral(true)
+            .set_components(menu)
+        )).await {
+            log_error!("Error handling {}: {:?}", component.data.custom_id, e);
+        }
+    }
+}
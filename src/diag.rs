@@ -0,0 +1,61 @@
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use mongodb::bson::doc;
+use serenity::client::Context as SContext;
+use serenity::client::bridge::gateway::ShardManager;
+use tokio::sync::Mutex;
+
+use crate::{get_conn, ClassResult, ENV};
+
+static STARTED_AT: OnceLock<Instant> = OnceLock::new();
+
+/// Start counting uptime. Called once when the bot starts.
+pub(crate) fn mark_started() {
+    STARTED_AT.get_or_init(Instant::now);
+}
+
+pub(crate) fn uptime() -> Duration {
+    STARTED_AT.get().map(Instant::elapsed).unwrap_or_default()
+}
+
+/// How long a round trip to the database takes.
+pub(crate) async fn mongo_ping() -> ClassResult<Duration> {
+    let started = Instant::now();
+    get_conn().await
+        .database(&ENV.mongodb_name)
+        .run_command(doc! { "ping": 1 }, None)
+        .await?;
+
+    Ok(started.elapsed())
+}
+
+/// The latency of each shard's last heartbeat, if it has had one.
+pub(crate) async fn gateway_latencies(shard_manager: &Mutex<ShardManager>) -> Vec<(u64, Option<Duration>)> {
+    let manager = shard_manager.lock().await;
+    let runners = manager.runners.lock().await;
+    let mut latencies = runners.iter().map(|(id, runner)| (id.0, runner.latency)).collect::<Vec<_>>();
+    latencies.sort_by_key(|(id, _)| *id);
+    latencies
+}
+
+/// How many guilds, channels and users are cached.
+pub(crate) fn cache_sizes(ctx: &SContext) -> (usize, usize, usize) {
+    (ctx.cache.guild_count(), ctx.cache.guild_channel_count(), ctx.cache.user_count())
+}
+
+/// Format a duration like `3d 4h 5m` or `12s`.
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
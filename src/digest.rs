@@ -0,0 +1,227 @@
+use chrono::{Duration, Utc};
+use itertools::Itertools;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::UpdateOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::builder::CreateEmbed;
+use serenity::client::Context as SContext;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::prelude::Mentionable;
+use tokio::sync::OnceCell;
+
+use crate::classes::{Class, Server};
+use crate::history::EnrollmentEvent;
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::{audit, get_conn, set_all, ClassResult, ENV};
+
+/// How many classes and threads are listed before the rest are summarized.
+const LIST_LIMIT: usize = 15;
+
+/// A summary of what happened in a server over a period, shared by every digest the bot sends.
+pub(crate) struct Digest {
+    pub(crate) since: DateTime,
+    /// Class name, members joined and members left, busiest classes first.
+    pub(crate) enrollments: Vec<(String, u64, u64)>,
+    pub(crate) new_classes: Vec<String>,
+    /// Open homework-help threads nobody but the asker has joined, along with their class.
+    pub(crate) unanswered: Vec<(ChannelId, String)>,
+    pub(crate) errors: u64,
+}
+
+impl Digest {
+    pub(crate) async fn generate(ctx: &SContext, server_id: GuildId, since: DateTime) -> ClassResult<Self> {
+        let classes = Class::list(server_id).await?;
+        let counts = EnrollmentEvent::counts_since(server_id, since).await?;
+        let enrollments = classes.iter()
+            .filter_map(|c| counts.get(&c.role).map(|(joined, left)| (c.name.clone(), *joined, *left)))
+            .sorted_by(|(_, j1, l1), (_, j2, l2)| (j2 + l2).cmp(&(j1 + l1)))
+            .collect();
+
+        let mut unanswered = Vec::new();
+        for thread in server_id.get_active_threads(&ctx.http).await?.threads {
+            let parent = match thread.parent_id {
+                Some(p) => p,
+                None => continue,
+            };
+            let is_homework_help = ctx.cache
+                .guild_channel_field(parent, |c| c.name.starts_with("homework-help"))
+                .unwrap_or(false);
+            if !is_homework_help || thread.member_count.unwrap_or(0) > 1 {
+                continue;
+            }
+
+            if let Some(class) = classes.iter().find(|c| c.text_channels.contains(&parent)) {
+                unanswered.push((thread.id, class.name.clone()));
+            }
+        }
+
+        Ok(Self {
+            since,
+            enrollments,
+            new_classes: audit::classes_created_since(server_id, since).await?,
+            unanswered,
+            errors: audit::errors_since(server_id, since).await?,
+        })
+    }
+
+    /// Narrow the digest down to a single class. Errors aren't tied to a class, so they are
+    /// dropped.
+    pub(crate) fn only_class(mut self, name: &str) -> Self {
+        self.enrollments.retain(|(n, _, _)| n == name);
+        self.new_classes.retain(|n| n == name);
+        self.unanswered.retain(|(_, n)| n == name);
+        self.errors = 0;
+        self
+    }
+
+    pub(crate) fn render<'a>(&self, e: &'a mut CreateEmbed, title: &str) -> &'a mut CreateEmbed {
+        e.title(title)
+            .description(format!("Since <t:{}:f>", self.since.timestamp_millis() / 1000))
+            .field(
+                "Enrollments",
+                if self.enrollments.is_empty() {
+                    "No changes".to_string()
+                } else {
+                    limited(self.enrollments.iter().map(|(name, joined, left)| {
+                        format!("**{}**: +{} / -{}", name, joined, left)
+                    }))
+                },
+                false,
+            )
+            .field(
+                format!("New classes ({})", self.new_classes.len()),
+                if self.new_classes.is_empty() { "None".to_string() } else { limited(self.new_classes.iter().cloned()) },
+                false,
+            )
+            .field(
+                format!("Unanswered homework-help threads ({})", self.unanswered.len()),
+                if self.unanswered.is_empty() {
+                    "None".to_string()
+                } else {
+                    limited(self.unanswered.iter().map(|(thread, class)| format!("{} ({})", thread.mention(), class)))
+                },
+                false,
+            )
+            .field("Errors logged", self.errors, false)
+    }
+
+    /// The digest as plain text, for places that can't show an embed.
+    pub(crate) fn render_text(&self, title: &str) -> String {
+        let mut text = format!("{}\n\nEnrollments:\n", title);
+        if self.enrollments.is_empty() {
+            text += "No changes\n";
+        } else {
+            text += &limited(self.enrollments.iter().map(|(name, joined, left)| {
+                format!("{}: +{} / -{}", name, joined, left)
+            }));
+            text += "\n";
+        }
+
+        if !self.new_classes.is_empty() {
+            text += &format!("\nNew classes:\n{}\n", limited(self.new_classes.iter().cloned()));
+        }
+        if !self.unanswered.is_empty() {
+            text += &format!(
+                "\nUnanswered homework-help threads:\n{}\n",
+                limited(
+                    self.unanswered.iter()
+                        .counts_by(|(_, class)| class.as_str())
+                        .into_iter()
+                        .sorted()
+                        .map(|(class, count)| format!("{}: {}", class, count))
+                        .collect::<Vec<_>>()
+                        .into_iter()
+                ),
+            );
+        }
+        if self.errors > 0 {
+            text += &format!("\nErrors logged: {}\n", self.errors);
+        }
+
+        text
+    }
+}
+
+/// Join lines, cutting the list off at `LIST_LIMIT` so the embed field stays under Discord's limit.
+fn limited(lines: impl ExactSizeIterator<Item = String>) -> String {
+    let len = lines.len();
+    let mut text = lines.take(LIST_LIMIT).join("\n");
+    if len > LIST_LIMIT {
+        text += &format!("\n...and {} more", len - LIST_LIMIT);
+    }
+    text
+}
+
+/// When a server was last sent its weekly staff digest.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct StaffDigest {
+    server_id: GuildId,
+    sent_at: DateTime,
+}
+
+impl StaffDigest {
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static DIGESTS: OnceCell<Collection<StaffDigest>> = OnceCell::const_new();
+
+        DIGESTS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("staff_digests")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Send a server its weekly staff digest if it has a staff channel and hasn't had one in a week.
+async fn send_staff_digest(ctx: &SContext, server_id: GuildId, week_ago: DateTime) -> ClassResult<()> {
+    let staff_channel = match Server::get_or_create(server_id).await?.staff_channel {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+    let scoped = StaffDigest::scoped(server_id).await;
+    let since = match scoped.find_one(None, None).await? {
+        Some(last) if last.sent_at > week_ago => return Ok(()),
+        Some(last) => last.sent_at,
+        None => week_ago,
+    };
+
+    let sent = async {
+        let digest = Digest::generate(ctx, server_id, since).await?;
+        staff_channel.send_message(&ctx.http, |m| m.embed(|e| digest.render(e, "Weekly staff digest"))).await?;
+        Ok(())
+    }.await;
+
+    // Recorded even if it couldn't be sent, so an unreachable staff channel is tried again next
+    // week rather than every minute
+    scoped.update_one(
+        doc! {},
+        set_all(&StaffDigest { server_id, sent_at: DateTime::now() })?,
+        UpdateOptions::builder().upsert(true).build(),
+    ).await?;
+
+    sent
+}
+
+/// Send the weekly staff digest to every server with a staff channel that hasn't had one in a
+/// week.
+pub(crate) async fn tick(ctx: &SContext) -> ClassResult<()> {
+    let week_ago = DateTime::from_millis((Utc::now() - Duration::weeks(1)).timestamp_millis());
+
+    for server_id in ctx.cache.guilds() {
+        // One unreachable staff channel shouldn't hold up every other server's digest
+        if let Err(e) = send_staff_digest(ctx, server_id, week_ago).await {
+            log_error!("[{}] Error sending staff digest: {:?}", server_id, e);
+        }
+    }
+
+    Ok(())
+}
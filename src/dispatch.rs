@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use serenity::client::Context as SContext;
+use serenity::model::application::interaction::Interaction;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::errors::{self, ErrorContext};
+use crate::latency;
+use crate::redact::log_error;
+
+/// How many component interactions can be waiting to be handled before new ones are shed.
+const QUEUE_SIZE: usize = 256;
+/// How many component interactions are handled at once.
+const WORKERS: usize = 16;
+
+type Job = (SContext, Interaction);
+
+lazy_static! {
+    static ref QUEUE: mpsc::Sender<Job> = start();
+}
+
+static QUEUED: AtomicU64 = AtomicU64::new(0);
+static HANDLED: AtomicU64 = AtomicU64::new(0);
+static SHED: AtomicU64 = AtomicU64::new(0);
+static IN_FLIGHT: AtomicU64 = AtomicU64::new(0);
+
+/// A snapshot of the interaction queue counters since startup.
+pub(crate) struct Metrics {
+    pub(crate) queued: u64,
+    pub(crate) handled: u64,
+    pub(crate) shed: u64,
+    pub(crate) in_flight: u64,
+    pub(crate) waiting: usize,
+}
+
+pub(crate) fn metrics() -> Metrics {
+    Metrics {
+        queued: QUEUED.load(Ordering::Relaxed),
+        handled: HANDLED.load(Ordering::Relaxed),
+        shed: SHED.load(Ordering::Relaxed),
+        in_flight: IN_FLIGHT.load(Ordering::Relaxed),
+        waiting: QUEUE_SIZE - QUEUE.capacity(),
+    }
+}
+
+fn error_context(interaction: &Interaction) -> ErrorContext {
+    match interaction {
+        Interaction::MessageComponent(component) => ErrorContext {
+            server_id: component.guild_id,
+            command: Some(format!("component {}", component.data.custom_id)),
+            user: Some(component.user.id),
+        },
+        _ => ErrorContext::default(),
+    }
+}
+
+fn start() -> mpsc::Sender<Job> {
+    let (sender, mut receiver) = mpsc::channel::<Job>(QUEUE_SIZE);
+
+    tokio::spawn(async move {
+        let workers = Arc::new(Semaphore::new(WORKERS));
+        while let Some((ctx, interaction)) = receiver.recv().await {
+            // Unwrapping because the semaphore is never closed
+            let permit = workers.clone().acquire_owned().await.unwrap();
+            IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+            tokio::spawn(async move {
+                let context = error_context(&interaction);
+                let name = context.command.clone().unwrap_or_else(|| "interaction".to_string());
+                // Handled in its own task so a panic only loses this interaction, and the
+                // counters and permit below are still released
+                if let Err(e) = tokio::spawn(async move {
+                    latency::measure(&name, crate::handle_interaction(ctx, interaction)).await
+                }).await {
+                    if e.is_panic() {
+                        errors::report("interaction", context, &errors::panic_message(e.into_panic())).await;
+                    }
+                }
+                IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+                HANDLED.fetch_add(1, Ordering::Relaxed);
+                drop(permit);
+            });
+        }
+    });
+
+    sender
+}
+
+/// Queue a component interaction to be handled off the gateway task. If the queue is full the
+/// interaction is dropped, and the user sees Discord's "interaction failed" message.
+pub(crate) fn enqueue(ctx: SContext, interaction: Interaction) {
+    match QUEUE.try_send((ctx, interaction)) {
+        Ok(()) => {
+            QUEUED.fetch_add(1, Ordering::Relaxed);
+        }
+        Err(TrySendError::Full(_)) => {
+            let shed = SHED.fetch_add(1, Ordering::Relaxed) + 1;
+            log_error!("Interaction queue is full, shed an interaction ({} so far)", shed);
+        }
+        Err(TrySendError::Closed(_)) => {
+            log_error!("Interaction queue is closed, dropped an interaction");
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use chrono::{Duration, Utc};
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::UpdateOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::model::id::{GuildId, RoleId};
+use tokio::sync::OnceCell;
+
+use crate::classes::Class;
+use crate::digest::Digest;
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, lookup, set_all, ClassError, ClassResult, ENV};
+
+/// How much of each announcement is included in an email.
+const ANNOUNCEMENT_LENGTH: usize = 300;
+
+lazy_static! {
+    /// The SMTP connection, if `SMTP_URL` and `SMTP_FROM` are set. Email digests are disabled
+    /// otherwise.
+    static ref MAILER: Option<AsyncSmtpTransport<Tokio1Executor>> = ENV.smtp_url.as_ref()
+        .filter(|_| ENV.smtp_from.is_some())
+        .and_then(|url| match AsyncSmtpTransport::<Tokio1Executor>::from_url(url) {
+            Ok(builder) => Some(builder.build()),
+            Err(e) => {
+                log_error!("Invalid SMTP_URL, email digests are disabled: {:?}", e);
+                None
+            }
+        });
+}
+
+pub(crate) fn is_configured() -> bool {
+    MAILER.is_some()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub(crate) enum EmailFrequency {
+    #[name = "Daily"]
+    Daily,
+    #[name = "Weekly"]
+    Weekly,
+}
+
+impl EmailFrequency {
+    fn period(self) -> Duration {
+        match self {
+            Self::Daily => Duration::days(1),
+            Self::Weekly => Duration::weeks(1),
+        }
+    }
+}
+
+/// A staff member's email subscription to a server's digest, or to a single class's.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct EmailSubscription {
+    server_id: GuildId,
+    pub(crate) address: String,
+    /// The class to send the digest for, or the whole server if unset.
+    pub(crate) role: Option<RoleId>,
+    pub(crate) frequency: EmailFrequency,
+    last_sent: DateTime,
+}
+
+impl EmailSubscription {
+    /// Subscribe an address, or change how often an existing subscription is sent.
+    pub(crate) async fn subscribe(
+        server_id: GuildId,
+        address: &str,
+        role: Option<RoleId>,
+        frequency: EmailFrequency,
+    ) -> ClassResult<()> {
+        if !is_configured() {
+            return Err(ClassError::EmailNotConfigured);
+        }
+        let address = address.trim()
+            .parse::<lettre::Address>()
+            .map_err(|_| ClassError::InvalidEmail)?
+            .to_string();
+
+        Self::scoped(server_id).await
+            .update_one(
+                doc! {
+                    "address": &address,
+                    "role": role.map(|r| r.to_string()),
+                },
+                set_all(&Self { server_id, address: address.clone(), role, frequency, last_sent: DateTime::now() })?,
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub(crate) async fn unsubscribe(server_id: GuildId, address: &str, role: Option<RoleId>) -> ClassResult<()> {
+        let result = Self::scoped(server_id).await
+            .delete_one(
+                doc! {
+                    "address": address.trim(),
+                    "role": role.map(|r| r.to_string()),
+                },
+                None,
+            )
+            .await?;
+
+        if result.deleted_count == 0 {
+            return Err(ClassError::InvalidEmailSubscription);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn list(server_id: GuildId) -> ClassResult<Vec<EmailSubscription>> {
+        Ok(
+            Self::scoped(server_id).await
+                .find(None, None)
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    /// Build and send this subscription's digest, covering everything since it was last sent.
+    async fn send(&self, ctx: &SContext) -> ClassResult<()> {
+        let server_name = lookup::guild(ctx, self.server_id).await?.name;
+        let mut classes = Class::list(self.server_id).await?;
+        let mut digest = Digest::generate(ctx, self.server_id, self.last_sent).await?;
+        let mut subject = format!("{} digest", server_name);
+        if let Some(role) = self.role {
+            classes.retain(|c| c.role == role);
+            let class = classes.first().ok_or(ClassError::InvalidClass)?;
+            digest = digest.only_class(&class.name);
+            subject = format!("{} digest for {}", server_name, class.name);
+        }
+
+        let mut body = digest.render_text(&subject);
+        let announcements = announcements(ctx, &classes, self.last_sent).await;
+        if !announcements.is_empty() {
+            body += &format!("\nAnnouncements:\n\n{}\n", announcements.join("\n\n"));
+        }
+
+        let message = Message::builder()
+            // Unwrapping because the mailer is only set up when SMTP_FROM is set
+            .from(ENV.smtp_from.as_ref().unwrap().parse().map_err(|_| ClassError::InvalidEmail)?)
+            .to(self.address.parse().map_err(|_| ClassError::InvalidEmail)?)
+            .subject(subject)
+            .body(body)?;
+        MAILER.as_ref().ok_or(ClassError::EmailNotConfigured)?.send(message).await?;
+
+        Self::scoped(self.server_id).await
+            .update_one(
+                doc! {
+                    "address": &self.address,
+                    "role": self.role.map(|r| r.to_string()),
+                },
+                doc! { "$set": { "last_sent": DateTime::now() } },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static SUBSCRIPTIONS: OnceCell<Collection<EmailSubscription>> = OnceCell::const_new();
+
+        SUBSCRIPTIONS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("email_subscriptions")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Messages pinned in the classes' channels since the given time, as a class's announcements are
+/// whatever its staff pin.
+async fn announcements(ctx: &SContext, classes: &[Class], since: DateTime) -> Vec<String> {
+    let since = since.timestamp_millis() / 1000;
+    let mut announcements = Vec::new();
+    for class in classes {
+        for channel in &class.text_channels {
+            // Ignoring errors as the channel may have been deleted by hand
+            let pins = channel.pins(&ctx.http).await.unwrap_or_default();
+            announcements.extend(
+                pins.into_iter()
+                    .filter(|m| m.timestamp.unix_timestamp() >= since)
+                    .map(|m| format!(
+                        "[{}] {}: {}",
+                        class.name,
+                        m.author.tag(),
+                        m.content.chars().take(ANNOUNCEMENT_LENGTH).collect::<String>(),
+                    )),
+            );
+        }
+    }
+    announcements
+}
+
+/// Send every email digest that is due.
+pub(crate) async fn tick(ctx: &SContext) -> ClassResult<()> {
+    if !is_configured() {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let subscriptions = EmailSubscription::get_collection().await
+        .find(None, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    for subscription in subscriptions {
+        let due = DateTime::from_millis((now - subscription.frequency.period()).timestamp_millis());
+        if subscription.last_sent > due || ctx.cache.guild_field(subscription.server_id, |_| ()).is_none() {
+            continue;
+        }
+
+        // One bad address shouldn't hold up everyone else's digest
+        if let Err(e) = subscription.send(ctx).await {
+            log_error!("[{}] Error sending email digest to {}: {:?}", subscription.server_id, subscription.address, e);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::builder::CreateActionRow;
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::application::interaction::Interaction;
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, RoleId};
+use serenity::model::prelude::component::{ButtonStyle, ComponentType};
+use serenity::prelude::EventHandler;
+
+use crate::classes::{Class, Server};
+use crate::history::{self, EnrollmentMechanism};
+use crate::redact::log_error;
+use crate::{ClassError, ClassResult};
+
+/// A period during which members may join and leave classes, like a university's add/drop period.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct EnrollmentWindow {
+    pub(crate) opens: DateTime,
+    pub(crate) closes: DateTime,
+}
+
+impl EnrollmentWindow {
+    fn is_open(&self) -> bool {
+        let now = DateTime::now();
+        self.opens <= now && now < self.closes
+    }
+}
+
+/// Check whether `member` may currently join or leave classes. Outside the server's enrollment
+/// window only members who can manage roles may, so staff can still make changes by hand.
+pub(crate) async fn check_window(member: &Member) -> ClassResult<()> {
+    if member.permissions.map(|p| p.manage_roles()).unwrap_or(false) {
+        return Ok(());
+    }
+
+    match Server::get_or_create(member.guild_id).await?.enrollment_window {
+        Some(window) if !window.is_open() => Err(ClassError::EnrollmentClosed(
+            window.opens.timestamp_millis() / 1000,
+            window.closes.timestamp_millis() / 1000,
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Check the bot can add and remove every one of `roles`: each has to be below the bot's highest
+/// role, and not managed by an integration.
+pub(crate) fn check_assignable(ctx: &SContext, server_id: GuildId, roles: &[RoleId]) -> ClassResult<()> {
+    let bot = ctx.cache.current_user_id();
+    let problems = ctx.cache
+        .guild_field(server_id, |g| {
+            let highest = g.members.get(&bot)
+                .and_then(|m| m.roles.iter().filter_map(|r| g.roles.get(r)).map(|r| r.position).max())
+                .unwrap_or(0);
+            roles.iter()
+                .filter_map(|r| g.roles.get(r))
+                .filter(|r| r.managed || r.position >= highest)
+                .map(|r| r.name.clone())
+                .collect::<Vec<_>>()
+        })
+        // Member roles only come from the gateway, so a server that isn't cached yet can't be
+        // checked
+        .ok_or(ClassError::NotCached)?;
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ClassError::UnassignableRoles(problems.join(", ")))
+    }
+}
+
+/// Add a Join/Leave button for `class` to an action row. Clicks are handled by
+/// [`EnrollmentButtonHandler`], so this can be attached anywhere a class is displayed.
+pub(crate) fn enrollment_button<'a>(row: &'a mut CreateActionRow, class: &Class) -> &'a mut CreateActionRow {
+    row.create_button(|b| b
+        .custom_id(format!("class_enroll_{}", class.role))
+        .style(ButtonStyle::Secondary)
+        // Button labels are limited to 80 characters
+        .label(format!("Join/Leave {}", class.name.chars().take(64).collect::<String>()))
+    )
+}
+
+pub(crate) struct EnrollmentButtonHandler;
+
+#[async_trait]
+impl EventHandler for EnrollmentButtonHandler {
+    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
+        let component = if let Interaction::MessageComponent(c) = interaction {
+            c
+        } else {
+            return;
+        };
+        if component.data.component_type != ComponentType::Button {
+            return;
+        }
+
+        let role = if let Some(role) = component.data.custom_id
+            .strip_prefix("class_enroll_")
+            .and_then(|id| id.parse::<u64>().ok())
+        {
+            RoleId(role)
+        } else {
+            return;
+        };
+
+        let response = match toggle_enrollment(&ctx, &component, role).await {
+            Ok(message) => message,
+            Err(e) => e.to_string(),
+        };
+
+        if let Err(e) = component.create_interaction_response(ctx.http(), |r| r
+            .interaction_response_data(|d| d.ephemeral(true).content(response))
+        ).await {
+            log_error!("Error handling {}: {:?}", component.data.custom_id, e);
+        }
+    }
+}
+
+async fn toggle_enrollment(
+    ctx: &SContext,
+    component: &MessageComponentInteraction,
+    role: RoleId,
+) -> ClassResult<String> {
+    let mut member = component.member.clone().ok_or(ClassError::NoServer)?;
+    let class = Class::find_by_role(role)
+        .await?
+        .filter(|c| c.server_id == member.guild_id)
+        .ok_or(ClassError::InvalidClass)?;
+    check_window(&member).await?;
+
+    let leaving = member.roles.contains(&role);
+    check_assignable(ctx, member.guild_id, &[role])?;
+    if leaving {
+        member.remove_role(ctx.http(), role).await?;
+    } else {
+        member.add_role(ctx.http(), role).await?;
+    }
+
+    history::enrolled(
+        member.guild_id,
+        member.user.id,
+        component.user.id,
+        EnrollmentMechanism::Button,
+        if leaving { Vec::new() } else { vec![role] },
+        if leaving { vec![role] } else { Vec::new() },
+    ).await;
+
+    Ok(if leaving {
+        format!("You have left {}.", class.name)
+    } else {
+        format!("You have joined {}.", class.name)
+    })
+}
@@ -0,0 +1,145 @@
+use std::any::Any;
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use reqwest::Url;
+use serde_json::json;
+use serenity::http::Http;
+use serenity::model::id::{GuildId, UserId};
+use serenity::prelude::Mentionable;
+
+use crate::classes::Server;
+use crate::redact::{self, log_error};
+use crate::{audit, ENV};
+
+/// Embed field values can be at most 1024 characters.
+const FIELD_LIMIT: usize = 1024;
+
+static HTTP: OnceLock<Arc<Http>> = OnceLock::new();
+
+lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::new();
+}
+
+/// Let errors be posted to alert channels. Called once the bot has connected.
+pub(crate) fn init(http: Arc<Http>) {
+    HTTP.get_or_init(|| http);
+}
+
+/// Where an error happened, as far as is known.
+#[derive(Default, Debug, Clone)]
+pub(crate) struct ErrorContext {
+    pub(crate) server_id: Option<GuildId>,
+    pub(crate) command: Option<String>,
+    pub(crate) user: Option<UserId>,
+}
+
+/// Report an unexpected error: record it for the staff digest and `/admin diag`, post it to the
+/// server's alert channel if it has one, and send it to Sentry if `SENTRY_DSN` is set.
+pub(crate) async fn report(what: &str, context: ErrorContext, error: &str) {
+    let error = &redact::redact(error);
+    audit::record_error(context.server_id, what, error).await;
+
+    if let Err(e) = alert(what, &context, error).await {
+        log_error!("Error posting error alert: {:?}", e);
+    }
+    if let Err(e) = send_to_sentry(what, &context, error).await {
+        log_error!("Error sending error to Sentry: {:?}", e);
+    }
+}
+
+async fn alert(what: &str, context: &ErrorContext, error: &str) -> Result<(), crate::ClassError> {
+    let (http, server_id) = match (HTTP.get(), context.server_id) {
+        (Some(http), Some(server_id)) => (http, server_id),
+        _ => return Ok(()),
+    };
+    let channel = match Server::get_or_create(server_id).await?.alert_channel {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+
+    channel
+        .send_message(http, |m| m.embed(|e| {
+            e.title(format!("Error in {}", what))
+                .description(error.chars().take(FIELD_LIMIT * 2).collect::<String>())
+                .timestamp(Utc::now().to_rfc3339());
+            if let Some(command) = &context.command {
+                e.field("Command", format!("/{}", command), true);
+            }
+            if let Some(user) = context.user {
+                e.field("User", user.mention(), true);
+            }
+            e
+        }))
+        .await?;
+
+    Ok(())
+}
+
+/// Send an error to Sentry's store endpoint, using the DSN's key and project.
+async fn send_to_sentry(what: &str, context: &ErrorContext, error: &str) -> Result<(), reqwest::Error> {
+    let dsn = match ENV.sentry_dsn.as_deref().and_then(|d| Url::parse(d).ok()) {
+        Some(d) => d,
+        None => return Ok(()),
+    };
+    let project = dsn.path().trim_matches('/');
+    let mut store = dsn.clone();
+    store.set_path(&format!("/api/{}/store/", project));
+    // Unwrapping because clearing the credentials of an http(s) URL can't fail
+    store.set_username("").unwrap();
+    store.set_password(None).unwrap();
+
+    CLIENT.post(store)
+        .header(
+            "X-Sentry-Auth",
+            format!(
+                "Sentry sentry_version=7, sentry_key={}, sentry_client=cs_discord_rs/{}",
+                dsn.username(),
+                env!("CARGO_PKG_VERSION"),
+            ),
+        )
+        .json(&json!({
+            "event_id": format!("{:032x}", rand::random::<u128>()),
+            "timestamp": Utc::now().to_rfc3339(),
+            "level": "error",
+            "platform": "other",
+            "release": env!("GIT_COMMIT"),
+            "transaction": what,
+            "message": { "formatted": error },
+            "tags": {
+                "guild": context.server_id.map(|s| s.to_string()),
+                "command": context.command,
+            },
+            "user": context.user.map(|u| json!({ "id": u.to_string() })),
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+/// Describe a caught panic for reporting, using its message if it has one.
+pub(crate) fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    format!("Panicked: {}", message)
+}
+
+/// Run a task in the background, reporting it if it panics instead of letting it vanish.
+pub(crate) fn spawn_reported<F>(what: &'static str, context: ErrorContext, task: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let handle = tokio::spawn(task);
+    tokio::spawn(async move {
+        if let Err(e) = handle.await {
+            if e.is_panic() {
+                report(what, context, &panic_message(e.into_panic())).await;
+            }
+        }
+    });
+}
@@ -0,0 +1,188 @@
+use std::time::{Duration as StdDuration, Instant};
+
+use chrono::{Duration, Utc};
+use lazy_static::lazy_static;
+use mongodb::bson::{doc, DateTime};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::prelude::Mentionable;
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::classes::{Class, Server};
+use crate::mentions::{self, Pings};
+use crate::redact::log_error;
+use crate::{get_conn, ClassResult, ENV};
+
+/// Questions older than this are left alone, so turning escalation on doesn't dig up old threads.
+const MAX_AGE_DAYS: i64 = 7;
+
+/// How often homework-help channels are checked.
+const SCAN_INTERVAL: StdDuration = StdDuration::from_secs(15 * 60);
+
+lazy_static! {
+    static ref LAST_SCAN: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/// How many recent messages in each homework-help channel are checked for unanswered questions.
+const SCAN_LIMIT: u64 = 50;
+
+/// A question that has already been escalated, so it isn't escalated again. `question` is the
+/// thread's ID for threads, and the message's ID for messages posted straight in the channel.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Escalation {
+    server_id: GuildId,
+    question: String,
+    at: DateTime,
+}
+
+impl Escalation {
+    async fn exists(question: &str) -> ClassResult<bool> {
+        Ok(Self::get_collection().await.find_one(doc! { "question": question }, None).await?.is_some())
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static ESCALATIONS: OnceCell<Collection<Escalation>> = OnceCell::const_new();
+
+        ESCALATIONS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("escalations")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Whether a message was posted in the window where it counts as unanswered.
+fn in_window(message: &Message, hours: u32) -> bool {
+    let posted = message.timestamp.unix_timestamp();
+    let now = Utc::now();
+    posted <= (now - Duration::hours(hours as i64)).timestamp()
+        && posted > (now - Duration::days(MAX_AGE_DAYS)).timestamp()
+}
+
+/// Ping the class's staff role where the question was asked, or post to the staff channel if the
+/// class has no staff role, then record the escalation.
+async fn escalate(
+    ctx: &SContext,
+    server: &Server,
+    class: &Class,
+    channel: ChannelId,
+    question: &str,
+    link: String,
+    hours: u32,
+) -> ClassResult<()> {
+    if Escalation::exists(question).await? {
+        return Ok(());
+    }
+
+    match (class.staff_role, server.staff_channel) {
+        (Some(staff_role), _) => {
+            let content = format!(
+                "{} this question hasn't had an answer in {} hours: {}",
+                staff_role.mention(),
+                hours,
+                link,
+            );
+            mentions::send(ctx, channel, class.role, content, Pings::role(staff_role)).await?;
+        }
+        (None, Some(staff_channel)) => {
+            staff_channel.say(&ctx.http, format!(
+                "A question in \"{}\" hasn't had an answer in {} hours: {}",
+                class.name,
+                hours,
+                link,
+            )).await?;
+        }
+        (None, None) => return Ok(()),
+    }
+
+    Escalation::get_collection().await
+        .insert_one(Escalation { server_id: class.server_id, question: question.to_string(), at: DateTime::now() }, None)
+        .await?;
+
+    Ok(())
+}
+
+/// Escalate unanswered homework-help questions in one server.
+async fn check_server(ctx: &SContext, server_id: GuildId) -> ClassResult<()> {
+    let server = Server::get_or_create(server_id).await?;
+    let hours = match server.escalation_hours {
+        Some(h) => h,
+        None => return Ok(()),
+    };
+    let classes = Class::list(server_id).await?;
+    let homework_help = |channel: ChannelId| ctx.cache
+        .guild_channel_field(channel, |c| c.name.starts_with("homework-help"))
+        .unwrap_or(false);
+
+    let threads = server_id.get_active_threads(&ctx.http).await?.threads;
+
+    // Threads nobody but the asker has posted in
+    for thread in &threads {
+        let parent = match thread.parent_id {
+            Some(p) if homework_help(p) => p,
+            _ => continue,
+        };
+        let class = match classes.iter().find(|c| c.text_channels.contains(&parent)) {
+            Some(c) => c,
+            None => continue,
+        };
+
+        let messages = thread.id.messages(&ctx.http, |m| m.limit(SCAN_LIMIT)).await?;
+        let first = match messages.last() {
+            Some(m) => m,
+            None => continue,
+        };
+        let answered = messages.iter().any(|m| !m.author.bot && m.author.id != first.author.id);
+        if !answered && in_window(first, hours) {
+            escalate(ctx, &server, class, thread.id, &thread.id.to_string(), thread.id.mention().to_string(), hours).await?;
+        }
+    }
+
+    // Messages posted straight in the channel, with no thread and nobody else posting after them
+    for class in &classes {
+        for channel in class.text_channels.iter().copied().filter(|c| homework_help(*c)) {
+            let messages = channel.messages(&ctx.http, |m| m.limit(SCAN_LIMIT)).await?;
+            let mut later_authors = Vec::new();
+            for message in messages.iter().filter(|m| !m.author.bot) {
+                // A thread started from a message shares its ID
+                let has_thread = threads.iter().any(|t| t.id.0 == message.id.0);
+                let answered = has_thread || later_authors.iter().any(|a| *a != message.author.id);
+                later_authors.push(message.author.id);
+                if answered || !in_window(message, hours) {
+                    continue;
+                }
+
+                let link = message.id.link(channel, Some(server_id));
+                escalate(ctx, &server, class, channel, &message.id.to_string(), link, hours).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Escalate unanswered homework-help questions in every server that has it turned on.
+pub(crate) async fn tick(ctx: &SContext) -> ClassResult<()> {
+    {
+        let mut last_scan = LAST_SCAN.lock().await;
+        if last_scan.is_some_and(|t| t.elapsed() < SCAN_INTERVAL) {
+            return Ok(());
+        }
+        *last_scan = Some(Instant::now());
+    }
+
+    for server_id in ctx.cache.guilds() {
+        if let Err(e) = check_server(ctx, server_id).await {
+            log_error!("[{}] Error escalating unanswered questions: {:?}", server_id, e);
+        }
+    }
+
+    Ok(())
+}
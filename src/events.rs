@@ -0,0 +1,132 @@
+use std::future::Future;
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serenity::client::Context as SContext;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::classes::Class;
+use crate::history::EnrollmentMechanism;
+use crate::redact::log_error;
+use crate::{audit, automod, federation, joinlog, mentors, newclasses, say, teams, webhooks};
+
+lazy_static! {
+    static ref EVENTS: broadcast::Sender<BotEvent> = broadcast::channel(256).0;
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum BotEvent {
+    ClassCreated {
+        server_id: GuildId,
+        class: Class,
+    },
+    ClassDeleted {
+        server_id: GuildId,
+        class: Class,
+        /// Whether the class's role and channels were deleted, or it was only untracked.
+        channels_deleted: bool,
+    },
+    MemberEnrolled {
+        server_id: GuildId,
+        user_id: UserId,
+        /// Who caused the change, which is the bot itself for automatic changes.
+        actor: UserId,
+        mechanism: EnrollmentMechanism,
+        joined: Vec<RoleId>,
+        left: Vec<RoleId>,
+    },
+    ConfigChanged {
+        server_id: GuildId,
+        setting: String,
+    },
+    /// A staff member posted a message as the bot.
+    MessagePosted {
+        server_id: GuildId,
+        user_id: UserId,
+        channel: ChannelId,
+        message: MessageId,
+        /// The command used to post it.
+        command: String,
+    },
+    /// A member was temporarily allowed to run a command or group of commands.
+    AccessGranted {
+        server_id: GuildId,
+        user_id: UserId,
+        granted_by: UserId,
+        command: String,
+        /// When the grant expires, as a unix timestamp.
+        expires_at: i64,
+    },
+    /// A temporary grant expired and was revoked.
+    AccessRevoked {
+        server_id: GuildId,
+        user_id: UserId,
+        command: String,
+    },
+}
+
+impl BotEvent {
+    pub(crate) fn server_id(&self) -> GuildId {
+        match self {
+            Self::ClassCreated { server_id, .. }
+            | Self::ClassDeleted { server_id, .. }
+            | Self::MemberEnrolled { server_id, .. }
+            | Self::ConfigChanged { server_id, .. }
+            | Self::MessagePosted { server_id, .. }
+            | Self::AccessGranted { server_id, .. }
+            | Self::AccessRevoked { server_id, .. } => *server_id,
+        }
+    }
+}
+
+/// Publish an event to every running subscriber. Events published while nothing is subscribed
+/// are dropped.
+pub(crate) fn publish(event: BotEvent) {
+    EVENTS.send(event).ok();
+}
+
+pub(crate) fn subscribe() -> broadcast::Receiver<BotEvent> {
+    EVENTS.subscribe()
+}
+
+/// Spawn a task that calls `handler` for every event published from now on.
+pub(crate) fn spawn_subscriber<F, Fut>(name: &'static str, handler: F)
+where
+    F: Fn(BotEvent) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    let mut events = subscribe();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => handler(event).await,
+                Err(RecvError::Lagged(n)) => {
+                    log_error!("Event subscriber {} fell behind, skipped {} events", name, n);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+pub(crate) fn start_subscribers(ctx: &SContext) {
+    spawn_subscriber("audit_log", audit::record);
+    spawn_subscriber("webhooks", webhooks::deliver);
+    let teams_ctx = ctx.clone();
+    spawn_subscriber("teams", move |event| teams::cleanup(teams_ctx.clone(), event));
+    let federation_ctx = ctx.clone();
+    spawn_subscriber("federation", move |event| federation::sync(federation_ctx.clone(), event));
+    let automod_ctx = ctx.clone();
+    spawn_subscriber("automod", move |event| automod::lifecycle(automod_ctx.clone(), event));
+    let mentors_ctx = ctx.clone();
+    spawn_subscriber("mentors", move |event| mentors::grant(mentors_ctx.clone(), event));
+    let join_log_ctx = ctx.clone();
+    spawn_subscriber("join_log", move |event| joinlog::log(join_log_ctx.clone(), event));
+    let new_classes_ctx = ctx.clone();
+    spawn_subscriber("new_classes", move |event| newclasses::created(new_classes_ctx.clone(), event));
+    let say_ctx = ctx.clone();
+    spawn_subscriber("say", move |event| say::log(say_ctx.clone(), event));
+}
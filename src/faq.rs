@@ -0,0 +1,242 @@
+use std::collections::HashSet;
+
+use futures::TryStreamExt;
+use itertools::Itertools;
+use mongodb::bson::doc;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::channel::Message;
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, RoleId};
+use serenity::prelude::EventHandler;
+use tokio::sync::OnceCell;
+
+use crate::classes::Class;
+use crate::redact::log_error;
+use crate::{get_conn, is_class_staff, ClassError, ClassResult, Context, Error, ENV};
+
+/// How similar a homework-help question has to be to a stored one to be answered automatically.
+const AUTO_SUGGEST_THRESHOLD: f64 = 0.6;
+/// How similar a `/faq get` query has to be to a stored question to count as a match.
+const GET_THRESHOLD: f64 = 0.3;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Faq {
+    server_id: GuildId,
+    role: RoleId,
+    question: String,
+    answer: String,
+}
+
+impl Faq {
+    async fn add(class: &Class, question: &str, answer: &str) -> ClassResult<()> {
+        let question = question.trim();
+        if Self::list(class.role).await?.iter().any(|f| f.question.eq_ignore_ascii_case(question)) {
+            return Err(ClassError::FaqExists);
+        }
+
+        Self::get_collection().await
+            .insert_one(
+                Self {
+                    server_id: class.server_id,
+                    role: class.role,
+                    question: question.to_string(),
+                    answer: answer.trim().to_string(),
+                },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove(class: &Class, question: &str) -> ClassResult<()> {
+        let faq = Self::list(class.role).await?
+            .into_iter()
+            .find(|f| f.question.eq_ignore_ascii_case(question.trim()))
+            .ok_or(ClassError::InvalidFaq)?;
+
+        Self::get_collection().await
+            .delete_one(doc! { "role": class.role.to_string(), "question": faq.question }, None)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Find the stored question most similar to `query`, if any is at least `threshold` similar.
+    async fn lookup(role: RoleId, query: &str, threshold: f64) -> ClassResult<Option<Faq>> {
+        Ok(
+            Self::list(role).await?
+                .into_iter()
+                .map(|f| (similarity(&f.question, query), f))
+                .filter(|(score, _)| *score >= threshold)
+                .max_by(|(a, _), (b, _)| a.total_cmp(b))
+                .map(|(_, f)| f)
+        )
+    }
+
+    async fn list(role: RoleId) -> ClassResult<Vec<Faq>> {
+        Ok(
+            Self::get_collection().await
+                .find(doc! { "role": role.to_string() }, None)
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static FAQS: OnceCell<Collection<Faq>> = OnceCell::const_new();
+
+        FAQS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("faqs")
+            })
+            .await
+            .clone()
+    }
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2)
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// Dice coefficient of the two texts' sets of words, from 0 (nothing shared) to 1 (the same words).
+pub(crate) fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (words(a), words(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    2.0 * a.intersection(&b).count() as f64 / (a.len() + b.len()) as f64
+}
+
+#[poise::command(
+    slash_command,
+    subcommands("FaqCommand::add", "FaqCommand::remove", "FaqCommand::get")
+)]
+pub(crate) async fn faq(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct FaqCommand;
+impl FaqCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn add(ctx: Context<'_>, class: Role, question: String, answer: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        if !is_class_staff(ctx, &class).await {
+            Err(ClassError::MissingPermissions)?;
+        }
+
+        Faq::add(&class, &question, &answer).await?;
+
+        ctx.say(format!("Added FAQ to \"{}\".", class.name)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn remove(ctx: Context<'_>, class: Role, question: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        if !is_class_staff(ctx, &class).await {
+            Err(ClassError::MissingPermissions)?;
+        }
+
+        Faq::remove(&class, &question).await?;
+
+        ctx.say(format!("Removed FAQ from \"{}\".", class.name)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn get(ctx: Context<'_>, class: Role, question: Option<String>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        match question {
+            Some(question) => {
+                let faq = Faq::lookup(class.role, &question, GET_THRESHOLD).await?
+                    .ok_or(ClassError::InvalidFaq)?;
+                ctx.say(format!("**Q: {}**\n{}", faq.question, faq.answer)).await?;
+            }
+            None => {
+                let faqs = Faq::list(class.role).await?;
+                if faqs.is_empty() {
+                    ctx.say(format!("There are no FAQs for \"{}\".", class.name)).await?;
+                } else {
+                    ctx.say(format!(
+                        "FAQs for \"{}\":\n{}",
+                        class.name,
+                        faqs.iter().map(|f| format!("• {}", f.question)).join("\n"),
+                    )).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) struct FaqSuggestHandler;
+
+#[async_trait]
+impl EventHandler for FaqSuggestHandler {
+    async fn message(&self, ctx: SContext, message: Message) {
+        if message.author.bot || message.guild_id.is_none() {
+            return;
+        }
+
+        // Only answer in homework-help channels
+        let is_homework_help = ctx.cache
+            .guild_channel_field(message.channel_id, |c| c.name.starts_with("homework-help"))
+            .unwrap_or(false);
+        if !is_homework_help {
+            return;
+        }
+
+        if let Err(e) = suggest(&ctx, &message).await {
+            log_error!("Error suggesting FAQ: {:?}", e);
+        }
+    }
+}
+
+async fn suggest(ctx: &SContext, message: &Message) -> ClassResult<()> {
+    let class = match Class::find_by_text_channel(message.channel_id).await? {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+
+    if let Some(faq) = Faq::lookup(class.role, &message.content, AUTO_SUGGEST_THRESHOLD).await? {
+        message
+            .reply(ctx.http(), format!(
+                "This looks like a frequently asked question:\n**Q: {}**\n{}",
+                faq.question, faq.answer,
+            ))
+            .await?;
+    }
+
+    Ok(())
+}
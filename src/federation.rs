@@ -0,0 +1,332 @@
+use futures::TryStreamExt;
+use itertools::Itertools;
+use mongodb::bson::{doc, Document};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use tokio::sync::OnceCell;
+
+use crate::classes::{Class, Server};
+use crate::events::BotEvent;
+use crate::history::{self, EnrollmentMechanism};
+use crate::joinlog;
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, lookup, ClassError, ClassResult, Context, Error, ENV};
+
+/// A class on a hub server mirrored as a class on a member server. Enrollment in either is kept in
+/// sync with the other.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Mirror {
+    hub_server: GuildId,
+    hub_role: RoleId,
+    server_id: GuildId,
+    role: RoleId,
+}
+
+impl Mirror {
+    /// Whether the hub still allows the mirror's server in its federation.
+    async fn allowed(&self) -> ClassResult<bool> {
+        Ok(Server::get_or_create(self.hub_server).await?.allows_federation(self.server_id))
+    }
+
+    /// Every mirror linked to a role through `filter` that its hub still allows.
+    async fn find_allowed(filter: Document) -> ClassResult<Vec<Self>> {
+        let mut allowed = Vec::new();
+        for mirror in Self::get_collection().await
+            .find(filter, None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+        {
+            if mirror.allowed().await? {
+                allowed.push(mirror);
+            }
+        }
+
+        Ok(allowed)
+    }
+
+    /// Every role linked to `role`, on any server, along with the server it belongs to. Mirrors
+    /// whose hub no longer allows their server aren't linked.
+    async fn linked(role: RoleId) -> ClassResult<Vec<(GuildId, RoleId)>> {
+        let role = role.to_string();
+        let mirrors = Self::find_allowed(doc! { "$or": [{ "hub_role": &role }, { "role": &role }] }).await?;
+
+        // A member server's role links to the hub role, which in turn links to every other mirror
+        let mut linked = Vec::new();
+        for mirror in mirrors {
+            if mirror.hub_role.to_string() == role {
+                linked.push((mirror.server_id, mirror.role));
+            } else {
+                linked.push((mirror.hub_server, mirror.hub_role));
+                linked.extend(
+                    Self::find_allowed(doc! { "hub_role": mirror.hub_role.to_string() })
+                        .await?
+                        .into_iter()
+                        .filter(|m| m.role.to_string() != role)
+                        .map(|m| (m.server_id, m.role)),
+                );
+            }
+        }
+
+        Ok(linked.into_iter().unique().collect())
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static MIRRORS: OnceCell<Collection<Mirror>> = OnceCell::const_new();
+
+        MIRRORS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("federation_mirrors")
+            })
+            .await
+            .clone()
+    }
+}
+
+async fn sync_role(ctx: &SContext, user: UserId, role: RoleId, joined: bool) -> ClassResult<()> {
+    for (server_id, linked_role) in Mirror::linked(role).await? {
+        let has_role = match ctx.cache.member_field(server_id, user, |m| m.roles.contains(&linked_role)) {
+            Some(r) => r,
+            // Not a member of that server
+            None => continue,
+        };
+        if has_role == joined {
+            continue;
+        }
+
+        // Not republished as a MemberEnrolled event, so the change isn't synced straight back
+        if joined {
+            ctx.http().add_member_role(server_id.0, user.0, linked_role.0, Some("Federated class enrollment")).await?;
+        } else {
+            ctx.http().remove_member_role(server_id.0, user.0, linked_role.0, Some("Federated class enrollment")).await?;
+        }
+        let (added, removed) = if joined { (vec![linked_role], Vec::new()) } else { (Vec::new(), vec![linked_role]) };
+        joinlog::post(ctx, server_id, user, &added, &removed, EnrollmentMechanism::Federation.describe()).await?;
+        history::log(server_id, user, ctx.cache.current_user_id(), EnrollmentMechanism::Federation, added, removed).await?;
+    }
+
+    Ok(())
+}
+
+/// Event bus subscriber mirroring enrollment changes to linked classes on other servers.
+pub(crate) async fn sync(ctx: SContext, event: BotEvent) {
+    let (user, joined, left) = match event {
+        BotEvent::MemberEnrolled { user_id, joined, left, .. } => (user_id, joined, left),
+        _ => return,
+    };
+
+    let changes = joined.into_iter()
+        .map(|r| (r, true))
+        .chain(left.into_iter().map(|r| (r, false)));
+    for (role, joined) in changes {
+        if let Err(e) = sync_role(&ctx, user, role, joined).await {
+            log_error!("Error syncing federated enrollment: {:?}", e);
+        }
+    }
+}
+
+#[poise::command(
+    slash_command,
+    subcommands(
+        "FederationCommand::join",
+        "FederationCommand::leave",
+        "FederationCommand::allow",
+        "FederationCommand::revoke",
+        "FederationCommand::mirror",
+        "FederationCommand::unmirror",
+        "FederationCommand::list",
+    )
+)]
+pub(crate) async fn federation(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct FederationCommand;
+impl FederationCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn join(ctx: Context<'_>, hub_server_id: String) -> Result<(), Error> {
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let hub = hub_server_id.trim().parse::<u64>()
+            .map(GuildId)
+            .ok()
+            .filter(|h| *h != server_id && ctx.discord().cache.guild_field(*h, |g| g.id).is_some())
+            .ok_or(ClassError::InvalidHub)?;
+        if !Server::get_or_create(hub).await?.allows_federation(server_id) {
+            Err(ClassError::HubNotAllowed)?;
+        }
+
+        let mut server = Server::get_or_create(server_id).await?;
+        server.set_federation_hub(Some(hub)).await?;
+
+        ctx.say("This server can now mirror classes from the hub server with `/federation mirror`.").await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn leave(ctx: Context<'_>) -> Result<(), Error> {
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+
+        Mirror::scoped(server_id).await
+            .delete_many(None, None)
+            .await?;
+        let mut server = Server::get_or_create(server_id).await?;
+        server.set_federation_hub(None).await?;
+
+        ctx.say("This server has left its federation. Mirrored classes are kept, but no longer synced.").await?;
+
+        Ok(())
+    }
+
+    /// Let another server join this one as its federation hub.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn allow(ctx: Context<'_>, member_server_id: String) -> Result<(), Error> {
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let member = member_server_id.trim().parse::<u64>()
+            .map(GuildId)
+            .ok()
+            .filter(|m| *m != server_id)
+            .ok_or(ClassError::InvalidHub)?;
+
+        let mut server = Server::get_or_create(server_id).await?;
+        server.set_federation_member(member, true).await?;
+
+        ctx.say("That server can now join this one and mirror its classes with `/federation join`.").await?;
+
+        Ok(())
+    }
+
+    /// Stop syncing another server's mirrors of this server's classes, and remove its mirrors.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn revoke(ctx: Context<'_>, member_server_id: String) -> Result<(), Error> {
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let member = member_server_id.trim().parse::<u64>()
+            .map(GuildId)
+            .map_err(|_| ClassError::InvalidHub)?;
+
+        let mut server = Server::get_or_create(server_id).await?;
+        server.set_federation_member(member, false).await?;
+        Mirror::scoped(member).await
+            .delete_many(doc! { "hub_server": server_id.to_string() }, None)
+            .await?;
+
+        ctx.say("That server can no longer mirror this server's classes.").await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        required_bot_permissions = "MANAGE_ROLES | MANAGE_CHANNELS",
+    )]
+    async fn mirror(ctx: Context<'_>, hub_class: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let guild = lookup::current_guild(ctx).await?;
+        let hub = Server::get_or_create(guild.id).await?
+            .federation_hub
+            .ok_or(ClassError::NoHub)?;
+        if !Server::get_or_create(hub).await?.allows_federation(guild.id) {
+            Err(ClassError::HubNotAllowed)?;
+        }
+        let hub_class = Class::find_by_name(hub, hub_class.trim()).await?
+            .ok_or(ClassError::InvalidClass)?;
+
+        // Mirror the class's role and channels with the normal class layout
+        let class = Class::create(ctx.discord(), &guild, &hub_class.name, hub_class.visibility).await?;
+        Mirror::get_collection().await
+            .insert_one(
+                Mirror {
+                    hub_server: hub,
+                    hub_role: hub_class.role,
+                    server_id: guild.id,
+                    role: class.role,
+                },
+                None,
+            )
+            .await?;
+
+        ctx.say(format!("Mirrored \"{}\" from the hub server.", class.name)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn unmirror(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let deleted = Mirror::get_collection().await
+            .delete_one(doc! { "role": class.id.to_string() }, None)
+            .await?
+            .deleted_count;
+        if deleted == 0 {
+            Err(ClassError::InvalidClass)?;
+        }
+
+        ctx.say(format!("\"{}\" is no longer synced with the hub server.", class.name)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn list(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let mut names = Vec::new();
+        for mirror in Mirror::scoped(server_id).await
+            .find(None, None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+        {
+            if let Some(class) = Class::find_by_role(mirror.role).await? {
+                names.push(class.name);
+            }
+        }
+
+        if names.is_empty() {
+            ctx.say("No classes are mirrored from a hub server.").await?;
+        } else {
+            ctx.say(format!("Mirrored classes: {}", names.join(", "))).await?;
+        }
+
+        Ok(())
+    }
+}
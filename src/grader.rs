@@ -0,0 +1,197 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use base64::Engine;
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{AUTHORIZATION, CONTENT_LENGTH};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use ring::digest::{digest, SHA256};
+use serde::Deserialize;
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::id::{RoleId, UserId};
+use serenity::prelude::Mentionable;
+
+use crate::classes::Class;
+use crate::mentions::{self, Pings};
+use crate::redact::log_error;
+use crate::{ClassError, ENV};
+
+/// Used when a class hasn't set its own template for DMing a student their result.
+pub(crate) const DEFAULT_DM_TEMPLATE: &str =
+    "Your result for **{assignment}** in {class}: **{score}**\n{details}";
+
+/// Used when a class hasn't set its own template for the summary posted when a student can't be
+/// DMed. Only `{student}`, `{assignment}` and `{class}` are filled in, so results stay private.
+pub(crate) const DEFAULT_SUMMARY_TEMPLATE: &str =
+    "{student}, your result for **{assignment}** is ready, but it couldn't be sent to you. Turn on \
+    DMs from server members and ask the class staff to resend it.";
+
+/// The largest result body accepted, which is far more than a result needs.
+pub(crate) const MAX_BODY: usize = 64 * 1024;
+
+/// A result posted by an autograder or CI job.
+#[derive(Deserialize)]
+struct GraderResult {
+    class: RoleId,
+    student: UserId,
+    assignment: String,
+    score: Option<f64>,
+    max_score: Option<f64>,
+    #[serde(default)]
+    details: String,
+}
+
+pub(crate) enum RelayError {
+    Unauthorized,
+    BadRequest(String),
+    TooLarge,
+    NotFound(&'static str),
+    Internal(ClassError),
+}
+
+impl From<ClassError> for RelayError {
+    fn from(e: ClassError) -> Self {
+        Self::Internal(e)
+    }
+}
+
+/// Make a new grader token. Only its hash is stored, so it can't be shown again later.
+pub(crate) fn new_token() -> (String, String) {
+    let token = BASE64.encode(rand::random::<[u8; 32]>());
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+fn hash_token(token: &str) -> String {
+    digest(&SHA256, token.as_bytes()).as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Read a request body, giving up as soon as it's longer than `limit`.
+pub(crate) async fn read_limited(mut body: Body, limit: usize) -> Result<Bytes, RelayError> {
+    let mut read = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| RelayError::BadRequest(e.to_string()))?;
+        if read.len() + chunk.len() > limit {
+            return Err(RelayError::TooLarge);
+        }
+        read.extend_from_slice(&chunk);
+    }
+
+    Ok(read.into())
+}
+
+fn render(template: &str, class: &Class, result: &GraderResult) -> String {
+    let score = match (result.score, result.max_score) {
+        (Some(score), Some(max)) => format!("{}/{}", score, max),
+        (Some(score), None) => score.to_string(),
+        (None, _) => "no score".to_string(),
+    };
+
+    template
+        .replace("{student}", &result.student.mention().to_string())
+        .replace("{class}", &class.name)
+        .replace("{assignment}", &result.assignment)
+        .replace("{score}", &score)
+        .replace("{details}", &result.details)
+}
+
+/// DM the student their result, or post a summary without it to the class channel if they can't
+/// be DMed. Returns where the result went.
+async fn relay(ctx: &SContext, request: Request<Body>) -> Result<&'static str, RelayError> {
+    let token = request.headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .ok_or(RelayError::Unauthorized)?;
+    // Checked before the body is read, so unauthenticated requests can't make the bot buffer
+    // anything
+    let class = Class::find_by_grader_token(&hash_token(token)).await?.ok_or(RelayError::Unauthorized)?;
+
+    let length = request.headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.parse::<usize>().ok());
+    if length.is_some_and(|l| l > MAX_BODY) {
+        return Err(RelayError::TooLarge);
+    }
+    let body = read_limited(request.into_body(), MAX_BODY).await?;
+    let result = serde_json::from_slice::<GraderResult>(&body)
+        .map_err(|e| RelayError::BadRequest(e.to_string()))?;
+
+    // Other classes are reported the same as a wrong token, so tokens can't be used to probe
+    // which roles are classes
+    if result.class != class.role {
+        return Err(RelayError::Unauthorized);
+    }
+
+    let member = class.server_id.member(ctx, result.student).await
+        .map_err(|_| RelayError::NotFound("The student is not in the server."))?;
+    if !member.roles.contains(&class.role) {
+        return Err(RelayError::NotFound("The student is not in the class."));
+    }
+
+    let dm = render(class.grader_dm_template.as_deref().unwrap_or(DEFAULT_DM_TEMPLATE), &class, &result);
+    let sent = match member.user.create_dm_channel(ctx).await {
+        Ok(channel) => channel.say(ctx.http(), dm).await.is_ok(),
+        Err(_) => false,
+    };
+    if sent {
+        return Ok("dm");
+    }
+
+    let channel = *class.text_channels.first().ok_or(ClassError::NoClassChannels)?;
+    let student = result.student;
+    let summary = render(
+        class.grader_summary_template.as_deref().unwrap_or(DEFAULT_SUMMARY_TEMPLATE),
+        &class,
+        &GraderResult { score: None, max_score: None, details: String::new(), ..result },
+    );
+    mentions::send(ctx, channel, class.role, summary, Pings::users([student])).await?;
+
+    Ok("channel")
+}
+
+async fn handle(ctx: SContext, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let (status, body) = if request.method() != Method::POST || request.uri().path() != "/results" {
+        (StatusCode::NOT_FOUND, "Not found.".to_string())
+    } else {
+        match relay(&ctx, request).await {
+            Ok(delivered) => (StatusCode::OK, format!("Delivered by {}.", delivered)),
+            Err(RelayError::Unauthorized) => (StatusCode::UNAUTHORIZED, "Invalid class or token.".to_string()),
+            Err(RelayError::BadRequest(e)) => (StatusCode::BAD_REQUEST, e),
+            Err(RelayError::TooLarge) => (StatusCode::PAYLOAD_TOO_LARGE, "The result is too large.".to_string()),
+            Err(RelayError::NotFound(e)) => (StatusCode::NOT_FOUND, e.to_string()),
+            Err(RelayError::Internal(e)) => {
+                log_error!("Error relaying grader result: {:?}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Could not deliver the result.".to_string())
+            }
+        }
+    };
+
+    // Unwrapping because the status and body are always valid
+    Ok(Response::builder().status(status).body(Body::from(body)).unwrap())
+}
+
+/// Listen for grader results on `GRADER_PORT`, if it is set. Graders `POST /results` with a JSON
+/// result and `Authorization: Bearer <token>`, using the token from `/admin grader`.
+pub(crate) fn start(ctx: SContext) {
+    let port = match ENV.grader_port {
+        Some(p) => p,
+        None => return,
+    };
+
+    tokio::spawn(async move {
+        let make_service = make_service_fn(move |_| {
+            let ctx = ctx.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |request| handle(ctx.clone(), request))) }
+        });
+
+        let address = SocketAddr::from(([0, 0, 0, 0], port));
+        if let Err(e) = hyper::Server::bind(&address).serve(make_service).await {
+            log_error!("Error running grader endpoint: {:?}", e);
+        }
+    });
+}
@@ -0,0 +1,180 @@
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::options::UpdateOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::{Context as SContext, EventHandler};
+use serenity::http::{CacheHttp, StatusCode};
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::prelude::Mentionable;
+use tokio::sync::OnceCell;
+
+use crate::classes::Class;
+use crate::history::{self, EnrollmentMechanism};
+use crate::jobs::JobStatus;
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, rolequeue, set_all, ClassResult, ENV};
+
+/// How long to wait between members, on top of Discord's own rate limits, so a large cohort
+/// doesn't hold up members enrolling through class menus.
+const MEMBER_DELAY: Duration = Duration::from_millis(500);
+
+/// A role for a user who wasn't in the server when it was granted, given to them when they join.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PendingGrant {
+    server_id: GuildId,
+    user: UserId,
+    role: RoleId,
+    granted_by: UserId,
+}
+
+impl PendingGrant {
+    /// Remember the grant until the user joins. Granting the same role twice keeps one.
+    async fn save(&self) -> ClassResult<()> {
+        Self::scoped(self.server_id).await
+            .update_one(
+                doc! { "user": self.user.to_string(), "role": self.role.to_string() },
+                set_all(self)?,
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static PENDING_GRANTS: OnceCell<Collection<PendingGrant>> = OnceCell::const_new();
+
+        PENDING_GRANTS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("pending_grants")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Read user IDs from a CSV file. Every field that is an ID counts, so headers and other columns
+/// such as names are skipped. Duplicates are dropped.
+pub(crate) fn parse_users(csv: &str) -> Vec<UserId> {
+    let mut users = Vec::new();
+    for field in csv.split([',', '\n', '\r', '\t', ';']) {
+        let field = field.trim().trim_matches('"').trim_start_matches("<@").trim_start_matches('!').trim_end_matches('>');
+        if let Ok(id) = field.parse::<u64>() {
+            if !users.contains(&UserId(id)) {
+                users.push(UserId(id));
+            }
+        }
+    }
+    users
+}
+
+/// Give a role to every user, for a `GrantRole` job. Users who already have it are skipped, so
+/// the job can be run again after an interruption. Returns a summary of what was done.
+pub(crate) async fn run(
+    ctx: &SContext,
+    server_id: GuildId,
+    role: RoleId,
+    users: &[UserId],
+    granted_by: UserId,
+    status: &JobStatus,
+) -> ClassResult<String> {
+    let is_class = Class::find_by_role(role).await?.is_some();
+    let (mut granted, mut pending, mut failed) = (0, 0, 0);
+
+    for (i, &user) in users.iter().enumerate() {
+        match ctx.http.get_member(server_id.0, user.0).await {
+            Ok(member) if member.roles.contains(&role) => granted += 1,
+            Ok(_) => {
+                let applied = rolequeue::apply(ctx.http(), server_id, user, &[role], &[], "Bulk grant").await;
+                if applied.error.is_some() {
+                    failed += 1;
+                } else {
+                    granted += 1;
+                    if is_class {
+                        history::enrolled(server_id, user, granted_by, EnrollmentMechanism::BulkGrant, applied.added, Vec::new())
+                            .await;
+                    }
+                }
+            }
+            Err(serenity::Error::Http(e)) if e.status_code() == Some(StatusCode::NOT_FOUND) => {
+                PendingGrant { server_id, user, role, granted_by }.save().await?;
+                pending += 1;
+            }
+            Err(_) => failed += 1,
+        }
+
+        status.progress(ctx, &format!(
+            "{} of {} users handled, {} granted, {} not in the server yet.",
+            i + 1,
+            users.len(),
+            granted,
+            pending,
+        )).await?;
+        tokio::time::sleep(MEMBER_DELAY).await;
+    }
+
+    let mut summary = format!("Granted {} to {} of {} users.", role.mention(), granted, users.len());
+    if pending > 0 {
+        summary += &format!(" {} aren't in the server yet, and will get the role when they join.", pending);
+    }
+    if failed > 0 {
+        summary += &format!(" {} failed.", failed);
+    }
+    Ok(summary)
+}
+
+/// Gives users the roles they were granted before they joined the server.
+pub(crate) struct PendingGrantHandler;
+
+#[async_trait]
+impl EventHandler for PendingGrantHandler {
+    async fn guild_member_addition(&self, ctx: SContext, new_member: Member) {
+        if let Err(e) = grant_pending(&ctx, &new_member).await {
+            log_error!("Error granting pending roles: {:?}", e);
+        }
+    }
+}
+
+async fn grant_pending(ctx: &SContext, member: &Member) -> ClassResult<()> {
+    let collection = PendingGrant::scoped(member.guild_id).await;
+    let grants = collection
+        .find(doc! { "user": member.user.id.to_string() }, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    for grant in grants {
+        let applied = rolequeue::apply(ctx.http(), member.guild_id, member.user.id, &[grant.role], &[], "Bulk grant").await;
+        if let Some(e) = applied.error {
+            return Err(e);
+        }
+        if Class::find_by_role(grant.role).await?.is_some() {
+            history::enrolled(
+                member.guild_id,
+                member.user.id,
+                grant.granted_by,
+                EnrollmentMechanism::BulkGrant,
+                applied.added,
+                Vec::new(),
+            ).await;
+        }
+        collection
+            .delete_one(doc! { "user": member.user.id.to_string(), "role": grant.role.to_string() }, None)
+            .await?;
+    }
+
+    Ok(())
+}
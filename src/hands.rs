@@ -0,0 +1,245 @@
+use mongodb::bson::{doc, Document};
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::builder::CreateEmbed;
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+use serenity::model::voice::VoiceState;
+use serenity::prelude::{EventHandler, Mentionable};
+use tokio::sync::OnceCell;
+
+use crate::classes::Class;
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::sessions::StudySession;
+use crate::{get_conn, is_class_staff, ClassError, ClassResult, Context, Error, ENV};
+
+/// The members waiting to ask a question in a class voice channel, in the order they raised their
+/// hands, shown in a live embed in the channel's text chat.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct HandQueue {
+    server_id: GuildId,
+    role: RoleId,
+    channel: ChannelId,
+    queue: Vec<UserId>,
+    /// Who the host last called on.
+    current: Option<UserId>,
+    message: Option<MessageId>,
+}
+
+impl HandQueue {
+    fn render<'a>(&self, e: &'a mut CreateEmbed) -> &'a mut CreateEmbed {
+        e.title("Raised hands")
+            .description(if self.queue.is_empty() {
+                "Nobody has their hand raised. Use `/hand raise` to ask a question.".to_string()
+            } else {
+                self.queue.iter()
+                    .enumerate()
+                    .map(|(i, u)| format!("{}. {}", i + 1, u.mention()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+        if let Some(current) = self.current {
+            e.field("Speaking", current.mention(), false);
+        }
+        e
+    }
+
+    async fn update(channel: ChannelId, update: Document) -> ClassResult<Option<HandQueue>> {
+        Ok(
+            Self::get_collection().await
+                .find_one_and_update(
+                    doc! { "channel": channel.to_string() },
+                    update,
+                    FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build(),
+                )
+                .await?
+        )
+    }
+
+    /// Edit the queue's embed to match, posting a new one if it hasn't been posted or was deleted.
+    async fn refresh(&mut self, cache_http: impl CacheHttp) -> ClassResult<()> {
+        if let Some(message) = self.message {
+            if self.channel.edit_message(cache_http.http(), message, |m| m.embed(|e| self.render(e))).await.is_ok() {
+                return Ok(());
+            }
+        }
+
+        let message = self.channel.send_message(cache_http.http(), |m| m.embed(|e| self.render(e))).await?;
+        self.message = Some(message.id);
+        Self::get_collection().await
+            .update_one(
+                doc! { "channel": self.channel.to_string() },
+                doc! { "$set": { "message": message.id.to_string() } },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static HAND_QUEUES: OnceCell<Collection<HandQueue>> = OnceCell::const_new();
+
+        HAND_QUEUES
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("hand_queues")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// The class voice channel the author is in, along with its class and the host of the
+/// study session using it, if it's a session's temporary channel.
+async fn author_voice_channel(ctx: Context<'_>) -> ClassResult<(ChannelId, Class, Option<UserId>)> {
+    let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+    // Voice states only come from the gateway, so they can't be fetched for an uncached server
+    let channel = ctx.discord().cache
+        .guild_field(server_id, |g| g.voice_states.get(&ctx.author().id).and_then(|v| v.channel_id))
+        .ok_or(ClassError::NotCached)?
+        .ok_or(ClassError::NotInClassVoice)?;
+
+    if let Some(class) = Class::find_by_voice_channel(channel).await? {
+        return Ok((channel, class, None));
+    }
+    match StudySession::in_voice_channel(channel).await? {
+        Some((role, host)) => {
+            let class = Class::find_by_role(role).await?.ok_or(ClassError::NotInClassVoice)?;
+            Ok((channel, class, Some(host)))
+        }
+        None => Err(ClassError::NotInClassVoice),
+    }
+}
+
+/// Takes members out of the queue when they leave the voice channel, so the host isn't left
+/// calling on people who have gone.
+pub(crate) struct HandQueueHandler;
+
+#[async_trait]
+impl EventHandler for HandQueueHandler {
+    async fn voice_state_update(&self, ctx: SContext, old: Option<VoiceState>, new: VoiceState) {
+        let left = match old.and_then(|o| o.channel_id) {
+            Some(channel) if new.channel_id != Some(channel) => channel,
+            _ => return,
+        };
+
+        let update = doc! { "$pull": { "queue": new.user_id.to_string() } };
+        match HandQueue::update(left, update).await {
+            Ok(Some(mut queue)) if queue.message.is_some() => {
+                if let Err(e) = queue.refresh(&ctx).await {
+                    log_error!("Error updating raised hands: {:?}", e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => log_error!("Error updating raised hands: {:?}", e),
+        }
+    }
+}
+
+#[poise::command(slash_command, subcommands("HandCommand::raise", "HandCommand::lower", "HandCommand::next"))]
+pub(crate) async fn hand(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct HandCommand;
+impl HandCommand {
+    /// Join the queue to ask a question in the class voice channel you are in.
+    #[poise::command(slash_command, ephemeral)]
+    async fn raise(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let (channel, class, _) = author_voice_channel(ctx).await?;
+        let user = ctx.author().id;
+        // Unwrapping because upserting always returns the document
+        let mut queue = HandQueue::scoped(class.server_id).await
+            .find_one_and_update(
+                doc! { "channel": channel.to_string() },
+                doc! {
+                    "$setOnInsert": {
+                        "role": class.role.to_string(),
+                        "current": null,
+                        "message": null,
+                    },
+                    "$addToSet": { "queue": user.to_string() },
+                },
+                FindOneAndUpdateOptions::builder().upsert(true).return_document(ReturnDocument::After).build(),
+            )
+            .await?
+            .unwrap();
+        queue.refresh(ctx.discord()).await?;
+
+        // Unwrapping because the user was just added
+        let position = queue.queue.iter().position(|u| *u == user).unwrap() + 1;
+        ctx.say(format!("Your hand is raised. You are number {} in the queue.", position)).await?;
+
+        Ok(())
+    }
+
+    /// Leave the queue of raised hands.
+    #[poise::command(slash_command, ephemeral)]
+    async fn lower(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let (channel, _, _) = author_voice_channel(ctx).await?;
+        let user = ctx.author().id;
+        let mut queue = HandQueue::get_collection().await
+            .find_one_and_update(
+                doc! { "channel": channel.to_string(), "queue": user.to_string() },
+                doc! { "$pull": { "queue": user.to_string() } },
+                FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build(),
+            )
+            .await?
+            .ok_or(ClassError::HandNotRaised)?;
+        queue.refresh(ctx.discord()).await?;
+
+        ctx.say("Your hand is lowered.").await?;
+
+        Ok(())
+    }
+
+    /// Call on the next member in the queue. Only for class staff and the session host.
+    #[poise::command(slash_command, ephemeral)]
+    async fn next(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let (channel, class, host) = author_voice_channel(ctx).await?;
+        if host != Some(ctx.author().id) && !is_class_staff(ctx, &class).await {
+            Err(ClassError::MissingPermissions)?;
+        }
+
+        let queue = HandQueue::get_collection().await
+            .find_one(doc! { "channel": channel.to_string() }, None)
+            .await?;
+        let next = queue.and_then(|q| q.queue.first().copied()).ok_or(ClassError::NoRaisedHands)?;
+        let mut queue = HandQueue::get_collection().await
+            .find_one_and_update(
+                // Only pops if nobody else advanced the queue in the meantime
+                doc! { "channel": channel.to_string(), "queue.0": next.to_string() },
+                doc! { "$pop": { "queue": -1 }, "$set": { "current": next.to_string() } },
+                FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build(),
+            )
+            .await?
+            .ok_or(ClassError::NoRaisedHands)?;
+        queue.refresh(ctx.discord()).await?;
+
+        channel
+            .send_message(ctx.discord(), |m| m
+                .content(format!("{}, it's your turn to ask your question.", next.mention()))
+                .allowed_mentions(|a| a.empty_parse().users([next]))
+            )
+            .await?;
+        ctx.say(format!("Called on {}. {} still waiting.", next.mention(), queue.queue.len())).await?;
+
+        Ok(())
+    }
+}
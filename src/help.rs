@@ -0,0 +1,113 @@
+use serenity::async_trait;
+use serenity::builder::CreateActionRow;
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::application::interaction::{Interaction, InteractionResponseType};
+use serenity::model::prelude::component::{ButtonStyle, ComponentType};
+use serenity::prelude::EventHandler;
+
+use crate::redact::log_error;
+use crate::{ClassError, Context};
+
+/// A page of setup help, linked from errors that someone has to change a setting to fix.
+pub(crate) struct HelpTopic {
+    pub(crate) id: &'static str,
+    pub(crate) title: &'static str,
+    pub(crate) body: &'static str,
+}
+
+pub(crate) const TOPICS: [HelpTopic; 2] = [
+    HelpTopic {
+        id: "refrole",
+        title: "Setting the refrole",
+        body: "New class roles are created just below the server's **refrole**, so that the bot can manage \
+            them and they sit together in the role list.\n\n\
+            1. Create a role for the bot to place class roles under, or pick an existing one.\n\
+            2. Make sure the bot's own role is above it in **Server Settings → Roles**.\n\
+            3. Run `/config refrole set role:<role>`. This needs the Manage Server permission.\n\n\
+            If the refrole was deleted, set a new one the same way.",
+    },
+    HelpTopic {
+        id: "staffchannel",
+        title: "Setting the staff channel",
+        body: "Class requests, digests and other messages for staff are posted to the server's **staff \
+            channel**.\n\n\
+            1. Pick a text channel that only staff can see.\n\
+            2. Make sure the bot can view and send messages in it.\n\
+            3. Run `/config staffchannel set channel:<channel>`. This needs the Manage Server permission.",
+    },
+];
+
+pub(crate) fn topic(id: &str) -> Option<&'static HelpTopic> {
+    TOPICS.iter().find(|t| t.id == id)
+}
+
+impl ClassError {
+    /// The help page explaining how to fix the error, for errors that are fixed by changing a
+    /// setting rather than by trying again.
+    pub(crate) fn remediation(&self) -> Option<&'static HelpTopic> {
+        match self {
+            ClassError::NoRefrole | ClassError::InvalidRefrole => topic("refrole"),
+            ClassError::NoStaffChannel => topic("staffchannel"),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn help_button<'a>(r: &'a mut CreateActionRow, topic: &HelpTopic) -> &'a mut CreateActionRow {
+    r.create_button(|b| b
+        .custom_id(format!("help:{}", topic.id))
+        .style(ButtonStyle::Secondary)
+        .label(format!("How to fix: {}", topic.title))
+    )
+}
+
+/// Reply to a failed command with its error and a button that shows how to fix it. Returns
+/// whether the error had a fix to show, so other errors can be handled as usual.
+pub(crate) async fn reply_with_remediation(ctx: Context<'_>, error: &ClassError) -> bool {
+    let topic = match error.remediation() {
+        Some(t) => t,
+        None => return false,
+    };
+
+    if let Err(e) = ctx.send(|m| m
+        .ephemeral(true)
+        .content(error.to_string())
+        .components(|c| c.create_action_row(|r| help_button(r, topic)))
+    ).await {
+        log_error!("Error replying with remediation: {:?}", e);
+        return false;
+    }
+    true
+}
+
+/// Shows help pages when their buttons are pressed.
+pub(crate) struct HelpButtonHandler;
+
+#[async_trait]
+impl EventHandler for HelpButtonHandler {
+    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
+        let component = if let Interaction::MessageComponent(c) = interaction {
+            c
+        } else {
+            return;
+        };
+        if component.data.component_type != ComponentType::Button {
+            return;
+        }
+        let topic = match component.data.custom_id.strip_prefix("help:").and_then(topic) {
+            Some(t) => t,
+            None => return,
+        };
+
+        if let Err(e) = component.create_interaction_response(ctx.http(), |r| r
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|d| d
+                .ephemeral(true)
+                .embed(|e| e.title(topic.title).description(topic.body))
+            )
+        ).await {
+            log_error!("Error handling {}: {:?}", component.data.custom_id, e);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+use futures::TryStreamExt;
+use itertools::Itertools;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument, UpdateOptions};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::Context as SContext;
+use serenity::model::channel::{ChannelType, GuildChannel, Message};
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::prelude::{EventHandler, Mentionable};
+use tokio::sync::OnceCell;
+
+use crate::classes::{Class, Server};
+use crate::faq::similarity;
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, is_class_staff, ClassError, ClassResult, Context, Error, ENV};
+
+/// Prefixed to the name of a thread once it is marked solved.
+const SOLVED_PREFIX: &str = "✅ ";
+/// How far back to look for questions related to a new one.
+const RELATED_DAYS: i64 = 120;
+/// The most related questions linked from a new one.
+const RELATED_LIMIT: usize = 3;
+
+/// A question asked in a thread in a class's homework-help channel.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct HelpThread {
+    server_id: GuildId,
+    role: RoleId,
+    thread_id: ChannelId,
+    asker: UserId,
+    created_at: DateTime,
+    /// When someone other than the asker first posted in the thread.
+    #[serde(default)]
+    first_response_at: Option<DateTime>,
+    /// Everyone other than the asker who posted in the thread.
+    #[serde(default)]
+    answerers: Vec<UserId>,
+    #[serde(default)]
+    solved_at: Option<DateTime>,
+    /// The thread's name and first message, compared against new questions.
+    #[serde(default)]
+    question: String,
+}
+
+/// How a class's homework-help threads have gone over a period.
+pub(crate) struct HelpStats {
+    pub(crate) solved: usize,
+    pub(crate) open: usize,
+    /// The median time for a question to get its first response, in seconds.
+    pub(crate) median_response: Option<i64>,
+    /// The members who answered the most threads, and how many, most first.
+    pub(crate) top_answerers: Vec<(UserId, usize)>,
+}
+
+impl HelpThread {
+    /// Statistics for the threads in a class created since the given time.
+    pub(crate) async fn stats(role: RoleId, since: DateTime) -> ClassResult<HelpStats> {
+        let threads = Self::get_collection().await
+            .find(doc! { "role": role.to_string(), "created_at": { "$gte": since } }, None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let solved = threads.iter().filter(|t| t.solved_at.is_some()).count();
+
+        let mut response_times = threads.iter()
+            .filter_map(|t| t.first_response_at.map(|r| (r.timestamp_millis() - t.created_at.timestamp_millis()) / 1000))
+            .collect::<Vec<_>>();
+        response_times.sort_unstable();
+        let median_response = response_times.get(response_times.len() / 2).copied();
+
+        let mut answered = HashMap::<UserId, usize>::new();
+        for answerer in threads.iter().flat_map(|t| &t.answerers) {
+            *answered.entry(*answerer).or_default() += 1;
+        }
+        let mut top_answerers = answered.into_iter().collect::<Vec<_>>();
+        top_answerers.sort_by(|(_, a), (_, b)| b.cmp(a));
+        top_answerers.truncate(5);
+
+        Ok(HelpStats { solved, open: threads.len() - solved, median_response, top_answerers })
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static THREADS: OnceCell<Collection<HelpThread>> = OnceCell::const_new();
+
+        THREADS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("help_threads")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// The class a thread's homework-help channel belongs to, if it is in one.
+async fn help_class(ctx: &SContext, thread: &GuildChannel) -> ClassResult<Option<Class>> {
+    if !matches!(thread.kind, ChannelType::PublicThread | ChannelType::PrivateThread) {
+        return Ok(None);
+    }
+    let parent = match thread.parent_id {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+    let is_homework_help = ctx.cache
+        .guild_channel_field(parent, |c| c.name.starts_with("homework-help"))
+        .unwrap_or(false);
+    if !is_homework_help {
+        return Ok(None);
+    }
+
+    Ok(Class::list(thread.guild_id).await?.into_iter().find(|c| c.text_channels.contains(&parent)))
+}
+
+fn new_thread(class: &Class, thread: ChannelId, question: &str) -> mongodb::bson::Document {
+    doc! {
+        "role": class.role.to_string(),
+        "thread_id": thread.to_string(),
+        "created_at": DateTime::now(),
+        "answerers": [],
+        "question": question,
+    }
+}
+
+/// The earlier questions at least `threshold` similar to a new one, most similar first.
+pub(crate) fn possibly_related(question: &str, earlier: &[(ChannelId, String)], threshold: f64) -> Vec<ChannelId> {
+    let mut related = earlier.iter()
+        .map(|(thread, q)| (*thread, similarity(question, q)))
+        .filter(|(_, s)| *s >= threshold)
+        .collect::<Vec<_>>();
+    related.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    related.into_iter().take(RELATED_LIMIT).map(|(t, _)| t).collect()
+}
+
+/// Reply to a new question with links to recent ones like it, for servers that have turned it on.
+/// Says nothing if there aren't any.
+async fn hint_related(ctx: &SContext, class: &Class, thread: ChannelId, question: &str) -> ClassResult<()> {
+    let threshold = match Server::get_or_create(class.server_id).await?.dedup_threshold {
+        Some(t) => t as f64 / 100.0,
+        None => return Ok(()),
+    };
+
+    let since = Utc::now() - Duration::days(RELATED_DAYS);
+    let earlier = HelpThread::get_collection().await
+        .find(
+            doc! {
+                "role": class.role.to_string(),
+                "thread_id": { "$ne": thread.to_string() },
+                "created_at": { "$gte": DateTime::from_millis(since.timestamp_millis()) },
+            },
+            None,
+        )
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?
+        .into_iter()
+        .map(|t| (t.thread_id, t.question))
+        .collect::<Vec<_>>();
+
+    let related = possibly_related(question, &earlier, threshold);
+    if related.is_empty() {
+        return Ok(());
+    }
+    thread
+        .say(&ctx.http, format!(
+            "🔎 Possibly related questions:\n{}",
+            related.iter().map(|t| format!("• {}", t.mention())).join("\n"),
+        ))
+        .await?;
+
+    Ok(())
+}
+
+/// Start tracking a new thread. A thread started from a message was asked by that message's
+/// author, otherwise the asker is whoever posts in it first.
+async fn track_thread(ctx: &SContext, thread: &GuildChannel) -> ClassResult<()> {
+    let class = match help_class(ctx, thread).await? {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+    let parent = match thread.parent_id {
+        Some(p) => p,
+        None => return Ok(()),
+    };
+    // A thread started from a message shares its ID
+    let starter = match parent.message(&ctx.http, thread.id.0).await {
+        Ok(starter) => starter,
+        Err(_) => return Ok(()),
+    };
+    let question = format!("{}\n{}", thread.name, starter.content);
+
+    let inserted = HelpThread::scoped(class.server_id).await
+        .update_one(
+            doc! { "thread_id": thread.id.to_string() },
+            doc! {
+                "$set": { "asker": starter.author.id.to_string() },
+                "$setOnInsert": new_thread(&class, thread.id, &question),
+            },
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await?
+        .upserted_id
+        .is_some();
+
+    if inserted {
+        hint_related(ctx, &class, thread.id, &question).await?;
+    }
+
+    Ok(())
+}
+
+async fn track_message(ctx: &SContext, message: &Message) -> ClassResult<()> {
+    if message.author.bot {
+        return Ok(());
+    }
+    let thread = match ctx.cache.guild_channel(message.channel_id) {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+    let class = match help_class(ctx, &thread).await? {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+
+    let question = format!("{}\n{}", thread.name, message.content);
+    let mut insert = new_thread(&class, thread.id, &question);
+    insert.insert("asker", message.author.id.to_string());
+    // Nothing is returned if this message started tracking the thread
+    let tracked = HelpThread::scoped(class.server_id).await
+        .find_one_and_update(
+            doc! { "thread_id": thread.id.to_string() },
+            doc! { "$setOnInsert": insert },
+            FindOneAndUpdateOptions::builder().upsert(true).return_document(ReturnDocument::Before).build(),
+        )
+        .await?;
+
+    let tracked = match tracked {
+        Some(t) => t,
+        None => return hint_related(ctx, &class, thread.id, &question).await,
+    };
+    if tracked.asker != message.author.id {
+        HelpThread::get_collection().await
+            .update_one(
+                doc! { "thread_id": thread.id.to_string() },
+                doc! {
+                    "$min": { "first_response_at": DateTime::now() },
+                    "$addToSet": { "answerers": message.author.id.to_string() },
+                },
+                None,
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub(crate) struct HelpThreadHandler;
+
+#[async_trait]
+impl EventHandler for HelpThreadHandler {
+    async fn thread_create(&self, ctx: SContext, thread: GuildChannel) {
+        if let Err(e) = track_thread(&ctx, &thread).await {
+            log_error!("Error tracking help thread {}: {:?}", thread.id, e);
+        }
+    }
+
+    async fn message(&self, ctx: SContext, message: Message) {
+        if let Err(e) = track_message(&ctx, &message).await {
+            log_error!("Error tracking help thread {}: {:?}", message.channel_id, e);
+        }
+    }
+}
+
+/// Mark the homework-help thread this is run in as solved.
+#[poise::command(slash_command, ephemeral)]
+pub(crate) async fn solved(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let thread = HelpThread::get_collection().await
+        .find_one(doc! { "thread_id": ctx.channel_id().to_string() }, None)
+        .await?
+        .ok_or(ClassError::NotHelpThread)?;
+    let class = Class::find_by_role(thread.role).await?.ok_or(ClassError::InvalidClass)?;
+    if thread.asker != ctx.author().id && !is_class_staff(ctx, &class).await {
+        Err(ClassError::MissingPermissions)?;
+    }
+
+    HelpThread::get_collection().await
+        .update_one(
+            doc! { "thread_id": ctx.channel_id().to_string() },
+            doc! { "$min": { "solved_at": DateTime::now() } },
+            None,
+        )
+        .await?;
+
+    let name = ctx.discord().cache.guild_channel_field(ctx.channel_id(), |c| c.name.clone());
+    if let Some(name) = name.filter(|n| !n.starts_with(SOLVED_PREFIX)) {
+        ctx.channel_id()
+            .edit_thread(ctx.discord(), |t| t.name(format!("{}{}", SOLVED_PREFIX, name)))
+            .await?;
+    }
+
+    ctx.say("Marked this question as solved.").await?;
+
+    Ok(())
+}
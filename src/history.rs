@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, RoleId, UserId};
+use tokio::sync::OnceCell;
+
+use crate::events::{self, BotEvent};
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::terms::Term;
+use crate::{get_conn, ClassResult, ENV};
+
+/// How the bot came to add or remove a class role.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EnrollmentMechanism {
+    Menu,
+    Button,
+    Invite,
+    TermExpiry,
+    Federation,
+    BulkGrant,
+}
+
+impl EnrollmentMechanism {
+    pub(crate) fn describe(&self) -> &'static str {
+        match self {
+            Self::Menu => "class menu",
+            Self::Button => "enrollment button",
+            Self::Invite => "class invite",
+            Self::TermExpiry => "term expiry",
+            Self::Federation => "federation",
+            Self::BulkGrant => "bulk grant",
+        }
+    }
+}
+
+/// A single class role being added to or removed from a member.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct EnrollmentEvent {
+    server_id: GuildId,
+    pub(crate) user: UserId,
+    pub(crate) role: RoleId,
+    pub(crate) joined: bool,
+    pub(crate) at: DateTime,
+    /// The server's active term when the change happened.
+    #[serde(default)]
+    term: Option<String>,
+    /// Who caused the change. This is the bot itself for automatic changes, and missing on events
+    /// recorded before it was tracked.
+    #[serde(default)]
+    pub(crate) actor: Option<UserId>,
+    #[serde(default)]
+    pub(crate) mechanism: Option<EnrollmentMechanism>,
+}
+
+impl EnrollmentEvent {
+    /// Whether the member held the role at some point and has since left it.
+    pub(crate) async fn has_completed(server_id: GuildId, user: UserId, role: RoleId) -> ClassResult<bool> {
+        Ok(
+            Self::scoped(server_id).await
+                .find_one(
+                    doc! {
+                        "user": user.to_string(),
+                        "role": role.to_string(),
+                        "joined": false,
+                    },
+                    None,
+                )
+                .await?
+                .is_some()
+        )
+    }
+
+    /// Whether the member held the role during a term other than the active one.
+    pub(crate) async fn held_in_earlier_term(
+        server_id: GuildId,
+        user: UserId,
+        role: RoleId,
+        active_term: Option<&str>,
+    ) -> ClassResult<bool> {
+        Ok(
+            Self::scoped(server_id).await
+                .find_one(
+                    doc! {
+                        "user": user.to_string(),
+                        "role": role.to_string(),
+                        "joined": true,
+                        "term": { "$nin": [null, active_term] },
+                    },
+                    None,
+                )
+                .await?
+                .is_some()
+        )
+    }
+
+    /// How many members joined and left each class in a server since the given time.
+    pub(crate) async fn counts_since(server_id: GuildId, since: DateTime) -> ClassResult<HashMap<RoleId, (u64, u64)>> {
+        let events = Self::scoped(server_id).await
+            .find(doc! { "at": { "$gte": since } }, None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut counts = HashMap::<RoleId, (u64, u64)>::new();
+        for event in events {
+            let (joined, left) = counts.entry(event.role).or_default();
+            if event.joined {
+                *joined += 1;
+            } else {
+                *left += 1;
+            }
+        }
+
+        Ok(counts)
+    }
+
+    /// Every enrollment change in a server since the given time, optionally only for one class,
+    /// oldest first.
+    pub(crate) async fn since(server_id: GuildId, role: Option<RoleId>, since: DateTime) -> ClassResult<Vec<Self>> {
+        let mut filter = doc! { "at": { "$gte": since } };
+        if let Some(role) = role {
+            filter.insert("role", role.to_string());
+        }
+
+        Ok(
+            Self::scoped(server_id).await
+                .find(filter, FindOptions::builder().sort(doc! { "at": 1 }).build())
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    /// The most recent enrollment changes in a server, optionally only for one member or class.
+    pub(crate) async fn recent(
+        server_id: GuildId,
+        user: Option<UserId>,
+        role: Option<RoleId>,
+        limit: i64,
+    ) -> ClassResult<Vec<Self>> {
+        let mut filter = doc! {};
+        if let Some(user) = user {
+            filter.insert("user", user.to_string());
+        }
+        if let Some(role) = role {
+            filter.insert("role", role.to_string());
+        }
+
+        Ok(
+            Self::scoped(server_id).await
+                .find(filter, FindOptions::builder().sort(doc! { "at": -1 }).limit(limit).build())
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static EVENTS: OnceCell<Collection<EnrollmentEvent>> = OnceCell::const_new();
+
+        EVENTS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("enrollment_events")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Record a member joining and leaving classes. Changes published as `MemberEnrolled` events are
+/// recorded by `record`, so this only needs calling directly for changes that aren't published.
+pub(crate) async fn log(
+    server_id: GuildId,
+    user: UserId,
+    actor: UserId,
+    mechanism: EnrollmentMechanism,
+    joined: Vec<RoleId>,
+    left: Vec<RoleId>,
+) -> ClassResult<()> {
+    let at = DateTime::now();
+    let term = match Term::active(server_id).await {
+        Ok(t) => t.map(|t| t.name),
+        Err(e) => {
+            log_error!("Error looking up term for enrollment history: {:?}", e);
+            None
+        }
+    };
+    let events = joined.into_iter()
+        .map(|role| (role, true))
+        .chain(left.into_iter().map(|role| (role, false)))
+        .map(|(role, joined)| EnrollmentEvent {
+            server_id,
+            user,
+            role,
+            joined,
+            at,
+            term: term.clone(),
+            actor: Some(actor),
+            mechanism: Some(mechanism),
+        })
+        .collect::<Vec<_>>();
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    EnrollmentEvent::get_collection().await.insert_many(events, None).await?;
+
+    Ok(())
+}
+
+/// Record an enrollment change, then publish it on the event bus. History is written here rather
+/// than by a subscriber, as the bus drops events when a subscriber falls behind and access
+/// decisions are made from history.
+pub(crate) async fn enrolled(
+    server_id: GuildId,
+    user: UserId,
+    actor: UserId,
+    mechanism: EnrollmentMechanism,
+    joined: Vec<RoleId>,
+    left: Vec<RoleId>,
+) {
+    if let Err(e) = log(server_id, user, actor, mechanism, joined.clone(), left.clone()).await {
+        log_error!("[{}] Error recording enrollment history: {:?}", server_id, e);
+    }
+
+    events::publish(BotEvent::MemberEnrolled { server_id, user_id: user, actor, mechanism, joined, left });
+}
@@ -0,0 +1,307 @@
+//! String tables for how commands appear in Discord: descriptions for parameters that don't have
+//! one in their `#[description]`, and translations of names and descriptions for servers that use
+//! another language. Anything missing from a locale's table is shown in English.
+
+use crate::{Data, Error};
+
+/// English descriptions of parameters, keyed by the command's full name and the parameter, or by
+/// the parameter alone for ones that mean the same thing everywhere. Numbered parameters like
+/// `channel2` share the description of `channel`.
+const PARAMETERS: &[(&str, &str)] = &[
+    ("class", "The class's role"),
+    ("channel", "The channel to use"),
+    ("enabled", "Whether to turn it on"),
+    ("mention", "Mention the class roles instead of just naming them"),
+    ("days", "How many days"),
+    ("url", "The URL"),
+    ("number", "The number shown on it"),
+    ("reason", "Why, shown to the member"),
+    ("template", "The template's name"),
+    ("question", "The question"),
+    ("answer", "The answer"),
+    ("title", "The title"),
+    ("content", "The text"),
+    ("query", "What to search for"),
+    ("department", "The department, like \"CS\""),
+    ("address", "The email address"),
+    ("member", "A member to add"),
+    ("name", "The name"),
+    ("admin chart enrollment period", "How far back to chart"),
+    ("admin chart enrollment class", "The class to chart, or every class if left out"),
+    ("admin grant role", "The role to give"),
+    ("admin grant-temp command", "The command, or group of commands, like \"class edit\""),
+    ("admin grant-temp duration", "How long for, like \"3d\" or \"1w 2d\", up to 30 days"),
+    ("admin grant-temp user", "The member to give access to"),
+    ("admin memberships export format", "How to lay out the CSV file"),
+    ("admin order categories by", "How to order the categories"),
+    ("admin template apply template", "A template file exported with /admin template export"),
+    ("announce message", "The announcement"),
+    ("assignment countdown channel", "The voice channel to rename, or leave out to stop"),
+    ("assignment create due", "When it's due, like \"2024-10-31 23:59\""),
+    ("assignment create title", "The assignment's title"),
+    ("assignment delete title", "The assignment's title"),
+    ("automod apply template", "The template to apply, or all of them if left out"),
+    ("automod template add keywords", "Comma separated words to block"),
+    ("automod template add mention_limit", "How many mentions a message can have"),
+    ("badges show", "Whether to show your staff badges"),
+    ("class create name", "The class's name, like \"CS 341\""),
+    ("class create visibility", "Who can see and post in the class's channels"),
+    ("class deleted author", "Only show messages by this member"),
+    ("class difference without", "The class whose members to leave out"),
+    ("class edit archive enabled", "Whether to archive the class's messages"),
+    ("class edit badge tier", "The staff tier's role, like the TA role"),
+    ("class edit description description", "Shown under the class in menus, or leave out to clear it"),
+    ("class edit staff staff_role", "The class's staff role, or leave out to clear it"),
+    ("class edit tag tags", "Comma separated tags, or leave empty to remove them all"),
+    ("class edit visibility visibility", "Who can see and post in the class's channels"),
+    ("class helpstats days", "How many days back to look"),
+    ("class history class", "Only show changes to this class"),
+    ("class history user", "Only show changes for this member"),
+    ("class icebreaker enabled", "Whether to post prompts in the class"),
+    ("class icebreaker every_days", "How many days between questions of the day"),
+    ("class intersect class1", "The first class"),
+    ("class intersect class2", "The second class"),
+    ("class invite channel", "The channel the invite leads to"),
+    ("class managers add user", "The member to make a manager"),
+    ("class managers add role", "The role whose members become managers"),
+    ("class managers remove user", "The member to remove"),
+    ("class managers remove role", "The role to remove"),
+    ("class menu post channel", "The channel to post in, or this one if left out"),
+    ("class menu post-all channel", "The channel to post in, or this one if left out"),
+    ("class request name", "The name of the class you'd like"),
+    ("class restore name", "The deleted class's name"),
+    ("class search name", "Part of a class's name"),
+    ("class search tag", "Only show classes with this tag"),
+    ("class track category", "The class's category"),
+    ("class track channel", "One of the class's channels"),
+    ("class track name", "The class's name, or the role's name if left out"),
+    ("class track role", "The class's role"),
+    ("class transfer category new_category", "The category to move the class's channels to"),
+    ("class transfer role migrate_members", "Also move members from the old role to the new one"),
+    ("class transfer role new_role", "The role the class should use"),
+    ("config archiveretention set days", "How many days to keep archived messages"),
+    ("config autotrack set enabled", "Whether to track new categories as classes"),
+    ("config calendar remove-break name", "The break's name"),
+    ("config dedup set threshold", "How similar questions must be, in percent"),
+    ("config department set emoji", "An emoji to show by the department's classes"),
+    ("config email subscribe class", "Only send digests about this class"),
+    ("config email subscribe frequency", "How often to send the digest"),
+    ("config email unsubscribe class", "The class the subscription is for, if any"),
+    ("config enrollment set closes", "When enrollment closes, like \"2024-09-15 23:59\""),
+    ("config enrollment set opens", "When enrollment opens, like \"2024-08-20 00:00\""),
+    ("config escalation set hours", "How many hours before staff are pinged"),
+    ("config logchannel set manual_changes", "Also log roles changed by hand"),
+    ("config menugrouping set enabled", "Whether to group class menus by department"),
+    ("config orphanprune set days", "How many days before orphaned data is removed"),
+    ("config policy clear command", "The command, like \"class edit tag\""),
+    ("config policy set command", "The command, or group of commands, like \"class edit\""),
+    ("config policy set role", "A role allowed to run it"),
+    ("config prompts add prompt", "The question"),
+    ("config prompts remove number", "The question's number in /config prompts list"),
+    ("config refrole set role", "The role new class roles are placed under"),
+    ("config renamesync set mode", "What to keep in step when a class is renamed"),
+    ("config webhook add url", "The webhook's URL"),
+    ("config webhook remove url", "The webhook's URL"),
+    ("config welcome set enabled", "Whether to DM new members"),
+    ("contact staff message", "Your message to the class's staff"),
+    ("federation allow member_server_id", "The ID of the server to let join"),
+    ("federation join hub_server_id", "The ID of the hub server"),
+    ("federation mirror hub_class", "The name of the hub's class"),
+    ("federation revoke member_server_id", "The ID of the server to remove"),
+    ("mentor setup mentor_role", "The mentor role, or a new one if left out"),
+    ("notifications announcements mode", "How to hear about announcements"),
+    ("peerreview pair dm", "Also DM each member their group"),
+    ("privacy forget confirm", "Confirm you want everything deleted"),
+    ("snippet get name", "The snippet's name"),
+    ("snippet save name", "A name for the snippet"),
+    ("studysession create temp_voice", "Make a voice channel just for the session"),
+    ("studysession create time", "When it starts, like \"2024-10-31 18:00\""),
+    ("studysession create topic", "What the session is about"),
+    ("studysession links add url", "The link"),
+    ("studysession links remove label", "The link's label"),
+    ("embed channel", "The channel to post in"),
+    ("say channel", "The channel to post in"),
+    ("say text", "The message to post"),
+    ("suggest text", "Your suggestion"),
+    ("tag create embed", "Post the tag as an embed"),
+    ("tag create name", "The tag's name"),
+    ("tag delete name", "The tag's name"),
+    ("tag edit embed", "Post the tag as an embed"),
+    ("tag edit name", "The tag's name"),
+    ("tag show name", "The tag's name"),
+    ("team create name", "The team's name"),
+    ("team disband name", "The team's name"),
+    ("term start ends", "When the term ends, like \"2024-12-20 23:59\""),
+    ("term start expire_roles", "Remove class roles from everyone when the term ends"),
+    ("term start name", "The term's name, like \"Fall 2024\""),
+];
+
+/// A language's translations. Names are keyed by the English name of a command or parameter, and
+/// descriptions by the same keys as `PARAMETERS`.
+struct Locale {
+    code: &'static str,
+    names: &'static [(&'static str, &'static str)],
+    descriptions: &'static [(&'static str, &'static str)],
+}
+
+const LOCALES: [Locale; 2] = [
+    Locale {
+        code: "es-ES",
+        names: &[
+            ("class", "clase"),
+            ("catalog", "catálogo"),
+            ("hand", "mano"),
+            ("raise", "levantar"),
+            ("lower", "bajar"),
+            ("next", "siguiente"),
+            ("notifications", "notificaciones"),
+            ("announcements", "anuncios"),
+            ("mute", "silenciar"),
+            ("unmute", "reactivar"),
+            ("badges", "insignias"),
+            ("studysession", "sesióndeestudio"),
+            ("suggest", "sugerir"),
+            ("contact", "contactar"),
+            ("privacy", "privacidad"),
+            ("list", "lista"),
+            ("info", "info"),
+            ("favorite", "favorita"),
+            ("search", "buscar"),
+            ("request", "solicitar"),
+            ("channel", "canal"),
+            ("mention", "mencionar"),
+            ("name", "nombre"),
+            ("tag", "etiqueta"),
+            ("mode", "modo"),
+            ("department", "departamento"),
+            ("show", "mostrar"),
+            ("text", "texto"),
+            ("message", "mensaje"),
+            ("time", "hora"),
+            ("topic", "tema"),
+            ("confirm", "confirmar"),
+        ],
+        descriptions: &[
+            ("class", "El rol de la clase"),
+            ("channel", "El canal que se usará"),
+            ("mention", "Mencionar los roles de las clases en lugar de solo nombrarlos"),
+            ("name", "El nombre"),
+            ("query", "Lo que se buscará"),
+            ("department", "El departamento, como \"CS\""),
+            ("badges show", "Si se muestran tus insignias de personal"),
+            ("class request name", "El nombre de la clase que te gustaría"),
+            ("class search name", "Parte del nombre de una clase"),
+            ("class search tag", "Mostrar solo las clases con esta etiqueta"),
+            ("contact staff message", "Tu mensaje para el personal de la clase"),
+            ("notifications announcements mode", "Cómo quieres enterarte de los anuncios"),
+            ("privacy forget confirm", "Confirma que quieres borrarlo todo"),
+            ("studysession create time", "Cuándo empieza, como \"2024-10-31 18:00\""),
+            ("studysession create topic", "De qué trata la sesión"),
+            ("suggest text", "Tu sugerencia"),
+        ],
+    },
+    Locale {
+        code: "fr",
+        names: &[
+            ("class", "cours"),
+            ("catalog", "catalogue"),
+            ("hand", "main"),
+            ("raise", "lever"),
+            ("lower", "baisser"),
+            ("next", "suivant"),
+            ("notifications", "notifications"),
+            ("announcements", "annonces"),
+            ("mute", "sourdine"),
+            ("unmute", "réactiver"),
+            ("badges", "badges"),
+            ("studysession", "séancedétude"),
+            ("suggest", "suggérer"),
+            ("contact", "contacter"),
+            ("privacy", "confidentialité"),
+            ("list", "liste"),
+            ("info", "info"),
+            ("favorite", "favori"),
+            ("search", "rechercher"),
+            ("request", "demander"),
+            ("channel", "salon"),
+            ("mention", "mentionner"),
+            ("name", "nom"),
+            ("tag", "étiquette"),
+            ("mode", "mode"),
+            ("department", "département"),
+            ("show", "afficher"),
+            ("text", "texte"),
+            ("message", "message"),
+            ("time", "heure"),
+            ("topic", "sujet"),
+            ("confirm", "confirmer"),
+        ],
+        descriptions: &[
+            ("class", "Le rôle du cours"),
+            ("channel", "Le salon à utiliser"),
+            ("mention", "Mentionner les rôles des cours au lieu de simplement les nommer"),
+            ("name", "Le nom"),
+            ("query", "Ce qu'il faut rechercher"),
+            ("department", "Le département, comme \"CS\""),
+            ("badges show", "Afficher ou non tes badges d'encadrant"),
+            ("class request name", "Le nom du cours que tu souhaites"),
+            ("class search name", "Une partie du nom d'un cours"),
+            ("class search tag", "N'afficher que les cours avec cette étiquette"),
+            ("contact staff message", "Ton message pour l'équipe du cours"),
+            ("notifications announcements mode", "Comment tu veux être prévenu des annonces"),
+            ("privacy forget confirm", "Confirme que tu veux tout supprimer"),
+            ("studysession create time", "Quand elle commence, comme \"2024-10-31 18:00\""),
+            ("studysession create topic", "Le sujet de la séance"),
+            ("suggest text", "Ta suggestion"),
+        ],
+    },
+];
+
+fn lookup(table: &[(&str, &'static str)], key: &str) -> Option<&'static str> {
+    table.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+/// Look up a parameter's text, trying the command-specific key before the parameter alone, and
+/// numbered parameters under their unnumbered name.
+fn lookup_parameter(table: &[(&str, &'static str)], command: &str, parameter: &str) -> Option<&'static str> {
+    let base = parameter.trim_end_matches(|c: char| c.is_ascii_digit());
+    [format!("{} {}", command, parameter), format!("{} {}", command, base), parameter.to_string(), base.to_string()]
+        .iter()
+        .find_map(|key| lookup(table, key))
+}
+
+/// Fill in missing parameter descriptions and add every locale's translations, for the commands
+/// and all of their subcommands.
+pub(crate) fn localize(commands: &mut [poise::Command<Data, Error>]) {
+    for command in commands {
+        localize_command(command, "");
+    }
+}
+
+fn localize_command(command: &mut poise::Command<Data, Error>, parent: &str) {
+    let path = if parent.is_empty() { command.name.clone() } else { format!("{} {}", parent, command.name) };
+
+    for locale in &LOCALES {
+        if let Some(name) = lookup(locale.names, &command.name) {
+            command.name_localizations.insert(locale.code.to_string(), name.to_string());
+        }
+    }
+
+    for parameter in &mut command.parameters {
+        if parameter.description.is_none() {
+            parameter.description = lookup_parameter(PARAMETERS, &path, &parameter.name).map(|d| d.to_string());
+        }
+        for locale in &LOCALES {
+            if let Some(name) = lookup(locale.names, &parameter.name) {
+                parameter.name_localizations.insert(locale.code.to_string(), name.to_string());
+            }
+            if let Some(description) = lookup_parameter(locale.descriptions, &path, &parameter.name) {
+                parameter.description_localizations.insert(locale.code.to_string(), description.to_string());
+            }
+        }
+    }
+
+    for subcommand in &mut command.subcommands {
+        localize_command(subcommand, &path);
+    }
+}
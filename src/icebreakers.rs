@@ -0,0 +1,141 @@
+use chrono::{Duration, Utc};
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::UpdateOptions;
+use mongodb::Collection;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::id::{GuildId, RoleId};
+use tokio::sync::OnceCell;
+
+use crate::classes::{Class, Server};
+use crate::redact::log_error;
+use crate::{get_conn, set_all, ClassError, ClassResult, ENV};
+
+/// A class that has opted in to a regular question of the day from the server's prompt pool.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Icebreaker {
+    server_id: GuildId,
+    role: RoleId,
+    every_days: u32,
+    last_posted: DateTime,
+    /// Prompts already posted in this class, so they aren't repeated until the pool runs out.
+    asked: Vec<String>,
+}
+
+impl Icebreaker {
+    /// Opt a class in, posting and pinning an intro prompt in its first text channel. The first
+    /// question of the day follows after `every_days`.
+    pub(crate) async fn enable(cache_http: impl CacheHttp, class: &Class, every_days: u32) -> ClassResult<()> {
+        let channel = *class.text_channels.first().ok_or(ClassError::NoClassChannels)?;
+        let http = cache_http.http();
+
+        let intro = channel.say(http, format!(
+            "👋 Welcome to **{}**! Introduce yourself: what should people call you, what are you \
+            hoping to get out of the class, and what's something you're into outside of school?",
+            class.name,
+        )).await?;
+        intro.pin(http).await?;
+
+        let asked = Self::get_collection().await
+            .find_one(doc! { "role": class.role.to_string() }, None)
+            .await?
+            .map(|i| i.asked)
+            .unwrap_or_default();
+        Self::get_collection().await
+            .update_one(
+                doc! { "role": class.role.to_string() },
+                set_all(&Self { server_id: class.server_id, role: class.role, every_days, last_posted: DateTime::now(), asked })?,
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Opt a class out, returning whether it was opted in.
+    pub(crate) async fn disable(role: RoleId) -> ClassResult<bool> {
+        Ok(
+            Self::get_collection().await
+                .delete_one(doc! { "role": role.to_string() }, None)
+                .await?
+                .deleted_count > 0
+        )
+    }
+
+    fn is_due(&self) -> bool {
+        let due = Utc::now() - Duration::days(self.every_days as i64);
+        self.last_posted.timestamp_millis() <= due.timestamp_millis()
+    }
+
+    /// Post a prompt from the pool that hasn't been asked yet, starting over once all have been.
+    async fn post(&mut self, cache_http: impl CacheHttp, class: &Class, prompts: &[String]) -> ClassResult<()> {
+        let channel = *class.text_channels.first().ok_or(ClassError::NoClassChannels)?;
+
+        let mut unasked = prompts.iter().filter(|p| !self.asked.contains(p)).collect::<Vec<_>>();
+        if unasked.is_empty() {
+            self.asked.clear();
+            unasked = prompts.iter().collect();
+        }
+        let prompt = match unasked.choose(&mut rand::thread_rng()) {
+            Some(p) => (*p).clone(),
+            None => return Ok(()),
+        };
+
+        channel.say(cache_http.http(), format!("💬 **Question of the day:** {}", prompt)).await?;
+
+        self.asked.push(prompt);
+        self.last_posted = DateTime::now();
+        Self::get_collection().await
+            .update_one(
+                doc! { "role": self.role.to_string() },
+                doc! { "$set": { "asked": &self.asked, "last_posted": self.last_posted } },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static ICEBREAKERS: OnceCell<Collection<Icebreaker>> = OnceCell::const_new();
+
+        ICEBREAKERS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("icebreakers")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Post a question of the day in every opted-in class that is due one.
+pub(crate) async fn tick(ctx: &SContext) -> ClassResult<()> {
+    let icebreakers = Icebreaker::get_collection().await
+        .find(None, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    for mut icebreaker in icebreakers.into_iter().filter(Icebreaker::is_due) {
+        // One class without channels, or a failed send, shouldn't hold up every other class
+        let posted = async {
+            let class = match Class::find_by_role(icebreaker.role).await? {
+                Some(c) => c,
+                None => return Icebreaker::disable(icebreaker.role).await.map(|_| ()),
+            };
+            let prompts = Server::get_or_create(icebreaker.server_id).await?.icebreaker_prompts;
+            icebreaker.post(ctx, &class, &prompts).await
+        }.await;
+        if let Err(e) = posted {
+            log_error!("[{}] Error posting icebreaker for {}: {:?}", icebreaker.server_id, icebreaker.role, e);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use futures::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::channel::GuildChannel;
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::prelude::EventHandler;
+use tokio::sync::OnceCell;
+
+use crate::classes::Class;
+use crate::history::{self, EnrollmentMechanism};
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, ClassResult, ENV};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ClassInvite {
+    server_id: GuildId,
+    pub(crate) code: String,
+    role: RoleId,
+    uses: u64,
+    created_by: UserId,
+}
+
+impl ClassInvite {
+    /// Create a permanent invite into `channel` that enrolls anyone who joins through it in
+    /// `class`.
+    pub(crate) async fn create(
+        cache_http: impl CacheHttp,
+        class: &Class,
+        channel: &GuildChannel,
+        created_by: UserId,
+    ) -> ClassResult<ClassInvite> {
+        let invite = channel
+            .create_invite(&cache_http, |i| i.max_age(0).unique(true))
+            .await?;
+
+        let invite = Self {
+            server_id: class.server_id,
+            code: invite.code,
+            role: class.role,
+            uses: invite.uses,
+            created_by,
+        };
+
+        Self::get_collection().await.insert_one(&invite, None).await?;
+
+        Ok(invite)
+    }
+
+    pub(crate) fn url(&self) -> String {
+        format!("https://discord.gg/{}", self.code)
+    }
+
+    async fn list(server_id: GuildId) -> ClassResult<Vec<ClassInvite>> {
+        Ok(
+            Self::scoped(server_id).await
+                .find(None, None)
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn set_uses(&mut self, uses: u64) -> ClassResult<()> {
+        Self::get_collection().await
+            .update_one(
+                doc! { "code": &self.code },
+                doc! { "$set": { "uses": uses as i64 } },
+                None,
+            )
+            .await?;
+        self.uses = uses;
+
+        Ok(())
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static INVITES: OnceCell<Collection<ClassInvite>> = OnceCell::const_new();
+
+        INVITES
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("class_invites")
+            })
+            .await
+            .clone()
+    }
+}
+
+pub(crate) struct ClassInviteHandler;
+
+#[async_trait]
+impl EventHandler for ClassInviteHandler {
+    async fn guild_member_addition(&self, ctx: SContext, new_member: Member) {
+        if let Err(e) = enroll_from_invite(&ctx, new_member).await {
+            log_error!("Error handling class invite: {:?}", e);
+        }
+    }
+}
+
+/// Discord doesn't say which invite a member joined through, so compare the use counts of every
+/// tracked invite against the last known counts to find the one that was used.
+async fn enroll_from_invite(ctx: &SContext, mut member: Member) -> ClassResult<()> {
+    let tracked = ClassInvite::list(member.guild_id).await?;
+    if tracked.is_empty() {
+        return Ok(());
+    }
+
+    let current = member.guild_id.invites(ctx.http()).await?;
+
+    for mut invite in tracked {
+        let uses = match current.iter().find(|i| i.code == invite.code) {
+            Some(i) => i.uses,
+            None => continue,
+        };
+        if uses <= invite.uses {
+            continue;
+        }
+
+        invite.set_uses(uses).await?;
+        let class = match Class::find_by_role(invite.role).await? {
+            Some(c) => c,
+            None => continue,
+        };
+
+        member.add_role(ctx.http(), class.role).await?;
+        history::enrolled(
+            member.guild_id,
+            member.user.id,
+            member.user.id,
+            EnrollmentMechanism::Invite,
+            vec![class.role],
+            Vec::new(),
+        ).await;
+
+        // Throwing away the result as the member may have DMs disabled
+        if let Ok(dm) = member.user.create_dm_channel(ctx).await {
+            dm.say(ctx.http(), format!(
+                "Welcome! You've been added to {} because you joined through its invite link. \
+                You can leave it at any time from the class menu.",
+                class.name,
+            )).await.ok();
+        }
+    }
+
+    Ok(())
+}
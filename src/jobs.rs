@@ -0,0 +1,388 @@
+use std::time::{Duration, Instant};
+
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+use serenity::prelude::Mentionable;
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::classes::Class;
+use crate::errors::{self, ErrorContext};
+use crate::ordering::{self, CategoryOrder};
+use crate::scoped::GuildScoped;
+use crate::templates::ServerTemplate;
+use crate::terms::Term;
+use crate::{get_conn, grants, lookup, ClassError, ClassResult, Context, ENV};
+
+/// Progress is saved and edited into a job's status message at most this often, to stay clear of
+/// Discord's rate limits.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+/// A running job that hasn't saved progress for this long is taken to have been interrupted, and
+/// is picked up again by whichever instance claims it next.
+const LEASE: Duration = Duration::from_secs(5 * 60);
+/// How many times a job is tried before it is marked as failed.
+const MAX_ATTEMPTS: u32 = 3;
+/// How long to wait before retrying a job, multiplied by how many times it has been tried.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    /// Identifies this instance of the bot as the one running the jobs it claimed.
+    static ref INSTANCE: String = ObjectId::new().to_hex();
+}
+
+/// The work a job does. Each kind can be run again from the start after an interruption, skipping
+/// whatever was already done.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind")]
+pub(crate) enum JobKind {
+    OrderCategories { by: CategoryOrder },
+    ApplyTemplate { template: ServerTemplate },
+    TransferRole { class: RoleId, role: RoleId, migrate_members: bool },
+    Rollover { term: String },
+    GrantRole { role: RoleId, users: Vec<UserId>, granted_by: UserId },
+}
+
+impl JobKind {
+    pub(crate) fn describe(&self) -> &'static str {
+        match self {
+            JobKind::OrderCategories { .. } => "Ordering class categories",
+            JobKind::ApplyTemplate { .. } => "Applying the server template",
+            JobKind::TransferRole { .. } => "Transferring a class role",
+            JobKind::Rollover { .. } => "Removing class roles for the end of term",
+            JobKind::GrantRole { .. } => "Granting a role",
+        }
+    }
+
+    /// Do the work, returning a summary of what was done.
+    async fn run(self, ctx: &SContext, server_id: GuildId, status: &JobStatus) -> ClassResult<String> {
+        match self {
+            JobKind::OrderCategories { by } => Ok(match ordering::reorder_categories(ctx, server_id, by, status).await? {
+                0 => "Class categories are already in that order.".to_string(),
+                moved => format!("Moved {} class categories.", moved),
+            }),
+            JobKind::ApplyTemplate { template } => {
+                let created = template.apply(ctx, server_id, status).await?;
+                Ok(format!("Created {} classes.", created.len()))
+            }
+            JobKind::TransferRole { class, role, migrate_members } => {
+                let guild = lookup::guild(ctx, server_id).await?;
+                let mut class = Class::find_by_role(class).await?.ok_or(ClassError::InvalidClass)?;
+                class.transfer_role(ctx, &guild, role, migrate_members, status).await?;
+                Ok(format!("\"{}\" now uses the role {}.", class.name, role.mention()))
+            }
+            JobKind::Rollover { term } => {
+                let removed = Term::expire_roles(ctx, server_id, status).await?;
+                Ok(format!("Removed class roles from {} members for the end of \"{}\".", removed, term))
+            }
+            JobKind::GrantRole { role, users, granted_by } => {
+                grants::run(ctx, server_id, role, &users, granted_by, status).await
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JobState {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub(crate) fn describe(&self) -> &'static str {
+        match self {
+            JobState::Queued => "⏳ Queued",
+            JobState::Running => "⏳ Running",
+            JobState::Finished => "✅ Finished",
+            JobState::Failed => "❌ Failed",
+            JobState::Cancelled => "🛑 Cancelled",
+        }
+    }
+}
+
+/// A long-running piece of work, queued in the database so it outlives the command that started
+/// it and survives restarts. Instances claim queued jobs, and hold them for as long as they keep
+/// saving progress.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Job {
+    #[serde(rename = "_id")]
+    pub(crate) id: ObjectId,
+    pub(crate) server_id: GuildId,
+    /// Who started the job, if it wasn't started by the bot itself.
+    pub(crate) started_by: Option<UserId>,
+    pub(crate) job: JobKind,
+    pub(crate) state: JobState,
+    pub(crate) progress: Option<String>,
+    /// How the job ended, or why the last attempt failed.
+    pub(crate) result: Option<String>,
+    pub(crate) attempts: u32,
+    /// The job isn't claimed again before this time, so retries back off.
+    run_after: DateTime,
+    claimed_by: Option<String>,
+    heartbeat: Option<DateTime>,
+    /// The message kept up to date with the job's progress.
+    status_message: Option<(ChannelId, MessageId)>,
+    pub(crate) created_at: DateTime,
+    pub(crate) finished_at: Option<DateTime>,
+}
+
+impl Job {
+    /// Queue a job and start it straight away. Progress is posted to the status channel, if given.
+    pub(crate) async fn enqueue(
+        ctx: &SContext,
+        server_id: GuildId,
+        started_by: Option<UserId>,
+        job: JobKind,
+        status_channel: Option<ChannelId>,
+    ) -> ClassResult<Job> {
+        let id = ObjectId::new();
+        let status_message = match status_channel {
+            Some(channel) => {
+                let message = channel.say(ctx.http(), status_line(job.describe(), id, JobState::Queued.describe())).await?;
+                Some((channel, message.id))
+            }
+            None => None,
+        };
+        let job = Job {
+            id,
+            server_id,
+            started_by,
+            job,
+            state: JobState::Queued,
+            progress: None,
+            result: None,
+            attempts: 0,
+            run_after: DateTime::now(),
+            claimed_by: None,
+            heartbeat: None,
+            status_message,
+            created_at: DateTime::now(),
+            finished_at: None,
+        };
+        Self::get_collection().await.insert_one(&job, None).await?;
+
+        if let Some(claimed) = Self::claim(doc! { "_id": id }).await? {
+            spawn(ctx.clone(), claimed);
+        }
+
+        Ok(job)
+    }
+
+    /// Take a job that's ready to run, or whose instance stopped saving progress, for this instance.
+    async fn claim(mut filter: Document) -> ClassResult<Option<Job>> {
+        let now = DateTime::now();
+        let expired = DateTime::from_millis(now.timestamp_millis() - LEASE.as_millis() as i64);
+        filter.insert("$or", vec![
+            doc! { "state": "Queued", "run_after": { "$lte": now } },
+            doc! { "state": "Running", "heartbeat": { "$lt": expired } },
+        ]);
+
+        Ok(
+            Self::get_collection().await
+                .find_one_and_update(
+                    filter,
+                    doc! {
+                        "$set": { "state": "Running", "claimed_by": INSTANCE.as_str(), "heartbeat": now },
+                        "$inc": { "attempts": 1 },
+                    },
+                    FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build(),
+                )
+                .await?
+        )
+    }
+
+    /// The server's most recent jobs, newest first.
+    pub(crate) async fn list(server_id: GuildId, limit: i64) -> ClassResult<Vec<Job>> {
+        Ok(
+            Self::scoped(server_id).await
+                .find(
+                    None,
+                    FindOptions::builder().sort(doc! { "_id": -1 }).limit(limit).build(),
+                )
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    pub(crate) async fn find(server_id: GuildId, id: &str) -> ClassResult<Job> {
+        let id = ObjectId::parse_str(id.trim()).map_err(|_| ClassError::InvalidJob)?;
+        Self::scoped(server_id).await
+            .find_one(doc! { "_id": id }, None)
+            .await?
+            .ok_or(ClassError::InvalidJob)
+    }
+
+    /// Stop a job that hasn't finished. A running job stops the next time it saves progress.
+    pub(crate) async fn cancel(&self, cache_http: impl CacheHttp) -> ClassResult<()> {
+        let cancelled = Self::get_collection().await
+            .update_one(
+                doc! { "_id": self.id, "state": { "$in": ["Queued", "Running"] } },
+                doc! { "$set": { "state": "Cancelled", "finished_at": DateTime::now() } },
+                None,
+            )
+            .await?
+            .modified_count;
+        if cancelled == 0 {
+            return Err(ClassError::JobFinished);
+        }
+
+        if let Some((channel, message)) = self.status_message {
+            let content = status_line(self.job.describe(), self.id, JobState::Cancelled.describe());
+            // Throwing away the result as the message may have been deleted
+            channel.edit_message(cache_http.http(), message, |m| m.content(content)).await.ok();
+        }
+
+        Ok(())
+    }
+
+    /// Update the job, as long as this instance still holds it and it wasn't cancelled. Returns
+    /// whether it was updated.
+    async fn update(&self, update: Document) -> ClassResult<bool> {
+        let matched = Self::get_collection().await
+            .update_one(doc! { "_id": self.id, "claimed_by": INSTANCE.as_str(), "state": "Running" }, update, None)
+            .await?
+            .matched_count;
+        Ok(matched > 0)
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static JOBS: OnceCell<Collection<Job>> = OnceCell::const_new();
+
+        JOBS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("jobs")
+            })
+            .await
+            .clone()
+    }
+}
+
+fn status_line(what: &str, id: ObjectId, status: &str) -> String {
+    format!("{} (job `{}`): {}", what, id.to_hex(), status)
+}
+
+/// Whether an error might go away if the job is tried again, like Discord or the database being
+/// briefly unavailable.
+fn is_transient(e: &ClassError) -> bool {
+    matches!(e, ClassError::ApiError(_) | ClassError::DatabaseError(_))
+}
+
+/// A running job's handle for reporting progress, which also keeps its claim on the job alive.
+pub(crate) struct JobStatus {
+    job: Job,
+    last_progress: Mutex<Option<Instant>>,
+}
+
+impl JobStatus {
+    /// Save how far the job has got and show it in its status message. Updates that come too soon
+    /// after the last one are skipped.
+    pub(crate) async fn progress(&self, cache_http: impl CacheHttp, status: &str) -> ClassResult<()> {
+        let mut last_progress = self.last_progress.lock().await;
+        if last_progress.map(|l| l.elapsed() < PROGRESS_INTERVAL).unwrap_or(false) {
+            return Ok(());
+        }
+        *last_progress = Some(Instant::now());
+
+        // Stops the job if it was cancelled, or if another instance took it over
+        Job::get_collection().await
+            .find_one_and_update(
+                doc! { "_id": self.job.id, "claimed_by": INSTANCE.as_str(), "state": "Running" },
+                doc! { "$set": { "progress": status, "heartbeat": DateTime::now() } },
+                None,
+            )
+            .await?
+            .ok_or(ClassError::JobCancelled)?;
+        if let Some((channel, message)) = self.job.status_message {
+            let content = status_line(self.job.job.describe(), self.job.id, &format!("⏳ {}", status));
+            // Throwing away the result as the job should carry on even if its message was deleted
+            channel.edit_message(cache_http.http(), message, |m| m.content(content)).await.ok();
+        }
+
+        Ok(())
+    }
+}
+
+fn spawn(ctx: SContext, job: Job) {
+    let context = ErrorContext { server_id: Some(job.server_id), user: job.started_by, ..Default::default() };
+    errors::spawn_reported("background job", context.clone(), async move {
+        if let Err(e) = run(&ctx, job, context.clone()).await {
+            errors::report("background job", context, &e.to_string()).await;
+        }
+    });
+}
+
+async fn run(ctx: &SContext, job: Job, context: ErrorContext) -> ClassResult<()> {
+    let what = job.job.describe();
+    let status = JobStatus { job: job.clone(), last_progress: Mutex::new(None) };
+
+    let (update, line) = match job.job.clone().run(ctx, job.server_id, &status).await {
+        // Left as it is, as the job was cancelled or is now someone else's
+        Err(ClassError::JobCancelled) => return Ok(()),
+        Ok(summary) => (
+            doc! { "$set": { "state": "Finished", "result": &summary, "finished_at": DateTime::now() } },
+            format!("✅ {}", summary),
+        ),
+        Err(e) if is_transient(&e) && job.attempts < MAX_ATTEMPTS => {
+            let delay = RETRY_DELAY.as_millis() as i64 * job.attempts as i64;
+            let run_after = DateTime::from_millis(DateTime::now().timestamp_millis() + delay);
+            (
+                doc! { "$set": { "state": "Queued", "result": e.to_string(), "run_after": run_after } },
+                format!("⏳ Attempt {} of {} failed, trying again soon: {}", job.attempts, MAX_ATTEMPTS, e),
+            )
+        }
+        Err(e) => {
+            errors::report(what, context, &e.to_string()).await;
+            (
+                doc! { "$set": { "state": "Failed", "result": e.to_string(), "finished_at": DateTime::now() } },
+                format!("❌ Stopped early: {}", e),
+            )
+        }
+    };
+    if !job.update(update).await? {
+        return Ok(());
+    }
+
+    if let Some((channel, message)) = job.status_message {
+        // Throwing away the result as there is nowhere else to report to
+        channel
+            .edit_message(ctx.http(), message, |m| m.content(status_line(what, job.id, &line)))
+            .await
+            .ok();
+    }
+
+    Ok(())
+}
+
+/// Start every job that is ready to run, including retries and jobs interrupted by a restart.
+pub(crate) async fn tick(ctx: &SContext) -> ClassResult<()> {
+    while let Some(job) = Job::claim(doc! {}).await? {
+        spawn(ctx.clone(), job);
+    }
+
+    Ok(())
+}
+
+/// Start a command's work as a background job, with a status message in the command's channel
+/// that is edited until the job is done. Returns the job's ID, for the command to reply with.
+pub(crate) async fn start(ctx: Context<'_>, job: JobKind) -> ClassResult<String> {
+    let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+    let job = Job::enqueue(ctx.discord(), server_id, Some(ctx.author().id), job, Some(ctx.channel_id())).await?;
+    Ok(job.id.to_hex())
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use serenity::async_trait;
+use serenity::client::{Context as SContext, EventHandler};
+use serenity::http::CacheHttp;
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::prelude::Mentionable;
+
+use crate::classes::{Class, Server};
+use crate::events::BotEvent;
+use crate::ClassResult;
+use crate::redact::log_error;
+
+/// The audit log action for a member's roles being changed.
+const MEMBER_ROLE_UPDATE: u8 = 25;
+
+/// Post a line to the server's log channel for each class the member joined or left. `how` says
+/// what made the change, such as the mechanism or who made it by hand.
+pub(crate) async fn post(
+    cache_http: impl CacheHttp,
+    server_id: GuildId,
+    user: UserId,
+    joined: &[RoleId],
+    left: &[RoleId],
+    how: &str,
+) -> ClassResult<()> {
+    if joined.is_empty() && left.is_empty() {
+        return Ok(());
+    }
+    let log_channel = match Server::get_or_create(server_id).await?.log_channel {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+
+    let names = Class::list(server_id).await?
+        .into_iter()
+        .map(|c| (c.role, c.name))
+        .collect::<HashMap<_, _>>();
+    let name = |role: &RoleId| names.get(role).cloned().unwrap_or_else(|| role.mention().to_string());
+    let content = joined.iter()
+        .map(|r| format!("📥 {} joined **{}** ({})", user.mention(), name(r), how))
+        .chain(left.iter().map(|r| format!("📤 {} left **{}** ({})", user.mention(), name(r), how)))
+        .join("\n");
+
+    log_channel
+        .send_message(cache_http.http(), |m| m
+            .content(content)
+            .allowed_mentions(|a| a.empty_parse())
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Event bus subscriber logging enrollment changes made by the bot.
+pub(crate) async fn log(ctx: SContext, event: BotEvent) {
+    if let BotEvent::MemberEnrolled { server_id, user_id, mechanism, joined, left, .. } = event {
+        if let Err(e) = post(&ctx, server_id, user_id, &joined, &left, mechanism.describe()).await {
+            log_error!("Error posting to log channel: {:?}", e);
+        }
+    }
+}
+
+/// Logs class roles changed by hand, for servers that opt in. Changes made by the bot are already
+/// logged through the event bus, so they are told apart using the audit log.
+pub(crate) struct JoinLogHandler;
+
+#[async_trait]
+impl EventHandler for JoinLogHandler {
+    async fn guild_member_update(&self, ctx: SContext, old: Option<Member>, new: Member) {
+        if let Some(old) = old {
+            if let Err(e) = log_manual_change(&ctx, old, new).await {
+                log_error!("Error logging manual role change: {:?}", e);
+            }
+        }
+    }
+}
+
+async fn log_manual_change(ctx: &SContext, old: Member, new: Member) -> ClassResult<()> {
+    if new.user.bot || old.roles == new.roles {
+        return Ok(());
+    }
+    let server = Server::get_or_create(new.guild_id).await?;
+    if server.log_channel.is_none() || !server.log_manual_changes {
+        return Ok(());
+    }
+
+    let class_roles = Class::list(new.guild_id).await?
+        .into_iter()
+        .map(|c| c.role)
+        .collect::<Vec<_>>();
+    let joined = new.roles.iter()
+        .filter(|r| class_roles.contains(r) && !old.roles.contains(r))
+        .copied()
+        .collect::<Vec<_>>();
+    let left = old.roles.iter()
+        .filter(|r| class_roles.contains(r) && !new.roles.contains(r))
+        .copied()
+        .collect::<Vec<_>>();
+    if joined.is_empty() && left.is_empty() {
+        return Ok(());
+    }
+
+    let entry = new.guild_id
+        .audit_logs(ctx.http(), Some(MEMBER_ROLE_UPDATE), None, None, Some(10))
+        .await?
+        .entries
+        .into_iter()
+        .find(|e| e.target_id == Some(new.user.id.0));
+    let editor = match entry {
+        Some(e) if e.user_id == ctx.cache.current_user_id() => return Ok(()),
+        Some(e) => format!("by {}", e.user_id.mention()),
+        None => "by hand".to_string(),
+    };
+
+    post(ctx, new.guild_id, new.user.id, &joined, &left, &editor).await
+}
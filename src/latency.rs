@@ -0,0 +1,203 @@
+//! Timing of commands and component interactions, including how much of it was spent waiting on
+//! MongoDB and on Discord's API. Anything slower than `SLOW` is logged with that breakdown, to
+//! show where caching or batching would help.
+//!
+//! Time is attributed to whichever task is being timed when a database command finishes or a
+//! Discord request is dropped, so work spawned onto other tasks isn't counted.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use mongodb::event::command::{CommandEventHandler, CommandFailedEvent, CommandSucceededEvent};
+use serde::Serialize;
+use tokio::task;
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Event, Metadata, Subscriber};
+
+use crate::redact::{log_error, log_info};
+
+/// Handling that takes longer than this is logged as slow.
+pub(crate) const SLOW: Duration = Duration::from_secs(2);
+/// Interaction tokens expire after 15 minutes, so anything still being timed after that was
+/// abandoned, like a command that panicked.
+const ABANDONED: Duration = Duration::from_secs(15 * 60);
+
+/// Serenity instruments every Discord API request with a span by this name.
+const REQUEST_TARGET: &str = "serenity::http::client";
+const REQUEST_SPAN: &str = "request";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Breakdown {
+    pub(crate) total: Duration,
+    pub(crate) mongo: Duration,
+    pub(crate) discord: Duration,
+}
+
+/// The structured log line for slow handling, in milliseconds. Concurrent database commands and
+/// requests can add up to more than the total, so the rest is never negative.
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct SlowPath<'a> {
+    event: &'static str,
+    interaction: &'a str,
+    total_ms: u128,
+    mongo_ms: u128,
+    discord_ms: u128,
+    other_ms: u128,
+}
+
+impl Breakdown {
+    pub(crate) fn slow_path<'a>(&self, interaction: &'a str) -> Option<SlowPath<'a>> {
+        (self.total >= SLOW).then(|| SlowPath {
+            event: "slow_interaction",
+            interaction,
+            total_ms: self.total.as_millis(),
+            mongo_ms: self.mongo.as_millis(),
+            discord_ms: self.discord.as_millis(),
+            other_ms: self.total.saturating_sub(self.mongo + self.discord).as_millis(),
+        })
+    }
+}
+
+struct Timing {
+    started: Instant,
+    mongo: Duration,
+    discord: Duration,
+}
+
+lazy_static! {
+    static ref TIMINGS: Mutex<HashMap<task::Id, Timing>> = Mutex::new(HashMap::new());
+    static ref REQUESTS: Mutex<HashMap<u64, Instant>> = Mutex::new(HashMap::new());
+}
+
+static TIMED: AtomicU64 = AtomicU64::new(0);
+static SLOW_COUNT: AtomicU64 = AtomicU64::new(0);
+static NEXT_SPAN: AtomicU64 = AtomicU64::new(1);
+
+/// How many commands and interactions have been timed since startup, and how many were slow.
+pub(crate) struct Metrics {
+    pub(crate) timed: u64,
+    pub(crate) slow: u64,
+}
+
+pub(crate) fn metrics() -> Metrics {
+    Metrics {
+        timed: TIMED.load(Ordering::Relaxed),
+        slow: SLOW_COUNT.load(Ordering::Relaxed),
+    }
+}
+
+/// Start timing the current task.
+pub(crate) fn start() {
+    if let Some(id) = task::try_id() {
+        let mut timings = TIMINGS.lock().unwrap();
+        timings.retain(|_, t| t.started.elapsed() < ABANDONED);
+        timings.insert(id, Timing { started: Instant::now(), mongo: Duration::ZERO, discord: Duration::ZERO });
+    }
+}
+
+/// Stop timing the current task, logging it if it was slow.
+pub(crate) fn finish(interaction: &str) {
+    let timing = match task::try_id().and_then(|id| TIMINGS.lock().unwrap().remove(&id)) {
+        Some(t) => t,
+        None => return,
+    };
+    let breakdown = Breakdown { total: timing.started.elapsed(), mongo: timing.mongo, discord: timing.discord };
+
+    TIMED.fetch_add(1, Ordering::Relaxed);
+    if let Some(slow_path) = breakdown.slow_path(interaction) {
+        SLOW_COUNT.fetch_add(1, Ordering::Relaxed);
+        match serde_json::to_string(&slow_path) {
+            Ok(line) => log_info!("{}", line),
+            Err(e) => log_error!("Error serializing slow path event: {}", e),
+        }
+    }
+}
+
+/// Time a future run on the current task.
+pub(crate) async fn measure<F: Future>(interaction: &str, future: F) -> F::Output {
+    start();
+    let output = future.await;
+    finish(interaction);
+    output
+}
+
+fn add(update: impl FnOnce(&mut Timing)) {
+    if let Some(id) = task::try_id() {
+        if let Some(timing) = TIMINGS.lock().unwrap().get_mut(&id) {
+            update(timing);
+        }
+    }
+}
+
+/// Adds the time taken by database commands to the task that ran them.
+struct MongoTimer;
+
+impl CommandEventHandler for MongoTimer {
+    fn handle_command_succeeded_event(&self, event: CommandSucceededEvent) {
+        add(|t| t.mongo += event.duration);
+    }
+
+    fn handle_command_failed_event(&self, event: CommandFailedEvent) {
+        add(|t| t.mongo += event.duration);
+    }
+}
+
+pub(crate) fn mongo_handler() -> Arc<dyn CommandEventHandler> {
+    Arc::new(MongoTimer)
+}
+
+/// Adds the time taken by Discord API requests to the task that made them, from serenity's
+/// request spans. Every other span and event is accepted and ignored rather than disabled, so
+/// installing it doesn't turn off tracing for other subscribers.
+pub(crate) struct DiscordTimer;
+
+fn is_request(metadata: &Metadata<'_>) -> bool {
+    metadata.is_span() && metadata.target() == REQUEST_TARGET && metadata.name() == REQUEST_SPAN
+}
+
+impl Subscriber for DiscordTimer {
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        Interest::always()
+    }
+
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = NEXT_SPAN.fetch_add(1, Ordering::Relaxed);
+        if is_request(span.metadata()) {
+            REQUESTS.lock().unwrap().insert(id, Instant::now());
+        }
+        Id::from_u64(id)
+    }
+
+    fn try_close(&self, id: Id) -> bool {
+        if let Some(started) = REQUESTS.lock().unwrap().remove(&id.into_u64()) {
+            add(|t| t.discord += started.elapsed());
+        }
+        true
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+/// Start timing Discord API requests. Must be called once, before the client starts.
+pub(crate) fn install() {
+    if tracing::subscriber::set_global_default(DiscordTimer).is_err() {
+        log_error!("A tracing subscriber was already installed, so Discord API time won't be measured");
+    }
+}
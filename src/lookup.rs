@@ -0,0 +1,122 @@
+//! Server, role and channel lookups that fall back to fetching from Discord when the cache
+//! doesn't have them, as happens in large servers whose cache is only partly filled. Fetched
+//! results are kept for `TTL`, so a command looking up several in a row fetches them once.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serenity::http::{CacheHttp, StatusCode};
+use serde_json::json;
+use serenity::model::channel::{Channel, GuildChannel};
+use serenity::model::guild::{Guild, PartialGuild, Role};
+use serenity::model::Timestamp;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+
+use crate::{ClassError, ClassResult, Context};
+
+/// How long fetched roles and channels are used for before being fetched again.
+const TTL: Duration = Duration::from_secs(30);
+
+/// Something fetched from Discord, and when.
+type Fetched<T> = (Instant, T);
+
+lazy_static! {
+    static ref ROLES: Mutex<HashMap<GuildId, Fetched<HashMap<RoleId, Role>>>> = Mutex::new(HashMap::new());
+    static ref CHANNELS: Mutex<HashMap<ChannelId, Fetched<Option<Channel>>>> = Mutex::new(HashMap::new());
+    static ref GUILDS: Mutex<HashMap<GuildId, Fetched<Guild>>> = Mutex::new(HashMap::new());
+}
+
+/// The server a command was run in. Only commands run in DMs have none; servers missing from the
+/// cache are fetched.
+pub(crate) async fn current_guild(ctx: Context<'_>) -> ClassResult<Guild> {
+    guild(ctx.discord(), ctx.guild_id().ok_or(ClassError::NoServer)?).await
+}
+
+/// A server the bot is in. Fetched servers have no members, presences or voice states, which
+/// Discord only sends over the gateway.
+pub(crate) async fn guild(cache_http: impl CacheHttp, server_id: GuildId) -> ClassResult<Guild> {
+    if let Some(guild) = cache_http.cache().and_then(|c| c.guild(server_id)) {
+        return Ok(guild);
+    }
+
+    if let Some((_, guild)) = GUILDS.lock().unwrap().get(&server_id).filter(|(at, _)| at.elapsed() < TTL) {
+        return Ok(guild.clone());
+    }
+    let partial = server_id.to_partial_guild(cache_http.http()).await?;
+    let channels = server_id.channels(cache_http.http()).await?;
+    let guild = guild_from_partial(partial, channels.into_values().collect())?;
+    let mut fetched = GUILDS.lock().unwrap();
+    fetched.retain(|_, (at, _)| at.elapsed() < TTL);
+    fetched.insert(server_id, (Instant::now(), guild.clone()));
+
+    Ok(guild)
+}
+
+/// Build a full server from the parts Discord's API returns. `Guild` can only be made by
+/// deserializing, so this fills in what a gateway server has and a fetched one doesn't.
+pub(crate) fn guild_from_partial(partial: PartialGuild, channels: Vec<GuildChannel>) -> ClassResult<Guild> {
+    let mut guild = serde_json::to_value(&partial)?;
+    let fields = guild.as_object_mut().ok_or(ClassError::NoServer)?;
+    // Some optional fields can be left out but not null
+    fields.retain(|_, v| !v.is_null());
+    fields.insert("channels".to_string(), serde_json::to_value(channels)?);
+    fields.insert("joined_at".to_string(), serde_json::to_value(Timestamp::now())?);
+    fields.insert("large".to_string(), json!(partial.approximate_member_count.unwrap_or(0) > 250));
+    fields.insert("member_count".to_string(), json!(partial.approximate_member_count.unwrap_or(0)));
+    fields.insert("explicit_content_filter".to_string(), json!(0));
+    fields.insert("preferred_locale".to_string(), json!("en-US"));
+    for field in ["members", "presences", "voice_states", "threads", "stage_instances"] {
+        fields.insert(field.to_string(), json!([]));
+    }
+
+    Ok(serde_json::from_value(guild)?)
+}
+
+/// Every role in a server.
+pub(crate) async fn roles(cache_http: impl CacheHttp, server_id: GuildId) -> ClassResult<HashMap<RoleId, Role>> {
+    if let Some(roles) = cache_http.cache().and_then(|c| c.guild_roles(server_id)) {
+        return Ok(roles);
+    }
+
+    if let Some((_, roles)) = ROLES.lock().unwrap().get(&server_id).filter(|(at, _)| at.elapsed() < TTL) {
+        return Ok(roles.clone());
+    }
+    let roles = server_id.roles(cache_http.http()).await?;
+    let mut fetched = ROLES.lock().unwrap();
+    fetched.retain(|_, (at, _)| at.elapsed() < TTL);
+    fetched.insert(server_id, (Instant::now(), roles.clone()));
+
+    Ok(roles)
+}
+
+/// A role in a server, or `None` if it doesn't exist.
+pub(crate) async fn role(cache_http: impl CacheHttp, server_id: GuildId, role_id: RoleId) -> ClassResult<Option<Role>> {
+    match cache_http.cache().and_then(|c| c.role(server_id, role_id)) {
+        Some(role) => Ok(Some(role)),
+        None => Ok(roles(cache_http, server_id).await?.remove(&role_id)),
+    }
+}
+
+/// A channel or category, or `None` if it doesn't exist or the bot can't see it.
+pub(crate) async fn channel(cache_http: impl CacheHttp, channel_id: ChannelId) -> ClassResult<Option<Channel>> {
+    if let Some(channel) = cache_http.cache().and_then(|c| c.channel(channel_id)) {
+        return Ok(Some(channel));
+    }
+
+    if let Some((_, channel)) = CHANNELS.lock().unwrap().get(&channel_id).filter(|(at, _)| at.elapsed() < TTL) {
+        return Ok(channel.clone());
+    }
+    let channel = match cache_http.http().get_channel(channel_id.0).await {
+        Ok(channel) => Some(channel),
+        Err(serenity::Error::Http(e))
+            if matches!(e.status_code(), Some(StatusCode::NOT_FOUND | StatusCode::FORBIDDEN)) => None,
+        Err(e) => Err(e)?,
+    };
+    let mut fetched = CHANNELS.lock().unwrap();
+    fetched.retain(|_, (at, _)| at.elapsed() < TTL);
+    fetched.insert(channel_id, (Instant::now(), channel.clone()));
+
+    Ok(channel)
+}
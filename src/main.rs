@@ -1,15 +1,19 @@
 #![deny(unused_must_use)]
+#![allow(clippy::result_large_err)]
 
-use std::borrow::Borrow;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{Duration, Utc};
 use dotenv::dotenv;
 use futures::future::join_all;
 use itertools::Itertools;
 use lazy_static::lazy_static;
 // use poise::serenity_prelude as p_serenity;
-use mongodb::bson::doc;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::ClientOptions;
 use mongodb::Client;
 use seq_macro::seq;
 use serenity::async_trait;
@@ -17,23 +21,140 @@ use serenity::builder::{CreateActionRow, CreateComponents, CreateSelectMenuOptio
 use serenity::client::Context as SContext;
 use serenity::http::CacheHttp;
 use serenity::model::application::component::ActionRowComponent;
-use serenity::model::application::interaction::Interaction;
-use serenity::model::channel::{Channel, ChannelType, GuildChannel};
-use serenity::model::guild::{Member, Role};
-use serenity::model::id::{GuildId, RoleId};
+use serenity::model::application::interaction::{Interaction, InteractionResponseType};
+use serenity::model::channel::{Attachment, Channel, ChannelType, GuildChannel, Message, ReactionType};
+use serenity::model::guild::{Guild, Member, Role};
+use serenity::model::event::MessageUpdateEvent;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
 use serenity::model::mention::Mention;
+use serenity::model::permissions::Permissions;
+use serenity::model::user::User;
 use serenity::model::prelude::component::{ButtonStyle, ComponentType};
+use serenity::model::voice::VoiceState;
+use serenity::model::webhook::Webhook;
 use serenity::prelude::*;
 use serenity::utils::MessageBuilder;
 use thiserror::Error;
 use tokio::sync::OnceCell;
 
 use crate::ClassError::InvalidChannelType;
-use crate::classes::{Class, Server};
-
+use crate::archive::{ArchivedMessage, MessageArchiveHandler};
+use crate::autotrack::AutoTrackHandler;
+use crate::badges::StaffBadgeHandler;
+use crate::calendar::CalendarBreak;
+use crate::classes::{emoji_limit, Class, Server, EMOJI_SIZE_LIMIT};
+use crate::courses::{course_for, Course};
+use crate::departments::{department_of, DepartmentMenuHandler, DepartmentTheme};
+use crate::email::{EmailFrequency, EmailSubscription};
+use crate::enrollment::{check_assignable, check_window, enrollment_button, EnrollmentButtonHandler, EnrollmentWindow};
+use crate::errors::ErrorContext;
+use crate::faq::FaqSuggestHandler;
+use crate::grants::PendingGrantHandler;
+use crate::hands::HandQueueHandler;
+use crate::help::HelpButtonHandler;
+use crate::helpthreads::{HelpThread, HelpThreadHandler};
+use crate::history::{EnrollmentEvent, EnrollmentMechanism};
+use crate::icebreakers::Icebreaker;
+use crate::invites::{ClassInvite, ClassInviteHandler};
+use crate::jobs::JobKind;
+use crate::joinlog::JoinLogHandler;
+use crate::modmail::ModmailHandler;
+use crate::orphans::OrphanFixHandler;
+use crate::pins::Pin;
+use crate::recordings::RecordingConsentHandler;
+use crate::redact::{log_error, log_info};
+use crate::renames::{ClassRenameHandler, RenameSync};
+use crate::requests::{ClassRequest, ClassRequestHandler};
+use crate::sessions::StudySessionRsvpHandler;
+use crate::suggestions::SuggestionVoteHandler;
+use crate::trash::TrashedClass;
+use crate::users::UserProfile;
+use crate::visibility::Visibility;
+use crate::voice::{VoiceTime, VoiceTimeHandler};
+use crate::welcome::WelcomeHandler;
+
+mod admin;
+mod announcements;
+mod archive;
+mod badges;
+mod assignments;
+mod audit;
+mod automod;
+mod autotrack;
+mod calendar;
+mod canvas;
+mod catalog;
+mod charts;
 mod classes;
-
-// const IS_DEV: bool = true;
+mod countdowns;
+mod courses;
+mod digest;
+mod departments;
+mod diag;
+mod dispatch;
+mod email;
+mod enrollment;
+mod escalation;
+mod errors;
+mod events;
+mod faq;
+mod federation;
+mod grader;
+mod grants;
+mod hands;
+mod help;
+mod helpthreads;
+mod history;
+mod i18n;
+mod icebreakers;
+mod invites;
+mod jobs;
+mod joinlog;
+mod latency;
+mod lookup;
+mod mentions;
+mod mentors;
+mod migrations;
+mod modmail;
+mod newclasses;
+mod notifications;
+mod ordering;
+mod orphans;
+mod peerreview;
+mod pins;
+mod policy;
+mod privacy;
+mod recordings;
+mod redact;
+mod renames;
+mod requests;
+mod rolequeue;
+mod say;
+mod scheduler;
+mod scoped;
+mod secrets;
+mod selfcheck;
+mod sessions;
+mod snippets;
+mod suggestions;
+mod tags;
+mod teams;
+mod tempgrants;
+mod templates;
+mod terms;
+mod trash;
+mod tutors;
+mod users;
+mod visibility;
+mod voice;
+mod webhooks;
+mod welcome;
+
+#[cfg(test)]
+mod tests;
+
+/// Marks roles and channels created in dev mode.
+const TEST_PREFIX: &str = "[TEST] ";
 
 lazy_static! {
     static ref ENV: EnvVars = EnvVars::init().unwrap();
@@ -41,73 +162,277 @@ lazy_static! {
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Data, Error>;
-struct Data {}
+struct Data {
+    /// The permissions each command requires unless a server's policy says otherwise, by the
+    /// command's full name.
+    required_permissions: HashMap<String, Permissions>,
+}
 
 struct EnvVars {
+    /// Whether the bot is running against the staging server. Set with `DEV_MODE=true`, which
+    /// makes the bot read `DEV_GUILD_ID` and `DEV_MONGODB_NAME` instead, and `DEV_BOT_TOKEN` if set.
+    dev_mode: bool,
     bot_token: String,
     guild_id: u64,
     mongodb_name: String,
     mongodb_user: String,
     mongodb_password: String,
+    /// A connection string to use instead of the hosted cluster, such as a local mongod.
+    mongodb_uri: Option<String>,
+    smtp_url: Option<String>,
+    smtp_from: Option<String>,
+    /// A base64 AES-256 key used to encrypt stored secrets such as webhook URLs.
+    secret_key: Option<String>,
+    /// Names `secret_key` in sealed secrets, so it can be told apart from older keys.
+    secret_key_id: String,
+    /// Keys replaced by `secret_key`, as `id:key,id:key`, kept until `rotate-secrets` has run.
+    old_secret_keys: Option<String>,
+    /// The port to listen for autograder results on. The endpoint is off if this isn't set.
+    grader_port: Option<u16>,
+    /// Sentry DSN to send unexpected errors to, on top of server alert channels.
+    sentry_dsn: Option<String>,
 }
 
 impl EnvVars {
     fn init() -> Result<Self, Error> {
         use std::env::var;
-        // use std::env::VarError;
-
-        // fn get_var(name: &str) -> Result<String, VarError> {
-        //     if IS_DEV {
-        //         var(format!("DEV_{}", name))
-        //     } else {
-        //         var(name)
-        //     }
-        // }
 
         if Path::new(".env").exists() {
             dotenv()?;
         }
 
+        let dev_mode = var("DEV_MODE").is_ok_and(|v| v == "true" || v == "1");
+        let get_var = |name: &str| if dev_mode {
+            var(format!("DEV_{}", name))
+        } else {
+            var(name)
+        };
+
+        let guild_id = get_var("GUILD_ID")?.parse::<u64>()?;
+        let mongodb_name = get_var("MONGODB_NAME")?;
+        // Refuse to run tests against the real server or database
+        if dev_mode && (var("GUILD_ID").ok() == Some(guild_id.to_string()) || var("MONGODB_NAME").ok() == Some(mongodb_name.clone())) {
+            return Err("DEV_GUILD_ID and DEV_MONGODB_NAME must differ from GUILD_ID and MONGODB_NAME".into());
+        }
+
         Ok(Self {
-            bot_token: var("BOT_TOKEN")?,
-            guild_id: var("GUILD_ID")?.parse::<u64>()?,
-            mongodb_name: var("MONGODB_NAME")?,
+            dev_mode,
+            bot_token: get_var("BOT_TOKEN").or_else(|_| var("BOT_TOKEN"))?,
+            guild_id,
+            mongodb_name,
             mongodb_user: var("MONGODB_USER")?,
             mongodb_password: var("MONGODB_PASSWORD")?,
+            mongodb_uri: var("MONGODB_URI").ok(),
+            smtp_url: var("SMTP_URL").ok(),
+            smtp_from: var("SMTP_FROM").ok(),
+            secret_key: var("SECRET_KEY").ok(),
+            secret_key_id: var("SECRET_KEY_ID").unwrap_or_else(|_| "1".to_string()),
+            old_secret_keys: var("OLD_SECRET_KEYS").ok(),
+            grader_port: var("GRADER_PORT").ok().map(|p| p.parse()).transpose()?,
+            sentry_dsn: var("SENTRY_DSN").ok(),
         })
     }
 }
 
+/// The name to give a role or channel the bot creates. In dev mode, everything is marked so test
+/// classes can't be mistaken for real ones.
+fn discord_name(name: &str) -> String {
+    if ENV.dev_mode && !name.starts_with(TEST_PREFIX) {
+        format!("{}{}", TEST_PREFIX, name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Undo `discord_name`, for comparing names from Discord with the ones stored for classes.
+fn stored_name(name: &str) -> &str {
+    if ENV.dev_mode {
+        name.strip_prefix(TEST_PREFIX).unwrap_or(name)
+    } else {
+        name
+    }
+}
+
 static MONGODB_CONN: OnceCell<Client> = OnceCell::const_new();
 
 async fn get_conn() -> Client {
     MONGODB_CONN
         .get_or_init(|| async {
-            Client::with_uri_str(format!(
+            let uri = ENV.mongodb_uri.clone().unwrap_or_else(|| format!(
                 "mongodb+srv://{}:{}@cs-discord.kev09.mongodb.net/?retryWrites=true&w=majority",
                 ENV.mongodb_user, ENV.mongodb_password,
-            ))
-            .await
-            .expect("Failed to connect to Mongo server.")
+            ));
+            let mut options = ClientOptions::parse(uri)
+                .await
+                .expect("Failed to connect to Mongo server.");
+            options.command_event_handler = Some(latency::mongo_handler());
+            Client::with_options(options).expect("Failed to connect to Mongo server.")
         })
         .await
         .clone()
 }
 
+/// An update setting every field of a document while leaving fields it doesn't have alone. Used
+/// instead of replacing documents, which would drop fields written by a newer version of the bot.
+fn set_all(document: &impl serde::Serialize) -> ClassResult<Document> {
+    let mut fields = mongodb::bson::to_document(document)?;
+    fields.remove("_id");
+    Ok(doc! { "$set": fields })
+}
+
+/// Whether the author of the command has the Manage Server permission.
+async fn is_manager(ctx: Context<'_>) -> bool {
+    ctx.author_member().await
+        .and_then(|m| m.permissions)
+        .map(|p| p.manage_guild())
+        .unwrap_or(false)
+}
+
+/// Whether the author of the command is staff for the given class, or can manage the server.
+async fn is_class_staff(ctx: Context<'_>, class: &Class) -> bool {
+    let member = match ctx.author_member().await {
+        Some(m) => m,
+        None => return false,
+    };
+
+    member.permissions.map(|p| p.manage_guild()).unwrap_or(false)
+        || class.staff_role.map(|r| member.roles.contains(&r)).unwrap_or(false)
+        || class.is_managed_by(member.user.id, &member.roles)
+}
+
+/// Whether a member may change a class: only its managers and those who can manage the server.
+/// Being on the class's staff isn't enough.
+fn may_manage_class(class: &Class, permissions: Permissions, user: UserId, roles: &[RoleId]) -> bool {
+    permissions.manage_guild() || class.is_managed_by(user, roles)
+}
+
+/// Command check for commands run on the class in their `class` argument, letting through the
+/// class's managers as well as those who can manage the server.
+async fn manages_class(ctx: Context<'_>) -> Result<bool, Error> {
+    let member = match ctx.author_member().await {
+        Some(m) => m,
+        None => return Ok(false),
+    };
+    let permissions = member.permissions.unwrap_or_else(Permissions::empty);
+    if permissions.manage_guild() {
+        return Ok(true);
+    }
+    let role = match ctx {
+        poise::Context::Application(actx) => actx.args.iter()
+            .find(|o| o.name == "class")
+            .and_then(|o| o.value.as_ref()?.as_str()?.parse().ok())
+            .map(RoleId),
+        poise::Context::Prefix(_) => None,
+    };
+    let class = match role {
+        Some(role) => Class::find_by_role(role).await?,
+        None => None,
+    };
+
+    Ok(match class {
+        Some(class) => may_manage_class(&class, permissions, member.user.id, &member.roles),
+        None => false,
+    })
+}
+
+/// Every command, with descriptions and localizations filled in from the string tables.
+fn commands() -> Vec<poise::Command<Data, Error>> {
+    let mut commands = vec![
+        register(),
+        class(),
+        config(),
+        modmail::contact(),
+        modmail::modmail(),
+        suggestions::suggest(),
+        suggestions::suggestion(),
+        sessions::studysession(),
+        tutors::tutor(),
+        peerreview::peerreview(),
+        teams::team(),
+        faq::faq(),
+        tags::tag(),
+        snippets::snippet(),
+        snippets::save_snippet(),
+        terms::term(),
+        admin::admin(),
+        federation::federation(),
+        automod::automod(),
+        privacy::privacy(),
+        helpthreads::solved(),
+        mentors::mentor(),
+        assignments::assignment(),
+        notifications::notifications(),
+        announcements::announce(),
+        hands::hand(),
+        catalog::catalog(),
+        badges::badges(),
+        say::say(),
+        say::embed(),
+    ];
+    i18n::localize(&mut commands);
+    commands
+}
+
 #[tokio::main]
 async fn main() {
-    println!("Hello, world!");
+    log_info!("Hello, world!");
+
+    diag::mark_started();
+    latency::install();
+    migrations::run().await.expect("Error running database migrations");
+
+    if std::env::args().nth(1).as_deref() == Some("rotate-secrets") {
+        let rotated = secrets::rotate().await.expect("Error rotating secrets");
+        log_info!("Re-wrapped {} secrets with the current key.", rotated);
+        return;
+    }
 
-    let commands = vec![echo(), register(), class(), config()];
+    let mut commands = commands();
+    let required_permissions = policy::take_required_permissions(&mut commands);
     let create_commands = poise::builtins::create_application_commands(&commands);
 
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
             commands,
+            command_check: Some(|ctx| Box::pin(policy::check(ctx))),
+            pre_command: |_ctx| Box::pin(async { latency::start() }),
+            post_command: |ctx| Box::pin(async move { latency::finish(&ctx.command().qualified_name) }),
+            on_error: |error| Box::pin(async move {
+                // Poise doesn't tell the member when a check fails
+                if let poise::FrameworkError::CommandCheckFailed { error: None, ctx } = &error {
+                    let reply = ctx.send(|m| m.content(ClassError::MissingPermissions.to_string()).ephemeral(true)).await;
+                    if let Err(e) = reply {
+                        log_error!("Error replying to a failed check: {}", e);
+                    }
+                    return;
+                }
+                if let poise::FrameworkError::Command { error, ctx } = &error {
+                    latency::finish(&ctx.command().qualified_name);
+                    // Errors with a known fix are a setting to change, not something to report
+                    if let Some(e) = error.downcast_ref::<ClassError>() {
+                        if help::reply_with_remediation(*ctx, e).await {
+                            return;
+                        }
+                    }
+                    let context = ErrorContext {
+                        server_id: ctx.guild_id(),
+                        command: Some(ctx.command().qualified_name.clone()),
+                        user: Some(ctx.author().id),
+                    };
+                    errors::report(&ctx.command().qualified_name, context, &error.to_string()).await;
+                }
+                if let Err(e) = poise::builtins::on_error(error).await {
+                    log_error!("Error while handling error: {}", e);
+                }
+            }),
             ..Default::default()
         })
         .token(&ENV.bot_token)
-        .intents(GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT)
+        .intents(
+            GatewayIntents::non_privileged()
+                | GatewayIntents::MESSAGE_CONTENT
+                | GatewayIntents::GUILD_MEMBERS
+        )
         .client_settings(|c| c.event_handler(Handler))
         // .client_settings(|c| c
         //     .event_handler(ClassMenuButtonHandler)
@@ -123,7 +448,12 @@ async fn main() {
                     .await
                     .expect("Error registering guild commands");
 
-                Ok(Data {})
+                errors::init(ctx.http.clone());
+                events::start_subscribers(ctx);
+                scheduler::start(ctx.clone());
+                grader::start(ctx.clone());
+
+                Ok(Data { required_permissions })
             })
         })
         .build()
@@ -144,12 +474,6 @@ async fn register(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-#[poise::command(slash_command)]
-async fn echo(context: Context<'_>, text: String) -> Result<(), Error> {
-    context.say(format!("{}{}", &text, text)).await?;
-    Ok(())
-}
-
 // macro_rules! repeat_arg {
 //     ($name:ident: $type:ty, $num:expr) => { $name$num: $type };
 //     ($name:ident: $type:ty, $num:expr, $($nums:expr),+) => { $name$num: $type, repeat_arg!($name: $type, $num $($nums),+) };
@@ -165,6 +489,25 @@ async fn echo(context: Context<'_>, text: String) -> Result<(), Error> {
         "ClassCommand::untrack",
         "ClassCommand::delete",
         "ClassCommand::menu",
+        "ClassCommand::request",
+        "ClassCommand::invite",
+        "ClassCommand::voicestats",
+        "ClassCommand::intersect",
+        "ClassCommand::difference",
+        "ClassCommand::transfer",
+        "ClassCommand::edit",
+        "ClassCommand::favorite",
+        "ClassCommand::search",
+        "ClassCommand::deleted",
+        "ClassCommand::searchmsg",
+        "ClassCommand::restore",
+        "ClassCommand::history",
+        "ClassCommand::icebreaker",
+        "ClassCommand::helpstats",
+        "ClassCommand::webhook",
+        "ClassCommand::managers",
+        "ClassCommand::pin",
+        "ClassCommand::unpin",
     )
 )]
 async fn class(_ctx: Context<'_>) -> Result<(), Error> {
@@ -180,7 +523,7 @@ impl ClassCommand {
         ctx.defer_ephemeral().await?;
 
         let mention = mention.unwrap_or(false);
-        let classes = Class::list(ctx.guild().ok_or(ClassError::NoServer)?.id).await?;
+        let classes = Class::list(ctx.guild_id().ok_or(ClassError::NoServer)?).await?;
 
         if classes.is_empty() {
             ctx.say("No classes found for this server.").await?;
@@ -207,18 +550,35 @@ impl ClassCommand {
         ctx.defer_ephemeral().await?;
 
         let mention = mention.unwrap_or(false);
-        let guild = ctx.guild().ok_or(ClassError::NoServer)?;
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
         let role = class;
         let class = Class::find_by_role(role.id).await?.ok_or(ClassError::InvalidClass)?;
 
+        let mut categories = Vec::new();
+        for category in &class.categories {
+            match lookup::channel(ctx.discord(), *category).await? {
+                Some(Channel::Category(c)) => categories.push(format!("`{}`", c.name())),
+                Some(_) => Err(ClassError::InvalidChannelType(category.mention()))?,
+                None => Err(ClassError::InvalidChannel(category.mention()))?,
+            }
+        }
+        let staff_role = match class.staff_role {
+            Some(r) if mention => r.mention().to_string(),
+            Some(r) => lookup::role(ctx.discord(), server_id, r).await?
+                .map(|r| format!("`{}`", r.name))
+                .unwrap_or_else(|| r.mention().to_string()),
+            None => "None".to_string(),
+        };
+
         let message = format!(
             r#"
 Name: \"{}\",
 Short name: \"{}\",
 Role: {},
-Category: `{}`,
+Categories: {},
 Text Channels: {},
 Voice Channels: {},
+Staff Role: {},
 "#,
             class.name,
             class.short_name,
@@ -227,26 +587,25 @@ Voice Channels: {},
             } else {
                 format!("`{}`", role.name)
             },
-            guild.channels.get(&class.category)
-                .ok_or_else(|| ClassError::InvalidChannel(class.category.mention()))
-                .and_then(|c| match c {
-                    Channel::Category(cc) => Ok(cc.name()),
-                    _ => Err(ClassError::InvalidChannelType(class.category.mention())),
-                })?,
+            categories.join(", "),
             class.text_channels.iter()
                 .map(|c| c.mention())
                 .join(", "),
             class.voice_channels.iter()
                 .map(|c| c.mention())
                 .join(", "),
+            staff_role,
         );
 
-        ctx.say(
-            MessageBuilder::new()
-                .push_bold("Class info:")
-                .quote_rest()
-                .push(&message[1..])
-                .build()
+        ctx.send(|m| m
+            .content(
+                MessageBuilder::new()
+                    .push_bold("Class info:")
+                    .quote_rest()
+                    .push(&message[1..])
+                    .build()
+            )
+            .components(|c| c.create_action_row(|r| enrollment_button(r, &class)))
         ).await?;
 
         Ok(())
@@ -258,10 +617,19 @@ Voice Channels: {},
         required_permissions = "MANAGE_GUILD",
         required_bot_permissions = "MANAGE_GUILD",
     )]
-    async fn create(ctx: Context<'_>, name: String) -> Result<(), Error> {
+    async fn create(
+        ctx: Context<'_>,
+        #[autocomplete = "courses::autocomplete_code"] name: String,
+        visibility: Option<Visibility>,
+    ) -> Result<(), Error> {
         ctx.defer_ephemeral().await?;
 
-        Class::create(ctx, &name).await?;
+        let guild = lookup::current_guild(ctx).await?;
+        let mut class = Class::create(ctx.discord(), &guild, &name, visibility.unwrap_or_default()).await?;
+        // Describe the class from the official catalog, if it's in there
+        if let Some(course) = course_for(&class.name, &Course::list(guild.id).await?) {
+            class.set_description(Some(course.summary())).await?;
+        }
 
         ctx.say(format!("Created new class \"{}\"", name)).await?;
 
@@ -295,6 +663,8 @@ Voice Channels: {},
         #[channel_types("Text", "Voice")] channel13: Option<GuildChannel>,
         #[channel_types("Text", "Voice")] channel14: Option<GuildChannel>,
         #[channel_types("Text", "Voice")] channel15: Option<GuildChannel>,
+        #[description = "Change the category's permissions to match. Left as they are if not given."]
+        visibility: Option<Visibility>,
     ) -> Result<(), Error> {
         ctx.defer_ephemeral().await?;
 
@@ -310,7 +680,8 @@ Voice Channels: {},
             return Err(ClassError::InvalidChannelType(category.mention()))?;
         };
 
-        let class = Class::track(ctx, name, role, category, &channels).await?;
+        let guild = lookup::current_guild(ctx).await?;
+        let class = Class::track(ctx.discord(), &guild, name, role, category, &channels, visibility).await?;
 
         ctx.say(format!("Now tracking class \"{}\"", class.name)).await?;
 
@@ -367,213 +738,2524 @@ Voice Channels: {},
         Ok(())
     }
 
+    #[poise::command(slash_command, subcommands("ClassMenuCommand::post", "ClassMenuCommand::post_all"))]
+    async fn menu(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
     #[poise::command(
         slash_command,
         ephemeral,
-        required_permissions = "MANAGE_GUILD",
     )]
-    async fn menu(ctx: Context<'_>, #[channel_types("Text")] channel: Option<GuildChannel>) -> Result<(), Error> {
-        let guild = ctx.guild().ok_or(ClassError::NoServer)?;
-        let channel = channel.unwrap_or(
-            guild.channels.get(&ctx.channel_id())
-                .ok_or_else(|| ClassError::InvalidChannel(ctx.channel_id().mention()))
-                .and_then(|c| c.clone().guild().ok_or_else(|| InvalidChannelType(c.mention())))?
-        );
-        if channel.kind != ChannelType::Text {
-            Err(ClassError::InvalidChannelType(channel.mention()))?;
-        }
-
-        let http = ctx.discord().http();
+    async fn request(ctx: Context<'_>, name: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
 
-        channel.send_message(http, |m| m
-            .components(|c| c
-                .create_action_row(|r| r
-                    .create_button(|b| b
-                        .custom_id("class_menu_button")
-                        .style(ButtonStyle::Primary)
-                        .label("Click here to choose classes!")
-                        .emoji('📝') // U+1F4DD : MEMO
-                    )
-                )
-            )
+        let request = ClassRequest::submit(
+            ctx.discord(),
+            ctx.guild_id().ok_or(ClassError::NoServer)?,
+            ctx.author().id,
+            &name,
         ).await?;
 
-        ctx.say("Done!").await?;
+        ctx.say(format!("Requested new class \"{}\". Staff will review it shortly.", request.name)).await?;
 
         Ok(())
     }
-}
 
-#[poise::command(slash_command, subcommands("ConfigCommand::refrole"))]
-async fn config(_ctx: Context<'_>) -> Result<(), Error> {
-    Ok(())
-}
-struct ConfigCommand;
-impl ConfigCommand {
-    #[poise::command(slash_command, subcommands("ConfigRefroleCommand::set"))]
-    async fn refrole(_ctx: Context<'_>) -> Result<(), Error> {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        required_bot_permissions = "MANAGE_GUILD | CREATE_INSTANT_INVITE",
+    )]
+    async fn invite(
+        ctx: Context<'_>,
+        class: Role,
+        #[channel_types("Text")] channel: Option<GuildChannel>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let channel = match channel {
+            Some(c) => c,
+            None => match lookup::channel(ctx.discord(), ctx.channel_id()).await? {
+                Some(Channel::Guild(c)) => c,
+                _ => Err(ClassError::InvalidChannel(ctx.channel_id().mention()))?,
+            },
+        };
+
+        let invite = ClassInvite::create(ctx.discord(), &class, &channel, ctx.author().id).await?;
+
+        ctx.say(format!(
+            "Members who join through {} will automatically be added to \"{}\".",
+            invite.url(),
+            class.name,
+        )).await?;
+
         Ok(())
     }
-}
 
-struct ConfigRefroleCommand;
-impl ConfigRefroleCommand {
     #[poise::command(
         slash_command,
         ephemeral,
-        required_permissions = "MANAGE_GUILD",
-        required_bot_permissions = "MANAGE_GUILD",
     )]
-    async fn set(ctx: Context<'_>, role: Role) -> Result<(), Error> {
-        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
-            .await?;
-        server
-            .set_refrole(ctx, role.id)
-            .await?;
+    async fn voicestats(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
 
-        ctx.say(format!("{} is now the refrole for this server.", role.mention())).await?;
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let leaderboard = VoiceTime::leaderboard(&class, 10).await?;
+
+        let content = if leaderboard.is_empty() {
+            format!("Nobody has spent time in the voice channels for \"{}\" yet.", class.name)
+        } else {
+            format!(
+                "**Voice time for \"{}\":**\n{}",
+                class.name,
+                leaderboard.iter()
+                    .enumerate()
+                    .map(|(i, t)| format!("{}. {} — {} minutes", i + 1, t.user.mention(), t.minutes()))
+                    .join("\n"),
+            )
+        };
+
+        ctx.send(|m| m
+            .content(content)
+            .components(|c| c.create_action_row(|r| enrollment_button(r, &class)))
+        ).await?;
 
         Ok(())
     }
-}
 
-struct Handler;
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn intersect(ctx: Context<'_>, class1: Role, class2: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
 
-#[async_trait]
-impl EventHandler for Handler {
-    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
-        join_all(vec![
-            EventHandler::interaction_create(&ClassMenuButtonHandler, ctx.clone(), interaction.clone()),
-            EventHandler::interaction_create(&ClassMenuHandler, ctx.clone(), interaction.clone()),
-        ]).await;
+        let guild = lookup::current_guild(ctx).await?;
+        let class1 = Class::find_by_role(class1.id).await?.ok_or(ClassError::InvalidClass)?;
+        let class2 = Class::find_by_role(class2.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        let members = &class_members(&guild, class1.role) & &class_members(&guild, class2.role);
+
+        ctx.say(format!(
+            "{} members are in both \"{}\" and \"{}\": {}",
+            members.len(),
+            class1.name,
+            class2.name,
+            member_list(&members),
+        )).await?;
+
+        Ok(())
     }
-}
 
-struct ClassMenuButtonHandler;
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn favorite(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
 
-#[async_trait]
-impl EventHandler for ClassMenuButtonHandler {
-    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
-        let component = if let Interaction::MessageComponent(c) = interaction {
-            c
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        if UserProfile::toggle_favorite(ctx.author().id, class.role).await? {
+            ctx.say(format!("\"{}\" will now be shown first in the class menu.", class.name)).await?;
         } else {
-            return;
-        };
-        if component.data.component_type != ComponentType::Button || component.data.custom_id != "class_menu_button" {
-            return;
+            ctx.say(format!("\"{}\" is no longer a favorite.", class.name)).await?;
         }
 
-        let http = ctx.http();
+        Ok(())
+    }
 
-        // Throw away the result as deferring is not critical
-        // component.defer(http).await.ok();
+    /// Restore a class deleted in the last 30 days. Members are not re-enrolled.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        required_bot_permissions = "MANAGE_ROLES | MANAGE_CHANNELS",
+    )]
+    async fn restore(ctx: Context<'_>, name: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
 
-        let member = if let Some(m) = &component.member {
-            m
-        } else {
-            eprintln!("Error handling class_menu_button: {:?}", ClassError::NoServer);
-            return;
-        };
+        let guild = lookup::current_guild(ctx).await?;
+        let class = TrashedClass::restore(ctx.discord(), &guild, &name).await?;
 
-        let server_id = if let Some(id) = component.guild_id {
-            id
-        } else {
-            eprintln!("Error handling class_menu_button: {:?}", ClassError::NoServer);
-            return;
-        };
+        ctx.say(format!("Restored class \"{}\" as {}.", class.name, class.role.mention())).await?;
 
-        let menu = match build_class_menu(server_id, member).await {
-            Ok(m) => m,
-            Err(e) => {
-                eprintln!("Error handling class_menu_button: {:?}", e);
-                return;
-            }
+        Ok(())
+    }
+
+    /// Show recent enrollment changes for a member, a class, or the whole server.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn history(ctx: Context<'_>, user: Option<User>, class: Option<Role>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let class = match class {
+            Some(role) => Some(Class::find_by_role(role.id).await?.ok_or(ClassError::InvalidClass)?),
+            None => None,
         };
+        let allowed = match &class {
+            Some(class) => is_class_staff(ctx, class).await,
+            None => is_manager(ctx).await,
+        };
+        if !allowed {
+            Err(ClassError::MissingPermissions)?;
+        }
 
-        // Throwing away the error as if the deletion fails, we will know from the error message
-        // from creating the new response
-        // component.delete_original_interaction_response(http).await.ok();
-        if let Err(e) = component.create_interaction_response(http, |r| r.interaction_response_data(|d| d
-            .ephemeral(true)
-            .set_components(menu)
-        )).await {
-            eprintln!("Error handling class_menu_button: {:?}", e);
-            return;
+        let events = EnrollmentEvent::recent(
+            server_id,
+            user.as_ref().map(|u| u.id),
+            class.as_ref().map(|c| c.role),
+            20,
+        ).await?;
+
+        if events.is_empty() {
+            ctx.say("No enrollment changes have been recorded.").await?;
+        } else {
+            let names = Class::list(server_id).await?
+                .into_iter()
+                .map(|c| (c.role, c.name))
+                .collect::<HashMap<_, _>>();
+            ctx.say(events.iter()
+                .map(|e| format!(
+                    "<t:{}:f> {} {} {}{}{}",
+                    e.at.timestamp_millis() / 1000,
+                    e.user.mention(),
+                    if e.joined { "joined" } else { "left" },
+                    names.get(&e.role).map(|n| format!("\"{}\"", n)).unwrap_or_else(|| e.role.mention().to_string()),
+                    e.mechanism.map(|m| format!(" via {}", m.describe())).unwrap_or_default(),
+                    e.actor.filter(|a| *a != e.user).map(|a| format!(" by {}", a.mention())).unwrap_or_default(),
+                ))
+                .join("\n")
+            ).await?;
         }
+
+        Ok(())
     }
-}
 
-async fn build_class_menu(server_id: GuildId, member: &Member) -> ClassResult<CreateComponents> {
-    let member_roles = member.roles.iter().collect::<HashSet<_>>();
+    /// Post an intro prompt in a class and a regular question of the day from the server's pool.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn icebreaker(
+        ctx: Context<'_>,
+        class: Role,
+        enabled: bool,
+        #[min = 1] every_days: Option<u32>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
 
-    let action_rows = Class::list(server_id).await?
-        .iter()
-        .sorted_by(|c1, c2| human_sort::compare(&c1.name, &c2.name))
-        .map(|c| {
-            let mut o = CreateSelectMenuOption::new(&c.name, c.role.to_string());
-            o.default_selection(member_roles.contains(&c.role));
-            o
-        })
-        .chunks(25)
-        .borrow()
-        .into_iter()
-        .map(|chunk| chunk.collect::<Vec<_>>())
-        .enumerate()
-        .map(|(i, chunk)| {
-            let mut row = CreateActionRow::default();
-            row.create_select_menu(|m| m
-                .custom_id(format!("class_menu_button_{}", i))
-                .min_values(0)
-                .max_values(chunk.len() as u64)
-                .options(|o| o.set_options(chunk))
-            );
-            row
-        })
-        .collect::<Vec<_>>();
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        if !is_class_staff(ctx, &class).await {
+            Err(ClassError::MissingPermissions)?;
+        }
 
-    let mut cc = CreateComponents::default();
-    cc.set_action_rows(action_rows);
+        if enabled {
+            let every_days = every_days.unwrap_or(1);
+            Icebreaker::enable(ctx.discord(), &class, every_days).await?;
+            ctx.say(format!(
+                "Posted an intro prompt in \"{}\". A question of the day will be posted every {} days.",
+                class.name,
+                every_days,
+            )).await?;
+        } else if Icebreaker::disable(class.role).await? {
+            ctx.say(format!("Questions of the day will no longer be posted in \"{}\".", class.name)).await?;
+        } else {
+            ctx.say(format!("Questions of the day aren't being posted in \"{}\".", class.name)).await?;
+        }
 
-    Ok(cc)
-}
+        Ok(())
+    }
 
-struct ClassMenuHandler;
+    /// Add a message to its channel's pinned index, with a description.
+    #[poise::command(slash_command, ephemeral)]
+    async fn pin(
+        ctx: Context<'_>,
+        #[description = "A link to the message, from Copy Message Link"] link: String,
+        #[description = "What the message is, shown in the index"] description: String,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
 
-#[async_trait]
-impl EventHandler for ClassMenuHandler {
-    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
-        let component = if let Interaction::MessageComponent(c) = interaction {
-            c
-        } else {
-            return;
-        };
-        if component.data.component_type != ComponentType::SelectMenu {
-            return;
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let (link_server, channel, message) = pins::parse_message_link(&link)
+            .filter(|(s, _, _)| *s == server_id)
+            .ok_or(ClassError::InvalidMessageLink)?;
+        let class = Class::find_by_text_channel(channel).await?.ok_or(ClassError::NotClassChannel)?;
+        if !is_class_staff(ctx, &class).await {
+            Err(ClassError::MissingPermissions)?;
         }
 
-        let custom_id = &*component.data.custom_id;
+        Pin::add(ctx.discord(), link_server, channel, message, description.trim(), ctx.author().id).await?;
+        ctx.say(format!("Added the message to the pinned index in {}.", channel.mention())).await?;
 
-        let _id = if let Some(id) = parse_class_button_id(custom_id) {
-            id
-        } else {
-            return;
-        };
+        Ok(())
+    }
 
-        let http = ctx.http();
+    /// Take a message off its channel's pinned index.
+    #[poise::command(slash_command, ephemeral)]
+    async fn unpin(
+        ctx: Context<'_>,
+        #[description = "A link to the message, from Copy Message Link"] link: String,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
 
-        // Throwing away the result as if the defer fails, the user will see an error message
-        // regardless of how the error is handled, so we might as well finish handling the input
-        component.defer(http).await.ok();
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let (_, channel, message) = pins::parse_message_link(&link)
+            .filter(|(s, _, _)| *s == server_id)
+            .ok_or(ClassError::InvalidMessageLink)?;
+        let class = Class::find_by_text_channel(channel).await?.ok_or(ClassError::NotClassChannel)?;
+        if !is_class_staff(ctx, &class).await {
+            Err(ClassError::MissingPermissions)?;
+        }
 
-        let member = if let Some(m) = &component.member {
-            m
+        if Pin::remove(ctx.discord(), server_id, channel, message).await? {
+            ctx.say(format!("Removed the message from the pinned index in {}.", channel.mention())).await?;
         } else {
-            eprintln!("Error handling {}: {:?}", custom_id, ClassError::NoServer);
-            return;
-        };
+            ctx.say("That message isn't in the pinned index.").await?;
+        }
+
+        Ok(())
+    }
+
+    /// Show how a class's homework-help threads have gone recently.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn helpstats(ctx: Context<'_>, class: Role, #[min = 1] days: Option<u32>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        if !is_class_staff(ctx, &class).await {
+            Err(ClassError::MissingPermissions)?;
+        }
+        let days = days.unwrap_or(30);
+        let since = Utc::now() - Duration::days(days as i64);
+        let stats = HelpThread::stats(class.role, DateTime::from_millis(since.timestamp_millis())).await?;
+
+        ctx.say(format!(
+            "**Homework help in \"{}\" over the last {} days**\nSolved: {}\nOpen: {}\nMedian time to first response: {}\nTop answerers:\n{}",
+            class.name,
+            days,
+            stats.solved,
+            stats.open,
+            stats.median_response
+                .map(|s| format!("{}h {}m", s / 3600, s % 3600 / 60))
+                .unwrap_or_else(|| "no responses yet".to_string()),
+            if stats.top_answerers.is_empty() {
+                "None yet".to_string()
+            } else {
+                stats.top_answerers.iter()
+                    .map(|(user, count)| format!("{} ({} threads)", user.mention(), count))
+                    .join("\n")
+            },
+        )).await?;
+
+        Ok(())
+    }
+
+    /// Show recently deleted messages from a class's archive.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn deleted(ctx: Context<'_>, class: Role, author: Option<User>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        if !is_class_staff(ctx, &class).await {
+            Err(ClassError::MissingPermissions)?;
+        }
+        let messages = ArchivedMessage::deleted(class.role, author.map(|u| u.id), 10).await?;
+
+        if messages.is_empty() {
+            ctx.say(format!("There are no deleted messages archived for \"{}\".", class.name)).await?;
+        } else {
+            ctx.say(format!(
+                "Recently deleted messages in \"{}\":\n{}",
+                class.name,
+                messages.iter()
+                    .map(|m| format!(
+                        "{} in {} <t:{}:f>: {}{}",
+                        m.author.mention(),
+                        m.channel_id.mention(),
+                        m.at.timestamp_millis() / 1000,
+                        m.content.chars().take(200).collect::<String>(),
+                        m.attachments.iter().map(|a| format!(" [{}]", a.filename)).collect::<String>(),
+                    ))
+                    .join("\n"),
+            )).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Search a class's archived messages.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn searchmsg(ctx: Context<'_>, class: Role, query: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let is_member = ctx.author_member().await
+            .map(|m| m.roles.contains(&class.role))
+            .unwrap_or(false);
+        if !is_member && !is_class_staff(ctx, &class).await {
+            Err(ClassError::MissingPermissions)?;
+        }
+        let messages = ArchivedMessage::search(class.role, &query, 10).await?;
+
+        if messages.is_empty() {
+            ctx.say(format!("No archived messages in \"{}\" match that search.", class.name)).await?;
+        } else {
+            ctx.say(format!(
+                "Archived messages in \"{}\" matching \"{}\":\n{}",
+                class.name,
+                query,
+                messages.iter()
+                    .map(|m| format!(
+                        "{} <t:{}:d>: {} ([jump]({}))",
+                        m.author.mention(),
+                        m.at.timestamp_millis() / 1000,
+                        m.content.chars().take(150).collect::<String>(),
+                        m.link(),
+                    ))
+                    .join("\n"),
+            )).await?;
+        }
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn search(ctx: Context<'_>, tag: Option<String>, name: Option<String>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let tag = tag.map(|t| t.trim().to_lowercase());
+        let name = name.map(|n| n.trim().to_lowercase());
+        let classes = Class::list(ctx.guild_id().ok_or(ClassError::NoServer)?).await?
+            .into_iter()
+            .filter(|c| tag.as_ref().map(|t| c.tags.contains(t)).unwrap_or(true))
+            .filter(|c| name.as_ref().map(|n| c.name.to_lowercase().contains(n)).unwrap_or(true))
+            .sorted_by(|c1, c2| human_sort::compare(&c1.name, &c2.name))
+            .collect::<Vec<_>>();
+
+        if classes.is_empty() {
+            ctx.say("No matching classes found.").await?;
+        } else {
+            ctx.say(format!(
+                "Found {} classes:\n{}",
+                classes.len(),
+                classes.iter()
+                    .map(|c| if c.tags.is_empty() {
+                        c.name.clone()
+                    } else {
+                        format!("{} ({})", c.name, c.tags.join(", "))
+                    })
+                    .join("\n"),
+            )).await?;
+        }
+
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ClassTransferCommand::role", "ClassTransferCommand::category"))]
+    async fn transfer(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ClassWebhookCommand::create"))]
+    async fn webhook(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        subcommands("ClassManagersCommand::add", "ClassManagersCommand::remove", "ClassManagersCommand::list")
+    )]
+    async fn managers(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        subcommands(
+            "ClassEditCommand::description",
+            "ClassEditCommand::staff",
+            "ClassEditCommand::tag",
+            "ClassEditCommand::archive",
+            "ClassEditCommand::grader",
+            "ClassEditCommand::visibility",
+            "ClassEditCommand::emoji",
+            "ClassEditCommand::badge",
+        )
+    )]
+    async fn edit(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn difference(ctx: Context<'_>, class: Role, without: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let guild = lookup::current_guild(ctx).await?;
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let without = Class::find_by_role(without.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        let members = &class_members(&guild, class.role) - &class_members(&guild, without.role);
+
+        ctx.say(format!(
+            "{} members are in \"{}\" but not \"{}\": {}",
+            members.len(),
+            class.name,
+            without.name,
+            member_list(&members),
+        )).await?;
+
+        Ok(())
+    }
+}
+
+struct ClassMenuCommand;
+impl ClassMenuCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn post(ctx: Context<'_>, #[channel_types("Text")] channel: Option<GuildChannel>) -> Result<(), Error> {
+        let guild = lookup::current_guild(ctx).await?;
+        let channel = match channel {
+            Some(c) => c,
+            None => match lookup::channel(ctx.discord(), ctx.channel_id()).await? {
+                Some(Channel::Guild(c)) => c,
+                Some(c) => Err(InvalidChannelType(c.mention()))?,
+                None => Err(ClassError::InvalidChannel(ctx.channel_id().mention()))?,
+            },
+        };
+        if channel.kind != ChannelType::Text {
+            Err(ClassError::InvalidChannelType(channel.mention()))?;
+        }
+
+        let http = ctx.discord().http();
+
+        let message = channel.send_message(http, |m| m
+            .components(|c| c
+                .create_action_row(|r| r
+                    .create_button(|b| b
+                        .custom_id("class_menu_button")
+                        .style(ButtonStyle::Primary)
+                        .label("Click here to choose classes!")
+                        .emoji('📝') // U+1F4DD : MEMO
+                    )
+                )
+            )
+        ).await?;
+        Server::get_or_create(guild.id).await?
+            .set_menu_message(channel.id, message.id)
+            .await?;
+
+        ctx.say("Done!").await?;
+
+        Ok(())
+    }
+
+    /// Post or refresh one class menu per department in a channel.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        rename = "post-all",
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn post_all(ctx: Context<'_>, #[channel_types("Text")] channel: Option<GuildChannel>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let channel = match channel {
+            Some(c) => c.id,
+            None => Server::get_or_create(server_id).await?.menu_channel.unwrap_or(ctx.channel_id()),
+        };
+        let posted = departments::post_all(ctx.discord(), server_id, channel).await?;
+
+        ctx.say(format!("Posted menus for {} departments in {}.", posted, channel.mention())).await?;
+
+        Ok(())
+    }
+}
+
+struct ClassWebhookCommand;
+impl ClassWebhookCommand {
+    /// Create a webhook that posts into a class's general channel, replacing any earlier one.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn create(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let channel = *class.text_channels.first().ok_or(ClassError::NoClassChannels)?;
+        let http = ctx.discord().http();
+
+        if let Some(old) = class.webhook.as_deref().and_then(|w| secrets::open(w).ok()) {
+            // Throwing away the result as the old webhook may already have been deleted
+            if let Ok(old) = Webhook::from_url(http, &old).await {
+                old.delete(http).await.ok();
+            }
+        }
+
+        let webhook = channel.create_webhook(http, &class.name).await?;
+        let url = webhook.url()?;
+        class.set_webhook(Some(secrets::seal(&url)?)).await?;
+
+        ctx.say(format!(
+            "Created a webhook for \"{}\" posting in {}. Use `/admin webhook` to get its URL.",
+            class.name,
+            channel.mention(),
+        )).await?;
+
+        Ok(())
+    }
+}
+
+/// Managers can edit their class, and run its schedule, resource and announcement commands, without
+/// Manage Server.
+struct ClassManagersCommand;
+impl ClassManagersCommand {
+    /// Let a member, or everyone with a role, manage a class.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn add(ctx: Context<'_>, class: Role, user: Option<User>, role: Option<Role>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let (mut managers, mut manager_roles) = (class.managers.clone(), class.manager_roles.clone());
+        let added = match (user, role) {
+            (Some(user), None) => {
+                managers.push(user.id);
+                user.mention()
+            }
+            (None, Some(role)) => {
+                manager_roles.push(role.id);
+                role.mention()
+            }
+            _ => Err(ClassError::NoManagerGiven)?,
+        };
+        class.set_managers(managers.into_iter().unique().collect(), manager_roles.into_iter().unique().collect()).await?;
+
+        ctx.say(format!("{} can now manage \"{}\".", added, class.name)).await?;
+
+        Ok(())
+    }
+
+    /// Stop a member, or a role, from managing a class.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn remove(ctx: Context<'_>, class: Role, user: Option<User>, role: Option<Role>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let (mut managers, mut manager_roles) = (class.managers.clone(), class.manager_roles.clone());
+        let removed = match (user, role) {
+            (Some(user), None) if managers.contains(&user.id) => {
+                managers.retain(|u| *u != user.id);
+                user.mention()
+            }
+            (None, Some(role)) if manager_roles.contains(&role.id) => {
+                manager_roles.retain(|r| *r != role.id);
+                role.mention()
+            }
+            (Some(_), None) | (None, Some(_)) => Err(ClassError::InvalidManager)?,
+            _ => Err(ClassError::NoManagerGiven)?,
+        };
+        class.set_managers(managers, manager_roles).await?;
+
+        ctx.say(format!("{} can no longer manage \"{}\".", removed, class.name)).await?;
+
+        Ok(())
+    }
+
+    /// List who can manage a class.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn list(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let managers = class.managers.iter()
+            .map(|u| u.mention())
+            .chain(class.manager_roles.iter().map(|r| r.mention()))
+            .join(", ");
+
+        if managers.is_empty() {
+            ctx.say(format!("\"{}\" has no managers.", class.name)).await?;
+        } else {
+            ctx.say(format!("\"{}\" is managed by {}.", class.name, managers)).await?;
+        }
+
+        Ok(())
+    }
+}
+
+struct ClassEditCommand;
+impl ClassEditCommand {
+    /// Set the description shown for a class in the class menu.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        check = "manages_class",
+    )]
+    async fn description(ctx: Context<'_>, class: Role, description: Option<String>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        class.set_description(description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty())).await?;
+
+        if class.description.is_some() {
+            ctx.say(format!("Updated the description of \"{}\".", class.name)).await?;
+        } else {
+            ctx.say(format!("Cleared the description of \"{}\".", class.name)).await?;
+        }
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn staff(ctx: Context<'_>, class: Role, staff_role: Option<Role>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        class.set_staff_role(staff_role.as_ref().map(|r| r.id)).await?;
+
+        if let Some(role) = staff_role {
+            ctx.say(format!("{} is now the staff role for class \"{}\".", role.mention(), class.name)).await?;
+        } else {
+            ctx.say(format!("Cleared the staff role for class \"{}\".", class.name)).await?;
+        }
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        check = "manages_class",
+    )]
+    async fn tag(ctx: Context<'_>, class: Role, tags: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        class.set_tags(tags.split(',')).await?;
+
+        if class.tags.is_empty() {
+            ctx.say(format!("Removed all tags from \"{}\".", class.name)).await?;
+        } else {
+            ctx.say(format!(
+                "\"{}\" is now tagged {}.",
+                class.name,
+                class.tags.iter().map(|t| format!("`{}`", t)).join(", "),
+            )).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Turn archiving of the class's messages on or off.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        check = "manages_class",
+    )]
+    async fn archive(ctx: Context<'_>, class: Role, enabled: bool) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        class.set_archive_messages(enabled).await?;
+
+        if enabled {
+            ctx.say(format!("Messages in \"{}\" will now be archived.", class.name)).await?;
+        } else {
+            ctx.say(format!("Messages in \"{}\" will no longer be archived.", class.name)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Change who can see and post in a class's channels.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        check = "manages_class",
+        required_bot_permissions = "MANAGE_ROLES",
+    )]
+    async fn visibility(ctx: Context<'_>, class: Role, visibility: Visibility) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        class.set_visibility(ctx.discord(), visibility).await?;
+
+        ctx.say(format!("Updated the channel permissions of \"{}\".", class.name)).await?;
+
+        Ok(())
+    }
+
+    /// Give a class its own custom emoji, used in menus and the catalog. Leave the image out to remove it.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        check = "manages_class",
+        required_bot_permissions = "MANAGE_EMOJIS_AND_STICKERS",
+    )]
+    async fn emoji(
+        ctx: Context<'_>,
+        class: Role,
+        #[description = "A square PNG, JPEG or GIF of at most 256 KB"] image: Option<Attachment>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        let image = match image {
+            Some(image) => {
+                let content_type = image.content_type.clone()
+                    .filter(|t| ["image/png", "image/jpeg", "image/gif"].contains(&t.as_str()))
+                    .ok_or(ClassError::InvalidEmojiImage)?;
+                if image.size > EMOJI_SIZE_LIMIT {
+                    Err(ClassError::InvalidEmojiImage)?;
+                }
+                // Replacing the class's own emoji doesn't take up another slot
+                if class.emoji.is_none() {
+                    let guild = lookup::current_guild(ctx).await?;
+                    let used = guild.emojis.values().filter(|e| !e.animated).count();
+                    if used >= emoji_limit(guild.premium_tier) {
+                        Err(ClassError::EmojiLimit)?;
+                    }
+                }
+                Some(format!("data:{};base64,{}", content_type, BASE64.encode(image.download().await?)))
+            }
+            None => None,
+        };
+        class.set_emoji(ctx.discord(), image.as_deref()).await?;
+
+        match &class.emoji {
+            Some(emoji) => ctx.say(format!("{} is now the emoji for \"{}\".", emoji, class.name)).await?,
+            None => ctx.say(format!("Removed the emoji from \"{}\".", class.name)).await?,
+        };
+
+        Ok(())
+    }
+
+    /// Add a badge like "[TA CS341]" to the nicknames of a staff tier. Leave the label out to stop.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        required_bot_permissions = "MANAGE_NICKNAMES",
+    )]
+    async fn badge(
+        ctx: Context<'_>,
+        class: Role,
+        tier: Role,
+        #[description = "Shown before the class's name, like \"TA\""] label: Option<String>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let stale = class.badges.iter()
+            .filter(|b| b.role == tier.id)
+            .map(|b| b.text(&class))
+            .collect::<Vec<_>>();
+        class.set_badge(tier.id, label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty())).await?;
+
+        let guild = lookup::current_guild(ctx).await?;
+        for member in guild.members.values().filter(|m| m.roles.contains(&tier.id) && !m.user.bot) {
+            if let Err(e) = badges::refresh(ctx.discord(), member, &stale).await {
+                log_error!("Error updating staff badges: {:?}", e);
+            }
+        }
+
+        match class.badges.iter().find(|b| b.role == tier.id) {
+            Some(badge) => ctx.say(format!("Members of {} now get the badge {}.", tier.mention(), badge.text(&class))).await?,
+            None => ctx.say(format!("Members of {} no longer get a badge for \"{}\".", tier.mention(), class.name)).await?,
+        };
+
+        Ok(())
+    }
+
+    /// Set how autograder results are worded for a class. Leave a template out to use the default.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        check = "manages_class",
+    )]
+    async fn grader(
+        ctx: Context<'_>,
+        class: Role,
+        #[description = "Sent to the student. Use {student}, {class}, {assignment}, {score} and {details}."]
+        dm_template: Option<String>,
+        #[description = "Posted when the student can't be DMed. Use {student}, {class} and {assignment}."]
+        summary_template: Option<String>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let clean = |t: Option<String>| t.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+        class.set_grader_templates(clean(dm_template), clean(summary_template)).await?;
+
+        ctx.say(format!("Updated the grader templates for \"{}\".", class.name)).await?;
+
+        Ok(())
+    }
+}
+
+struct ClassTransferCommand;
+impl ClassTransferCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        required_bot_permissions = "MANAGE_ROLES | MANAGE_CHANNELS",
+    )]
+    async fn role(ctx: Context<'_>, class: Role, new_role: Role, migrate_members: Option<bool>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        // Moving every member over can take longer than the interaction stays open
+        let id = jobs::start(ctx, JobKind::TransferRole {
+            class: class.role,
+            role: new_role.id,
+            migrate_members: migrate_members.unwrap_or(false),
+        }).await?;
+
+        ctx.say(format!(
+            "Moving \"{}\" to its new role as job `{}`. Its progress is shown in this channel.",
+            class.name,
+            id,
+        )).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        required_bot_permissions = "MANAGE_CHANNELS",
+    )]
+    async fn category(
+        ctx: Context<'_>,
+        class: Role,
+        #[channel_types("Category")] new_category: Channel,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let guild = lookup::current_guild(ctx).await?;
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let new_category = if let Channel::Category(c) = new_category {
+            c
+        } else {
+            return Err(ClassError::InvalidChannelType(new_category.mention()))?;
+        };
+
+        class.transfer_category(ctx.discord(), &guild, new_category.id).await?;
+
+        ctx.say(format!("Moved the channels of \"{}\" to `{}`.", class.name, new_category.name)).await?;
+
+        Ok(())
+    }
+}
+
+fn class_members(guild: &Guild, role: RoleId) -> HashSet<UserId> {
+    guild.members.values()
+        .filter(|m| m.roles.contains(&role))
+        .map(|m| m.user.id)
+        .collect()
+}
+
+/// Mention a set of members, cutting the list short to stay within Discord's message limit.
+fn member_list(members: &HashSet<UserId>) -> String {
+    const LIMIT: usize = 50;
+
+    let mut list = members.iter()
+        .sorted()
+        .take(LIMIT)
+        .map(|u| u.mention().to_string())
+        .join(", ");
+    if members.len() > LIMIT {
+        list += &format!(" and {} more", members.len() - LIMIT);
+    }
+    list
+}
+
+#[poise::command(
+    slash_command,
+    subcommands(
+        "ConfigCommand::refrole",
+        "ConfigCommand::staffchannel",
+        "ConfigCommand::suggestions",
+        "ConfigCommand::tutoring",
+        "ConfigCommand::enrollment",
+        "ConfigCommand::renamesync",
+        "ConfigCommand::autotrack",
+        "ConfigCommand::menugrouping",
+        "ConfigCommand::webhook",
+        "ConfigCommand::email",
+        "ConfigCommand::archiveretention",
+        "ConfigCommand::orphanprune",
+        "ConfigCommand::logchannel",
+        "ConfigCommand::welcome",
+        "ConfigCommand::prompts",
+        "ConfigCommand::escalation",
+        "ConfigCommand::dedup",
+        "ConfigCommand::department",
+        "ConfigCommand::alerts",
+        "ConfigCommand::calendar",
+        "ConfigCommand::newclasses",
+        "ConfigCommand::policy",
+    )
+)]
+async fn config(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct ConfigCommand;
+impl ConfigCommand {
+    #[poise::command(slash_command, subcommands("ConfigRefroleCommand::set"))]
+    async fn refrole(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ConfigStaffchannelCommand::set"))]
+    async fn staffchannel(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ConfigSuggestionsCommand::set"))]
+    async fn suggestions(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ConfigTutoringCommand::set"))]
+    async fn tutoring(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        subcommands("ConfigEnrollmentCommand::set", "ConfigEnrollmentCommand::clear")
+    )]
+    async fn enrollment(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ConfigRenamesyncCommand::set"))]
+    async fn renamesync(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ConfigAutotrackCommand::set"))]
+    async fn autotrack(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ConfigMenugroupingCommand::set"))]
+    async fn menugrouping(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        subcommands(
+            "ConfigWebhookCommand::add",
+            "ConfigWebhookCommand::remove",
+            "ConfigWebhookCommand::list",
+        )
+    )]
+    async fn webhook(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        subcommands(
+            "ConfigEmailCommand::subscribe",
+            "ConfigEmailCommand::unsubscribe",
+            "ConfigEmailCommand::list",
+        )
+    )]
+    async fn email(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        subcommands("ConfigArchiveretentionCommand::set", "ConfigArchiveretentionCommand::clear")
+    )]
+    async fn archiveretention(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        subcommands("ConfigOrphanpruneCommand::set", "ConfigOrphanpruneCommand::clear")
+    )]
+    async fn orphanprune(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ConfigLogchannelCommand::set", "ConfigLogchannelCommand::clear"))]
+    async fn logchannel(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        subcommands("ConfigWelcomeCommand::set", "ConfigWelcomeCommand::clear", "ConfigWelcomeCommand::preview")
+    )]
+    async fn welcome(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        subcommands("ConfigPromptsCommand::add", "ConfigPromptsCommand::remove", "ConfigPromptsCommand::list")
+    )]
+    async fn prompts(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ConfigEscalationCommand::set", "ConfigEscalationCommand::clear"))]
+    async fn escalation(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ConfigDedupCommand::set", "ConfigDedupCommand::clear"))]
+    async fn dedup(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ConfigDepartmentCommand::set", "ConfigDepartmentCommand::clear"))]
+    async fn department(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ConfigAlertsCommand::set", "ConfigAlertsCommand::clear"))]
+    async fn alerts(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        subcommands(
+            "ConfigCalendarCommand::add_break",
+            "ConfigCalendarCommand::remove_break",
+            "ConfigCalendarCommand::list",
+        )
+    )]
+    async fn calendar(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("ConfigNewclassesCommand::set", "ConfigNewclassesCommand::clear"))]
+    async fn newclasses(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        subcommands("ConfigPolicyCommand::set", "ConfigPolicyCommand::clear", "ConfigPolicyCommand::list")
+    )]
+    async fn policy(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+struct ConfigRefroleCommand;
+impl ConfigRefroleCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        required_bot_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, role: Role) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server
+            .set_refrole(ctx, role.id)
+            .await?;
+
+        ctx.say(format!("{} is now the refrole for this server.", role.mention())).await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigStaffchannelCommand;
+impl ConfigStaffchannelCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, #[channel_types("Text")] channel: GuildChannel) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server
+            .set_staff_channel(channel.id)
+            .await?;
+
+        ctx.say(format!("{} is now the staff channel for this server.", channel.mention())).await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigSuggestionsCommand;
+impl ConfigSuggestionsCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, #[channel_types("Text")] channel: GuildChannel) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server
+            .set_suggestions_channel(channel.id)
+            .await?;
+
+        ctx.say(format!("{} is now the suggestions channel for this server.", channel.mention())).await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigTutoringCommand;
+impl ConfigTutoringCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, #[channel_types("Text")] channel: GuildChannel) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server
+            .set_tutoring_channel(channel.id)
+            .await?;
+
+        ctx.say(format!("Tutoring threads will now be created in {}.", channel.mention())).await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigEnrollmentCommand;
+impl ConfigEnrollmentCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, opens: String, closes: String) -> Result<(), Error> {
+        let opens = scheduler::parse_time(&opens).ok_or(ClassError::InvalidTime)?;
+        let closes = scheduler::parse_time(&closes)
+            .filter(|t| *t > opens)
+            .ok_or(ClassError::InvalidTime)?;
+
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server
+            .set_enrollment_window(Some(EnrollmentWindow {
+                opens: DateTime::from_millis(opens.timestamp_millis()),
+                closes: DateTime::from_millis(closes.timestamp_millis()),
+            }))
+            .await?;
+
+        ctx.say(format!(
+            "Members can now only join and leave classes between <t:{}:f> and <t:{}:f>.",
+            opens.timestamp(),
+            closes.timestamp(),
+        )).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn clear(ctx: Context<'_>) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_enrollment_window(None).await?;
+
+        ctx.say("Members can now join and leave classes at any time.").await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigRenamesyncCommand;
+impl ConfigRenamesyncCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, mode: RenameSync) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_rename_sync(mode).await?;
+
+        ctx.say(match mode {
+            RenameSync::Update => "Classes will now be renamed when their role or category is renamed.",
+            RenameSync::Flag => "Renamed class roles and categories will now be reported in the staff channel.",
+            RenameSync::Ignore => "Renamed class roles and categories will now be ignored.",
+        }).await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigAutotrackCommand;
+impl ConfigAutotrackCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, enabled: bool) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_auto_track_channels(enabled).await?;
+
+        ctx.say(if enabled {
+            "Channels created in a class category will now be added to the class automatically."
+        } else {
+            "Channels created in a class category will no longer be added to the class automatically."
+        }).await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigMenugroupingCommand;
+impl ConfigMenugroupingCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, enabled: bool) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_menu_group_by_tag(enabled).await?;
+
+        ctx.say(if enabled {
+            "The class menu will now ask members to choose a tag first."
+        } else {
+            "The class menu will now show every class at once."
+        }).await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigWebhookCommand;
+impl ConfigWebhookCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn add(ctx: Context<'_>, url: String) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.add_webhook(url.trim()).await?;
+
+        ctx.say(format!("Class events will now be sent to <{}>.", url.trim())).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn remove(ctx: Context<'_>, url: String) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.remove_webhook(url.trim()).await?;
+
+        ctx.say(format!("Class events will no longer be sent to <{}>.", url.trim())).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn list(ctx: Context<'_>) -> Result<(), Error> {
+        let server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+
+        let webhooks = server.webhook_urls()?;
+        if webhooks.is_empty() {
+            ctx.say("No webhooks are configured for this server.").await?;
+        } else {
+            ctx.say(format!(
+                "Configured webhooks:\n{}",
+                webhooks.iter().map(|w| format!("<{}>", w)).join("\n")
+            )).await?;
+        }
+
+        Ok(())
+    }
+}
+
+struct ConfigArchiveretentionCommand;
+impl ConfigArchiveretentionCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, #[min = 1] days: u32) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_archive_retention_days(Some(days)).await?;
+
+        ctx.say(format!("Archived class messages will now be kept for {} days.", days)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn clear(ctx: Context<'_>) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_archive_retention_days(None).await?;
+
+        ctx.say("Archived class messages will now be kept forever.").await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigOrphanpruneCommand;
+impl ConfigOrphanpruneCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, #[min = 1] days: u32) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_orphan_prune_days(Some(days)).await?;
+
+        ctx.say(format!(
+            "Classes and channels that were deleted in Discord will be cleaned up after {} days.",
+            days,
+        )).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn clear(ctx: Context<'_>) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_orphan_prune_days(None).await?;
+
+        ctx.say("Classes and channels that were deleted in Discord will only be reported, not cleaned up.").await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigLogchannelCommand;
+impl ConfigLogchannelCommand {
+    /// Log members joining and leaving classes, optionally including roles changed by hand.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(
+        ctx: Context<'_>,
+        #[channel_types("Text")] channel: GuildChannel,
+        manual_changes: Option<bool>,
+    ) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        let manual_changes = manual_changes.unwrap_or(false);
+        server.set_log_channel(Some(channel.id), manual_changes).await?;
+
+        ctx.say(if manual_changes {
+            format!("Class roles added or removed by the bot or by hand will now be logged in {}.", channel.mention())
+        } else {
+            format!("Class roles added or removed by the bot will now be logged in {}.", channel.mention())
+        }).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn clear(ctx: Context<'_>) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_log_channel(None, false).await?;
+
+        ctx.say("Class role changes will no longer be logged.").await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigAlertsCommand;
+impl ConfigAlertsCommand {
+    /// Post unexpected errors in this server to a channel for staff.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, #[channel_types("Text")] channel: GuildChannel) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_alert_channel(Some(channel.id)).await?;
+
+        ctx.say(format!("Unexpected errors will now be posted in {}.", channel.mention())).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn clear(ctx: Context<'_>) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_alert_channel(None).await?;
+
+        ctx.say("Unexpected errors will no longer be posted.").await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigNewclassesCommand;
+impl ConfigNewclassesCommand {
+    /// Announce new classes in a channel, with a button to join each.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, #[channel_types("Text", "News")] channel: GuildChannel) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_new_class_channel(Some(channel.id)).await?;
+
+        ctx.say(format!("New classes will now be announced in {}.", channel.mention())).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn clear(ctx: Context<'_>) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_new_class_channel(None).await?;
+
+        ctx.say("New classes will no longer be announced.").await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigWelcomeCommand;
+impl ConfigWelcomeCommand {
+    /// Turn the welcome DM for new members on or off, and optionally change its message.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(
+        ctx: Context<'_>,
+        enabled: bool,
+        #[description = "{user} and {server} are replaced with the new member and the server's name"]
+        template: Option<String>,
+    ) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        let template = template
+            .map(|t| t.replace("\\n", "\n"))
+            .or_else(|| server.welcome_template.clone());
+        server.set_welcome(enabled, template).await?;
+
+        ctx.say(if enabled {
+            "New members will now be sent a welcome DM. Use `/config welcome preview` to see it."
+        } else {
+            "New members will no longer be sent a welcome DM."
+        }).await?;
+
+        Ok(())
+    }
+
+    /// Go back to the default welcome message.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn clear(ctx: Context<'_>) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        let enabled = !server.welcome_dm_disabled;
+        server.set_welcome(enabled, None).await?;
+
+        ctx.say("The welcome DM will now use the default message.").await?;
+
+        Ok(())
+    }
+
+    /// Send yourself the welcome DM.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn preview(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        welcome::send(ctx.discord(), ctx.guild_id().ok_or(ClassError::NoServer)?, ctx.author()).await?;
+
+        ctx.say("Sent you the welcome DM.").await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigPromptsCommand;
+impl ConfigPromptsCommand {
+    /// Add a question to the pool that classes' questions of the day are drawn from.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn add(ctx: Context<'_>, prompt: String) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.add_icebreaker_prompt(prompt.trim()).await?;
+
+        ctx.say(format!("Added the prompt. There are now {} prompts in the pool.", server.icebreaker_prompts.len())).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn remove(ctx: Context<'_>, #[min = 1] number: usize) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        let prompt = server.remove_icebreaker_prompt(number).await?;
+
+        ctx.say(format!("Removed \"{}\" from the pool.", prompt)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn list(ctx: Context<'_>) -> Result<(), Error> {
+        let server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+
+        if server.icebreaker_prompts.is_empty() {
+            ctx.say("There are no prompts in the pool. Add some with `/config prompts add`.").await?;
+        } else {
+            ctx.say(format!(
+                "Question of the day prompts:\n{}",
+                server.icebreaker_prompts.iter()
+                    .enumerate()
+                    .map(|(i, p)| format!("{}. {}", i + 1, p))
+                    .join("\n"),
+            )).await?;
+        }
+
+        Ok(())
+    }
+}
+
+struct ConfigCalendarCommand;
+impl ConfigCalendarCommand {
+    /// Add a holiday or break, during which session reminders and exam countdowns are paused.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        rename = "add-break",
+    )]
+    async fn add_break(
+        ctx: Context<'_>,
+        name: String,
+        #[description = "The first day of the break, like 2024-12-23, or a time"]
+        starts: String,
+        #[description = "The last day of the break, like 2025-01-03, or a time"]
+        ends: String,
+    ) -> Result<(), Error> {
+        let starts = calendar::parse_break_time(&starts, false).ok_or(ClassError::InvalidTime)?;
+        let ends = calendar::parse_break_time(&ends, true)
+            .filter(|t| *t > starts)
+            .ok_or(ClassError::InvalidTime)?;
+
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server
+            .add_break(CalendarBreak {
+                name: name.trim().to_string(),
+                starts: DateTime::from_millis(starts.timestamp_millis()),
+                ends: DateTime::from_millis(ends.timestamp_millis()),
+            })
+            .await?;
+
+        ctx.say(format!(
+            "Added \"{}\", from <t:{}:f> to <t:{}:f>.",
+            name.trim(),
+            starts.timestamp(),
+            ends.timestamp(),
+        )).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+        rename = "remove-break",
+    )]
+    async fn remove_break(ctx: Context<'_>, name: String) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        let removed = server.remove_break(&name).await?;
+
+        ctx.say(format!("Removed \"{}\" from the calendar.", removed.name)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn list(ctx: Context<'_>) -> Result<(), Error> {
+        let server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+
+        if server.breaks.is_empty() {
+            ctx.say("There are no breaks in the calendar. Add some with `/config calendar add-break`.").await?;
+        } else {
+            ctx.say(format!(
+                "Breaks:\n{}",
+                server.breaks.iter()
+                    .map(|b| format!(
+                        "**{}**: <t:{}:f> to <t:{}:f>",
+                        b.name,
+                        b.starts.timestamp_millis() / 1000,
+                        b.ends.timestamp_millis() / 1000,
+                    ))
+                    .join("\n"),
+            )).await?;
+        }
+
+        Ok(())
+    }
+}
+
+struct ConfigEscalationCommand;
+impl ConfigEscalationCommand {
+    /// Ping class staff about homework-help questions that go unanswered for this many hours.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, #[min = 1] hours: u32) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_escalation_hours(Some(hours)).await?;
+
+        ctx.say(format!(
+            "Homework-help questions unanswered after {} hours will now be escalated to the class's staff role, \
+            or to the staff channel for classes without one.",
+            hours,
+        )).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn clear(ctx: Context<'_>) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_escalation_hours(None).await?;
+
+        ctx.say("Unanswered homework-help questions will no longer be escalated.").await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigDedupCommand;
+impl ConfigDedupCommand {
+    /// Link new homework-help questions to recent ones at least this similar.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(ctx: Context<'_>, #[min = 1] #[max = 100] threshold: u32) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_dedup_threshold(Some(threshold)).await?;
+
+        ctx.say(format!(
+            "New homework-help questions will be linked to recent questions that are at least {}% similar.",
+            threshold,
+        )).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn clear(ctx: Context<'_>) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_dedup_threshold(None).await?;
+
+        ctx.say("New homework-help questions will no longer be linked to related ones.").await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigPolicyCommand;
+impl ConfigPolicyCommand {
+    /// Only let members with these roles, or who can manage the server, run a command or group.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(
+        ctx: Context<'_>,
+        #[autocomplete = "policy::autocomplete_command"] command: String,
+        role: Role,
+        role2: Option<Role>,
+        role3: Option<Role>,
+    ) -> Result<(), Error> {
+        let command = command.trim().to_lowercase();
+        if !policy::command_names(&ctx.framework().options().commands).contains(&command) {
+            Err(ClassError::InvalidCommand)?;
+        }
+        let roles = [Some(role), role2, role3].into_iter()
+            .flatten()
+            .map(|r| r.id)
+            .unique()
+            .collect::<Vec<_>>();
+
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        server.set_command_policy(&command, roles.clone()).await?;
+
+        ctx.say(format!(
+            "`/{}` can now only be run by {}, and members who can manage the server.",
+            command,
+            roles.iter().map(|r| r.mention().to_string()).join(", "),
+        )).await?;
+
+        Ok(())
+    }
+
+    /// Go back to a command's default permissions.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn clear(ctx: Context<'_>, #[autocomplete = "policy::autocomplete_command"] command: String) -> Result<(), Error> {
+        let command = command.trim().to_lowercase();
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        if !server.command_policies.iter().any(|p| p.command == command) {
+            Err(ClassError::InvalidCommand)?;
+        }
+        server.set_command_policy(&command, Vec::new()).await?;
+
+        ctx.say(format!("`/{}` is back to its default permissions.", command)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn list(ctx: Context<'_>) -> Result<(), Error> {
+        let server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+
+        if server.command_policies.is_empty() {
+            ctx.say("Every command uses its default permissions.").await?;
+        } else {
+            ctx.say(
+                server.command_policies.iter()
+                    .map(|p| format!("`/{}`: {}", p.command, p.roles.iter().map(|r| r.mention().to_string()).join(", ")))
+                    .join("\n")
+            ).await?;
+        }
+
+        Ok(())
+    }
+}
+
+struct ConfigDepartmentCommand;
+impl ConfigDepartmentCommand {
+    /// Set the emoji and colour a department's classes are shown with in menus.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn set(
+        ctx: Context<'_>,
+        department: String,
+        emoji: Option<String>,
+        #[description = "A hex colour like #2f7de1"] colour: Option<String>,
+    ) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        let department = department.trim().to_uppercase();
+        let emoji = emoji.map(|e| e.trim().to_string());
+        if emoji.as_deref().is_some_and(|e| ReactionType::try_from(e).is_err()) {
+            Err(ClassError::InvalidEmoji)?;
+        }
+        let colour = match colour {
+            Some(c) => Some(u32::from_str_radix(c.trim().trim_start_matches('#'), 16).map_err(|_| ClassError::InvalidColour)?),
+            None => None,
+        };
+        server.set_department_theme(DepartmentTheme { department: department.clone(), emoji, colour }).await?;
+
+        ctx.say(format!(
+            "Updated the theme for {}. Run `/class menu post-all` to refresh department menus.",
+            department,
+        )).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn clear(ctx: Context<'_>, department: String) -> Result<(), Error> {
+        let mut server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?)
+            .await?;
+        let department = department.trim().to_uppercase();
+        server.set_department_theme(DepartmentTheme { department: department.clone(), emoji: None, colour: None }).await?;
+
+        ctx.say(format!("Cleared the theme for {}.", department)).await?;
+
+        Ok(())
+    }
+}
+
+struct ConfigEmailCommand;
+impl ConfigEmailCommand {
+    /// Get a daily or weekly email digest for the whole server, or for one class.
+    #[poise::command(slash_command, ephemeral)]
+    async fn subscribe(
+        ctx: Context<'_>,
+        address: String,
+        frequency: EmailFrequency,
+        class: Option<Role>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let class = match class {
+            Some(role) => Some(Class::find_by_role(role.id).await?.ok_or(ClassError::InvalidClass)?),
+            None => None,
+        };
+        let allowed = match &class {
+            Some(class) => is_class_staff(ctx, class).await,
+            None => is_manager(ctx).await,
+        };
+        if !allowed {
+            Err(ClassError::MissingPermissions)?;
+        }
+
+        EmailSubscription::subscribe(server_id, &address, class.as_ref().map(|c| c.role), frequency).await?;
+
+        ctx.say(format!(
+            "<{}> will get a {} digest for {}.",
+            address.trim(),
+            frequency.name().to_lowercase(),
+            class.map(|c| format!("\"{}\"", c.name)).unwrap_or_else(|| "this server".to_string()),
+        )).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(slash_command, ephemeral)]
+    async fn unsubscribe(ctx: Context<'_>, address: String, class: Option<Role>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let class = match class {
+            Some(role) => Some(Class::find_by_role(role.id).await?.ok_or(ClassError::InvalidClass)?),
+            None => None,
+        };
+        let allowed = match &class {
+            Some(class) => is_class_staff(ctx, class).await,
+            None => is_manager(ctx).await,
+        };
+        if !allowed {
+            Err(ClassError::MissingPermissions)?;
+        }
+
+        EmailSubscription::unsubscribe(server_id, &address, class.map(|c| c.role)).await?;
+
+        ctx.say(format!("<{}> will no longer get that digest.", address.trim())).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn list(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let subscriptions = EmailSubscription::list(ctx.guild_id().ok_or(ClassError::NoServer)?).await?;
+
+        if subscriptions.is_empty() {
+            ctx.say("Nobody is subscribed to email digests for this server.").await?;
+        } else {
+            let mut lines = Vec::new();
+            for subscription in subscriptions {
+                let scope = match subscription.role {
+                    Some(role) => Class::find_by_role(role).await?
+                        .map(|c| format!("\"{}\"", c.name))
+                        .unwrap_or_else(|| "a deleted class".to_string()),
+                    None => "the server".to_string(),
+                };
+                lines.push(format!(
+                    "<{}>: {} digest for {}",
+                    subscription.address,
+                    subscription.frequency.name(),
+                    scope,
+                ));
+            }
+            ctx.say(format!("Email digest subscriptions:\n{}", lines.join("\n"))).await?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Handler;
+
+/// Run every component handler on an interaction. Called from the [`dispatch`] queue.
+async fn handle_interaction(ctx: SContext, interaction: Interaction) {
+    join_all(vec![
+        EventHandler::interaction_create(&ClassMenuButtonHandler, ctx.clone(), interaction.clone()),
+        EventHandler::interaction_create(&ClassMenuHandler, ctx.clone(), interaction.clone()),
+        EventHandler::interaction_create(&ClassMenuTagHandler, ctx.clone(), interaction.clone()),
+        EventHandler::interaction_create(&DepartmentMenuHandler, ctx.clone(), interaction.clone()),
+        EventHandler::interaction_create(&SuggestionVoteHandler, ctx.clone(), interaction.clone()),
+        EventHandler::interaction_create(&ClassRequestHandler, ctx.clone(), interaction.clone()),
+        EventHandler::interaction_create(&EnrollmentButtonHandler, ctx.clone(), interaction.clone()),
+        EventHandler::interaction_create(&StudySessionRsvpHandler, ctx.clone(), interaction.clone()),
+        EventHandler::interaction_create(&RecordingConsentHandler, ctx.clone(), interaction.clone()),
+        EventHandler::interaction_create(&HelpButtonHandler, ctx.clone(), interaction.clone()),
+        EventHandler::interaction_create(&OrphanFixHandler, ctx.clone(), interaction.clone()),
+    ]).await;
+}
+
+#[async_trait]
+impl EventHandler for Handler {
+    async fn cache_ready(&self, ctx: SContext, guilds: Vec<GuildId>) {
+        selfcheck::check_all(&ctx, &guilds).await;
+    }
+
+    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
+        // Commands are handled by poise, so only components need to go through the queue
+        if let Interaction::MessageComponent(_) = interaction {
+            dispatch::enqueue(ctx, interaction);
+        }
+    }
+
+    async fn channel_create(&self, ctx: SContext, channel: &GuildChannel) {
+        join_all(vec![
+            EventHandler::channel_create(&AutoTrackHandler, ctx.clone(), channel),
+        ]).await;
+    }
+
+    async fn channel_delete(&self, ctx: SContext, channel: &GuildChannel) {
+        join_all(vec![
+            EventHandler::channel_delete(&AutoTrackHandler, ctx.clone(), channel),
+        ]).await;
+    }
+
+    async fn thread_create(&self, ctx: SContext, thread: GuildChannel) {
+        join_all(vec![
+            EventHandler::thread_create(&HelpThreadHandler, ctx.clone(), thread.clone()),
+        ]).await;
+    }
+
+    async fn guild_role_update(&self, ctx: SContext, old: Option<Role>, new: Role) {
+        join_all(vec![
+            EventHandler::guild_role_update(&ClassRenameHandler, ctx.clone(), old.clone(), new.clone()),
+        ]).await;
+    }
+
+    async fn channel_update(&self, ctx: SContext, old: Option<Channel>, new: Channel) {
+        join_all(vec![
+            EventHandler::channel_update(&ClassRenameHandler, ctx.clone(), old.clone(), new.clone()),
+        ]).await;
+    }
+
+    async fn guild_member_addition(&self, ctx: SContext, new_member: Member) {
+        join_all(vec![
+            EventHandler::guild_member_addition(&ClassInviteHandler, ctx.clone(), new_member.clone()),
+            EventHandler::guild_member_addition(&WelcomeHandler, ctx.clone(), new_member.clone()),
+            EventHandler::guild_member_addition(&PendingGrantHandler, ctx.clone(), new_member.clone()),
+        ]).await;
+    }
+
+    async fn guild_member_update(&self, ctx: SContext, old: Option<Member>, new: Member) {
+        join_all(vec![
+            EventHandler::guild_member_update(&JoinLogHandler, ctx.clone(), old.clone(), new.clone()),
+            EventHandler::guild_member_update(&StaffBadgeHandler, ctx.clone(), old.clone(), new.clone()),
+        ]).await;
+    }
+
+    async fn voice_state_update(&self, ctx: SContext, old: Option<VoiceState>, new: VoiceState) {
+        join_all(vec![
+            EventHandler::voice_state_update(&VoiceTimeHandler, ctx.clone(), old.clone(), new.clone()),
+            EventHandler::voice_state_update(&HandQueueHandler, ctx.clone(), old.clone(), new.clone()),
+        ]).await;
+    }
+
+    async fn message(&self, ctx: SContext, message: Message) {
+        join_all(vec![
+            EventHandler::message(&ModmailHandler, ctx.clone(), message.clone()),
+            EventHandler::message(&FaqSuggestHandler, ctx.clone(), message.clone()),
+            EventHandler::message(&MessageArchiveHandler, ctx.clone(), message.clone()),
+            EventHandler::message(&HelpThreadHandler, ctx.clone(), message.clone()),
+        ]).await;
+    }
+
+    async fn message_update(
+        &self,
+        ctx: SContext,
+        old: Option<Message>,
+        new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        join_all(vec![
+            EventHandler::message_update(&MessageArchiveHandler, ctx.clone(), old.clone(), new.clone(), event.clone()),
+        ]).await;
+    }
+
+    async fn message_delete(
+        &self,
+        ctx: SContext,
+        channel_id: ChannelId,
+        deleted_message_id: MessageId,
+        guild_id: Option<GuildId>,
+    ) {
+        join_all(vec![
+            EventHandler::message_delete(&MessageArchiveHandler, ctx.clone(), channel_id, deleted_message_id, guild_id),
+        ]).await;
+    }
+
+    async fn message_delete_bulk(
+        &self,
+        ctx: SContext,
+        channel_id: ChannelId,
+        multiple_deleted_messages_ids: Vec<MessageId>,
+        guild_id: Option<GuildId>,
+    ) {
+        join_all(vec![
+            EventHandler::message_delete_bulk(
+                &MessageArchiveHandler,
+                ctx.clone(),
+                channel_id,
+                multiple_deleted_messages_ids.clone(),
+                guild_id,
+            ),
+        ]).await;
+    }
+}
+
+struct ClassMenuButtonHandler;
+
+#[async_trait]
+impl EventHandler for ClassMenuButtonHandler {
+    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
+        let component = if let Interaction::MessageComponent(c) = interaction {
+            c
+        } else {
+            return;
+        };
+        if component.data.component_type != ComponentType::Button || component.data.custom_id != "class_menu_button" {
+            return;
+        }
+
+        let http = ctx.http();
+
+        // Throw away the result as deferring is not critical
+        // component.defer(http).await.ok();
+
+        let member = if let Some(m) = &component.member {
+            m
+        } else {
+            log_error!("Error handling class_menu_button: {:?}", ClassError::NoServer);
+            return;
+        };
+
+        let server_id = if let Some(id) = component.guild_id {
+            id
+        } else {
+            log_error!("Error handling class_menu_button: {:?}", ClassError::NoServer);
+            return;
+        };
+
+        let menu = match build_tag_menu(server_id).await {
+            Ok(Some(m)) => Ok(m),
+            Ok(None) => build_class_menu(server_id, member, None, None).await,
+            Err(e) => Err(e),
+        };
+        let menu = match menu {
+            Ok(m) => m,
+            Err(e) => {
+                log_error!("Error handling class_menu_button: {:?}", e);
+                return;
+            }
+        };
+
+        // Throwing away the error as if the deletion fails, we will know from the error message
+        // from creating the new response
+        // component.delete_original_interaction_response(http).await.ok();
+        if let Err(e) = component.create_interaction_response(http, |r| r.interaction_response_data(|d| d
+            .ephemeral(true)
+            .set_components(menu)
+        )).await {
+            log_error!("Error handling class_menu_button: {:?}", e);
+            return;
+        }
+    }
+}
+
+struct ClassMenuTagHandler;
+
+#[async_trait]
+impl EventHandler for ClassMenuTagHandler {
+    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
+        let component = if let Interaction::MessageComponent(c) = interaction {
+            c
+        } else {
+            return;
+        };
+        if component.data.component_type != ComponentType::SelectMenu || component.data.custom_id != "class_menu_tag" {
+            return;
+        }
+
+        let (member, server_id) = if let (Some(m), Some(id)) = (&component.member, component.guild_id) {
+            (m, id)
+        } else {
+            log_error!("Error handling class_menu_tag: {:?}", ClassError::NoServer);
+            return;
+        };
+
+        let tag = component.data.values.first().filter(|t| *t != ALL_CLASSES_TAG);
+        let menu = match build_class_menu(server_id, member, tag.map(|t| t.as_str()), None).await {
+            Ok(m) => m,
+            Err(e) => {
+                log_error!("Error handling class_menu_tag: {:?}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = component.create_interaction_response(ctx.http(), |r| r
+            .kind(InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|d| d.set_components(menu))
+        ).await {
+            log_error!("Error handling class_menu_tag: {:?}", e);
+        }
+    }
+}
+
+/// The tag menu option which skips filtering.
+const ALL_CLASSES_TAG: &str = "*";
+
+/// Build a menu to pick a tag to filter the class menu by, if the server groups its class menu by
+/// tag and has any tagged classes.
+async fn build_tag_menu(server_id: GuildId) -> ClassResult<Option<CreateComponents>> {
+    if !Server::get_or_create(server_id).await?.menu_group_by_tag {
+        return Ok(None);
+    }
+
+    let tags = Class::list(server_id).await?
+        .into_iter()
+        .flat_map(|c| c.tags)
+        .unique()
+        .sorted()
+        // Select menus are limited to 25 options, one of which is for all classes
+        .take(24)
+        .collect::<Vec<_>>();
+    if tags.is_empty() {
+        return Ok(None);
+    }
+
+    let mut cc = CreateComponents::default();
+    cc.create_action_row(|r| r
+        .create_select_menu(|m| m
+            .custom_id("class_menu_tag")
+            .placeholder("Choose a kind of class")
+            .options(|o| {
+                o.create_option(|o| o.label("All classes").value(ALL_CLASSES_TAG));
+                for tag in &tags {
+                    o.create_option(|o| o.label(tag).value(tag));
+                }
+                o
+            })
+        )
+    );
+
+    Ok(Some(cc))
+}
+
+/// Discord's limit on the length of a select menu option's description.
+const MENU_DESCRIPTION_LIMIT: usize = 100;
+
+/// Cut text down to at most `limit` characters, marking where it was cut.
+fn truncate(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        text.to_string()
+    } else {
+        text.chars().take(limit - 1).collect::<String>() + "…"
+    }
+}
+
+/// Discord allows at most this many options in one select menu.
+const MENU_OPTION_LIMIT: usize = 25;
+
+/// A class in a class menu, and whether it starts selected.
+struct MenuOption<'a> {
+    class: &'a Class,
+    selected: bool,
+}
+
+/// Split the classes matching the tag and department into menus of at most
+/// `MENU_OPTION_LIMIT` options. Favorites and held classes come first, so they end up in the
+/// first menu, and held classes start selected.
+fn class_menu_options<'a>(
+    classes: &'a [Class],
+    member_roles: &HashSet<RoleId>,
+    favorites: &[RoleId],
+    tag: Option<&str>,
+    department: Option<&str>,
+) -> Vec<Vec<MenuOption<'a>>> {
+    classes.iter()
+        .filter(|c| tag.map(|t| c.tags.iter().any(|ct| ct == t)).unwrap_or(true))
+        .filter(|c| department.map(|d| department_of(c) == d).unwrap_or(true))
+        .sorted_by(|c1, c2| {
+            let pinned = |c: &Class| favorites.contains(&c.role) || member_roles.contains(&c.role);
+            pinned(c2).cmp(&pinned(c1))
+                .then_with(|| human_sort::compare(&c1.name, &c2.name))
+        })
+        .map(|class| MenuOption { class, selected: member_roles.contains(&class.role) })
+        .chunks(MENU_OPTION_LIMIT)
+        .into_iter()
+        .map(|chunk| chunk.collect())
+        .collect()
+}
+
+async fn build_class_menu(
+    server_id: GuildId,
+    member: &Member,
+    tag: Option<&str>,
+    department: Option<&str>,
+) -> ClassResult<CreateComponents> {
+    let member_roles = member.roles.iter().copied().collect::<HashSet<_>>();
+    let favorites = UserProfile::get(member.user.id).await?.favorites;
+    let server = Server::get_or_create(server_id).await?;
+    let classes = Class::list(server_id).await?;
+
+    let menus = class_menu_options(&classes, &member_roles, &favorites, tag, department);
+
+    let mut cc = CreateComponents::default();
+    cc.set_action_rows(class_menu_rows(menus, &server));
+
+    Ok(cc)
+}
+
+/// Turn class menu options into select menus, labelled with the server's menu generation and
+/// department emojis.
+fn class_menu_rows(menus: Vec<Vec<MenuOption>>, server: &Server) -> Vec<CreateActionRow> {
+    menus.into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let max_values = chunk.len() as u64;
+            let options = chunk.into_iter()
+                .map(|MenuOption { class: c, selected }| {
+                    let mut o = CreateSelectMenuOption::new(&c.name, c.role.to_string());
+                    o.default_selection(selected);
+                    if let Some(description) = &c.description {
+                        o.description(truncate(description, MENU_DESCRIPTION_LIMIT));
+                    }
+                    let emoji = c.reaction()
+                        .or_else(|| server.department_theme(&department_of(c)).and_then(|t| t.reaction()));
+                    if let Some(emoji) = emoji {
+                        o.emoji(emoji);
+                    }
+                    o
+                })
+                .collect();
+
+            let mut row = CreateActionRow::default();
+            row.create_select_menu(|m| m
+                .custom_id(class_menu_id(i, server.menu_generation))
+                .min_values(0)
+                .max_values(max_values)
+                .options(|o| o.set_options(options))
+            );
+            row
+        })
+        .collect()
+}
+
+struct ClassMenuHandler;
+
+#[async_trait]
+impl EventHandler for ClassMenuHandler {
+    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
+        let component = if let Interaction::MessageComponent(c) = interaction {
+            c
+        } else {
+            return;
+        };
+        if component.data.component_type != ComponentType::SelectMenu {
+            return;
+        }
+
+        let custom_id = &*component.data.custom_id;
+
+        let (_id, generation) = if let Some(id) = parse_class_button_id(custom_id) {
+            id
+        } else {
+            return;
+        };
+
+        let http = ctx.http();
+
+        // Throwing away the result as if the defer fails, the user will see an error message
+        // regardless of how the error is handled, so we might as well finish handling the input
+        component.defer(http).await.ok();
+
+        let member = if let Some(m) = &component.member {
+            m
+        } else {
+            log_error!("Error handling {}: {:?}", custom_id, ClassError::NoServer);
+            return;
+        };
+
+        if let Err(e) = check_window(member).await {
+            // Throwing away the result as there is nothing more to do if telling the member fails
+            component.create_followup_message(http, |m| m.ephemeral(true).content(e)).await.ok();
+            return;
+        }
 
         let menu = if let Some(menu) = component.message.components.iter()
-            .filter_map(|row| row.components.get(0)
+            .filter_map(|row| row.components.first()
                 .and_then(|c| match c {
                     ActionRowComponent::SelectMenu(menu) => Some(menu),
                     _ => None
@@ -583,39 +3265,130 @@ impl EventHandler for ClassMenuHandler {
         {
             menu
         } else {
-            eprintln!("Error handling {}: Could not find matching select menu", custom_id);
+            log_error!("Error handling {}: Could not find matching select menu", custom_id);
             return;
         };
 
-        let member_roles = member.roles.iter().copied().collect::<HashSet<_>>();
-        // Unwrapping because this should be a valid role ID
-        let menu_roles = menu.options.iter()
-            .map(|o| o.value.parse().unwrap())
+        match menu_is_current(member.guild_id, generation, menu.options.iter().map(|o| o.value.as_str())).await {
+            Ok(true) => {}
+            Ok(false) => {
+                // Throwing away the result as there is nothing more to do if telling the member fails
+                component.create_followup_message(http, |m| m
+                    .ephemeral(true)
+                    .content("This menu has expired because the classes have changed. Click the button again to get a new one.")
+                ).await.ok();
+                return;
+            }
+            Err(e) => {
+                log_error!("Error handling {}: {:?}", custom_id, e);
+                return;
+            }
+        }
+
+        // Classes whose role was deleted without the class being untracked are skipped, rather
+        // than failing the whole edit
+        let roles = match lookup::roles(&ctx, member.guild_id).await {
+            Ok(r) => r,
+            Err(e) => {
+                log_error!("Error handling {}: {:?}", custom_id, e);
+                return;
+            }
+        };
+        let (menu_roles, vanished): (Vec<_>, Vec<_>) = menu.options.iter()
+            .map(|o| (o.value.parse::<RoleId>().ok(), o.label.clone()))
+            .partition(|(role, _)| role.is_some_and(|r| roles.contains_key(&r)));
+        let menu_roles = menu_roles.into_iter()
+            .filter_map(|(role, _)| role)
             .collect::<HashSet<RoleId>>();
-        // Unwrapping because this should be a valid role ID
-        let new_roles = component.data.values.iter()
-            .map(|o| o.parse().unwrap())
+        let vanished = vanished.into_iter().map(|(_, label)| label).collect::<Vec<_>>();
+
+        let member_roles = member.roles.iter().copied().collect::<HashSet<_>>();
+        let selected = component.data.values.iter()
+            .filter_map(|o| o.parse().ok())
             .collect::<HashSet<RoleId>>();
 
-        if let Err(e) = member
-            .edit(http, |e| {
-                e.roles(&(&member_roles - &menu_roles) | &new_roles)
-            })
-            .await
-        {
-            println!(
-                "Error handling {}: {:?}", custom_id, ClassError::ApiError(e));
+        let (add, remove) = menu_changes(&menu_roles, &member_roles, &selected);
+        if let Err(e) = check_assignable(&ctx, member.guild_id, &[add.as_slice(), remove.as_slice()].concat()) {
+            // Throwing away the result as there is nothing more to do if telling the member fails
+            component.create_followup_message(http, |m| m.ephemeral(true).content(e)).await.ok();
+            return;
+        }
+
+        let applied = rolequeue::apply(http, member.guild_id, member.user.id, &add, &remove, "Class menu").await;
+
+        history::enrolled(
+            member.guild_id,
+            member.user.id,
+            component.user.id,
+            EnrollmentMechanism::Menu,
+            applied.added,
+            applied.removed,
+        ).await;
+
+        if let Some(e) = applied.error {
+            log_info!("Error handling {}: {:?}", custom_id, e);
             return;
         }
+
+        if !vanished.is_empty() {
+            // Throwing away the result as the other classes were already updated
+            component.create_followup_message(http, |m| m
+                .ephemeral(true)
+                .content(format!(
+                    "These classes no longer have a role, so they were skipped: {}. Let the server staff know.",
+                    vanished.join(", "),
+                ))
+            ).await.ok();
+        }
+    }
+}
+
+/// The roles to add and remove for a member's selection in a class menu. Only classes in the menu
+/// are changed, so classes picked from other menus are left alone.
+fn menu_changes(
+    menu_roles: &HashSet<RoleId>,
+    member_roles: &HashSet<RoleId>,
+    selected: &HashSet<RoleId>,
+) -> (Vec<RoleId>, Vec<RoleId>) {
+    let selected = selected & menu_roles;
+
+    let add = (&selected - member_roles).into_iter().sorted().collect();
+    let remove = (menu_roles - &selected).intersection(member_roles).copied().sorted().collect();
+
+    (add, remove)
+}
+
+fn class_menu_id(index: usize, generation: u64) -> String {
+    format!("class_menu_button_{}_{}", index, generation)
+}
+
+/// The index of a class select menu and the menu generation it was built in. Menus built before
+/// generations were tracked have none.
+fn parse_class_button_id(id: &str) -> Option<(u8, Option<u64>)> {
+    let id = id.strip_prefix("class_menu_button_")?;
+
+    match id.split_once('_') {
+        Some((index, generation)) => Some((index.parse().ok()?, Some(generation.parse().ok()?))),
+        None => Some((id.parse().ok()?, None)),
     }
 }
 
-fn parse_class_button_id(id: &str) -> Option<u8> {
-    if !id.starts_with("class_menu_button_") {
-        return None;
+/// Whether a class menu is still current: built in the server's current menu generation, with
+/// every option a class that still exists.
+async fn menu_is_current<'a>(
+    server_id: GuildId,
+    generation: Option<u64>,
+    mut values: impl Iterator<Item = &'a str>,
+) -> ClassResult<bool> {
+    if generation != Some(Server::get_or_create(server_id).await?.menu_generation) {
+        return Ok(false);
     }
 
-    id[18..].parse().ok()
+    let class_roles = Class::list(server_id).await?
+        .into_iter()
+        .map(|c| c.role)
+        .collect::<HashSet<_>>();
+    Ok(values.all(|v| v.parse().is_ok_and(|r| class_roles.contains(&r))))
 }
 
 #[derive(Error, Debug)]
@@ -640,12 +3413,200 @@ pub enum ClassError {
     InvalidChannelType(Mention),
     #[error("The given role is already being used for class {0}.")]
     RoleInUse(String),
+    #[error("The given category is already being used for class {0}.")]
+    CategoryInUse(String),
     #[error("There is no class assigned to the given role.")]
     InvalidClass,
-    #[error("{0}")]
+    #[error("The given URL is invalid.")]
+    InvalidUrl,
+    #[error("That webhook is already configured for this server.")]
+    WebhookExists,
+    #[error("That webhook is not configured for this server.")]
+    InvalidWebhook,
+    #[error("There is no staff channel set for this server.")]
+    NoStaffChannel,
+    #[error("You already have an open modmail ticket. Send the bot a DM to add to it.")]
+    TicketExists,
+    #[error("This channel is not an open modmail ticket.")]
+    NoTicket,
+    #[error("There is no suggestions channel set for this server.")]
+    NoSuggestionsChannel,
+    #[error("There is no suggestion with that number.")]
+    InvalidSuggestion,
+    #[error("A class with the given name has already been requested.")]
+    RequestExists,
+    #[error("This class request has already been reviewed.")]
+    InvalidRequest,
+    #[error("You do not have permission to do that.")]
+    MissingPermissions,
+    #[error("The given time is invalid. Use a future time formatted like `2022-09-01 18:30 -0500`.")]
+    InvalidTime,
+    #[error("This study session is no longer open.")]
+    InvalidSession,
+    #[error("The given class has no text channels.")]
+    NoClassChannels,
+    #[error("You can only tutor classes you have previously completed.")]
+    NotCompleted,
+    #[error("You are already registered as a tutor for that class.")]
+    TutorExists,
+    #[error("There are no tutors available for that class.")]
+    NoTutors,
+    #[error("There is no tutoring channel set for this server.")]
+    NoTutoringChannel,
+    #[error("At least two members are needed to do that.")]
+    NotEnoughMembers,
+    #[error("A team with the given name already exists for that class.")]
+    TeamExists,
+    #[error("There is no team with the given name for that class.")]
+    InvalidTeam,
+    #[error("That question is already in the FAQ for that class.")]
+    FaqExists,
+    #[error("There is no matching FAQ for that class.")]
+    InvalidFaq,
+    #[error("A tag with the given name already exists.")]
+    TagExists,
+    #[error("There is no tag with the given name.")]
+    InvalidTag,
+    #[error("A snippet with the given name already exists for that class.")]
+    SnippetExists,
+    #[error("There is no matching snippet for that class.")]
+    InvalidSnippet,
+    #[error("Classes can only be joined or left between <t:{0}:f> and <t:{1}:f>.")]
+    EnrollmentClosed(i64, i64),
+    #[error("There is already an active term for this server.")]
+    TermActive,
+    #[error("There is no active term for this server.")]
+    NoTerm,
+    #[error("The given server ID is not a server this bot is in.")]
+    InvalidHub,
+    #[error("This server has not joined a federation hub.")]
+    NoHub,
+    #[error("An AutoMod template with the given name already exists.")]
+    AutoModTemplateExists,
+    #[error("There is no AutoMod template with the given name.")]
+    InvalidAutoModTemplate,
+    #[error("An AutoMod template needs keywords, a mention limit, or both.")]
+    EmptyAutoModTemplate,
+    #[error("There are too many channels outside the class to limit an AutoMod rule to it.")]
+    TooManyChannelsForAutoMod,
+    #[error("Email digests are not set up for this bot.")]
+    EmailNotConfigured,
+    #[error("The given email address is not valid.")]
+    InvalidEmail,
+    #[error("That address is not subscribed to that digest.")]
+    InvalidEmailSubscription,
+    #[error("There is no recently deleted class with the given name.")]
+    InvalidTrashedClass,
+    #[error("That problem has already been fixed.")]
+    InvalidOrphan,
+    #[error("That prompt is already in the pool.")]
+    PromptExists,
+    #[error("There is no prompt with that number.")]
+    InvalidPrompt,
+    #[error("This command can only be used in a homework-help thread.")]
+    NotHelpThread,
+    #[error("The bot can't add or remove these roles: {0}. Ask server staff to move the bot's role above them.")]
+    UnassignableRoles(String),
+    #[error("The given emoji is not valid.")]
+    InvalidEmoji,
+    #[error("The given colour is not valid. Use a hex colour like `#2f7de1`.")]
+    InvalidColour,
+    #[error("No encryption key is set up for this bot, so secrets can't be stored.")]
+    NoSecretKey,
+    #[error("A stored secret could not be decrypted. It may need to be set up again.")]
+    InvalidSecret,
+    #[error("That class does not have a webhook.")]
+    NoClassWebhook,
+    #[error("That class already has an upcoming assignment with the given title.")]
+    AssignmentExists,
+    #[error("There is no upcoming assignment with the given title for that class.")]
+    InvalidAssignment,
+    #[error("Linking Canvas needs the Canvas address, the course ID and an access token.")]
+    IncompleteCanvasLink,
+    #[error("That class is not linked to a Canvas course. Link it with `/assignment link`.")]
+    NoCanvasLink,
+    #[error("You can only do that for classes you are in.")]
+    NotInClass,
+    #[error("That file is not a server template this bot can read.")]
+    InvalidTemplate,
+    #[error("That file doesn't list any user IDs.")]
+    NoGrantUsers,
+    #[error("There is already a break with that name.")]
+    BreakExists,
+    #[error("There is no break with that name.")]
+    InvalidBreak,
+    #[error("You need to be in a class's voice channel to do that.")]
+    NotInClassVoice,
+    #[error("You haven't raised your hand.")]
+    HandNotRaised,
+    #[error("Nobody has their hand raised.")]
+    NoRaisedHands,
+    #[error("That session already has a link with that label.")]
+    SessionLinkExists,
+    #[error("That session has no link with that label.")]
+    InvalidSessionLink,
+    #[error("A session can have at most {0} links.")]
+    TooManySessionLinks(usize),
+    #[error("There are no classes to show.")]
+    NoCatalogClasses,
+    #[error("No course catalog source is set up. Set one with `/admin catalog source`.")]
+    NoCatalogSource,
+    #[error("The course catalog didn't have any courses where they were expected.")]
+    InvalidCatalog,
+    #[error("Refused to write data belonging to another server.")]
+    CrossGuildWrite,
+    #[error("Give either a member or a role.")]
+    NoManagerGiven,
+    #[error("There is no command with that name.")]
+    InvalidCommand,
+    #[error("They aren't a manager of that class.")]
+    InvalidManager,
+    #[error("Someone else changed the same setting at the same time. Please try again.")]
+    EditConflict,
+    #[error("The duration is invalid. Use weeks, days, hours and minutes, like `3d` or `1w 2d`, up to 30 days.")]
+    InvalidDuration,
+    #[error("Temporary access can't include commands that change who can run what, like policies or temporary access itself.")]
+    UngrantableCommand,
+    #[error("Webhook URLs must use https, so class events aren't sent unencrypted.")]
+    InsecureWebhook,
+    #[error("This server's members are still loading. Please try again in a minute.")]
+    NotCached,
+    #[error("There is no job with that ID.")]
+    InvalidJob,
+    #[error("That job has already finished.")]
+    JobFinished,
+    #[error("The job was cancelled.")]
+    JobCancelled,
+    #[error("The emoji must be a PNG, JPEG or GIF image of at most 256 KB.")]
+    InvalidEmojiImage,
+    #[error("This server has no room for more custom emoji.")]
+    EmojiLimit,
+    #[error("That isn't a link to a message in this server.")]
+    InvalidMessageLink,
+    #[error("That message isn't in one of a class's text channels.")]
+    NotClassChannel,
+    #[error("A class can have at most {0} teams.")]
+    TooManyTeams(usize),
+    #[error("You are already in a team for that class.")]
+    AlreadyInTeam,
+    #[error("The hub server hasn't allowed this server to join. Ask its staff to run `/federation allow` with this server's ID.")]
+    HubNotAllowed,
+    #[error("{}", redact::redact(&.0.to_string()))]
     ApiError(#[from] serenity::Error),
-    #[error("{0}")]
+    #[error("{}", redact::redact(&.0.to_string()))]
     DatabaseError(#[from] mongodb::error::Error),
+    #[error("{}", redact::redact(&.0.to_string()))]
+    SerializationError(#[from] mongodb::bson::ser::Error),
+    #[error("{}", redact::redact(&.0.to_string()))]
+    DeserializationError(#[from] mongodb::bson::de::Error),
+    #[error("{}", redact::redact(&.0.to_string()))]
+    EmailError(#[from] lettre::error::Error),
+    #[error("{}", redact::redact(&.0.to_string()))]
+    SmtpError(#[from] lettre::transport::smtp::Error),
+    #[error("{}", redact::redact(&.0.to_string()))]
+    HttpError(#[from] reqwest::Error),
+    #[error("{}", redact::redact(&.0.to_string()))]
+    JsonError(#[from] serde_json::Error),
 }
 
 type ClassResult<T> = Result<T, ClassError>;
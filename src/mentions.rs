@@ -0,0 +1,120 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, RoleId, UserId};
+use serenity::prelude::Mentionable;
+
+use crate::{notifications, ClassResult};
+
+/// How many members are mentioned in each message pinging them about an announcement, keeping
+/// the message well under Discord's length limit.
+const MENTION_CHUNK: usize = 50;
+/// How many pinging messages a class can get in `PING_WINDOW` before its messages stop pinging.
+const PING_CAP: usize = 5;
+const PING_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+lazy_static! {
+    static ref RECENT_PINGS: Mutex<HashMap<RoleId, VecDeque<Instant>>> = Mutex::new(HashMap::new());
+}
+
+/// Who a message is allowed to ping. Anything else mentioned in it is shown without pinging.
+#[derive(Default)]
+pub(crate) struct Pings {
+    pub(crate) roles: Vec<RoleId>,
+    pub(crate) users: Vec<UserId>,
+}
+
+impl Pings {
+    pub(crate) fn role(role: RoleId) -> Self {
+        Self { roles: vec![role], users: Vec::new() }
+    }
+
+    pub(crate) fn users(users: impl IntoIterator<Item = UserId>) -> Self {
+        Self { roles: Vec::new(), users: users.into_iter().collect() }
+    }
+}
+
+/// Count a ping against the class, returning whether it is within the cap.
+fn take_ping(class: RoleId) -> bool {
+    let mut recent = RECENT_PINGS.lock().unwrap();
+    let pings = recent.entry(class).or_default();
+    while pings.front().is_some_and(|t| t.elapsed() > PING_WINDOW) {
+        pings.pop_front();
+    }
+
+    if pings.len() < PING_CAP {
+        pings.push_back(Instant::now());
+        true
+    } else {
+        false
+    }
+}
+
+/// Send a message about a class that may ping people. Members who muted the class aren't pinged,
+/// and once the class has had too many pinging messages recently, the message is sent as an embed
+/// instead, which never pings. Everything that pings about a class should go through this.
+pub(crate) async fn send(
+    http: impl AsRef<Http>,
+    channel: ChannelId,
+    class: RoleId,
+    content: String,
+    pings: Pings,
+) -> ClassResult<Message> {
+    let users = notifications::unmuted(class, pings.users).await?;
+    let roles = pings.roles;
+    let pinging = !(users.is_empty() && roles.is_empty());
+
+    let message = if !pinging || take_ping(class) {
+        channel
+            .send_message(http.as_ref(), |m| m
+                .content(content)
+                .allowed_mentions(|a| a.empty_parse().users(users).roles(roles))
+            )
+            .await?
+    } else {
+        channel
+            .send_message(http.as_ref(), |m| m
+                .embed(|e| e.description(content))
+                .allowed_mentions(|a| a.empty_parse())
+            )
+            .await?
+    };
+
+    Ok(message)
+}
+
+/// Post an announcement about a class, then ping the given members about it. The whole
+/// announcement counts as a single ping against the class's cap, and once the cap is reached the
+/// members aren't pinged at all. Members should already be filtered with
+/// `notifications::announcement_recipients`.
+pub(crate) async fn announce(
+    http: impl AsRef<Http>,
+    channel: ChannelId,
+    class: RoleId,
+    content: String,
+    users: Vec<UserId>,
+) -> ClassResult<Message> {
+    let http = http.as_ref();
+    let message = channel
+        .send_message(http, |m| m.content(content).allowed_mentions(|a| a.empty_parse()))
+        .await?;
+
+    if !users.is_empty() && take_ping(class) {
+        for chunk in users.chunks(MENTION_CHUNK) {
+            channel
+                .send_message(http, |m| m
+                    .content(chunk.iter().map(|u| u.mention()).join(" "))
+                    .reference_message(&message)
+                    .allowed_mentions(|a| a.empty_parse().users(chunk.iter().copied()).replied_user(false))
+                )
+                .await?;
+        }
+    }
+
+    Ok(message)
+}
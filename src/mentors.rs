@@ -0,0 +1,221 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::UpdateOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, UserId};
+use serenity::prelude::Mentionable;
+use tokio::sync::OnceCell;
+
+use crate::classes::Class;
+use crate::events::BotEvent;
+use crate::history::EnrollmentEvent;
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::terms::Term;
+use crate::{discord_name, get_conn, is_class_staff, lookup, ClassError, ClassResult, Context, Error, ENV};
+
+/// A member who wants to be made a mentor for classes they took in earlier terms.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct MentorOptIn {
+    server_id: GuildId,
+    user: UserId,
+    opted_in_at: DateTime,
+}
+
+impl MentorOptIn {
+    async fn is_opted_in(server_id: GuildId, user: UserId) -> ClassResult<bool> {
+        Ok(
+            Self::scoped(server_id).await
+                .find_one(doc! { "user": user.to_string() }, None)
+                .await?
+                .is_some()
+        )
+    }
+
+    async fn list(server_id: GuildId) -> ClassResult<Vec<MentorOptIn>> {
+        Ok(
+            Self::scoped(server_id).await
+                .find(None, None)
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static OPT_INS: OnceCell<Collection<MentorOptIn>> = OnceCell::const_new();
+
+        OPT_INS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("mentor_opt_ins")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Give an opted-in member the mentor role of every class they held in an earlier term and
+/// don't hold now, returning the names of the classes they were made a mentor for.
+async fn grant_eligible(cache_http: impl CacheHttp, server_id: GuildId, user: UserId) -> ClassResult<Vec<String>> {
+    if !MentorOptIn::is_opted_in(server_id, user).await? {
+        return Ok(Vec::new());
+    }
+    let mut member = server_id.member(&cache_http, user).await?;
+    let active_term = Term::active(server_id).await?.map(|t| t.name);
+
+    let mut granted = Vec::new();
+    for class in Class::list(server_id).await? {
+        let mentor_role = match class.mentor_role {
+            Some(r) => r,
+            None => continue,
+        };
+        if member.roles.contains(&mentor_role) || member.roles.contains(&class.role) {
+            continue;
+        }
+        if !EnrollmentEvent::held_in_earlier_term(server_id, user, class.role, active_term.as_deref()).await? {
+            continue;
+        }
+
+        member.add_role(cache_http.http(), mentor_role).await?;
+        granted.push(class.name);
+    }
+
+    Ok(granted)
+}
+
+/// Event bus subscriber making members mentors as they leave classes.
+pub(crate) async fn grant(ctx: SContext, event: BotEvent) {
+    if let BotEvent::MemberEnrolled { server_id, user_id, left, .. } = event {
+        if left.is_empty() {
+            return;
+        }
+        if let Err(e) = grant_eligible(&ctx, server_id, user_id).await {
+            log_error!("Error granting mentor roles: {:?}", e);
+        }
+    }
+}
+
+#[poise::command(
+    slash_command,
+    subcommands("MentorCommand::setup", "MentorCommand::optin", "MentorCommand::optout")
+)]
+pub(crate) async fn mentor(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct MentorCommand;
+impl MentorCommand {
+    /// Give past students of a class a mentor role, creating one if none is given.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_bot_permissions = "MANAGE_ROLES",
+    )]
+    async fn setup(ctx: Context<'_>, class: Role, mentor_role: Option<Role>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        if !is_class_staff(ctx, &class).await {
+            Err(ClassError::MissingPermissions)?;
+        }
+        let mentor_role = match mentor_role {
+            Some(role) => role.id,
+            None => {
+                let guild = lookup::current_guild(ctx).await?;
+                guild
+                    .create_role(ctx.discord(), |r| r
+                        .name(discord_name(&format!("Mentor – {}", class.short_name)))
+                        .mentionable(true)
+                    )
+                    .await?
+                    .id
+            }
+        };
+        class.set_mentor_role(Some(mentor_role)).await?;
+
+        let mut granted = 0;
+        for opt_in in MentorOptIn::list(class.server_id).await? {
+            granted += grant_eligible(ctx.discord(), class.server_id, opt_in.user).await?
+                .iter()
+                .filter(|n| **n == class.name)
+                .count();
+        }
+
+        ctx.say(format!(
+            "Past students of \"{}\" who opt in with `/mentor optin` will now get {}. {} members were given it now.",
+            class.name,
+            mentor_role.mention(),
+            granted,
+        )).await?;
+
+        Ok(())
+    }
+
+    /// Become a mentor for classes you took in earlier terms.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn optin(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        MentorOptIn::scoped(server_id).await
+            .update_one(
+                doc! { "user": ctx.author().id.to_string() },
+                doc! { "$setOnInsert": { "opted_in_at": DateTime::now() } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        let granted = grant_eligible(ctx.discord(), server_id, ctx.author().id).await?;
+
+        if granted.is_empty() {
+            ctx.say("You'll be made a mentor for classes once you've finished them. Thank you!").await?;
+        } else {
+            ctx.say(format!(
+                "You're now a mentor for {}, and will be for future classes once you've finished them. Thank you!",
+                granted.iter().map(|n| format!("\"{}\"", n)).collect::<Vec<_>>().join(", "),
+            )).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop being a mentor, removing your mentor roles.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn optout(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        MentorOptIn::scoped(server_id).await
+            .delete_one(doc! { "user": ctx.author().id.to_string() }, None)
+            .await?;
+
+        let mentor_roles = Class::list(server_id).await?
+            .into_iter()
+            .filter_map(|c| c.mentor_role)
+            .collect::<Vec<_>>();
+        let mut member = server_id.member(ctx.discord(), ctx.author().id).await?;
+        let held = member.roles.iter().filter(|r| mentor_roles.contains(r)).copied().collect::<Vec<_>>();
+        if !held.is_empty() {
+            member.remove_roles(ctx.discord().http(), &held).await?;
+        }
+
+        ctx.say("You're no longer a mentor, and won't be made one for future classes.").await?;
+
+        Ok(())
+    }
+}
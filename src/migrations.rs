@@ -0,0 +1,288 @@
+use std::collections::{HashMap, HashSet};
+
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::{FindOptions, IndexOptions, UpdateOptions};
+use mongodb::{Collection, IndexModel};
+use serde::{Deserialize, Serialize};
+
+use crate::redact::log_info;
+use crate::{get_conn, secrets, ClassError, ClassResult, ENV};
+
+/// Every migration, in the order they run. Entries must never be removed or reordered, as applied
+/// migrations are recorded by name.
+const MIGRATIONS: [&str; 7] = [
+    "class_categories",
+    "message_archive_text_index",
+    "hinted_indexes",
+    "seal_server_webhooks",
+    "grader_token_index",
+    "unique_suggestion_numbers",
+    "grant_jobs",
+];
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AppliedMigration {
+    name: String,
+    applied_at: DateTime,
+}
+
+async fn apply(name: &str) -> ClassResult<()> {
+    match name {
+        "class_categories" => class_categories().await,
+        "message_archive_text_index" => message_archive_text_index().await,
+        "hinted_indexes" => hinted_indexes().await,
+        "seal_server_webhooks" => seal_server_webhooks().await,
+        "grader_token_index" => grader_token_index().await,
+        "unique_suggestion_numbers" => unique_suggestion_numbers().await,
+        "grant_jobs" => grant_jobs().await,
+        _ => unreachable!("Unknown migration {}", name),
+    }
+}
+
+/// `Class.category` became `Class.categories`.
+async fn class_categories() -> ClassResult<()> {
+    get_conn().await
+        .database(&ENV.mongodb_name)
+        .collection::<Document>("classes")
+        .update_many(
+            doc! { "category": { "$exists": true } },
+            vec![
+                doc! { "$set": { "categories": ["$category"] } },
+                doc! { "$unset": "category" },
+            ],
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// `/class searchmsg` searches archived messages with a text index.
+async fn message_archive_text_index() -> ClassResult<()> {
+    get_conn().await
+        .database(&ENV.mongodb_name)
+        .collection::<Document>("message_archive")
+        .create_index(IndexModel::builder().keys(doc! { "content": "text" }).build(), None)
+        .await?;
+
+    Ok(())
+}
+
+/// Queries on servers and classes hint these indexes by name, so a fresh database needs them.
+/// Creating an index that already exists does nothing.
+async fn hinted_indexes() -> ClassResult<()> {
+    let database = get_conn().await.database(&ENV.mongodb_name);
+
+    database
+        .collection::<Document>("servers")
+        .create_index(IndexModel::builder().keys(doc! { "server_id": 1 }).build(), None)
+        .await?;
+    database
+        .collection::<Document>("classes")
+        .create_indexes(
+            [
+                doc! { "server_id": 1 },
+                doc! { "server_id": 1, "name": 1 },
+                doc! { "name": 1 },
+                doc! { "role": 1 },
+            ].map(|keys| IndexModel::builder().keys(keys).build()),
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Server webhook URLs were stored in plaintext. Needs `SECRET_KEY` if any server has webhooks.
+async fn seal_server_webhooks() -> ClassResult<()> {
+    let servers = get_conn().await
+        .database(&ENV.mongodb_name)
+        .collection::<Document>("servers");
+    let mut cursor = servers.find(doc! { "webhooks.0": { "$exists": true } }, None).await?;
+
+    while let Some(server) = cursor.try_next().await? {
+        let webhooks = server.get_array("webhooks").map_err(|_| ClassError::InvalidSecret)?
+            .iter()
+            .filter_map(|w| w.as_str())
+            .map(secrets::seal)
+            .collect::<ClassResult<Vec<_>>>()?;
+        servers
+            .update_one(doc! { "_id": server.get("_id") }, doc! { "$set": { "webhooks": webhooks } }, None)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Grader results are matched to their class by token before anything else is checked.
+async fn grader_token_index() -> ClassResult<()> {
+    get_conn().await
+        .database(&ENV.mongodb_name)
+        .collection::<Document>("classes")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "grader_token": 1 })
+                .options(IndexOptions::builder().sparse(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Suggestions were numbered by counting them, so concurrent ones could share a number. Later
+/// duplicates are renumbered after the server's last suggestion, and numbers are handed out from
+/// a counter from then on.
+async fn unique_suggestion_numbers() -> ClassResult<()> {
+    let database = get_conn().await.database(&ENV.mongodb_name);
+    let suggestions = database.collection::<Document>("suggestions");
+    let counters = database.collection::<Document>("suggestion_counters");
+
+    let mut last = HashMap::<String, i64>::new();
+    let mut seen = HashSet::<(String, i64)>::new();
+    let mut duplicates = Vec::new();
+    let options = FindOptions::builder().sort(doc! { "_id": 1 }).build();
+    let mut cursor = suggestions.find(None, options).await?;
+    while let Some(suggestion) = cursor.try_next().await? {
+        let server_id = suggestion.get_str("server_id").map_err(|_| ClassError::InvalidSuggestion)?.to_string();
+        let number = suggestion.get_i64("number").map_err(|_| ClassError::InvalidSuggestion)?;
+        let max = last.entry(server_id.clone()).or_insert(0);
+        *max = (*max).max(number);
+        if !seen.insert((server_id.clone(), number)) {
+            duplicates.push((suggestion.get("_id").cloned(), server_id));
+        }
+    }
+
+    for (id, server_id) in duplicates {
+        // Unwrapping because every server with a duplicate has a last number
+        let number = last.get_mut(&server_id).unwrap();
+        *number += 1;
+        suggestions
+            .update_one(doc! { "_id": id }, doc! { "$set": { "number": *number } }, None)
+            .await?;
+    }
+    for (server_id, number) in last {
+        counters
+            .update_one(
+                doc! { "server_id": server_id },
+                doc! { "$max": { "last": number } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+    }
+
+    suggestions
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "server_id": 1, "number": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+    counters
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "server_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Bulk grants kept their own `grant_jobs`, and now run as background jobs. Users still waiting to
+/// join get a pending grant each, and unfinished grants are queued as jobs for the users they
+/// hadn't reached.
+async fn grant_jobs() -> ClassResult<()> {
+    let database = get_conn().await.database(&ENV.mongodb_name);
+    let grant_jobs = database.collection::<Document>("grant_jobs");
+    let pending_grants = database.collection::<Document>("pending_grants");
+    let jobs = database.collection::<Document>("jobs");
+
+    let mut cursor = grant_jobs.find(None, None).await?;
+    while let Some(grant) = cursor.try_next().await? {
+        let field = |name| grant.get(name).cloned().ok_or(ClassError::InvalidJob);
+        let (server_id, role, started_by) = (field("server_id")?, field("role")?, field("started_by")?);
+
+        for user in grant.get_array("pending").map_err(|_| ClassError::InvalidJob)? {
+            pending_grants
+                .update_one(
+                    doc! { "server_id": server_id.clone(), "user": user.clone(), "role": role.clone() },
+                    doc! { "$set": { "granted_by": started_by.clone() } },
+                    UpdateOptions::builder().upsert(true).build(),
+                )
+                .await?;
+        }
+
+        if grant.get_bool("finished").unwrap_or(true) {
+            continue;
+        }
+        let users = grant.get_array("users").map_err(|_| ClassError::InvalidJob)?;
+        let next = grant.get_i64("next").or_else(|_| grant.get_i32("next").map(i64::from)).unwrap_or(0) as usize;
+        jobs
+            .insert_one(
+                doc! {
+                    "server_id": server_id,
+                    "started_by": started_by.clone(),
+                    "job": {
+                        "kind": "GrantRole",
+                        "role": role,
+                        "users": users.iter().skip(next).cloned().collect::<Vec<_>>(),
+                        "granted_by": started_by,
+                    },
+                    "state": "Queued",
+                    "progress": null,
+                    "result": null,
+                    "attempts": 0,
+                    "run_after": DateTime::now(),
+                    "claimed_by": null,
+                    "heartbeat": null,
+                    "status_message": grant.get("progress").cloned(),
+                    "created_at": DateTime::now(),
+                    "finished_at": null,
+                },
+                None,
+            )
+            .await?;
+    }
+
+    grant_jobs.drop(None).await?;
+
+    Ok(())
+}
+
+/// Apply every migration that hasn't been applied to the database yet.
+pub(crate) async fn run() -> ClassResult<()> {
+    let collection = get_collection().await;
+    let applied = collection
+        .find(None, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    for name in MIGRATIONS {
+        if applied.iter().any(|m| m.name == name) {
+            continue;
+        }
+
+        log_info!("Applying database migration {}", name);
+        apply(name).await?;
+        collection
+            .insert_one(AppliedMigration { name: name.to_string(), applied_at: DateTime::now() }, None)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn get_collection() -> Collection<AppliedMigration> {
+    get_conn()
+        .await
+        .database(&ENV.mongodb_name)
+        .collection("migrations")
+}
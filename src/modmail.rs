@@ -0,0 +1,287 @@
+use mongodb::bson::{self, doc, DateTime};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::channel::Message;
+use serenity::model::guild::Role;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::model::user::User;
+use serenity::prelude::{EventHandler, Mentionable};
+use tokio::sync::OnceCell;
+
+use crate::classes::{Class, Server};
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, ClassError, ClassResult, Context, Error, ENV};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Ticket {
+    server_id: GuildId,
+    user: UserId,
+    class: Option<RoleId>,
+    thread: ChannelId,
+    open: bool,
+    opened_at: DateTime,
+    transcript: Vec<TranscriptEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TranscriptEntry {
+    author: UserId,
+    staff: bool,
+    content: String,
+    sent_at: DateTime,
+}
+
+impl Ticket {
+    /// Open a new ticket for `user`, creating a private thread for it in the server's staff
+    /// channel.
+    pub(crate) async fn open(
+        cache_http: impl CacheHttp,
+        server_id: GuildId,
+        user: &User,
+        class: Option<&Class>,
+    ) -> ClassResult<Ticket> {
+        if Self::find_open_by_user(server_id, user.id).await?.is_some() {
+            return Err(ClassError::TicketExists);
+        }
+
+        let server = Server::get_or_create(server_id).await?;
+        let staff_channel = server.staff_channel.ok_or(ClassError::NoStaffChannel)?;
+
+        let tag = class.map(|c| c.short_name.as_str()).unwrap_or("general");
+        let thread = staff_channel
+            .create_private_thread(cache_http.http(), |t| t.name(format!("[{}] {}", tag, user.tag())))
+            .await?;
+
+        thread.say(cache_http.http(), format!(
+            "New modmail ticket from {}{}.{}\nMessages sent here will be relayed to them. Use `/modmail close` to close the ticket.",
+            user.mention(),
+            class.map(|c| format!(" about {}", c.name)).unwrap_or_default(),
+            class.and_then(|c| c.staff_role)
+                .map(|r| format!(" {}", r.mention()))
+                .unwrap_or_default(),
+        )).await?;
+
+        let ticket = Self {
+            server_id,
+            user: user.id,
+            class: class.map(|c| c.role),
+            thread: thread.id,
+            open: true,
+            opened_at: DateTime::now(),
+            transcript: Vec::new(),
+        };
+
+        Self::get_collection().await.insert_one(&ticket, None).await?;
+
+        Ok(ticket)
+    }
+
+    pub(crate) async fn close(&mut self, cache_http: impl CacheHttp) -> ClassResult<()> {
+        Self::get_collection().await
+            .update_one(
+                doc! { "thread": self.thread.to_string() },
+                doc! { "$set": { "open": false } },
+                None,
+            )
+            .await?;
+        self.open = false;
+
+        self.user
+            .create_dm_channel(&cache_http)
+            .await?
+            .say(cache_http.http(), "Your modmail ticket has been closed by staff.")
+            .await?;
+        self.thread
+            .edit_thread(cache_http.http(), |t| t.archived(true).locked(true))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn record(&self, author: UserId, staff: bool, content: String) -> ClassResult<()> {
+        let entry = TranscriptEntry {
+            author,
+            staff,
+            content,
+            sent_at: DateTime::now(),
+        };
+
+        Self::get_collection().await
+            .update_one(
+                doc! { "thread": self.thread.to_string() },
+                doc! { "$push": { "transcript": bson::to_bson(&entry)? } },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn find_open_by_user(server_id: GuildId, user: UserId) -> ClassResult<Option<Ticket>> {
+        Self::scoped(server_id).await
+            .find_one(
+                doc! {
+                    "user": user.to_string(),
+                    "open": true,
+                },
+                None,
+            )
+            .await
+    }
+
+    pub(crate) async fn find_open_by_thread(thread: ChannelId) -> ClassResult<Option<Ticket>> {
+        Ok(
+            Self::get_collection().await
+                .find_one(doc! { "thread": thread.to_string(), "open": true }, None)
+                .await?
+        )
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static TICKETS: OnceCell<Collection<Ticket>> = OnceCell::const_new();
+
+        TICKETS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("modmail")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Render a message for relaying, including links to any attachments since those can't be
+/// forwarded directly.
+fn relay_content(message: &Message) -> String {
+    message.attachments.iter()
+        .map(|a| a.url.as_str())
+        .fold(message.content.clone(), |acc, url| {
+            if acc.is_empty() { url.to_string() } else { format!("{}\n{}", acc, url) }
+        })
+}
+
+pub(crate) struct ModmailHandler;
+
+#[async_trait]
+impl EventHandler for ModmailHandler {
+    async fn message(&self, ctx: SContext, message: Message) {
+        if message.author.bot {
+            return;
+        }
+
+        let result = match message.guild_id {
+            None => relay_from_user(&ctx, &message).await,
+            Some(guild_id) => {
+                let is_thread = ctx.cache
+                    .guild_field(guild_id, |g| g.threads.iter().any(|t| t.id == message.channel_id))
+                    .unwrap_or(false);
+                if !is_thread {
+                    return;
+                }
+                relay_from_staff(&ctx, &message).await
+            }
+        };
+
+        if let Err(e) = result {
+            log_error!("Error relaying modmail message: {:?}", e);
+        }
+    }
+}
+
+async fn relay_from_user(ctx: &SContext, message: &Message) -> ClassResult<()> {
+    let server_id = GuildId(ENV.guild_id);
+    let ticket = match Ticket::find_open_by_user(server_id, message.author.id).await? {
+        Some(t) => t,
+        None => {
+            let ticket = Ticket::open(ctx, server_id, &message.author, None).await?;
+            message.channel_id
+                .say(ctx.http(), "Opened a new modmail ticket. Staff will reply here.")
+                .await?;
+            ticket
+        }
+    };
+
+    let content = relay_content(message);
+    ticket.thread
+        .say(ctx.http(), format!("**{}:** {}", message.author.tag(), content))
+        .await?;
+    ticket.record(message.author.id, false, content).await
+}
+
+async fn relay_from_staff(ctx: &SContext, message: &Message) -> ClassResult<()> {
+    let ticket = match Ticket::find_open_by_thread(message.channel_id).await? {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+
+    let content = relay_content(message);
+    ticket.user
+        .create_dm_channel(ctx)
+        .await?
+        .say(ctx.http(), format!("**Staff:** {}", content))
+        .await?;
+    ticket.record(message.author.id, true, content).await
+}
+
+#[poise::command(slash_command, subcommands("ContactCommand::staff"))]
+pub(crate) async fn contact(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct ContactCommand;
+impl ContactCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn staff(ctx: Context<'_>, class: Role, message: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        let ticket = Ticket::open(ctx.discord(), server_id, ctx.author(), Some(&class)).await?;
+        ticket.thread
+            .say(ctx.discord().http(), format!("**{}:** {}", ctx.author().tag(), message))
+            .await?;
+        ticket.record(ctx.author().id, false, message).await?;
+
+        ctx.say("Your message has been sent to staff. Replies will arrive in your DMs.").await?;
+
+        Ok(())
+    }
+}
+
+#[poise::command(slash_command, subcommands("ModmailCommand::close"))]
+pub(crate) async fn modmail(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct ModmailCommand;
+impl ModmailCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn close(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut ticket = Ticket::find_open_by_thread(ctx.channel_id())
+            .await?
+            .ok_or(ClassError::NoTicket)?;
+
+        // Reply before closing, as the thread will be locked afterwards
+        ctx.say("Closing ticket.").await?;
+        ticket.close(ctx.discord()).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::prelude::Mentionable;
+
+use crate::classes::{Class, Server};
+use crate::departments::department_of;
+use crate::enrollment::enrollment_button;
+use crate::events::BotEvent;
+use crate::redact::log_error;
+use crate::ClassResult;
+
+/// Post a new class to the server's new-classes channel, with a button to join it.
+async fn announce(cache_http: impl CacheHttp, class: &Class) -> ClassResult<()> {
+    let server = Server::get_or_create(class.server_id).await?;
+    let channel = match server.new_class_channel {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+    let theme = server.department_theme(&department_of(class));
+
+    channel
+        .send_message(cache_http.http(), |m| m
+            .embed(|e| {
+                e.title(format!("New class: {}", class.name))
+                    .description(class.description.clone().unwrap_or_else(|| {
+                        format!("{} is now open to join.", class.role.mention())
+                    }));
+                if let Some(colour) = theme.and_then(|t| t.colour) {
+                    e.colour(colour);
+                }
+                e
+            })
+            .components(|c| c.create_action_row(|r| enrollment_button(r, class)))
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Event bus subscriber announcing classes as they are created or tracked. They are listed in
+/// the weekly staff digest through the audit log either way.
+pub(crate) async fn created(ctx: SContext, event: BotEvent) {
+    if let BotEvent::ClassCreated { server_id, class } = event {
+        if let Err(e) = announce(&ctx, &class).await {
+            log_error!("[{}] Error announcing new class: {:?}", server_id, e);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use futures::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::options::UpdateOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use tokio::sync::OnceCell;
+
+use crate::classes::Class;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, ClassError, ClassResult, Context, Error, ENV};
+
+/// How a member wants to hear about announcements posted to a class through the bot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub(crate) enum AnnouncementMode {
+    #[name = "Ping me right away"]
+    Immediate,
+    #[name = "Send me a daily digest DM"]
+    Digest,
+    #[name = "Don't notify me"]
+    Silent,
+}
+
+/// The classes a member doesn't want to be pinged about, and the classes whose announcements they
+/// want in a daily digest instead.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct NotificationPrefs {
+    server_id: GuildId,
+    user: UserId,
+    muted: Vec<RoleId>,
+    #[serde(default)]
+    digest: Vec<RoleId>,
+}
+
+impl NotificationPrefs {
+    async fn get(server_id: GuildId, user: UserId) -> ClassResult<Option<NotificationPrefs>> {
+        Self::scoped(server_id).await
+            .find_one(doc! { "user": user.to_string() }, None)
+            .await
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static PREFS: OnceCell<Collection<NotificationPrefs>> = OnceCell::const_new();
+
+        PREFS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("notification_prefs")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Mute or unmute a class for a member, returning whether anything changed.
+async fn set_muted(server_id: GuildId, user: UserId, role: RoleId, muted: bool) -> ClassResult<bool> {
+    let update = if muted {
+        doc! { "$addToSet": { "muted": role.to_string() } }
+    } else {
+        doc! { "$pull": { "muted": role.to_string() } }
+    };
+
+    let result = NotificationPrefs::scoped(server_id).await
+        .update_one(
+            doc! { "user": user.to_string() },
+            update,
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await?;
+
+    Ok(result.modified_count > 0 || result.upserted_id.is_some())
+}
+
+/// Set how a member hears about a class's announcements. Silencing a class mutes it, so it stops
+/// every other ping about the class too.
+pub(crate) async fn set_announcement_mode(server_id: GuildId, user: UserId, role: RoleId, mode: AnnouncementMode) -> ClassResult<()> {
+    let role = role.to_string();
+    let update = match mode {
+        AnnouncementMode::Immediate => doc! { "$pull": { "muted": &role, "digest": &role } },
+        AnnouncementMode::Digest => doc! { "$pull": { "muted": &role }, "$addToSet": { "digest": &role } },
+        AnnouncementMode::Silent => doc! { "$addToSet": { "muted": &role }, "$pull": { "digest": &role } },
+    };
+
+    NotificationPrefs::scoped(server_id).await
+        .update_one(
+            doc! { "user": user.to_string() },
+            update,
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Split the given users into those to ping about a class's announcement right away and those to
+/// include in their daily digest. Members who muted the class are left out of both.
+pub(crate) async fn announcement_recipients(
+    role: RoleId,
+    users: impl IntoIterator<Item = UserId>,
+) -> ClassResult<(Vec<UserId>, Vec<UserId>)> {
+    let prefs = NotificationPrefs::get_collection().await
+        .find(doc! { "$or": [{ "muted": role.to_string() }, { "digest": role.to_string() }] }, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let muted = prefs.iter().filter(|p| p.muted.contains(&role)).map(|p| p.user).collect::<Vec<_>>();
+    let digest = prefs.iter()
+        .filter(|p| p.digest.contains(&role) && !p.muted.contains(&role))
+        .map(|p| p.user)
+        .collect::<Vec<_>>();
+
+    Ok(
+        users.into_iter()
+            .filter(|u| !muted.contains(u))
+            .partition(|u| !digest.contains(u))
+    )
+}
+
+/// The given users who haven't muted the class. Anything that pings members about a class should
+/// only ping these.
+pub(crate) async fn unmuted(role: RoleId, users: impl IntoIterator<Item = UserId>) -> ClassResult<Vec<UserId>> {
+    let muted = NotificationPrefs::get_collection().await
+        .find(doc! { "muted": role.to_string() }, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?
+        .into_iter()
+        .map(|p| p.user)
+        .collect::<Vec<_>>();
+
+    Ok(users.into_iter().filter(|u| !muted.contains(u)).collect())
+}
+
+#[poise::command(
+    slash_command,
+    subcommands(
+        "NotificationsCommand::mute",
+        "NotificationsCommand::unmute",
+        "NotificationsCommand::announcements",
+        "NotificationsCommand::list",
+    )
+)]
+pub(crate) async fn notifications(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct NotificationsCommand;
+impl NotificationsCommand {
+    /// Stop the bot pinging you about a class, without leaving it.
+    #[poise::command(slash_command, ephemeral)]
+    async fn mute(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let member = ctx.author_member().await.ok_or(ClassError::NoServer)?;
+        if !member.roles.contains(&class.role) {
+            Err(ClassError::NotInClass)?;
+        }
+
+        if set_muted(class.server_id, ctx.author().id, class.role, true).await? {
+            ctx.say(format!("The bot will no longer ping you about \"{}\".", class.name)).await?;
+        } else {
+            ctx.say(format!("You have already muted \"{}\".", class.name)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Let the bot ping you about a class again.
+    #[poise::command(slash_command, ephemeral)]
+    async fn unmute(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        if set_muted(class.server_id, ctx.author().id, class.role, false).await? {
+            ctx.say(format!("The bot will ping you about \"{}\" again.", class.name)).await?;
+        } else {
+            ctx.say(format!("You haven't muted \"{}\".", class.name)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Choose how you hear about announcements posted to a class.
+    #[poise::command(slash_command, ephemeral)]
+    async fn announcements(ctx: Context<'_>, class: Role, mode: AnnouncementMode) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let member = ctx.author_member().await.ok_or(ClassError::NoServer)?;
+        if !member.roles.contains(&class.role) {
+            Err(ClassError::NotInClass)?;
+        }
+
+        set_announcement_mode(class.server_id, ctx.author().id, class.role, mode).await?;
+        ctx.say(match mode {
+            AnnouncementMode::Immediate => format!("You will be pinged about announcements in \"{}\".", class.name),
+            AnnouncementMode::Digest => format!(
+                "Announcements in \"{}\" will be sent to you in a daily digest DM.",
+                class.name,
+            ),
+            AnnouncementMode::Silent => format!("The bot will no longer notify you about \"{}\".", class.name),
+        }).await?;
+
+        Ok(())
+    }
+
+    /// List the classes you have muted or get digests for.
+    #[poise::command(slash_command, ephemeral)]
+    async fn list(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let (muted, digest) = NotificationPrefs::get(server_id, ctx.author().id).await?
+            .map(|p| (p.muted, p.digest))
+            .unwrap_or_default();
+
+        let mut lines = Vec::new();
+        for (roles, label) in [(muted, "You have muted"), (digest, "You get daily digests for")] {
+            let mut names = Vec::new();
+            for role in roles {
+                if let Some(class) = Class::find_by_role(role).await? {
+                    names.push(class.name);
+                }
+            }
+            if !names.is_empty() {
+                lines.push(format!("{}: {}", label, names.join(", ")));
+            }
+        }
+
+        if lines.is_empty() {
+            ctx.say("You haven't muted any classes or chosen digests for any.").await?;
+        } else {
+            ctx.say(lines.join("\n")).await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::model::channel::Channel;
+use serenity::model::id::{ChannelId, GuildId};
+
+use crate::classes::Class;
+use crate::departments::department_of;
+use crate::jobs::JobStatus;
+use crate::{lookup, ClassResult};
+
+/// How many categories are moved in each request.
+const BATCH_SIZE: usize = 10;
+/// How long to wait between batches, to stay clear of Discord's rate limits.
+const BATCH_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub(crate) enum CategoryOrder {
+    #[name = "Alphabetical"]
+    Alphabetical,
+    /// By department, then by course number within each department
+    #[name = "Department"]
+    Department,
+    /// By course number, whatever the department
+    #[name = "Course number"]
+    CourseNumber,
+}
+
+/// The first number in a class's name, like 341 for "CS 341".
+fn course_number(class: &Class) -> Option<u32> {
+    class.name
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|s| !s.is_empty())
+        .and_then(|n| n.parse().ok())
+}
+
+/// Class categories in the given order. A class with more than one category keeps them together
+/// in the order they were added.
+pub(crate) fn category_order(classes: &[Class], by: CategoryOrder) -> Vec<ChannelId> {
+    classes.iter()
+        .sorted_by(|c1, c2| match by {
+            CategoryOrder::Alphabetical => c1.name.to_lowercase().cmp(&c2.name.to_lowercase()),
+            CategoryOrder::Department => department_of(c1).cmp(&department_of(c2))
+                .then_with(|| human_sort::compare(&c1.name, &c2.name)),
+            // Classes without a number go last
+            CategoryOrder::CourseNumber => course_number(c1).unwrap_or(u32::MAX)
+                .cmp(&course_number(c2).unwrap_or(u32::MAX))
+                .then_with(|| human_sort::compare(&c1.name, &c2.name)),
+        })
+        .flat_map(|c| c.categories.iter().copied())
+        .collect()
+}
+
+/// Reorder the server's class categories among the positions they already take up, so other
+/// categories stay where they are. Returns how many categories were moved.
+pub(crate) async fn reorder_categories(
+    ctx: &SContext,
+    server_id: GuildId,
+    by: CategoryOrder,
+    status: &JobStatus,
+) -> ClassResult<usize> {
+    let order = category_order(&Class::list(server_id).await?, by);
+    let current = lookup::guild(ctx, server_id).await?
+        .channels
+        .into_values()
+        .filter_map(|c| match c {
+            Channel::Category(c) if order.contains(&c.id) => Some((c.id, c.position as u64)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let slots = current.iter().map(|(_, p)| *p).sorted().collect::<Vec<_>>();
+    let moves = order.iter()
+        .filter(|c| current.iter().any(|(id, _)| id == *c))
+        .zip(slots)
+        .filter(|(c, position)| !current.contains(&(**c, *position)))
+        .map(|(c, position)| (*c, position))
+        .collect::<Vec<_>>();
+
+    for (i, batch) in moves.chunks(BATCH_SIZE).enumerate() {
+        if i > 0 {
+            tokio::time::sleep(BATCH_DELAY).await;
+        }
+        server_id.reorder_channels(&ctx.http, batch.iter().copied()).await?;
+        status.progress(ctx, &format!("Moved {} of {} categories.", i * BATCH_SIZE + batch.len(), moves.len())).await?;
+    }
+
+    Ok(moves.len())
+}
@@ -0,0 +1,379 @@
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::application::component::{ButtonStyle, ComponentType};
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::application::interaction::Interaction;
+use serenity::model::channel::{Channel, ChannelType};
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::prelude::{EventHandler, Mentionable};
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::classes::{Class, Server};
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, lookup, ClassError, ClassResult, ENV};
+
+/// How often servers are checked for orphaned classes, roles and channels.
+const SCAN_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+lazy_static! {
+    static ref LAST_SCAN: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/// A mismatch between the database and Discord.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Problem {
+    /// A class whose role was deleted.
+    MissingRole { role: RoleId, class: String },
+    /// A class channel that was deleted.
+    MissingChannel { role: RoleId, class: String, channel: ChannelId },
+    /// A role and category with the same name that look like a class, but aren't tracked as one.
+    UntrackedRole { role: RoleId, category: ChannelId, name: String },
+    /// A channel in a class's category that isn't part of the class.
+    UntrackedChannel { role: RoleId, class: String, channel: ChannelId },
+}
+
+impl Problem {
+    /// Identifies the problem between scans, so each one is only reported once.
+    fn key(&self) -> String {
+        match self {
+            Self::MissingRole { role, .. } => format!("missing_role:{}", role),
+            Self::MissingChannel { channel, .. } => format!("missing_channel:{}", channel),
+            Self::UntrackedRole { role, .. } => format!("untracked_role:{}", role),
+            Self::UntrackedChannel { channel, .. } => format!("untracked_channel:{}", channel),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::MissingRole { class, .. } => format!("The role for class \"{}\" no longer exists.", class),
+            Self::MissingChannel { class, channel, .. } => format!(
+                "Class \"{}\" tracks a channel that no longer exists ({}).",
+                class,
+                channel,
+            ),
+            Self::UntrackedRole { role, category, name } => format!(
+                "{} and the category {} look like a class called \"{}\", but aren't tracked as one.",
+                role.mention(),
+                category.mention(),
+                name,
+            ),
+            Self::UntrackedChannel { class, channel, .. } => format!(
+                "{} is in the category for class \"{}\", but isn't part of the class.",
+                channel.mention(),
+                class,
+            ),
+        }
+    }
+
+    fn fix_label(&self) -> &'static str {
+        match self {
+            Self::MissingRole { .. } => "Untrack class",
+            Self::MissingChannel { .. } => "Untrack channel",
+            Self::UntrackedRole { .. } => "Track as class",
+            Self::UntrackedChannel { .. } => "Add to class",
+        }
+    }
+
+    /// Whether the problem is fixed automatically once it is old enough. Only problems where
+    /// something is already gone from Discord are, as fixing them only removes dead references.
+    fn prunable(&self) -> bool {
+        matches!(self, Self::MissingRole { .. } | Self::MissingChannel { .. })
+    }
+
+    async fn fix(&self, ctx: &SContext, server_id: GuildId) -> ClassResult<()> {
+        match self {
+            Self::MissingRole { role, .. } => {
+                if let Some(class) = Class::find_by_role(*role).await? {
+                    class.untrack().await?;
+                }
+            }
+            Self::MissingChannel { role, channel, .. } => {
+                if let Some(mut class) = Class::find_by_role(*role).await? {
+                    class.remove_channel(*channel).await?;
+                }
+            }
+            Self::UntrackedRole { role, category, name } => {
+                let guild = lookup::guild(ctx, server_id).await?;
+                let role = lookup::role(ctx, server_id, *role).await?.ok_or(ClassError::InvalidRole)?;
+                let category = match lookup::channel(ctx, *category).await? {
+                    Some(Channel::Category(c)) => c,
+                    _ => return Err(ClassError::InvalidChannel(category.mention())),
+                };
+                Class::track(ctx, &guild, Some(name.clone()), role, category, &[], None).await?;
+            }
+            Self::UntrackedChannel { role, channel, .. } => {
+                let mut class = Class::find_by_role(*role).await?.ok_or(ClassError::InvalidClass)?;
+                let kind = match lookup::channel(ctx, *channel).await? {
+                    Some(Channel::Guild(c)) => c.kind,
+                    _ => return Err(ClassError::InvalidChannel(channel.mention())),
+                };
+                class.add_channel(*channel, kind).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A problem found by a scan, kept until it is fixed or goes away on its own.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Orphan {
+    server_id: GuildId,
+    key: String,
+    problem: Problem,
+    first_seen: DateTime,
+}
+
+impl Orphan {
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static ORPHANS: OnceCell<Collection<Orphan>> = OnceCell::const_new();
+
+        ORPHANS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("orphans")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Channels the bot creates in class categories on purpose without adding them to the class.
+const OWNED_CHANNELS: [(&str, &str); 3] = [
+    ("teams", "text_channel"),
+    ("teams", "voice_channel"),
+    ("study_sessions", "voice_channel"),
+];
+
+async fn owned_channels(server_id: GuildId) -> ClassResult<Vec<String>> {
+    let database = get_conn().await.database(&ENV.mongodb_name);
+    let mut channels = Vec::new();
+    for (collection, field) in OWNED_CHANNELS {
+        let documents = GuildScoped::new(server_id, database.collection::<Document>(collection))
+            .find(None, None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        channels.extend(documents.iter().filter_map(|d| d.get_str(field).ok()).map(str::to_string));
+    }
+
+    Ok(channels)
+}
+
+/// Cross-check a server's classes against its roles and channels.
+async fn scan(ctx: &SContext, server_id: GuildId) -> ClassResult<Vec<Problem>> {
+    let guild = lookup::guild(ctx, server_id).await?;
+    let classes = Class::list(server_id).await?;
+    let owned = owned_channels(server_id).await?;
+    let mut problems = Vec::new();
+
+    for class in &classes {
+        if !guild.roles.contains_key(&class.role) {
+            problems.push(Problem::MissingRole { role: class.role, class: class.name.clone() });
+            continue;
+        }
+
+        for channel in class.text_channels.iter().chain(class.voice_channels.iter()) {
+            if !guild.channels.contains_key(channel) {
+                problems.push(Problem::MissingChannel {
+                    role: class.role,
+                    class: class.name.clone(),
+                    channel: *channel,
+                });
+            }
+        }
+
+        let tracked = class.all_channels();
+        for channel in guild.channels.values().filter_map(|c| c.clone().guild()) {
+            let in_category = channel.parent_id.is_some_and(|p| class.categories.contains(&p));
+            let trackable = matches!(
+                channel.kind,
+                ChannelType::Text | ChannelType::News | ChannelType::Voice | ChannelType::Stage
+            );
+            let untracked = !tracked.contains(&channel.id) && !owned.contains(&channel.id.to_string());
+            if in_category && trackable && untracked {
+                problems.push(Problem::UntrackedChannel {
+                    role: class.role,
+                    class: class.name.clone(),
+                    channel: channel.id,
+                });
+            }
+        }
+    }
+
+    for channel in guild.channels.values() {
+        let category = match channel {
+            Channel::Category(c) => c,
+            _ => continue,
+        };
+        if classes.iter().any(|c| c.categories.contains(&category.id)) {
+            continue;
+        }
+
+        let role = guild.roles.values()
+            .find(|r| r.name.to_lowercase() == category.name.to_lowercase());
+        if let Some(role) = role {
+            if !classes.iter().any(|c| c.role == role.id) {
+                problems.push(Problem::UntrackedRole {
+                    role: role.id,
+                    category: category.id,
+                    name: category.name.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Scan a server, reporting new problems to the staff channel and pruning old ones if the server
+/// has asked for it.
+async fn check(ctx: &SContext, server_id: GuildId) -> ClassResult<()> {
+    let server = Server::get_or_create(server_id).await?;
+    let problems = scan(ctx, server_id).await?;
+    let collection = Orphan::scoped(server_id).await;
+    let known = collection
+        .find(None, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    // Anything no longer found has been fixed by hand
+    for orphan in &known {
+        if !problems.iter().any(|p| p.key() == orphan.key) {
+            collection.delete_one(doc! { "key": &orphan.key }, None).await?;
+        }
+    }
+
+    let prune_before = server.orphan_prune_days
+        .map(|d| DateTime::from_millis((Utc::now() - chrono::Duration::days(d.into())).timestamp_millis()));
+    for problem in problems {
+        let key = problem.key();
+        match known.iter().find(|o| o.key == key) {
+            Some(orphan) => {
+                let expired = prune_before.is_some_and(|t| orphan.first_seen < t);
+                if !expired || !problem.prunable() {
+                    continue;
+                }
+
+                problem.fix(ctx, server_id).await?;
+                collection.delete_one(doc! { "key": &key }, None).await?;
+                if let Some(channel) = server.staff_channel {
+                    channel.say(ctx.http(), format!("Pruned automatically: {}", problem.describe())).await?;
+                }
+            }
+            None => {
+                collection.insert_one(Orphan {
+                    server_id,
+                    key: key.clone(),
+                    problem: problem.clone(),
+                    first_seen: DateTime::now(),
+                }, None).await?;
+
+                if let Some(channel) = server.staff_channel {
+                    channel.send_message(ctx.http(), |m| m
+                        .content(problem.describe())
+                        .components(|c| c.create_action_row(|r| r.create_button(|b| b
+                            .custom_id(format!("orphan_fix:{}", key))
+                            .label(problem.fix_label())
+                            .style(ButtonStyle::Primary)
+                        )))
+                    ).await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check every server once a day.
+pub(crate) async fn tick(ctx: &SContext) -> ClassResult<()> {
+    {
+        let mut last_scan = LAST_SCAN.lock().await;
+        if last_scan.is_some_and(|t| t.elapsed() < SCAN_INTERVAL) {
+            return Ok(());
+        }
+        *last_scan = Some(Instant::now());
+    }
+
+    for server_id in ctx.cache.guilds() {
+        if let Err(e) = check(ctx, server_id).await {
+            log_error!("[{}] Error checking for orphans: {:?}", server_id, e);
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_fix(ctx: &SContext, component: &MessageComponentInteraction, key: &str) -> ClassResult<()> {
+    let http = ctx.http();
+
+    component.defer(http).await?;
+
+    let member = component.member.as_ref().ok_or(ClassError::NoServer)?;
+    if !member.permissions.map(|p| p.manage_guild()).unwrap_or(false) {
+        return Err(ClassError::MissingPermissions);
+    }
+
+    let server_id = component.guild_id.ok_or(ClassError::NoServer)?;
+    let collection = Orphan::scoped(server_id).await;
+    let filter = doc! { "key": key };
+    let orphan = collection.find_one(filter.clone(), None).await?.ok_or(ClassError::InvalidOrphan)?;
+
+    orphan.problem.fix(ctx, server_id).await?;
+    collection.delete_one(filter, None).await?;
+
+    component.edit_original_interaction_response(http, |r| r
+        .content(format!("{}\nFixed by {}.", orphan.problem.describe(), component.user.mention()))
+        .components(|c| c)
+    ).await?;
+
+    Ok(())
+}
+
+pub(crate) struct OrphanFixHandler;
+
+#[async_trait]
+impl EventHandler for OrphanFixHandler {
+    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
+        let component = if let Interaction::MessageComponent(c) = interaction {
+            c
+        } else {
+            return;
+        };
+        if component.data.component_type != ComponentType::Button {
+            return;
+        }
+        let key = match component.data.custom_id.strip_prefix("orphan_fix:") {
+            Some(k) => k.to_string(),
+            None => return,
+        };
+
+        if let Err(e) = handle_fix(&ctx, &component, &key).await {
+            if let Err(e) = component.create_followup_message(ctx.http(), |f| f
+                .ephemeral(true)
+                .content(e.to_string())
+            ).await {
+                log_error!("Error handling {}: {:?}", component.data.custom_id, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashSet;
+
+use futures::TryStreamExt;
+use itertools::Itertools;
+use mongodb::bson::{doc, DateTime};
+use mongodb::Collection;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use serenity::http::CacheHttp;
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::prelude::Mentionable;
+use tokio::sync::OnceCell;
+
+use crate::classes::Class;
+use crate::mentions::{self, Pings};
+use crate::{get_conn, lookup, ClassError, ClassResult, Context, Error, ENV};
+
+/// How many random shuffles to try when looking for pairs that haven't been used before.
+const ATTEMPTS: usize = 200;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct OptIn {
+    server_id: GuildId,
+    role: RoleId,
+    user: UserId,
+}
+
+impl OptIn {
+    async fn get_collection() -> Collection<Self> {
+        static OPT_INS: OnceCell<Collection<OptIn>> = OnceCell::const_new();
+
+        OPT_INS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("peer_review_opt_ins")
+            })
+            .await
+            .clone()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Round {
+    server_id: GuildId,
+    role: RoleId,
+    groups: Vec<Vec<UserId>>,
+    created_at: DateTime,
+}
+
+impl Round {
+    async fn list(role: RoleId) -> ClassResult<Vec<Round>> {
+        Ok(
+            Self::get_collection().await
+                .find(doc! { "role": role.to_string() }, None)
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static ROUNDS: OnceCell<Collection<Round>> = OnceCell::const_new();
+
+        ROUNDS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("peer_review_rounds")
+            })
+            .await
+            .clone()
+    }
+}
+
+fn ordered(a: UserId, b: UserId) -> (UserId, UserId) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+/// Split `members` into groups of two (with one group of three if there is an odd number of
+/// members), preferring the arrangement that repeats the fewest pairs from `history`.
+fn pair(members: &[UserId], history: &HashSet<(UserId, UserId)>) -> Vec<Vec<UserId>> {
+    let mut members = members.to_vec();
+    let mut rng = rand::thread_rng();
+    let mut best: Option<(usize, Vec<Vec<UserId>>)> = None;
+
+    for _ in 0..ATTEMPTS {
+        members.shuffle(&mut rng);
+
+        let mut groups = members.chunks(2).map(|c| c.to_vec()).collect::<Vec<_>>();
+        if groups.len() > 1 && groups.last().map(|g| g.len() == 1).unwrap_or(false) {
+            let odd = groups.pop().unwrap();
+            groups.last_mut().unwrap().extend(odd);
+        }
+
+        let repeats = groups.iter()
+            .flat_map(|g| g.iter().tuple_combinations())
+            .filter(|(a, b)| history.contains(&ordered(**a, **b)))
+            .count();
+
+        if best.as_ref().map(|(r, _)| repeats < *r).unwrap_or(true) {
+            best = Some((repeats, groups));
+        }
+        if repeats == 0 {
+            break;
+        }
+    }
+
+    best.map(|(_, groups)| groups).unwrap_or_default()
+}
+
+#[poise::command(
+    slash_command,
+    subcommands("PeerReviewCommand::optin", "PeerReviewCommand::optout", "PeerReviewCommand::pair")
+)]
+pub(crate) async fn peerreview(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct PeerReviewCommand;
+impl PeerReviewCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn optin(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let filter = doc! { "role": class.role.to_string(), "user": ctx.author().id.to_string() };
+
+        let collection = OptIn::get_collection().await;
+        if collection.find_one(filter, None).await?.is_none() {
+            collection
+                .insert_one(
+                    OptIn {
+                        server_id: class.server_id,
+                        role: class.role,
+                        user: ctx.author().id,
+                    },
+                    None,
+                )
+                .await?;
+        }
+
+        ctx.say(format!("You will be included in peer review pairings for \"{}\".", class.name)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn optout(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        OptIn::get_collection().await
+            .delete_many(doc! { "role": class.role.to_string(), "user": ctx.author().id.to_string() }, None)
+            .await?;
+
+        ctx.say(format!("You will no longer be included in peer review pairings for \"{}\".", class.name)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn pair(ctx: Context<'_>, class: Role, dm: Option<bool>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let guild = lookup::current_guild(ctx).await?;
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        // Only pair members who still hold the class role
+        let members = OptIn::get_collection().await
+            .find(doc! { "role": class.role.to_string() }, None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+            .into_iter()
+            .map(|o| o.user)
+            .filter(|u| guild.members.get(u).map(|m| m.roles.contains(&class.role)).unwrap_or(false))
+            .collect::<Vec<_>>();
+        if members.len() < 2 {
+            Err(ClassError::NotEnoughMembers)?;
+        }
+
+        let history = Round::list(class.role).await?
+            .iter()
+            .flat_map(|r| r.groups.iter())
+            .flat_map(|g| g.iter().tuple_combinations().map(|(a, b)| ordered(*a, *b)))
+            .collect::<HashSet<_>>();
+
+        let groups = pair(&members, &history);
+
+        Round::get_collection().await
+            .insert_one(
+                Round {
+                    server_id: class.server_id,
+                    role: class.role,
+                    groups: groups.clone(),
+                    created_at: DateTime::now(),
+                },
+                None,
+            )
+            .await?;
+
+        let http = ctx.discord().http();
+        if dm.unwrap_or(false) {
+            for group in &groups {
+                for user in group {
+                    let partners = group.iter().filter(|u| *u != user).map(|u| u.mention()).join(", ");
+                    // Throwing away the result as members may have DMs disabled
+                    if let Ok(channel) = user.create_dm_channel(ctx.discord()).await {
+                        channel.say(http, format!(
+                            "Your peer review partner(s) for {}: {}",
+                            class.name, partners,
+                        )).await.ok();
+                    }
+                }
+            }
+        } else {
+            let channel = class.text_channels.first().ok_or(ClassError::NoClassChannels)?;
+            let content = format!(
+                "**Peer review pairs:**\n{}",
+                groups.iter()
+                    .map(|g| g.iter().map(|u| u.mention()).join(" ↔ "))
+                    .join("\n"),
+            );
+            mentions::send(http, *channel, class.role, content, Pings::users(groups.iter().flatten().copied())).await?;
+        }
+
+        ctx.say(format!("Created {} peer review groups for \"{}\".", groups.len(), class.name)).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::http::CacheHttp;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use tokio::sync::OnceCell;
+
+use crate::redact::log_info;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, ClassResult, ENV};
+
+/// Discord's limit on the length of an embed's description.
+const INDEX_LIMIT: usize = 4096;
+
+/// A message pinned to a class channel's index, with a description of what it is.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Pin {
+    server_id: GuildId,
+    channel: ChannelId,
+    message: MessageId,
+    pub(crate) description: String,
+    pinned_by: UserId,
+    pinned_at: DateTime,
+}
+
+/// The message in a channel listing its pins, which is edited as pins are added and removed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PinIndex {
+    channel: ChannelId,
+    message: MessageId,
+}
+
+/// Split a message link, like `https://discord.com/channels/<server>/<channel>/<message>`, into
+/// its IDs.
+pub(crate) fn parse_message_link(link: &str) -> Option<(GuildId, ChannelId, MessageId)> {
+    let path = link.trim().trim_start_matches('<').trim_end_matches('>')
+        .split_once("/channels/")?
+        .1;
+    let mut ids = path.split('/');
+    let ids = (ids.next()?, ids.next()?, ids.next()?, ids.next());
+    match ids {
+        (server, channel, message, None) => Some((
+            GuildId(server.parse().ok()?),
+            ChannelId(channel.parse().ok()?),
+            MessageId(message.parse().ok()?),
+        )),
+        _ => None,
+    }
+}
+
+/// The index's text, listing each pin as a link. Pins that don't fit are counted at the end.
+pub(crate) fn index_text(server_id: GuildId, channel: ChannelId, pins: &[(MessageId, String)]) -> String {
+    if pins.is_empty() {
+        return "Nothing has been pinned yet. Staff can pin messages with `/class pin`.".to_string();
+    }
+
+    let mut text = String::new();
+    for (i, (message, description)) in pins.iter().enumerate() {
+        let line = format!("• [{}]({})\n", description, message.link(channel, Some(server_id)));
+        let more = format!("…and {} more", pins.len() - i);
+        if text.chars().count() + line.chars().count() + more.chars().count() > INDEX_LIMIT {
+            text += &more;
+            return text;
+        }
+        text += &line;
+    }
+    text.trim_end().to_string()
+}
+
+impl Pin {
+    /// Add a message to its channel's index, or update its description if it's already there.
+    /// The message is also pinned in Discord if there's room.
+    pub(crate) async fn add(
+        cache_http: impl CacheHttp,
+        server_id: GuildId,
+        channel: ChannelId,
+        message: MessageId,
+        description: &str,
+        pinned_by: UserId,
+    ) -> ClassResult<()> {
+        // Fetching first makes sure the message exists
+        let pinned = channel.message(cache_http.http(), message).await?;
+        Self::scoped(server_id).await
+            .update_one(
+                doc! { "message": message.to_string() },
+                doc! {
+                    "$set": { "description": description },
+                    "$setOnInsert": {
+                        "channel": channel.to_string(),
+                        "pinned_by": pinned_by.to_string(),
+                        "pinned_at": DateTime::now(),
+                    },
+                },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        if let Err(e) = pinned.pin(cache_http.http()).await {
+            // Most likely the channel already has 50 pins, which is what the index is for
+            log_info!("Couldn't pin {} in Discord: {:?}", message, e);
+        }
+
+        refresh_index(cache_http, server_id, channel).await
+    }
+
+    /// Take a message off its channel's index, returning whether it was there.
+    pub(crate) async fn remove(
+        cache_http: impl CacheHttp,
+        server_id: GuildId,
+        channel: ChannelId,
+        message: MessageId,
+    ) -> ClassResult<bool> {
+        let removed = Self::get_collection().await
+            .delete_one(doc! { "channel": channel.to_string(), "message": message.to_string() }, None)
+            .await?
+            .deleted_count > 0;
+        if !removed {
+            return Ok(false);
+        }
+
+        if let Err(e) = channel.unpin(cache_http.http(), message).await {
+            log_info!("Couldn't unpin {} in Discord: {:?}", message, e);
+        }
+        refresh_index(cache_http, server_id, channel).await?;
+
+        Ok(true)
+    }
+
+    /// The channel's pins, oldest first.
+    pub(crate) async fn list(channel: ChannelId) -> ClassResult<Vec<Pin>> {
+        Ok(
+            Self::get_collection().await
+                .find(
+                    doc! { "channel": channel.to_string() },
+                    FindOptions::builder().sort(doc! { "pinned_at": 1 }).build(),
+                )
+                .await?
+                .try_collect()
+                .await?
+        )
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static PINS: OnceCell<Collection<Pin>> = OnceCell::const_new();
+
+        PINS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("pins")
+            })
+            .await
+            .clone()
+    }
+}
+
+impl PinIndex {
+    async fn get_collection() -> Collection<Self> {
+        static PIN_INDEXES: OnceCell<Collection<PinIndex>> = OnceCell::const_new();
+
+        PIN_INDEXES
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("pin_indexes")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Edit the channel's index to match its pins, posting and pinning a new one if it has none yet or
+/// it was deleted.
+async fn refresh_index(cache_http: impl CacheHttp, server_id: GuildId, channel: ChannelId) -> ClassResult<()> {
+    let http = cache_http.http();
+    let pins = Pin::list(channel).await?
+        .into_iter()
+        .map(|p| (p.message, p.description))
+        .collect::<Vec<_>>();
+    let text = index_text(server_id, channel, &pins);
+
+    let existing = PinIndex::get_collection().await
+        .find_one(doc! { "channel": channel.to_string() }, None)
+        .await?;
+    if let Some(index) = existing {
+        let edited = channel
+            .edit_message(http, index.message, |m| m.embed(|e| e.title("📌 Pinned index").description(&text)))
+            .await;
+        if edited.is_ok() {
+            return Ok(());
+        }
+    }
+
+    let index = channel
+        .send_message(http, |m| m.embed(|e| e.title("📌 Pinned index").description(&text)))
+        .await?;
+    if let Err(e) = index.pin(http).await {
+        log_info!("Couldn't pin the pinned index in {}: {:?}", channel, e);
+    }
+    PinIndex::get_collection().await
+        .update_one(
+            doc! { "channel": channel.to_string() },
+            doc! { "$set": { "message": index.id.to_string() } },
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+//! Who may run each command. The `required_permissions` of every command are taken out when the
+//! bot starts and checked by the global command check here instead, so that a server can replace
+//! them with a policy listing the roles allowed to run a command or a whole group of commands.
+//! Members can also be given temporary access to commands they couldn't otherwise run.
+
+use std::collections::HashMap;
+
+use poise::AutocompleteChoice;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::RoleId;
+use serenity::model::permissions::Permissions;
+
+use crate::classes::Server;
+use crate::tempgrants::{self, TempGrant};
+use crate::{Context, Data, Error};
+
+/// Discord shows at most 25 autocomplete choices.
+const AUTOCOMPLETE_LIMIT: usize = 25;
+
+/// The roles allowed to run a command in a server, in place of its default permissions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct CommandPolicy {
+    /// The command's full name, like "class edit tag", or a group of commands, like "config".
+    pub(crate) command: String,
+    pub(crate) roles: Vec<RoleId>,
+}
+
+/// Clear the `required_permissions` of every command, returning them by the command's full name.
+pub(crate) fn take_required_permissions(commands: &mut [poise::Command<Data, Error>]) -> HashMap<String, Permissions> {
+    let mut required = HashMap::new();
+    for command in commands {
+        take(command, "", &mut required);
+    }
+    required
+}
+
+fn take(command: &mut poise::Command<Data, Error>, parent: &str, required: &mut HashMap<String, Permissions>) {
+    let path = if parent.is_empty() { command.name.clone() } else { format!("{} {}", parent, command.name) };
+    if !command.required_permissions.is_empty() {
+        required.insert(path.clone(), command.required_permissions);
+        command.required_permissions = Permissions::empty();
+    }
+    for subcommand in &mut command.subcommands {
+        take(subcommand, &path, required);
+    }
+}
+
+/// The full name of every command and command group.
+pub(crate) fn command_names(commands: &[poise::Command<Data, Error>]) -> Vec<String> {
+    fn add(command: &poise::Command<Data, Error>, parent: &str, names: &mut Vec<String>) {
+        let path = if parent.is_empty() { command.name.clone() } else { format!("{} {}", parent, command.name) };
+        for subcommand in &command.subcommands {
+            add(subcommand, &path, names);
+        }
+        names.push(path);
+    }
+
+    let mut names = Vec::new();
+    for command in commands {
+        add(command, "", &mut names);
+    }
+    names.sort();
+    names
+}
+
+/// The policy that applies to a command: its own, or else the one for the closest group it's in.
+pub(crate) fn policy_for<'a>(policies: &'a [CommandPolicy], command: &str) -> Option<&'a CommandPolicy> {
+    let mut name = command;
+    loop {
+        if let Some(policy) = policies.iter().find(|p| p.command == name) {
+            return Some(policy);
+        }
+        name = name.rsplit_once(' ')?.0;
+    }
+}
+
+/// Whether a member may run a command. Members who can manage the server may run anything with a
+/// policy, so a policy can't lock them out.
+pub(crate) fn allowed(policy: Option<&CommandPolicy>, default: Permissions, permissions: Permissions, roles: &[RoleId]) -> bool {
+    match policy {
+        Some(policy) => permissions.manage_guild() || policy.roles.iter().any(|r| roles.contains(r)),
+        None => permissions.contains(default),
+    }
+}
+
+/// The global command check, applying the server's policy or else the command's default
+/// permissions, then any temporary grants the member has.
+pub(crate) async fn check(ctx: Context<'_>) -> Result<bool, Error> {
+    // Commands in DMs have no permissions to check, as in poise
+    let server_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(true),
+    };
+    let member = match ctx.author_member().await {
+        Some(m) => m,
+        None => return Ok(false),
+    };
+    let command = &ctx.command().qualified_name;
+    let default = ctx.data().required_permissions.get(command).copied().unwrap_or_else(Permissions::empty);
+    let server = Server::get_or_create(server_id).await?;
+
+    if allowed(
+        policy_for(&server.command_policies, command),
+        default,
+        member.permissions.unwrap_or_else(Permissions::empty),
+        &member.roles,
+    ) {
+        return Ok(true);
+    }
+
+    Ok(TempGrant::active(server_id, member.user.id).await?.iter().any(|g| tempgrants::covers(&g.command, command)))
+}
+
+/// Suggest command names, for setting policies.
+pub(crate) async fn autocomplete_command(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice<String>> {
+    let partial = partial.trim().to_lowercase();
+    command_names(&ctx.framework().options().commands)
+        .into_iter()
+        .filter(|n| n.contains(&partial))
+        .take(AUTOCOMPLETE_LIMIT)
+        .map(|n| AutocompleteChoice { name: n.clone(), value: n })
+        .collect()
+}
@@ -0,0 +1,190 @@
+use std::borrow::Cow;
+
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::UpdateOptions;
+use serenity::model::channel::AttachmentType;
+use serenity::model::id::UserId;
+
+use crate::{get_conn, ClassResult, Context, Error, ENV};
+
+/// Stands in for a forgotten user wherever a record has to keep some author.
+const ANONYMOUS: &str = "0";
+
+/// What happens to a reference to a user when they ask to be forgotten.
+#[derive(Clone, Copy)]
+enum Forget {
+    /// Delete the whole document, as it is only about the user.
+    Delete,
+    /// Replace the user with `ANONYMOUS`, keeping the document for everyone else.
+    Anonymize,
+    /// Remove the user from an array of users.
+    Pull,
+    /// Remove the user from an array of arrays of users.
+    PullNested,
+    /// Anonymize the user in an array of entries, and remove what they wrote. The field is
+    /// `array.user_field`.
+    Redact,
+}
+
+/// Every collection field that refers to a user, and how to forget them there.
+const USER_REFERENCES: [(&str, &str, Forget); 39] = [
+    ("users", "user_id", Forget::Delete),
+    ("enrollment_events", "user", Forget::Delete),
+    ("enrollment_events", "actor", Forget::Anonymize),
+    ("audit_log", "user_id", Forget::Delete),
+    ("voice_time", "user", Forget::Delete),
+    ("tutors", "user", Forget::Delete),
+    ("mentor_opt_ins", "user", Forget::Delete),
+    ("notification_prefs", "user", Forget::Delete),
+    ("pending_notifications", "user", Forget::Delete),
+    ("peer_review_opt_ins", "user", Forget::Delete),
+    ("message_archive", "author", Forget::Delete),
+    ("modmail", "user", Forget::Delete),
+    ("modmail", "transcript.author", Forget::Redact),
+    ("study_sessions", "host", Forget::Anonymize),
+    ("assignments", "created_by", Forget::Anonymize),
+    ("study_sessions", "rsvps", Forget::Pull),
+    ("suggestions", "author", Forget::Anonymize),
+    ("suggestions", "upvotes", Forget::Pull),
+    ("suggestions", "downvotes", Forget::Pull),
+    ("class_requests", "requester", Forget::Anonymize),
+    ("class_invites", "created_by", Forget::Anonymize),
+    ("tags", "created_by", Forget::Anonymize),
+    ("snippets", "created_by", Forget::Anonymize),
+    ("teams", "members", Forget::Pull),
+    ("help_threads", "asker", Forget::Anonymize),
+    ("help_threads", "answerers", Forget::Pull),
+    ("peer_review_rounds", "groups", Forget::PullNested),
+    ("grant_jobs", "started_by", Forget::Anonymize),
+    ("grant_jobs", "users", Forget::Pull),
+    ("grant_jobs", "pending", Forget::Pull),
+    ("grant_jobs", "failed", Forget::Pull),
+    ("hand_queues", "queue", Forget::Pull),
+    ("hand_queues", "current", Forget::Anonymize),
+    ("recording_consents", "user", Forget::Delete),
+    ("jobs", "started_by", Forget::Anonymize),
+    ("pins", "pinned_by", Forget::Anonymize),
+    ("classes", "managers", Forget::Pull),
+    ("temp_grants", "user", Forget::Delete),
+    ("temp_grants", "granted_by", Forget::Anonymize),
+];
+
+fn filter(field: &str, forget: Forget, user: &str) -> Document {
+    match forget {
+        Forget::PullNested => doc! { field: { "$elemMatch": { "$elemMatch": { "$eq": user } } } },
+        _ => doc! { field: user },
+    }
+}
+
+/// Every document mentioning the user, grouped by collection and field.
+async fn export(user: UserId) -> ClassResult<Document> {
+    let user = user.to_string();
+    let database = get_conn().await.database(&ENV.mongodb_name);
+
+    let mut export = doc! { "user_id": &user };
+    for (collection, field, forget) in USER_REFERENCES {
+        let documents = database.collection::<Document>(collection)
+            .find(filter(field, forget, &user), None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        if !documents.is_empty() {
+            export.insert(format!("{}.{}", collection, field), documents);
+        }
+    }
+
+    Ok(export)
+}
+
+/// Delete or anonymize everything stored about the user, returning how many documents changed.
+async fn forget(user: UserId) -> ClassResult<u64> {
+    let user = user.to_string();
+    let database = get_conn().await.database(&ENV.mongodb_name);
+
+    let mut changed = 0;
+    for (collection, field, forget) in USER_REFERENCES {
+        let collection = database.collection::<Document>(collection);
+        let filter = filter(field, forget, &user);
+        changed += match forget {
+            Forget::Delete => collection.delete_many(filter, None).await?.deleted_count,
+            Forget::Anonymize => collection
+                .update_many(filter, doc! { "$set": { field: ANONYMOUS } }, None)
+                .await?
+                .modified_count,
+            Forget::Pull => collection
+                .update_many(filter, doc! { "$pull": { field: &user } }, None)
+                .await?
+                .modified_count,
+            Forget::PullNested => collection
+                .update_many(filter, doc! { "$pull": { format!("{}.$[]", field): &user } }, None)
+                .await?
+                .modified_count,
+            Forget::Redact => {
+                // Unwrapping because redacted fields are always written as `array.user_field`
+                let (array, user_field) = field.split_once('.').unwrap();
+                collection
+                    .update_many(
+                        filter,
+                        doc! { "$set": {
+                            format!("{}.$[entry].{}", array, user_field): ANONYMOUS,
+                            format!("{}.$[entry].content", array): "[removed]",
+                        } },
+                        UpdateOptions::builder()
+                            .array_filters(vec![doc! { format!("entry.{}", user_field): &user }])
+                            .build(),
+                    )
+                    .await?
+                    .modified_count
+            }
+        };
+    }
+
+    Ok(changed)
+}
+
+#[poise::command(slash_command, subcommands("PrivacyCommand::export", "PrivacyCommand::forget"))]
+pub(crate) async fn privacy(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct PrivacyCommand;
+impl PrivacyCommand {
+    /// Get a copy of everything the bot stores about you.
+    #[poise::command(slash_command, ephemeral)]
+    async fn export(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let export = Bson::Document(export(ctx.author().id).await?).into_relaxed_extjson();
+        let data = serde_json::to_vec_pretty(&export)?;
+
+        ctx.send(|m| m
+            .content("Here is everything the bot stores about you.")
+            .attachment(AttachmentType::Bytes {
+                data: Cow::Owned(data),
+                filename: format!("{}.json", ctx.author().id),
+            })
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Delete everything the bot stores about you. This can't be undone.
+    #[poise::command(slash_command, ephemeral)]
+    async fn forget(ctx: Context<'_>, confirm: bool) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        if !confirm {
+            ctx.say("Nothing was deleted. Run this again with `confirm` set to true to delete your data.").await?;
+            return Ok(());
+        }
+
+        let changed = forget(ctx.author().id).await?;
+
+        ctx.say(format!(
+            "Deleted or anonymized {} records. Your class roles have not been changed.",
+            changed,
+        )).await?;
+
+        Ok(())
+    }
+}
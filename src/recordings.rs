@@ -0,0 +1,131 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::UpdateOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::application::interaction::{Interaction, InteractionResponseType};
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::model::prelude::component::{ButtonStyle, ComponentType};
+use serenity::prelude::EventHandler;
+use tokio::sync::OnceCell;
+
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::sessions::StudySession;
+use crate::{get_conn, ClassError, ClassResult, ENV};
+
+/// A member's acknowledgement that a session is being recorded, kept for instructors who need a
+/// record of consent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct RecordingConsent {
+    server_id: GuildId,
+    /// The session's message, which identifies the session.
+    session: MessageId,
+    pub(crate) user: UserId,
+    pub(crate) acknowledged_at: DateTime,
+}
+
+impl RecordingConsent {
+    async fn record(server_id: GuildId, session: MessageId, user: UserId) -> ClassResult<()> {
+        Self::scoped(server_id).await
+            .update_one(
+                doc! { "session": session.to_string(), "user": user.to_string() },
+                doc! { "$setOnInsert": { "acknowledged_at": DateTime::now() } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Everyone who acknowledged the session's recording, earliest first.
+    pub(crate) async fn for_session(session: MessageId) -> ClassResult<Vec<RecordingConsent>> {
+        let mut consents = Self::get_collection().await
+            .find(doc! { "session": session.to_string() }, None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        consents.sort_by_key(|c| c.acknowledged_at);
+        Ok(consents)
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static CONSENTS: OnceCell<Collection<RecordingConsent>> = OnceCell::const_new();
+
+        CONSENTS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("recording_consents")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Post the notice that a session is being recorded, with a button to acknowledge it.
+pub(crate) async fn post_notice(cache_http: impl CacheHttp, channel: ChannelId, topic: &str) -> ClassResult<Message> {
+    Ok(
+        channel
+            .send_message(cache_http.http(), |m| m
+                .content(format!(
+                    "🔴 The study session \"{}\" is being recorded. By taking part, you agree to being recorded. \
+                    Please acknowledge this below.",
+                    topic,
+                ))
+                .components(|c| c.create_action_row(|r| r
+                    .create_button(|b| b
+                        .custom_id("recording_consent")
+                        .style(ButtonStyle::Secondary)
+                        .label("I understand")
+                    )
+                ))
+            )
+            .await?
+    )
+}
+
+pub(crate) struct RecordingConsentHandler;
+
+#[async_trait]
+impl EventHandler for RecordingConsentHandler {
+    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
+        let component = if let Interaction::MessageComponent(c) = interaction {
+            c
+        } else {
+            return;
+        };
+        if component.data.component_type != ComponentType::Button || component.data.custom_id != "recording_consent" {
+            return;
+        }
+
+        let recorded = match StudySession::find_by_recording_notice(component.message.id).await {
+            Ok(Some((server_id, session))) => RecordingConsent::record(server_id, session, component.user.id).await,
+            Ok(None) => Err(ClassError::InvalidSession),
+            Err(e) => Err(e),
+        };
+        let reply = match recorded {
+            Ok(()) => "Thanks, your acknowledgement has been recorded.".to_string(),
+            Err(e) => {
+                log_error!("Error handling recording_consent: {:?}", e);
+                e.to_string()
+            }
+        };
+
+        if let Err(e) = component.create_interaction_response(ctx.http(), |r| r
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|d| d.ephemeral(true).content(reply))
+        ).await {
+            log_error!("Error handling recording_consent: {:?}", e);
+        }
+    }
+}
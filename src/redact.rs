@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+const REDACTED: &str = "[redacted]";
+
+/// Environment variables whose values are secret, read directly so they are redacted even before
+/// `ENV` is loaded.
+const SECRET_VARS: [&str; 9] = [
+    "BOT_TOKEN",
+    "DEV_BOT_TOKEN",
+    "MONGODB_PASSWORD",
+    "MONGODB_URI",
+    "SMTP_URL",
+    "SECRET_KEY",
+    "OLD_SECRET_KEYS",
+    "SENTRY_DSN",
+    "TEST_MONGODB_URI",
+];
+
+lazy_static! {
+    /// Secrets decrypted from storage since startup.
+    static ref REMEMBERED: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+    /// Patterns for secrets that can be recognised without knowing them: credentials in URLs,
+    /// Discord webhook tokens, and bearer tokens.
+    static ref PATTERNS: [(Regex, &'static str); 3] = [
+        (Regex::new(r"://[^/\s@]+@").unwrap(), "://[redacted]@"),
+        (Regex::new(r"(/webhooks/\d+/)[\w-]+").unwrap(), "${1}[redacted]"),
+        (Regex::new(r"(?i)(bearer\s+)[\w.~+/=-]+").unwrap(), "${1}[redacted]"),
+    ];
+}
+
+/// Make sure a secret read from storage is redacted from then on.
+pub(crate) fn remember(secret: &str) {
+    REMEMBERED.lock().unwrap().insert(secret.to_string());
+}
+
+/// Scrub the bot token, database and SMTP credentials, encryption keys, and stored integration
+/// secrets from text that is about to be logged or shown.
+pub(crate) fn redact(text: &str) -> String {
+    let mut secrets = SECRET_VARS.iter()
+        .filter_map(|name| Some((name, std::env::var(name).ok()?)))
+        .flat_map(|(name, value)| if *name == "OLD_SECRET_KEYS" {
+            // Listed as `id:key,id:key`
+            value.split(',').filter_map(|k| Some(k.split_once(':')?.1.trim().to_string())).collect()
+        } else {
+            vec![value]
+        })
+        .chain(REMEMBERED.lock().unwrap().iter().cloned())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    // Longest first, so a secret containing another is redacted whole
+    secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+
+    let mut text = text.to_string();
+    for secret in secrets {
+        text = text.replace(&secret, REDACTED);
+    }
+    for (pattern, replacement) in PATTERNS.iter() {
+        text = pattern.replace_all(&text, *replacement).into_owned();
+    }
+
+    text
+}
+
+/// `println!`, with secrets redacted.
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        println!("{}", $crate::redact::redact(&format!($($arg)*)))
+    };
+}
+
+/// `eprintln!`, with secrets redacted.
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        eprintln!("{}", $crate::redact::redact(&format!($($arg)*)))
+    };
+}
+
+pub(crate) use {log_error, log_info};
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::channel::Channel;
+use serenity::model::guild::Role;
+use serenity::prelude::EventHandler;
+
+use crate::classes::{Class, Server};
+use crate::redact::log_error;
+use crate::{stored_name, ClassResult};
+
+/// What to do when a class's role or category is renamed in Discord.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, poise::ChoiceParameter)]
+pub(crate) enum RenameSync {
+    /// Rename the class to match
+    #[default]
+    #[name = "Update"]
+    Update,
+    /// Leave the class as is, and tell staff about the mismatch
+    #[name = "Flag"]
+    Flag,
+    /// Do nothing
+    #[name = "Ignore"]
+    Ignore,
+}
+
+async fn sync_name(ctx: &SContext, mut class: Class, name: &str, what: &str) -> ClassResult<()> {
+    let name = stored_name(name);
+    if class.name == name {
+        return Ok(());
+    }
+
+    let server = Server::get_or_create(class.server_id).await?;
+    match server.rename_sync {
+        RenameSync::Update => class.rename(name).await,
+        RenameSync::Flag => {
+            if let Some(channel) = server.staff_channel {
+                channel.say(ctx.http(), format!(
+                    "The {} for class \"{}\" was renamed to \"{}\". The class has not been renamed.",
+                    what, class.name, name,
+                )).await?;
+            }
+            Ok(())
+        }
+        RenameSync::Ignore => Ok(()),
+    }
+}
+
+pub(crate) struct ClassRenameHandler;
+
+#[async_trait]
+impl EventHandler for ClassRenameHandler {
+    async fn guild_role_update(&self, ctx: SContext, _old: Option<Role>, new: Role) {
+        let result = match Class::find_by_role(new.id).await {
+            Ok(Some(class)) => sync_name(&ctx, class, &new.name, "role").await,
+            Ok(None) => return,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            log_error!("Error syncing class name from role: {:?}", e);
+        }
+    }
+
+    async fn channel_update(&self, ctx: SContext, _old: Option<Channel>, new: Channel) {
+        let category = match new {
+            Channel::Category(c) => c,
+            _ => return,
+        };
+
+        // Only the first category is named after the class, the rest are numbered
+        let result = match Class::find_by_category(category.id).await {
+            Ok(Some(class)) if class.category() == category.id => sync_name(&ctx, class, &category.name, "category").await,
+            Ok(_) => return,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            log_error!("Error syncing class name from category: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use mongodb::bson::doc;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::application::interaction::Interaction;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::model::prelude::component::{ButtonStyle, ComponentType};
+use serenity::prelude::{EventHandler, Mentionable};
+use serenity::utils::Colour;
+use tokio::sync::OnceCell;
+
+use crate::classes::{Class, Server};
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::visibility::Visibility;
+use crate::{get_conn, lookup, ClassError, ClassResult, ENV};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum RequestStatus {
+    Pending,
+    Approved,
+    Declined,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ClassRequest {
+    server_id: GuildId,
+    pub(crate) name: String,
+    requester: UserId,
+    channel: ChannelId,
+    message: MessageId,
+    status: RequestStatus,
+}
+
+impl ClassRequest {
+    fn render<'a>(&self, e: &'a mut CreateEmbed) -> &'a mut CreateEmbed {
+        let (status, colour) = match self.status {
+            RequestStatus::Pending => ("Pending", Colour::BLURPLE),
+            RequestStatus::Approved => ("Approved", Colour::DARK_GREEN),
+            RequestStatus::Declined => ("Declined", Colour::RED),
+        };
+
+        e.title("Class request")
+            .description(&self.name)
+            .colour(colour)
+            .field("Requested by", self.requester.mention(), true)
+            .field("Status", status, true)
+    }
+
+    fn components<'a>(&self, c: &'a mut CreateComponents) -> &'a mut CreateComponents {
+        if self.status != RequestStatus::Pending {
+            return c;
+        }
+
+        c.create_action_row(|r| r
+            .create_button(|b| b
+                .custom_id("class_request_approve")
+                .style(ButtonStyle::Success)
+                .label("Approve & create")
+            )
+            .create_button(|b| b
+                .custom_id("class_request_decline")
+                .style(ButtonStyle::Danger)
+                .label("Decline")
+            )
+        )
+    }
+
+    /// Post a request for a new class to the server's staff channel for review.
+    pub(crate) async fn submit(
+        cache_http: impl CacheHttp,
+        server_id: GuildId,
+        requester: UserId,
+        name: &str,
+    ) -> ClassResult<ClassRequest> {
+        let name = name.trim();
+
+        if Class::class_exists(server_id, name).await? {
+            return Err(ClassError::ClassExists);
+        }
+
+        let collection = Self::scoped(server_id).await;
+        if collection
+            .find_one(
+                doc! { "name": name, "status": "pending" },
+                None,
+            )
+            .await?
+            .is_some()
+        {
+            return Err(ClassError::RequestExists);
+        }
+
+        let server = Server::get_or_create(server_id).await?;
+        let channel = server.staff_channel.ok_or(ClassError::NoStaffChannel)?;
+
+        let mut request = Self {
+            server_id,
+            name: name.to_string(),
+            requester,
+            channel,
+            message: MessageId(0),
+            status: RequestStatus::Pending,
+        };
+
+        let message = channel
+            .send_message(cache_http.http(), |m| m
+                .embed(|e| request.render(e))
+                .components(|c| request.components(c))
+            )
+            .await?;
+        request.message = message.id;
+
+        collection.insert_one(&request, None).await?;
+
+        Ok(request)
+    }
+
+    /// Mark a pending request as reviewed, returning it, or `None` if it isn't pending. Only one
+    /// reviewer can claim a request, even if several click at once.
+    async fn claim(message: MessageId, status: RequestStatus) -> ClassResult<Option<ClassRequest>> {
+        Ok(
+            Self::get_collection().await
+                .find_one_and_update(
+                    doc! { "message": message.to_string(), "status": "pending" },
+                    doc! { "$set": { "status": mongodb::bson::to_bson(&status)? } },
+                    FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build(),
+                )
+                .await?
+        )
+    }
+
+    /// Put a claimed request back to pending, so it can be reviewed again.
+    async fn release(&mut self) -> ClassResult<()> {
+        Self::get_collection().await
+            .update_one(
+                doc! { "message": self.message.to_string() },
+                doc! { "$set": { "status": "pending" } },
+                None,
+            )
+            .await?;
+        self.status = RequestStatus::Pending;
+
+        Ok(())
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static REQUESTS: OnceCell<Collection<ClassRequest>> = OnceCell::const_new();
+
+        REQUESTS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("class_requests")
+            })
+            .await
+            .clone()
+    }
+}
+
+pub(crate) struct ClassRequestHandler;
+
+#[async_trait]
+impl EventHandler for ClassRequestHandler {
+    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
+        let component = if let Interaction::MessageComponent(c) = interaction {
+            c
+        } else {
+            return;
+        };
+        if component.data.component_type != ComponentType::Button {
+            return;
+        }
+
+        let approve = match &*component.data.custom_id {
+            "class_request_approve" => true,
+            "class_request_decline" => false,
+            _ => return,
+        };
+
+        if let Err(e) = handle_review(&ctx, &component, approve).await {
+            if let Err(e) = component.create_followup_message(ctx.http(), |f| f
+                .ephemeral(true)
+                .content(e.to_string())
+            ).await {
+                log_error!("Error handling {}: {:?}", component.data.custom_id, e);
+            }
+        }
+    }
+}
+
+async fn handle_review(ctx: &SContext, component: &MessageComponentInteraction, approve: bool) -> ClassResult<()> {
+    let http = ctx.http();
+
+    component.defer(http).await?;
+
+    let member = component.member.as_ref().ok_or(ClassError::NoServer)?;
+    if !member.permissions.map(|p| p.manage_guild()).unwrap_or(false) {
+        return Err(ClassError::MissingPermissions);
+    }
+
+    let status = if approve { RequestStatus::Approved } else { RequestStatus::Declined };
+    let mut request = ClassRequest::claim(component.message.id, status)
+        .await?
+        .ok_or(ClassError::InvalidRequest)?;
+
+    if approve {
+        let created = async {
+            let guild = lookup::guild(ctx, request.server_id).await?;
+            Class::create(ctx, &guild, &request.name, Visibility::default()).await
+        }.await;
+        // Let the request be approved again rather than leaving it approved with no class
+        if let Err(e) = created {
+            request.release().await?;
+            return Err(e);
+        }
+    }
+
+    component.edit_original_interaction_response(http, |r| r
+        .embed(|e| request.render(e).footer(|f| f.text(format!("Reviewed by {}", component.user.tag()))))
+        .components(|c| request.components(c))
+    ).await?;
+
+    // Throwing away the result as the requester may have DMs disabled
+    if let Ok(dm) = request.requester.create_dm_channel(ctx).await {
+        dm.say(http, format!(
+            "Your request for the class \"{}\" was {}.",
+            request.name,
+            if approve { "approved" } else { "declined" },
+        )).await.ok();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serenity::http::{Http, StatusCode};
+use serenity::model::id::{GuildId, RoleId, UserId};
+
+use crate::ClassError;
+
+/// How long to wait before retrying a change Discord rate limited. Doubles with each retry.
+const BACKOFF: Duration = Duration::from_secs(1);
+/// How many times a change is tried before giving up on it.
+const MAX_ATTEMPTS: u32 = 4;
+
+lazy_static! {
+    /// One queue per server, since Discord rate limits role changes per server. Tokio's mutex
+    /// hands out turns in the order they were asked for.
+    static ref QUEUES: Mutex<HashMap<GuildId, Arc<tokio::sync::Mutex<()>>>> = Mutex::new(HashMap::new());
+}
+
+/// The roles that were actually changed, which may be only some of them if a change failed.
+pub(crate) struct Applied {
+    pub(crate) added: Vec<RoleId>,
+    pub(crate) removed: Vec<RoleId>,
+    pub(crate) error: Option<ClassError>,
+}
+
+fn queue(server_id: GuildId) -> Arc<tokio::sync::Mutex<()>> {
+    let mut queues = QUEUES.lock().unwrap();
+    // Drop queues nobody is waiting in
+    queues.retain(|_, q| Arc::strong_count(q) > 1);
+    queues.entry(server_id).or_default().clone()
+}
+
+fn is_rate_limited(error: &serenity::Error) -> bool {
+    matches!(error, serenity::Error::Http(e) if e.status_code() == Some(StatusCode::TOO_MANY_REQUESTS))
+}
+
+/// Make a request, backing off and retrying while Discord answers that it was rate limited.
+/// Serenity waits out the rate limits it's told about, but not 429s without a `retry-after`.
+async fn with_backoff<F, Fut>(mut request: F) -> serenity::Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = serenity::Result<()>>,
+{
+    let mut delay = BACKOFF;
+    for _ in 1..MAX_ATTEMPTS {
+        match request().await {
+            Err(e) if is_rate_limited(&e) => {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    request().await
+}
+
+/// Add and remove roles from a member one at a time, after waiting for the server's earlier
+/// changes to finish. Unlike replacing the member's whole role list, this leaves any roles changed
+/// meanwhile alone.
+pub(crate) async fn apply(
+    http: impl AsRef<Http>,
+    server_id: GuildId,
+    user: UserId,
+    add: &[RoleId],
+    remove: &[RoleId],
+    reason: &str,
+) -> Applied {
+    let mut applied = Applied { added: Vec::new(), removed: Vec::new(), error: None };
+    let queue = queue(server_id);
+    let _turn = queue.lock().await;
+    let http = http.as_ref();
+
+    for role in add {
+        match with_backoff(|| http.add_member_role(server_id.0, user.0, role.0, Some(reason))).await {
+            Ok(()) => applied.added.push(*role),
+            Err(e) => {
+                applied.error = Some(e.into());
+                return applied;
+            }
+        }
+    }
+    for role in remove {
+        match with_backoff(|| http.remove_member_role(server_id.0, user.0, role.0, Some(reason))).await {
+            Ok(()) => applied.removed.push(*role),
+            Err(e) => {
+                applied.error = Some(e.into());
+                return applied;
+            }
+        }
+    }
+
+    applied
+}
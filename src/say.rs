@@ -0,0 +1,165 @@
+use poise::Modal;
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::channel::GuildChannel;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::prelude::Mentionable;
+
+use crate::classes::{Class, Server};
+use crate::events::{self, BotEvent};
+use crate::redact::log_error;
+use crate::{is_class_staff, ClassError, ClassResult, Context, Error};
+
+/// Whether the author can post as the bot in a channel: anyone who can manage the server, or the
+/// staff of the class the channel belongs to.
+async fn can_post(ctx: Context<'_>, channel: ChannelId) -> ClassResult<bool> {
+    let manages_server = ctx.author_member().await
+        .and_then(|m| m.permissions)
+        .map(|p| p.manage_guild())
+        .unwrap_or(false);
+    if manages_server {
+        return Ok(true);
+    }
+    Ok(match Class::find_by_text_channel(channel).await? {
+        Some(class) => is_class_staff(ctx, &class).await,
+        None => false,
+    })
+}
+
+fn posted(ctx: Context<'_>, server_id: GuildId, channel: ChannelId, message: MessageId) {
+    events::publish(BotEvent::MessagePosted {
+        server_id,
+        user_id: ctx.author().id,
+        channel,
+        message,
+        command: ctx.command().name.clone(),
+    });
+}
+
+/// Post a message as the bot.
+#[poise::command(slash_command, ephemeral)]
+pub(crate) async fn say(
+    ctx: Context<'_>,
+    #[channel_types("Text", "News")] channel: GuildChannel,
+    text: String,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    if !can_post(ctx, channel.id).await? {
+        Err(ClassError::MissingPermissions)?;
+    }
+    // Mentions are shown but never ping, so staff can't ping @everyone or get around the class ping cap
+    let message = channel.send_message(ctx.discord(), |m| m
+        .content(&text)
+        .allowed_mentions(|a| a.empty_parse())
+    ).await?;
+    posted(ctx, channel.guild_id, channel.id, message.id);
+
+    ctx.say(format!("Posted in {}: {}", channel.mention(), message.link())).await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Default, Modal)]
+#[name = "Post an embed"]
+struct EmbedModal {
+    #[name = "Title"]
+    #[max_length = 256]
+    title: Option<String>,
+    #[name = "Text"]
+    #[paragraph]
+    #[max_length = 4000]
+    description: String,
+    #[name = "Colour"]
+    #[placeholder = "#2f7de1"]
+    #[max_length = 7]
+    colour: Option<String>,
+    #[name = "Image URL"]
+    image: Option<String>,
+    #[name = "Footer"]
+    #[max_length = 2048]
+    footer: Option<String>,
+}
+
+/// Post an embed as the bot, filled in with a form.
+#[poise::command(slash_command, ephemeral)]
+pub(crate) async fn embed(
+    ctx: Context<'_>,
+    #[channel_types("Text", "News")] channel: GuildChannel,
+) -> Result<(), Error> {
+    let actx = match ctx {
+        poise::Context::Application(actx) => actx,
+        poise::Context::Prefix(_) => return Ok(()),
+    };
+
+    // The form has to be the first response, so permissions are checked before showing it without
+    // deferring
+    if !can_post(ctx, channel.id).await? {
+        Err(ClassError::MissingPermissions)?;
+    }
+    let form = EmbedModal::execute(actx).await?;
+    let colour = match form.colour.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+        Some(c) => Some(u32::from_str_radix(c.trim_start_matches('#'), 16).map_err(|_| ClassError::InvalidColour)?),
+        None => None,
+    };
+
+    let message = channel.send_message(ctx.discord(), |m| m.embed(|e| {
+        e.description(&form.description);
+        if let Some(title) = &form.title {
+            e.title(title);
+        }
+        if let Some(colour) = colour {
+            e.colour(colour);
+        }
+        if let Some(image) = &form.image {
+            e.image(image.trim());
+        }
+        if let Some(footer) = &form.footer {
+            e.footer(|f| f.text(footer));
+        }
+        e
+    })).await?;
+    posted(ctx, channel.guild_id, channel.id, message.id);
+
+    ctx.say(format!("Posted in {}: {}", channel.mention(), message.link())).await?;
+
+    Ok(())
+}
+
+async fn post_to_log(
+    cache_http: impl CacheHttp,
+    server_id: GuildId,
+    user: UserId,
+    channel: ChannelId,
+    message: MessageId,
+    command: &str,
+) -> ClassResult<()> {
+    let log_channel = match Server::get_or_create(server_id).await?.log_channel {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+
+    log_channel
+        .send_message(cache_http.http(), |m| m
+            .content(format!(
+                "📝 {} posted in {} with /{}: {}",
+                user.mention(),
+                channel.mention(),
+                command,
+                message.link(channel, Some(server_id)),
+            ))
+            .allowed_mentions(|a| a.empty_parse())
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Event bus subscriber noting messages posted through the bot in the server's log channel.
+pub(crate) async fn log(ctx: SContext, event: BotEvent) {
+    if let BotEvent::MessagePosted { server_id, user_id, channel, message, command } = event {
+        if let Err(e) = post_to_log(&ctx, server_id, user_id, channel, message, &command).await {
+            log_error!("Error posting to log channel: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serenity::client::Context as SContext;
+
+use crate::{announcements, archive, assignments, countdowns, digest, email, escalation, icebreakers, jobs, orphans, sessions, tempgrants, terms, trash};
+use crate::errors::{self, ErrorContext};
+use crate::ClassResult;
+
+const TICK: Duration = Duration::from_secs(60);
+
+static LAST_TICK_AT: AtomicI64 = AtomicI64::new(0);
+static LAST_TICK_MILLIS: AtomicU64 = AtomicU64::new(0);
+
+/// When the last tick finished, as a unix timestamp, and how long it took. `None` before the
+/// first tick finishes.
+pub(crate) fn last_tick() -> Option<(i64, Duration)> {
+    let at = LAST_TICK_AT.load(Ordering::Relaxed);
+    (at != 0).then(|| (at, Duration::from_millis(LAST_TICK_MILLIS.load(Ordering::Relaxed))))
+}
+
+/// Run every scheduled job once per tick. Jobs run one after another, so a slow job delays the
+/// next tick instead of piling up.
+pub(crate) fn start(ctx: SContext) {
+    errors::spawn_reported("scheduler", ErrorContext::default(), async move {
+        let mut interval = tokio::time::interval(TICK);
+        loop {
+            interval.tick().await;
+            let started = Instant::now();
+
+            report("study sessions", sessions::tick(&ctx).await).await;
+            report("assignments", assignments::tick(&ctx).await).await;
+            report("exam countdowns", countdowns::tick(&ctx).await).await;
+            report("terms", terms::tick(&ctx).await).await;
+            report("background jobs", jobs::tick(&ctx).await).await;
+            report("staff digest", digest::tick(&ctx).await).await;
+            report("email digests", email::tick(&ctx).await).await;
+            report("announcement digests", announcements::tick(&ctx).await).await;
+            report("message archive retention", archive::tick(&ctx).await).await;
+            report("class trash", trash::tick().await).await;
+            report("temporary access grants", tempgrants::tick().await).await;
+            report("orphan cleanup", orphans::tick(&ctx).await).await;
+            report("icebreakers", icebreakers::tick(&ctx).await).await;
+            report("unanswered question escalation", escalation::tick(&ctx).await).await;
+
+            LAST_TICK_MILLIS.store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+            LAST_TICK_AT.store(Utc::now().timestamp(), Ordering::Relaxed);
+        }
+    });
+}
+
+async fn report(job: &str, result: ClassResult<()>) {
+    if let Err(e) = result {
+        errors::report(&format!("scheduled job {}", job), ErrorContext::default(), &format!("{:?}", e)).await;
+    }
+}
+
+/// Parse a user-supplied time. Accepts RFC 3339, `YYYY-MM-DD HH:MM` with an optional UTC offset
+/// (UTC if omitted), or a unix timestamp.
+pub(crate) fn parse_time(time: &str) -> Option<DateTime<Utc>> {
+    let time = time.trim();
+
+    DateTime::parse_from_rfc3339(time)
+        .or_else(|_| DateTime::parse_from_str(time, "%Y-%m-%d %H:%M %z"))
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M")
+                .ok()
+                .map(|t| Utc.from_utc_datetime(&t))
+        })
+        .or_else(|| time.parse::<i64>().ok().and_then(|t| Utc.timestamp_opt(t, 0).single()))
+}
+
+/// Parse a user-supplied duration made of weeks, days, hours and minutes, like `3d`, `12h` or
+/// `1w 2d`.
+pub(crate) fn parse_duration(duration: &str) -> Option<Duration> {
+    let mut total = Duration::ZERO;
+    let mut number = String::new();
+    for c in duration.chars().filter(|c| !c.is_whitespace()) {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            'w' => 7 * 24 * 60 * 60,
+            'd' => 24 * 60 * 60,
+            'h' => 60 * 60,
+            'm' => 60,
+            _ => return None,
+        };
+        total = total.checked_add(Duration::from_secs(number.parse::<u64>().ok()?.checked_mul(unit)?))?;
+        number.clear();
+    }
+
+    (number.is_empty() && !total.is_zero()).then_some(total)
+}
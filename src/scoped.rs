@@ -0,0 +1,148 @@
+//! Queries limited to one server's documents.
+//!
+//! Anything stored per server is queried through `GuildScoped`, which adds the server's ID to
+//! every filter, so a query can't read or change another server's data by leaving it out. Lookups
+//! by a role, channel or message ID don't need it, as those IDs already belong to a single server.
+//! The `scoping` tests fail if a module filters by `server_id` itself instead.
+
+use std::borrow::Borrow;
+
+use mongodb::bson::{self, Document};
+use mongodb::options::{
+    CountOptions, DeleteOptions, FindOneAndUpdateOptions, FindOneOptions, FindOptions, InsertManyOptions,
+    InsertOneOptions, UpdateModifications, UpdateOptions,
+};
+use mongodb::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+use mongodb::{Collection, Cursor};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serenity::model::id::GuildId;
+
+use crate::{ClassError, ClassResult};
+
+/// The field every per-server document stores its server's ID in.
+const SERVER_ID: &str = "server_id";
+
+pub(crate) struct GuildScoped<T> {
+    server_id: GuildId,
+    collection: Collection<T>,
+}
+
+impl<T> GuildScoped<T> {
+    pub(crate) fn new(server_id: GuildId, collection: Collection<T>) -> Self {
+        Self { server_id, collection }
+    }
+
+    /// The filter limited to the server. A `server_id` already in it is replaced rather than
+    /// combined, so it can't widen the query.
+    pub(crate) fn filter(&self, filter: impl Into<Option<Document>>) -> Document {
+        let mut filter = filter.into().unwrap_or_default();
+        filter.insert(SERVER_ID, self.server_id.to_string());
+        filter
+    }
+
+    /// Make sure a document being written belongs to the server.
+    fn check(&self, document: &Document) -> ClassResult<()> {
+        if document.get_str(SERVER_ID).ok() == Some(self.server_id.to_string().as_str()) {
+            Ok(())
+        } else {
+            Err(ClassError::CrossGuildWrite)
+        }
+    }
+
+    /// Make sure an update doesn't move a document to another server.
+    fn check_update(&self, update: impl Into<UpdateModifications>) -> ClassResult<UpdateModifications> {
+        let update = update.into();
+        if let UpdateModifications::Document(update) = &update {
+            match update.get_document("$set") {
+                Ok(set) if set.contains_key(SERVER_ID) => self.check(set)?,
+                _ => {}
+            }
+        }
+        Ok(update)
+    }
+
+    pub(crate) async fn count_documents(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<CountOptions>>,
+    ) -> ClassResult<u64> {
+        Ok(self.collection.count_documents(self.filter(filter), options).await?)
+    }
+
+    pub(crate) async fn update_one(
+        &self,
+        filter: Document,
+        update: impl Into<UpdateModifications>,
+        options: impl Into<Option<UpdateOptions>>,
+    ) -> ClassResult<UpdateResult> {
+        let update = self.check_update(update)?;
+        Ok(self.collection.update_one(self.filter(filter), update, options).await?)
+    }
+
+    pub(crate) async fn delete_one(
+        &self,
+        filter: Document,
+        options: impl Into<Option<DeleteOptions>>,
+    ) -> ClassResult<DeleteResult> {
+        Ok(self.collection.delete_one(self.filter(filter), options).await?)
+    }
+
+    pub(crate) async fn delete_many(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<DeleteOptions>>,
+    ) -> ClassResult<DeleteResult> {
+        Ok(self.collection.delete_many(self.filter(filter), options).await?)
+    }
+}
+
+impl<T: DeserializeOwned + Unpin + Send + Sync> GuildScoped<T> {
+    pub(crate) async fn find(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<FindOptions>>,
+    ) -> ClassResult<Cursor<T>> {
+        Ok(self.collection.find(self.filter(filter), options).await?)
+    }
+
+    pub(crate) async fn find_one(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<FindOneOptions>>,
+    ) -> ClassResult<Option<T>> {
+        Ok(self.collection.find_one(self.filter(filter), options).await?)
+    }
+
+    pub(crate) async fn find_one_and_update(
+        &self,
+        filter: Document,
+        update: impl Into<UpdateModifications>,
+        options: impl Into<Option<FindOneAndUpdateOptions>>,
+    ) -> ClassResult<Option<T>> {
+        let update = self.check_update(update)?;
+        Ok(self.collection.find_one_and_update(self.filter(filter), update, options).await?)
+    }
+}
+
+impl<T: Serialize> GuildScoped<T> {
+    pub(crate) async fn insert_one(
+        &self,
+        document: impl Borrow<T>,
+        options: impl Into<Option<InsertOneOptions>>,
+    ) -> ClassResult<InsertOneResult> {
+        self.check(&bson::to_document(document.borrow())?)?;
+        Ok(self.collection.insert_one(document, options).await?)
+    }
+
+    pub(crate) async fn insert_many(
+        &self,
+        documents: &[T],
+        options: impl Into<Option<InsertManyOptions>>,
+    ) -> ClassResult<InsertManyResult> {
+        for document in documents {
+            self.check(&bson::to_document(document)?)?;
+        }
+        Ok(self.collection.insert_many(documents, options).await?)
+    }
+}
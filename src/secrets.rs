@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use mongodb::bson::{doc, Bson, Document};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+
+use crate::redact::{self, log_error};
+use crate::{get_conn, ClassError, ClassResult, ENV};
+
+/// Marks secrets sealed with a per-secret data key, as `v2:<key id>:<wrapped data key>:<data>`.
+/// Secrets without it were sealed directly with a key, before data keys were used.
+const ENVELOPE_PREFIX: &str = "v2";
+
+/// Every stored field holding sealed secrets, either a string or an array of strings. SMTP
+/// credentials aren't stored, as they only come from the environment.
+const SEALED_FIELDS: [(&str, &str); 3] = [
+    ("classes", "webhook"),
+    ("classes", "canvas.token"),
+    ("servers", "webhooks"),
+];
+
+lazy_static! {
+    static ref KEYRING: Option<Keyring> = match Keyring::from_env() {
+        Ok(keyring) => Some(keyring),
+        Err(e) => {
+            log_error!("Secrets can't be stored or read: {}", e);
+            None
+        }
+    };
+}
+
+fn aead_key(key: &[u8]) -> ClassResult<LessSafeKey> {
+    UnboundKey::new(&AES_256_GCM, key)
+        .map(LessSafeKey::new)
+        .map_err(|_| ClassError::NoSecretKey)
+}
+
+/// Encrypt with a fresh nonce, returning the nonce followed by the ciphertext.
+fn encrypt(key: &LessSafeKey, plaintext: &[u8]) -> ClassResult<Vec<u8>> {
+    let nonce = rand::random::<[u8; NONCE_LEN]>();
+
+    let mut sealed = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut sealed)
+        .map_err(|_| ClassError::InvalidSecret)?;
+
+    Ok([nonce.as_slice(), &sealed].concat())
+}
+
+fn decrypt(key: &LessSafeKey, sealed: &[u8]) -> ClassResult<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return Err(ClassError::InvalidSecret);
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    // Unwrapping because the nonce was just split at the right length
+    let nonce = Nonce::try_assume_unique_for_key(nonce).unwrap();
+    let mut ciphertext = ciphertext.to_vec();
+    let plaintext = key.open_in_place(nonce, Aad::empty(), &mut ciphertext)
+        .map_err(|_| ClassError::InvalidSecret)?;
+
+    Ok(plaintext.to_vec())
+}
+
+fn decode(data: &str) -> ClassResult<Vec<u8>> {
+    BASE64.decode(data).map_err(|_| ClassError::InvalidSecret)
+}
+
+/// The keys that wrap each secret's data key. New secrets use the current key, and older keys are
+/// kept only to open secrets sealed before the last rotation.
+pub(crate) struct Keyring {
+    current: String,
+    keys: HashMap<String, LessSafeKey>,
+}
+
+impl Keyring {
+    /// Read the current key from `SECRET_KEY` and `SECRET_KEY_ID`, and older keys from
+    /// `OLD_SECRET_KEYS` as `id:key,id:key`. Keys are 32 bytes in base64.
+    fn from_env() -> ClassResult<Self> {
+        let key = ENV.secret_key.as_deref().ok_or(ClassError::NoSecretKey)?;
+        Self::new(&ENV.secret_key_id, key, ENV.old_secret_keys.as_deref().unwrap_or(""))
+    }
+
+    pub(crate) fn new(current_id: &str, current: &str, old: &str) -> ClassResult<Self> {
+        let mut keys = HashMap::new();
+        for entry in old.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (id, key) = entry.split_once(':').ok_or(ClassError::NoSecretKey)?;
+            keys.insert(id.to_string(), aead_key(&BASE64.decode(key).map_err(|_| ClassError::NoSecretKey)?)?);
+        }
+        keys.insert(
+            current_id.to_string(),
+            aead_key(&BASE64.decode(current).map_err(|_| ClassError::NoSecretKey)?)?,
+        );
+
+        Ok(Self { current: current_id.to_string(), keys })
+    }
+
+    fn wrap(&self, data_key: &[u8]) -> ClassResult<String> {
+        Ok(format!("{}:{}", self.current, BASE64.encode(encrypt(&self.keys[&self.current], data_key)?)))
+    }
+
+    fn unwrap(&self, id: &str, wrapped: &str) -> ClassResult<Vec<u8>> {
+        let key = self.keys.get(id).ok_or(ClassError::InvalidSecret)?;
+        decrypt(key, &decode(wrapped)?)
+    }
+
+    /// Encrypt a secret with a new data key, wrapped with the current key.
+    pub(crate) fn seal(&self, secret: &str) -> ClassResult<String> {
+        let data_key = rand::random::<[u8; 32]>();
+        let data = encrypt(&aead_key(&data_key)?, secret.as_bytes())?;
+
+        Ok(format!("{}:{}:{}", ENVELOPE_PREFIX, self.wrap(&data_key)?, BASE64.encode(data)))
+    }
+
+    pub(crate) fn open(&self, sealed: &str) -> ClassResult<String> {
+        let secret = match sealed.split(':').collect::<Vec<_>>()[..] {
+            [ENVELOPE_PREFIX, id, wrapped, data] => {
+                let data_key = aead_key(&self.unwrap(id, wrapped)?)?;
+                decrypt(&data_key, &decode(data)?)?
+            }
+            // Sealed directly with one of the keys
+            [data] => {
+                let data = decode(data)?;
+                self.keys.values()
+                    .find_map(|key| decrypt(key, &data).ok())
+                    .ok_or(ClassError::InvalidSecret)?
+            }
+            _ => return Err(ClassError::InvalidSecret),
+        };
+
+        String::from_utf8(secret).map_err(|_| ClassError::InvalidSecret)
+    }
+
+    /// Re-wrap a secret's data key with the current key, leaving the secret itself as it is.
+    /// Returns `None` if it already uses the current key.
+    pub(crate) fn rewrap(&self, sealed: &str) -> ClassResult<Option<String>> {
+        match sealed.split(':').collect::<Vec<_>>()[..] {
+            [ENVELOPE_PREFIX, id, _, _] if id == self.current => Ok(None),
+            [ENVELOPE_PREFIX, id, wrapped, data] => Ok(Some(format!(
+                "{}:{}:{}",
+                ENVELOPE_PREFIX,
+                self.wrap(&self.unwrap(id, wrapped)?)?,
+                data,
+            ))),
+            _ => self.seal(&self.open(sealed)?).map(Some),
+        }
+    }
+}
+
+fn keyring() -> ClassResult<&'static Keyring> {
+    KEYRING.as_ref().ok_or(ClassError::NoSecretKey)
+}
+
+/// Encrypt a secret, such as a webhook URL or access token, so it can be stored.
+pub(crate) fn seal(secret: &str) -> ClassResult<String> {
+    keyring()?.seal(secret)
+}
+
+/// Decrypt a secret stored by `seal`.
+pub(crate) fn open(sealed: &str) -> ClassResult<String> {
+    let secret = keyring()?.open(sealed)?;
+    redact::remember(&secret);
+    Ok(secret)
+}
+
+/// Re-wrap every stored secret with the current key, so older keys can be dropped from
+/// `OLD_SECRET_KEYS`. Run with `cs_discord_rs rotate-secrets` after changing `SECRET_KEY`.
+/// Returns how many secrets were updated.
+pub(crate) async fn rotate() -> ClassResult<usize> {
+    let keyring = keyring()?;
+    let database = get_conn().await.database(&ENV.mongodb_name);
+    let mut rotated = 0;
+
+    for (collection, field) in SEALED_FIELDS {
+        let collection = database.collection::<Document>(collection);
+        let mut documents = collection.find(doc! { field: { "$exists": true, "$ne": null } }, None).await?;
+
+        while let Some(document) = documents.try_next().await? {
+            let value = match field.split('.').try_fold(Bson::Document(document.clone()), |value, part| match value {
+                Bson::Document(d) => d.get(part).cloned(),
+                _ => None,
+            }) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let (new, changed) = match value {
+                Bson::String(sealed) => match keyring.rewrap(&sealed)? {
+                    Some(new) => (Bson::String(new), 1),
+                    None => continue,
+                },
+                Bson::Array(sealed) => {
+                    let mut changed = 0;
+                    let new = sealed.into_iter()
+                        .map(|s| match s {
+                            Bson::String(s) => Ok(match keyring.rewrap(&s)? {
+                                Some(new) => {
+                                    changed += 1;
+                                    Bson::String(new)
+                                }
+                                None => Bson::String(s),
+                            }),
+                            other => Ok(other),
+                        })
+                        .collect::<ClassResult<Vec<_>>>()?;
+                    if changed == 0 {
+                        continue;
+                    }
+                    (Bson::Array(new), changed)
+                }
+                _ => continue,
+            };
+
+            collection
+                // Servers and classes are versioned, so a write from a copy read before the
+                // rotation can't put back a value sealed with the old key
+                .update_one(
+                    doc! { "_id": document.get("_id") },
+                    doc! { "$set": { field: new }, "$inc": { "version": 1 } },
+                    None,
+                )
+                .await?;
+            rotated += changed;
+        }
+    }
+
+    Ok(rotated)
+}
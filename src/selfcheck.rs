@@ -0,0 +1,92 @@
+use serenity::client::Context as SContext;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::model::Permissions;
+use serenity::prelude::Mentionable;
+
+use crate::classes::{Class, Server};
+use crate::redact::log_error;
+use crate::{lookup, ClassResult};
+
+/// The permissions the bot needs, and what stops working without each of them.
+const REQUIRED_PERMISSIONS: [(Permissions, &str, &str); 5] = [
+    (Permissions::MANAGE_ROLES, "Manage Roles", "class enrollment"),
+    (Permissions::MANAGE_CHANNELS, "Manage Channels", "creating classes, teams and study session voice channels"),
+    (Permissions::MANAGE_GUILD, "Manage Server", "tracking class invites"),
+    (Permissions::CREATE_PRIVATE_THREADS, "Create Private Threads", "modmail and tutoring"),
+    (Permissions::SEND_MESSAGES, "Send Messages", "posting suggestions, reminders and menus"),
+];
+
+/// Check the bot can do everything it is configured to do in a server, returning a description of
+/// every problem found.
+pub(crate) async fn check(ctx: &SContext, server_id: GuildId) -> ClassResult<Vec<String>> {
+    let guild = lookup::guild(ctx, server_id).await?;
+    let server = Server::get_or_create(server_id).await?;
+    let classes = Class::list(server_id).await?;
+    let mut problems = Vec::new();
+
+    let bot = guild.member(ctx, ctx.cache.current_user_id()).await?;
+    let permissions = guild.member_permissions(ctx, bot.user.id).await?;
+    for (permission, name, feature) in REQUIRED_PERMISSIONS {
+        if !permissions.contains(permission) {
+            problems.push(format!("The bot is missing the {} permission, needed for {}.", name, feature));
+        }
+    }
+
+    // The bot can only manage roles below its own highest role
+    let top_position = bot.roles.iter()
+        .filter_map(|r| guild.roles.get(r))
+        .map(|r| r.position)
+        .max()
+        .unwrap_or(0);
+    let check_role = |role: RoleId, what: &str, problems: &mut Vec<String>| {
+        match guild.roles.get(&role) {
+            None => problems.push(format!("The {} no longer exists.", what)),
+            Some(r) if r.position >= top_position => problems.push(format!(
+                "The {} {} is not below the bot's highest role, so the bot cannot manage it.",
+                what,
+                r.mention(),
+            )),
+            _ => {}
+        }
+    };
+    match server.refrole {
+        Some(refrole) => check_role(refrole, "refrole", &mut problems),
+        None => problems.push("There is no refrole set, so classes cannot be created. Use `/config refrole set`.".to_string()),
+    }
+    for class in &classes {
+        check_role(class.role, &format!("role for class \"{}\"", class.name), &mut problems);
+
+        if class.categories.iter().any(|c| !guild.channels.contains_key(c)) {
+            problems.push(format!("A category for class \"{}\" no longer exists.", class.name));
+        }
+    }
+
+    let channels: [(Option<ChannelId>, &str); 3] = [
+        (server.staff_channel, "staff channel"),
+        (server.suggestions_channel, "suggestions channel"),
+        (server.tutoring_channel, "tutoring channel"),
+    ];
+    for (channel, name) in channels {
+        if let Some(channel) = channel {
+            if !guild.channels.contains_key(&channel) {
+                problems.push(format!("The configured {} no longer exists.", name));
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Run the self-check on every server once the cache is ready, logging any problems found.
+pub(crate) async fn check_all(ctx: &SContext, servers: &[GuildId]) {
+    for server_id in servers {
+        match check(ctx, *server_id).await {
+            Ok(problems) => {
+                for problem in problems {
+                    log_error!("[{}] Self-check: {}", server_id, problem);
+                }
+            }
+            Err(e) => log_error!("[{}] Error running self-check: {:?}", server_id, e),
+        }
+    }
+}
@@ -0,0 +1,617 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::application::interaction::{Interaction, InteractionResponseType};
+use serenity::model::channel::ChannelType;
+use serenity::model::guild::Role;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+use serenity::model::prelude::component::{ButtonStyle, ComponentType};
+use serenity::prelude::{EventHandler, Mentionable};
+use tokio::sync::OnceCell;
+
+use crate::autotrack;
+use crate::calendar;
+use crate::classes::Class;
+use crate::mentions::{self, Pings};
+use crate::recordings::{self, RecordingConsent};
+use crate::redact::log_error;
+use crate::scheduler::parse_time;
+use crate::scoped::GuildScoped;
+use crate::{discord_name, get_conn, is_class_staff, ClassError, ClassResult, Context, Error, ENV};
+
+/// How long before a session starts attendees are reminded.
+const REMINDER_LEAD: i64 = 15;
+/// How long after a session starts its temporary voice channel is removed.
+const SESSION_LENGTH: i64 = 3 * 60;
+/// How many links a session can have.
+const LINK_LIMIT: usize = 5;
+
+/// A shared document or whiteboard for a session, shown on its message and in its reminder.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SessionLink {
+    label: String,
+    url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct StudySession {
+    server_id: GuildId,
+    role: RoleId,
+    channel: ChannelId,
+    message: MessageId,
+    host: UserId,
+    topic: String,
+    starts_at: DateTime,
+    rsvps: Vec<UserId>,
+    temp_voice: bool,
+    voice_channel: Option<ChannelId>,
+    reminded: bool,
+    closed: bool,
+    /// Whether attendees are told the session is recorded and asked to acknowledge it.
+    #[serde(default)]
+    recorded: bool,
+    #[serde(default)]
+    recording_notice: Option<MessageId>,
+    #[serde(default)]
+    links: Vec<SessionLink>,
+}
+
+impl StudySession {
+    fn timestamp(&self) -> i64 {
+        self.starts_at.timestamp_millis() / 1000
+    }
+
+    fn render<'a>(&self, e: &'a mut CreateEmbed, class_name: &str) -> &'a mut CreateEmbed {
+        e.title(format!("Study session: {}", self.topic))
+            .description(class_name)
+            .field("Starts", format!("<t:{0}:F> (<t:{0}:R>)", self.timestamp()), false)
+            .field("Host", self.host.mention(), true)
+            .field(
+                format!("Going ({})", self.rsvps.len()),
+                if self.rsvps.is_empty() {
+                    "Nobody yet".to_string()
+                } else {
+                    self.rsvps.iter().map(|u| u.mention().to_string()).collect::<Vec<_>>().join(" ")
+                },
+                true,
+            );
+        if let Some(channel) = self.voice_channel {
+            e.field("Voice channel", channel.mention(), false);
+        }
+        if self.recorded {
+            e.field("Recorded", "This session will be recorded.", false);
+        }
+        if !self.links.is_empty() {
+            e.field(
+                "Links",
+                self.links.iter().map(|l| format!("[{}]({})", l.label, l.url)).collect::<Vec<_>>().join("\n"),
+                false,
+            );
+        }
+        e
+    }
+
+    fn components<'a>(&self, c: &'a mut CreateComponents) -> &'a mut CreateComponents {
+        if self.closed {
+            return c;
+        }
+
+        c.create_action_row(|r| r
+            .create_button(|b| b
+                .custom_id("study_session_rsvp")
+                .style(ButtonStyle::Primary)
+                .label("Going / Not going")
+            )
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn create(
+        cache_http: impl CacheHttp,
+        class: &Class,
+        channel: ChannelId,
+        host: UserId,
+        topic: String,
+        starts_at: chrono::DateTime<Utc>,
+        temp_voice: bool,
+        recorded: bool,
+    ) -> ClassResult<StudySession> {
+        let mut session = Self {
+            server_id: class.server_id,
+            role: class.role,
+            channel,
+            message: MessageId(0),
+            host,
+            topic,
+            starts_at: DateTime::from_millis(starts_at.timestamp_millis()),
+            rsvps: vec![host],
+            temp_voice,
+            voice_channel: None,
+            reminded: false,
+            closed: false,
+            recorded,
+            recording_notice: None,
+            links: Vec::new(),
+        };
+
+        let message = channel
+            .send_message(cache_http.http(), |m| m
+                .embed(|e| session.render(e, &class.name))
+                .components(|c| session.components(c))
+            )
+            .await?;
+        session.message = message.id;
+
+        Self::get_collection().await.insert_one(&session, None).await?;
+
+        Ok(session)
+    }
+
+    async fn toggle_rsvp(message: MessageId, user: UserId) -> ClassResult<StudySession> {
+        let collection = Self::get_collection().await;
+        let filter = doc! { "message": message.to_string(), "closed": false };
+
+        let session = collection.find_one(filter.clone(), None)
+            .await?
+            .ok_or(ClassError::InvalidSession)?;
+        let update = if session.rsvps.contains(&user) {
+            doc! { "$pull": { "rsvps": user.to_string() } }
+        } else {
+            doc! { "$addToSet": { "rsvps": user.to_string() } }
+        };
+
+        collection
+            .find_one_and_update(
+                filter,
+                update,
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?
+            .ok_or(ClassError::InvalidSession)
+    }
+
+    /// Ping everyone who RSVP'd and open the temporary voice channel if one was requested.
+    async fn remind(&mut self, ctx: &SContext) -> ClassResult<()> {
+        let mut class = Class::find_by_role(self.role).await?.ok_or(ClassError::InvalidClass)?;
+
+        if self.temp_voice && !self.rsvps.is_empty() {
+            let category = class.category_with_room(ctx, 1).await?;
+            let voice = self.server_id
+                .create_channel(ctx.http(), |c| c
+                    .name(discord_name(&format!("Study session ({})", class.short_name)))
+                    .kind(ChannelType::Voice)
+                    .category(category)
+                    .user_limit(self.rsvps.len().clamp(2, 99) as u32)
+                )
+                .await?;
+            autotrack::exclude(voice.id);
+            self.voice_channel = Some(voice.id);
+        }
+
+        if !self.rsvps.is_empty() {
+            let content = format!(
+                "{} The study session \"{}\" starts <t:{}:R>!{}{}",
+                self.rsvps.iter().map(|u| u.mention().to_string()).collect::<Vec<_>>().join(" "),
+                self.topic,
+                self.timestamp(),
+                self.voice_channel.map(|c| format!(" Join {}.", c.mention())).unwrap_or_default(),
+                self.links.iter().map(|l| format!("\n{}: <{}>", l.label, l.url)).collect::<String>(),
+            );
+            mentions::send(ctx, self.channel, self.role, content, Pings::users(self.rsvps.iter().copied())).await?;
+        }
+
+        if self.recorded {
+            // In the voice channel's text chat, where attendees will see it
+            let channel = self.voice_channel.unwrap_or(self.channel);
+            self.recording_notice = Some(recordings::post_notice(ctx, channel, &self.topic).await?.id);
+        }
+
+        self.reminded = true;
+        Self::get_collection().await
+            .update_one(
+                doc! { "message": self.message.to_string() },
+                doc! { "$set": {
+                    "reminded": true,
+                    "voice_channel": self.voice_channel.map(|c| c.to_string()),
+                    "recording_notice": self.recording_notice.map(|m| m.to_string()),
+                } },
+                None,
+            )
+            .await?;
+
+        self.refresh(ctx, &class.name).await
+    }
+
+    /// The class and host of the open session using a temporary voice channel.
+    pub(crate) async fn in_voice_channel(channel: ChannelId) -> ClassResult<Option<(RoleId, UserId)>> {
+        Ok(
+            Self::get_collection().await
+                .find_one(doc! { "voice_channel": channel.to_string(), "closed": false }, None)
+                .await?
+                .map(|s| (s.role, s.host))
+        )
+    }
+
+    /// Find the session posted in a message, given a link to the message or its ID, checking the
+    /// author of the command hosts it or is staff for its class.
+    async fn find_managed(ctx: Context<'_>, message: &str) -> ClassResult<(StudySession, Class)> {
+        let message = message.trim().rsplit('/').next()
+            .and_then(|id| id.parse::<u64>().ok())
+            .ok_or(ClassError::InvalidSession)?;
+        let session = Self::find(doc! { "message": message.to_string() }).await?
+            .pop()
+            .ok_or(ClassError::InvalidSession)?;
+        let class = Class::find_by_role(session.role).await?.ok_or(ClassError::InvalidClass)?;
+        if session.host != ctx.author().id && !is_class_staff(ctx, &class).await {
+            return Err(ClassError::MissingPermissions);
+        }
+
+        Ok((session, class))
+    }
+
+    async fn add_link(&mut self, label: &str, url: &str) -> ClassResult<()> {
+        match reqwest::Url::parse(url) {
+            Ok(u) if u.scheme() == "https" || u.scheme() == "http" => {}
+            _ => return Err(ClassError::InvalidUrl),
+        }
+        if self.links.iter().any(|l| l.label.eq_ignore_ascii_case(label)) {
+            return Err(ClassError::SessionLinkExists);
+        }
+        if self.links.len() >= LINK_LIMIT {
+            return Err(ClassError::TooManySessionLinks(LINK_LIMIT));
+        }
+
+        self.links.push(SessionLink { label: label.to_string(), url: url.to_string() });
+        self.save_links().await
+    }
+
+    async fn remove_link(&mut self, label: &str) -> ClassResult<()> {
+        let index = self.links.iter()
+            .position(|l| l.label.eq_ignore_ascii_case(label))
+            .ok_or(ClassError::InvalidSessionLink)?;
+
+        self.links.remove(index);
+        self.save_links().await
+    }
+
+    async fn save_links(&self) -> ClassResult<()> {
+        Self::get_collection().await
+            .update_one(
+                doc! { "message": self.message.to_string() },
+                doc! { "$set": { "links": mongodb::bson::to_bson(&self.links)? } },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// When each class's next open session starts, as a unix timestamp.
+    pub(crate) async fn next_by_class(server_id: GuildId) -> ClassResult<HashMap<RoleId, i64>> {
+        let mut next = HashMap::new();
+        let open = Self::scoped(server_id).await
+            .find(doc! { "closed": false, "reminded": false }, None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        for session in open {
+            let starts = next.entry(session.role).or_insert(session.timestamp());
+            *starts = (*starts).min(session.timestamp());
+        }
+        Ok(next)
+    }
+
+    /// The server and message of the session a recording notice was posted for.
+    pub(crate) async fn find_by_recording_notice(notice: MessageId) -> ClassResult<Option<(GuildId, MessageId)>> {
+        Ok(
+            Self::get_collection().await
+                .find_one(doc! { "recording_notice": notice.to_string() }, None)
+                .await?
+                .map(|s| (s.server_id, s.message))
+        )
+    }
+
+    /// Mark the session as reminded without pinging anyone or opening a voice channel, for
+    /// sessions that fall during a break.
+    async fn skip_reminder(&mut self) -> ClassResult<()> {
+        self.reminded = true;
+        Self::get_collection().await
+            .update_one(
+                doc! { "message": self.message.to_string() },
+                doc! { "$set": { "reminded": true } },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Close RSVPs and clean up the temporary voice channel once the session is over.
+    async fn close(&mut self, ctx: &SContext) -> ClassResult<()> {
+        if let Some(channel) = self.voice_channel {
+            // It may already have been deleted by hand, which shouldn't keep the session open
+            if let Err(e) = channel.delete(ctx.http()).await {
+                log_error!("[{}] Error deleting study session channel {}: {:?}", self.server_id, channel, e);
+            }
+        }
+
+        self.closed = true;
+        Self::get_collection().await
+            .update_one(
+                doc! { "message": self.message.to_string() },
+                doc! { "$set": { "closed": true } },
+                None,
+            )
+            .await?;
+
+        let class_name = Class::find_by_role(self.role).await?
+            .map(|c| c.name)
+            .unwrap_or_default();
+        self.refresh(ctx, &class_name).await
+    }
+
+    async fn refresh(&self, cache_http: impl CacheHttp, class_name: &str) -> ClassResult<()> {
+        self.channel
+            .edit_message(cache_http.http(), self.message, |m| m
+                .embed(|e| self.render(e, class_name))
+                .components(|c| self.components(c))
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn find(filter: mongodb::bson::Document) -> ClassResult<Vec<StudySession>> {
+        Ok(
+            Self::get_collection().await
+                .find(filter, None)
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static SESSIONS: OnceCell<Collection<StudySession>> = OnceCell::const_new();
+
+        SESSIONS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("study_sessions")
+            })
+            .await
+            .clone()
+    }
+}
+
+pub(crate) async fn tick(ctx: &SContext) -> ClassResult<()> {
+    let now = Utc::now();
+
+    let due = StudySession::find(doc! {
+        "reminded": false,
+        "starts_at": { "$lte": DateTime::from_millis((now + Duration::minutes(REMINDER_LEAD)).timestamp_millis()) },
+    }).await?;
+    for mut session in due {
+        // One broken session, like one whose class was deleted, shouldn't hold up the rest
+        let reminded = match calendar::on_break(session.server_id).await {
+            Ok(true) => session.skip_reminder().await,
+            Ok(false) => session.remind(ctx).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = reminded {
+            log_error!("[{}] Error reminding study session {}: {:?}", session.server_id, session.message, e);
+            // Rather than failing again every minute
+            if let Err(e) = session.skip_reminder().await {
+                log_error!("[{}] Error skipping study session {}: {:?}", session.server_id, session.message, e);
+            }
+        }
+    }
+
+    let finished = StudySession::find(doc! {
+        "closed": false,
+        "starts_at": { "$lte": DateTime::from_millis((now - Duration::minutes(SESSION_LENGTH)).timestamp_millis()) },
+    }).await?;
+    for mut session in finished {
+        if let Err(e) = session.close(ctx).await {
+            log_error!("[{}] Error closing study session {}: {:?}", session.server_id, session.message, e);
+        }
+    }
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    subcommands("StudySessionCommand::create", "StudySessionCommand::links", "StudySessionCommand::consents")
+)]
+pub(crate) async fn studysession(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct StudySessionCommand;
+impl StudySessionCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn create(
+        ctx: Context<'_>,
+        class: Role,
+        time: String,
+        topic: String,
+        temp_voice: Option<bool>,
+        #[description = "Whether the session will be recorded, so attendees are asked to acknowledge it"]
+        recorded: Option<bool>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let starts_at = parse_time(&time)
+            .filter(|t| *t > Utc::now())
+            .ok_or(ClassError::InvalidTime)?;
+
+        // Post in the current channel if it belongs to the class, otherwise its first text channel
+        let channel = if class.text_channels.contains(&ctx.channel_id()) {
+            ctx.channel_id()
+        } else {
+            *class.text_channels.first().ok_or(ClassError::NoClassChannels)?
+        };
+
+        StudySession::create(
+            ctx.discord(),
+            &class,
+            channel,
+            ctx.author().id,
+            topic,
+            starts_at,
+            temp_voice.unwrap_or(false),
+            recorded.unwrap_or(false),
+        ).await?;
+
+        ctx.say(format!("Scheduled a study session in {}.", channel.mention())).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(slash_command, subcommands("StudySessionLinksCommand::add", "StudySessionLinksCommand::remove"))]
+    async fn links(_ctx: Context<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// List who acknowledged that a recorded session is being recorded.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn consents(
+        ctx: Context<'_>,
+        #[description = "A link to the session's message, or its ID"]
+        session: String,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let (session, _) = StudySession::find_managed(ctx, &session).await?;
+        if !session.recorded {
+            Err(ClassError::InvalidSession)?;
+        }
+
+        let consents = RecordingConsent::for_session(session.message).await?;
+        if consents.is_empty() {
+            ctx.say(format!("Nobody has acknowledged the recording of \"{}\" yet.", session.topic)).await?;
+        } else {
+            ctx.say(format!(
+                "Acknowledged the recording of \"{}\" ({}):\n{}",
+                session.topic,
+                consents.len(),
+                consents.iter()
+                    .map(|c| format!("{} at <t:{}:f>", c.user.mention(), c.acknowledged_at.timestamp_millis() / 1000))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )).await?;
+        }
+
+        Ok(())
+    }
+}
+
+struct StudySessionLinksCommand;
+impl StudySessionLinksCommand {
+    /// Attach a shared doc or whiteboard link to a session, shown on it and in its reminder.
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn add(
+        ctx: Context<'_>,
+        #[description = "A link to the session's message, or its ID"]
+        session: String,
+        #[description = "What the link is, like \"Whiteboard\""]
+        label: String,
+        url: String,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let (mut session, class) = StudySession::find_managed(ctx, &session).await?;
+        session.add_link(label.trim(), url.trim()).await?;
+        session.refresh(ctx.discord(), &class.name).await?;
+
+        ctx.say(format!("Added \"{}\" to \"{}\".", label.trim(), session.topic)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn remove(
+        ctx: Context<'_>,
+        #[description = "A link to the session's message, or its ID"]
+        session: String,
+        label: String,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let (mut session, class) = StudySession::find_managed(ctx, &session).await?;
+        session.remove_link(label.trim()).await?;
+        session.refresh(ctx.discord(), &class.name).await?;
+
+        ctx.say(format!("Removed \"{}\" from \"{}\".", label.trim(), session.topic)).await?;
+
+        Ok(())
+    }
+}
+
+pub(crate) struct StudySessionRsvpHandler;
+
+#[async_trait]
+impl EventHandler for StudySessionRsvpHandler {
+    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
+        let component = if let Interaction::MessageComponent(c) = interaction {
+            c
+        } else {
+            return;
+        };
+        if component.data.component_type != ComponentType::Button || component.data.custom_id != "study_session_rsvp" {
+            return;
+        }
+
+        let session = match StudySession::toggle_rsvp(component.message.id, component.user.id).await {
+            Ok(s) => s,
+            Err(e) => {
+                log_error!("Error handling study_session_rsvp: {:?}", e);
+                return;
+            }
+        };
+        let class_name = match Class::find_by_role(session.role).await {
+            Ok(c) => c.map(|c| c.name).unwrap_or_default(),
+            Err(e) => {
+                log_error!("Error handling study_session_rsvp: {:?}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = component.create_interaction_response(ctx.http(), |r| r
+            .kind(InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|d| d.embed(|e| session.render(e, &class_name)))
+        ).await {
+            log_error!("Error handling study_session_rsvp: {:?}", e);
+        }
+    }
+}
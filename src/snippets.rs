@@ -0,0 +1,308 @@
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use itertools::Itertools;
+use mongodb::bson::{doc, DateTime};
+use mongodb::Collection;
+use poise::Modal;
+use serde::{Deserialize, Serialize};
+use serenity::builder::CreateComponents;
+use serenity::collector::CollectComponentInteraction;
+use serenity::http::CacheHttp;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::channel::Message;
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::model::prelude::component::ButtonStyle;
+use tokio::sync::OnceCell;
+
+use crate::classes::Class;
+use crate::{get_conn, is_class_staff, ClassError, ClassResult, Context, Error, ENV};
+
+/// How many snippets are listed on each page of `/snippet search`.
+const PAGE_SIZE: usize = 10;
+/// How long the page buttons of `/snippet search` keep working.
+const PAGE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Snippet {
+    server_id: GuildId,
+    role: RoleId,
+    name: String,
+    language: String,
+    code: String,
+    description: Option<String>,
+    created_by: UserId,
+    created_at: DateTime,
+}
+
+impl Snippet {
+    fn render(&self) -> String {
+        format!(
+            "**{}**{}\n```{}\n{}\n```",
+            self.name,
+            self.description.as_ref().map(|d| format!(" — {}", d)).unwrap_or_default(),
+            self.language,
+            self.code,
+        )
+    }
+
+    async fn save(class: &Class, form: SnippetModal, created_by: UserId) -> ClassResult<Snippet> {
+        let name = form.name.trim().to_lowercase();
+        let collection = Self::get_collection().await;
+        if collection
+            .find_one(doc! { "role": class.role.to_string(), "name": &name }, None)
+            .await?
+            .is_some()
+        {
+            return Err(ClassError::SnippetExists);
+        }
+
+        let snippet = Self {
+            server_id: class.server_id,
+            role: class.role,
+            name,
+            language: form.language.unwrap_or_default().trim().to_lowercase(),
+            code: form.code,
+            description: form.description,
+            created_by,
+            created_at: DateTime::now(),
+        };
+        collection.insert_one(&snippet, None).await?;
+
+        Ok(snippet)
+    }
+
+    async fn list(role: RoleId) -> ClassResult<Vec<Snippet>> {
+        Ok(
+            Self::get_collection().await
+                .find(doc! { "role": role.to_string() }, None)
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static SNIPPETS: OnceCell<Collection<Snippet>> = OnceCell::const_new();
+
+        SNIPPETS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("snippets")
+            })
+            .await
+            .clone()
+    }
+}
+
+#[derive(Debug, Default, Modal)]
+#[name = "Save snippet"]
+struct SnippetModal {
+    #[name = "Name"]
+    #[max_length = 100]
+    name: String,
+    #[name = "Language"]
+    #[placeholder = "rust"]
+    #[max_length = 20]
+    language: Option<String>,
+    #[name = "Code"]
+    #[paragraph]
+    code: String,
+    #[name = "Description"]
+    #[paragraph]
+    #[max_length = 500]
+    description: Option<String>,
+}
+
+/// Split a message into its code and language, unwrapping a fenced code block if there is one.
+fn parse_code_block(content: &str) -> (Option<String>, String) {
+    let trimmed = content.trim();
+    match trimmed.strip_prefix("```").and_then(|s| s.strip_suffix("```")) {
+        Some(inner) => {
+            let (language, code) = inner.split_once('\n').unwrap_or(("", inner));
+            let language = language.trim();
+            (
+                (!language.is_empty()).then(|| language.to_string()),
+                code.trim_end().to_string(),
+            )
+        }
+        None => (None, trimmed.to_string()),
+    }
+}
+
+/// Show the snippet form, and save the result to the class. Must be the first response to the
+/// command.
+async fn save_from_modal(ctx: Context<'_>, class: &Class, defaults: SnippetModal) -> Result<(), Error> {
+    let actx = match ctx {
+        poise::Context::Application(actx) => actx,
+        poise::Context::Prefix(_) => return Ok(()),
+    };
+
+    let form = SnippetModal::execute_with_defaults(actx, defaults).await?;
+    let snippet = Snippet::save(class, form, ctx.author().id).await?;
+
+    ctx.say(format!("Saved snippet `{}` to \"{}\".", snippet.name, class.name)).await?;
+
+    Ok(())
+}
+
+fn page_buttons(c: &mut CreateComponents, page: usize, pages: usize) -> &mut CreateComponents {
+    c.create_action_row(|r| r
+        .create_button(|b| b
+            .custom_id("snippet_page_prev")
+            .style(ButtonStyle::Secondary)
+            .label("Previous")
+            .disabled(page == 0)
+        )
+        .create_button(|b| b
+            .custom_id("snippet_page_next")
+            .style(ButtonStyle::Secondary)
+            .label("Next")
+            .disabled(page + 1 >= pages)
+        )
+    )
+}
+
+/// Send `pages` one at a time, with buttons to move between them until they time out.
+async fn paginate(ctx: Context<'_>, pages: &[String]) -> Result<(), Error> {
+    let mut page = 0;
+    let reply = ctx
+        .send(|m| m
+            .content(&pages[page])
+            .components(|c| page_buttons(c, page, pages.len()))
+        )
+        .await?;
+    let message = reply.message().await?.id;
+
+    while let Some(interaction) = CollectComponentInteraction::new(ctx.discord())
+        .message_id(message)
+        .author_id(ctx.author().id)
+        .timeout(PAGE_TIMEOUT)
+        .await
+    {
+        page = match interaction.data.custom_id.as_str() {
+            "snippet_page_prev" => page.saturating_sub(1),
+            "snippet_page_next" => (page + 1).min(pages.len() - 1),
+            _ => page,
+        };
+
+        interaction
+            .create_interaction_response(ctx.discord().http(), |r| r
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|d| d
+                    .content(&pages[page])
+                    .components(|c| page_buttons(c, page, pages.len()))
+                )
+            )
+            .await?;
+    }
+
+    reply.edit(ctx, |m| m.components(|c| c)).await?;
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    subcommands("SnippetCommand::save", "SnippetCommand::get", "SnippetCommand::search")
+)]
+pub(crate) async fn snippet(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct SnippetCommand;
+impl SnippetCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn save(ctx: Context<'_>, class: Role, name: Option<String>) -> Result<(), Error> {
+        // No deferring here, as the modal has to be the first response
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        if !is_class_staff(ctx, &class).await {
+            Err(ClassError::MissingPermissions)?;
+        }
+
+        save_from_modal(ctx, &class, SnippetModal {
+            name: name.unwrap_or_default(),
+            ..Default::default()
+        }).await
+    }
+
+    #[poise::command(slash_command)]
+    async fn get(ctx: Context<'_>, class: Role, name: String) -> Result<(), Error> {
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let snippet = Snippet::get_collection().await
+            .find_one(doc! { "role": class.role.to_string(), "name": name.trim().to_lowercase() }, None)
+            .await?
+            .ok_or(ClassError::InvalidSnippet)?;
+
+        ctx.say(snippet.render()).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn search(ctx: Context<'_>, class: Role, query: Option<String>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let query = query.unwrap_or_default().to_lowercase();
+
+        let matches = Snippet::list(class.role).await?
+            .into_iter()
+            .filter(|s| {
+                s.name.contains(&query)
+                    || s.language == query
+                    || s.description.as_ref().map(|d| d.to_lowercase().contains(&query)).unwrap_or(false)
+            })
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect::<Vec<_>>();
+        if matches.is_empty() {
+            Err(ClassError::InvalidSnippet)?;
+        }
+
+        let page_count = matches.len().div_ceil(PAGE_SIZE);
+        let pages = matches
+            .chunks(PAGE_SIZE)
+            .enumerate()
+            .map(|(i, chunk)| format!(
+                "Snippets for \"{}\" (page {}/{}):\n{}",
+                class.name,
+                i + 1,
+                page_count,
+                chunk.iter()
+                    .map(|s| format!(
+                        "`{}`{}{}",
+                        s.name,
+                        if s.language.is_empty() { String::new() } else { format!(" ({})", s.language) },
+                        s.description.as_ref().map(|d| format!(" — {}", d)).unwrap_or_default(),
+                    ))
+                    .join("\n"),
+            ))
+            .collect::<Vec<_>>();
+
+        paginate(ctx, &pages).await
+    }
+}
+
+#[poise::command(context_menu_command = "Save as snippet", ephemeral)]
+pub(crate) async fn save_snippet(ctx: Context<'_>, message: Message) -> Result<(), Error> {
+    let class = Class::find_by_text_channel(message.channel_id).await?
+        .ok_or(ClassError::InvalidClass)?;
+    if !is_class_staff(ctx, &class).await {
+        Err(ClassError::MissingPermissions)?;
+    }
+
+    let (language, code) = parse_code_block(&message.content);
+    save_from_modal(ctx, &class, SnippetModal {
+        language,
+        code,
+        ..Default::default()
+    }).await
+}
@@ -0,0 +1,357 @@
+use mongodb::bson::doc;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::application::interaction::{Interaction, InteractionResponseType};
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::model::prelude::component::{ButtonStyle, ComponentType};
+use serenity::prelude::{EventHandler, Mentionable};
+use serenity::utils::Colour;
+use tokio::sync::OnceCell;
+
+use crate::classes::Server;
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, ClassError, ClassResult, Context, Error, ENV};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum SuggestionStatus {
+    Open,
+    Accepted,
+    Rejected,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Suggestion {
+    server_id: GuildId,
+    number: u64,
+    author: UserId,
+    content: String,
+    channel: ChannelId,
+    message: MessageId,
+    upvotes: Vec<UserId>,
+    downvotes: Vec<UserId>,
+    status: SuggestionStatus,
+    reason: Option<String>,
+}
+
+/// The last suggestion number handed out in a server.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct SuggestionCounter {
+    server_id: GuildId,
+    last: u64,
+}
+
+impl SuggestionCounter {
+    /// Hand out the next suggestion number. Numbers are never reused, even by concurrent
+    /// suggestions.
+    pub(crate) async fn next(server_id: GuildId) -> ClassResult<u64> {
+        let counter = Self::scoped(server_id).await
+            .find_one_and_update(
+                doc! {},
+                doc! { "$inc": { "last": 1 } },
+                FindOneAndUpdateOptions::builder()
+                    .upsert(true)
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?
+            .ok_or(ClassError::InvalidSuggestion)?;
+
+        Ok(counter.last)
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static COUNTERS: OnceCell<Collection<SuggestionCounter>> = OnceCell::const_new();
+
+        COUNTERS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("suggestion_counters")
+            })
+            .await
+            .clone()
+    }
+}
+
+impl Suggestion {
+    fn render<'a>(&self, e: &'a mut CreateEmbed) -> &'a mut CreateEmbed {
+        let (status, colour) = match self.status {
+            SuggestionStatus::Open => ("Open", Colour::BLURPLE),
+            SuggestionStatus::Accepted => ("Accepted", Colour::DARK_GREEN),
+            SuggestionStatus::Rejected => ("Rejected", Colour::RED),
+        };
+
+        e.title(format!("Suggestion #{}", self.number))
+            .description(&self.content)
+            .colour(colour)
+            .field("Suggested by", self.author.mention(), true)
+            .field("Votes", format!("👍 {}  👎 {}", self.upvotes.len(), self.downvotes.len()), true)
+            .field("Status", status, true);
+        if let Some(reason) = &self.reason {
+            e.field("Reason", reason, false);
+        }
+        e
+    }
+
+    fn components<'a>(&self, c: &'a mut CreateComponents) -> &'a mut CreateComponents {
+        if self.status != SuggestionStatus::Open {
+            return c;
+        }
+
+        c.create_action_row(|r| r
+            .create_button(|b| b
+                .custom_id(format!("suggestion_upvote_{}", self.number))
+                .style(ButtonStyle::Success)
+                .emoji('👍')
+            )
+            .create_button(|b| b
+                .custom_id(format!("suggestion_downvote_{}", self.number))
+                .style(ButtonStyle::Danger)
+                .emoji('👎')
+            )
+        )
+    }
+
+    async fn post(
+        cache_http: impl CacheHttp,
+        server_id: GuildId,
+        author: UserId,
+        content: String,
+    ) -> ClassResult<Suggestion> {
+        let server = Server::get_or_create(server_id).await?;
+        let channel = server.suggestions_channel.ok_or(ClassError::NoSuggestionsChannel)?;
+
+        let collection = Self::scoped(server_id).await;
+        let number = SuggestionCounter::next(server_id).await?;
+
+        let mut suggestion = Self {
+            server_id,
+            number,
+            author,
+            content,
+            channel,
+            message: MessageId(0),
+            upvotes: Vec::new(),
+            downvotes: Vec::new(),
+            status: SuggestionStatus::Open,
+            reason: None,
+        };
+
+        let message = channel
+            .send_message(cache_http.http(), |m| m
+                .embed(|e| suggestion.render(e))
+                .components(|c| suggestion.components(c))
+            )
+            .await?;
+        suggestion.message = message.id;
+
+        collection.insert_one(&suggestion, None).await?;
+
+        Ok(suggestion)
+    }
+
+    /// Vote on the suggestion posted as `message`.
+    async fn vote(server_id: GuildId, message: MessageId, user: UserId, up: bool) -> ClassResult<Suggestion> {
+        let (field, other) = if up { ("upvotes", "downvotes") } else { ("downvotes", "upvotes") };
+        let filter = doc! {
+            "message": message.to_string(),
+            "status": "open",
+        };
+
+        let collection = Self::scoped(server_id).await;
+        let existing = collection.find_one(filter.clone(), None)
+            .await?
+            .ok_or(ClassError::InvalidSuggestion)?;
+        let already_voted = if up { &existing.upvotes } else { &existing.downvotes }.contains(&user);
+
+        // Voting the same way twice takes the vote back
+        let update = if already_voted {
+            doc! { "$pull": { field: user.to_string() } }
+        } else {
+            doc! {
+                "$addToSet": { field: user.to_string() },
+                "$pull": { other: user.to_string() },
+            }
+        };
+
+        collection
+            .find_one_and_update(
+                filter,
+                update,
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?
+            .ok_or(ClassError::InvalidSuggestion)
+    }
+
+    async fn resolve(
+        cache_http: impl CacheHttp,
+        server_id: GuildId,
+        number: u64,
+        status: SuggestionStatus,
+        reason: Option<String>,
+    ) -> ClassResult<Suggestion> {
+        let suggestion = Self::scoped(server_id).await
+            .find_one_and_update(
+                doc! { "number": number as i64 },
+                doc! { "$set": {
+                    "status": mongodb::bson::to_bson(&status)?,
+                    "reason": reason,
+                } },
+                FindOneAndUpdateOptions::builder()
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await?
+            .ok_or(ClassError::InvalidSuggestion)?;
+
+        suggestion.channel
+            .edit_message(cache_http.http(), suggestion.message, |m| m
+                .embed(|e| suggestion.render(e))
+                .components(|c| suggestion.components(c))
+            )
+            .await?;
+
+        Ok(suggestion)
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static SUGGESTIONS: OnceCell<Collection<Suggestion>> = OnceCell::const_new();
+
+        SUGGESTIONS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("suggestions")
+            })
+            .await
+            .clone()
+    }
+}
+
+#[poise::command(
+    slash_command,
+    ephemeral,
+)]
+pub(crate) async fn suggest(ctx: Context<'_>, text: String) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    let suggestion = Suggestion::post(
+        ctx.discord(),
+        ctx.guild_id().ok_or(ClassError::NoServer)?,
+        ctx.author().id,
+        text,
+    ).await?;
+
+    ctx.say(format!("Posted suggestion #{} in {}.", suggestion.number, suggestion.channel.mention())).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, subcommands("SuggestionCommand::accept", "SuggestionCommand::reject"))]
+pub(crate) async fn suggestion(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct SuggestionCommand;
+impl SuggestionCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn accept(ctx: Context<'_>, number: u64, reason: Option<String>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        Suggestion::resolve(ctx.discord(), server_id, number, SuggestionStatus::Accepted, reason).await?;
+
+        ctx.say(format!("Accepted suggestion #{}.", number)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn reject(ctx: Context<'_>, number: u64, reason: Option<String>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        Suggestion::resolve(ctx.discord(), server_id, number, SuggestionStatus::Rejected, reason).await?;
+
+        ctx.say(format!("Rejected suggestion #{}.", number)).await?;
+
+        Ok(())
+    }
+}
+
+pub(crate) struct SuggestionVoteHandler;
+
+#[async_trait]
+impl EventHandler for SuggestionVoteHandler {
+    async fn interaction_create(&self, ctx: SContext, interaction: Interaction) {
+        let component = if let Interaction::MessageComponent(c) = interaction {
+            c
+        } else {
+            return;
+        };
+        if component.data.component_type != ComponentType::Button {
+            return;
+        }
+
+        let custom_id = &*component.data.custom_id;
+        // Found by message rather than the number in the ID, which may have changed since
+        let up = if custom_id.starts_with("suggestion_upvote_") {
+            true
+        } else if custom_id.starts_with("suggestion_downvote_") {
+            false
+        } else {
+            return;
+        };
+
+        let server_id = if let Some(id) = component.guild_id {
+            id
+        } else {
+            log_error!("Error handling {}: {:?}", custom_id, ClassError::NoServer);
+            return;
+        };
+
+        let http = ctx.http();
+
+        let suggestion = match Suggestion::vote(server_id, component.message.id, component.user.id, up).await {
+            Ok(s) => s,
+            Err(e) => {
+                log_error!("Error handling {}: {:?}", custom_id, e);
+                return;
+            }
+        };
+
+        if let Err(e) = component.create_interaction_response(http, |r| r
+            .kind(InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|d| d.embed(|e| suggestion.render(e)))
+        ).await {
+            log_error!("Error handling {}: {:?}", custom_id, e);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use futures::TryStreamExt;
+use itertools::Itertools;
+use mongodb::bson::{doc, Bson};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use tokio::sync::OnceCell;
+
+use crate::classes::Class;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, is_class_staff, is_manager, ClassError, ClassResult, Context, Error, ENV};
+
+/// A reusable piece of text, scoped either to the whole server or to one class's channels.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Tag {
+    server_id: GuildId,
+    /// The class the tag belongs to, or `None` for a server-wide tag.
+    role: Option<RoleId>,
+    name: String,
+    content: String,
+    title: Option<String>,
+    embed: bool,
+    created_by: UserId,
+}
+
+impl Tag {
+    fn filter(role: Option<RoleId>, name: &str) -> mongodb::bson::Document {
+        doc! {
+            "role": role.map(|r| Bson::String(r.to_string())).unwrap_or(Bson::Null),
+            "name": name.trim().to_lowercase(),
+        }
+    }
+
+    async fn find(server_id: GuildId, role: Option<RoleId>, name: &str) -> ClassResult<Option<Tag>> {
+        Self::scoped(server_id).await.find_one(Self::filter(role, name), None).await
+    }
+
+    /// Find a tag usable in a channel, preferring the class's own tag over a server-wide one.
+    async fn resolve(server_id: GuildId, class: Option<&Class>, name: &str) -> ClassResult<Option<Tag>> {
+        if let Some(class) = class {
+            if let Some(tag) = Self::find(server_id, Some(class.role), name).await? {
+                return Ok(Some(tag));
+            }
+        }
+        Self::find(server_id, None, name).await
+    }
+
+    async fn list(server_id: GuildId) -> ClassResult<Vec<Tag>> {
+        Ok(
+            Self::scoped(server_id).await
+                .find(None, None)
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static TAGS: OnceCell<Collection<Tag>> = OnceCell::const_new();
+
+        TAGS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("tags")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Look up the class a tag command refers to, and check the author may manage its tags. Server-wide
+/// tags may only be managed by those with Manage Server.
+async fn authorize(ctx: Context<'_>, class: Option<Role>) -> ClassResult<Option<Class>> {
+    let class = match class {
+        Some(role) => Some(Class::find_by_role(role.id).await?.ok_or(ClassError::InvalidClass)?),
+        None => None,
+    };
+
+    let allowed = match &class {
+        Some(class) => is_class_staff(ctx, class).await,
+        None => is_manager(ctx).await,
+    };
+    if !allowed {
+        return Err(ClassError::MissingPermissions);
+    }
+
+    Ok(class)
+}
+
+#[poise::command(
+    slash_command,
+    subcommands(
+        "TagCommand::create",
+        "TagCommand::edit",
+        "TagCommand::delete",
+        "TagCommand::show",
+        "TagCommand::list",
+    )
+)]
+pub(crate) async fn tag(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct TagCommand;
+impl TagCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn create(
+        ctx: Context<'_>,
+        name: String,
+        content: String,
+        class: Option<Role>,
+        title: Option<String>,
+        embed: Option<bool>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let class = authorize(ctx, class).await?;
+        let role = class.as_ref().map(|c| c.role);
+
+        if Tag::find(server_id, role, &name).await?.is_some() {
+            Err(ClassError::TagExists)?;
+        }
+
+        Tag::scoped(server_id).await
+            .insert_one(
+                Tag {
+                    server_id,
+                    role,
+                    name: name.trim().to_lowercase(),
+                    content,
+                    title,
+                    embed: embed.unwrap_or(false),
+                    created_by: ctx.author().id,
+                },
+                None,
+            )
+            .await?;
+
+        ctx.say(format!("Created tag `{}`.", name.trim().to_lowercase())).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn edit(
+        ctx: Context<'_>,
+        name: String,
+        content: String,
+        class: Option<Role>,
+        title: Option<String>,
+        embed: Option<bool>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let class = authorize(ctx, class).await?;
+        let tag = Tag::find(server_id, class.as_ref().map(|c| c.role), &name).await?
+            .ok_or(ClassError::InvalidTag)?;
+
+        Tag::scoped(server_id).await
+            .update_one(
+                Tag::filter(tag.role, &tag.name),
+                doc! { "$set": {
+                    "content": content,
+                    "title": title.or(tag.title),
+                    "embed": embed.unwrap_or(tag.embed),
+                } },
+                None,
+            )
+            .await?;
+
+        ctx.say(format!("Updated tag `{}`.", tag.name)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn delete(ctx: Context<'_>, name: String, class: Option<Role>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let class = authorize(ctx, class).await?;
+
+        let deleted = Tag::scoped(server_id).await
+            .delete_one(Tag::filter(class.map(|c| c.role), &name), None)
+            .await?
+            .deleted_count;
+        if deleted == 0 {
+            Err(ClassError::InvalidTag)?;
+        }
+
+        ctx.say(format!("Deleted tag `{}`.", name.trim().to_lowercase())).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(slash_command)]
+    async fn show(ctx: Context<'_>, name: String) -> Result<(), Error> {
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let class = Class::find_by_text_channel(ctx.channel_id()).await?;
+
+        let tag = Tag::resolve(server_id, class.as_ref(), &name).await?
+            .ok_or(ClassError::InvalidTag)?;
+
+        if tag.embed {
+            ctx.send(|m| m.embed(|e| {
+                if let Some(title) = &tag.title {
+                    e.title(title);
+                }
+                e.description(&tag.content)
+            })).await?;
+        } else {
+            ctx.say(match &tag.title {
+                Some(title) => format!("**{}**\n{}", title, tag.content),
+                None => tag.content.clone(),
+            }).await?;
+        }
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn list(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let class = Class::find_by_text_channel(ctx.channel_id()).await?;
+
+        let tags = Tag::list(server_id).await?
+            .into_iter()
+            .filter(|t| t.role.is_none() || t.role == class.as_ref().map(|c| c.role))
+            .map(|t| format!("`{}`", t.name))
+            .sorted()
+            .dedup()
+            .collect::<Vec<_>>();
+
+        if tags.is_empty() {
+            ctx.say("There are no tags available here.").await?;
+        } else {
+            ctx.say(format!("Tags available here: {}", tags.join(", "))).await?;
+        }
+
+        Ok(())
+    }
+}
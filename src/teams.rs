@@ -0,0 +1,278 @@
+use futures::TryStreamExt;
+use itertools::Itertools;
+use mongodb::bson::doc;
+use mongodb::Collection;
+use seq_macro::seq;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::channel::{ChannelType, PermissionOverwrite, PermissionOverwriteType};
+use serenity::model::guild::Role;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::model::user::User;
+use serenity::model::Permissions;
+use serenity::prelude::Mentionable;
+use tokio::sync::OnceCell;
+
+use crate::autotrack;
+use crate::classes::Class;
+use crate::events::BotEvent;
+use crate::redact::log_error;
+use crate::{discord_name, get_conn, is_class_staff, is_manager, ClassError, ClassResult, Context, Error, ENV};
+
+/// The most teams a class can have, as each takes two channels under the class's categories.
+const MAX_TEAMS: usize = 25;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Team {
+    server_id: GuildId,
+    role: RoleId,
+    name: String,
+    members: Vec<UserId>,
+    text_channel: ChannelId,
+    voice_channel: ChannelId,
+}
+
+impl Team {
+    /// Create a private text and voice channel under the class category, visible only to the
+    /// team members and the class staff.
+    async fn create(
+        cache_http: impl CacheHttp,
+        class: &mut Class,
+        name: &str,
+        members: Vec<UserId>,
+    ) -> ClassResult<Team> {
+        let name = name.trim();
+        let collection = Self::get_collection().await;
+        if collection
+            .find_one(doc! { "role": class.role.to_string(), "name": name }, None)
+            .await?
+            .is_some()
+        {
+            return Err(ClassError::TeamExists);
+        }
+
+        let bot = cache_http.cache()
+            .map(|c| c.current_user_id())
+            .ok_or(ClassError::NoServer)?;
+        let allow = Permissions::VIEW_CHANNEL | Permissions::CONNECT;
+        let permissions = std::iter::once(PermissionOverwrite {
+            allow: Permissions::empty(),
+            deny: Permissions::VIEW_CHANNEL,
+            kind: PermissionOverwriteType::Role(class.server_id.0.into()),
+        })
+            .chain(members.iter().chain(std::iter::once(&bot)).map(|u| PermissionOverwrite {
+                allow,
+                deny: Permissions::empty(),
+                kind: PermissionOverwriteType::Member(*u),
+            }))
+            .chain(class.staff_role.map(|r| PermissionOverwrite {
+                allow,
+                deny: Permissions::empty(),
+                kind: PermissionOverwriteType::Role(r),
+            }))
+            .collect::<Vec<_>>();
+
+        let category = class.category_with_room(&cache_http, 2).await?;
+        let http = cache_http.http();
+        let slug = name.split_whitespace().join("-").to_lowercase();
+        let text_channel = class.server_id
+            .create_channel(http, |c| c
+                .name(discord_name(&format!("team-{}—〈{}〉", slug, class.short_name)))
+                .kind(ChannelType::Text)
+                .category(category)
+                .permissions(permissions.clone())
+            )
+            .await?;
+        autotrack::exclude(text_channel.id);
+        let voice_channel = class.server_id
+            .create_channel(http, |c| c
+                .name(discord_name(&format!("Team {} ({})", name, class.short_name)))
+                .kind(ChannelType::Voice)
+                .category(category)
+                .permissions(permissions)
+            )
+            .await?;
+        autotrack::exclude(voice_channel.id);
+
+        let team = Self {
+            server_id: class.server_id,
+            role: class.role,
+            name: name.to_string(),
+            members,
+            text_channel: text_channel.id,
+            voice_channel: voice_channel.id,
+        };
+
+        collection.insert_one(&team, None).await?;
+
+        Ok(team)
+    }
+
+    async fn disband(&self, cache_http: impl CacheHttp) -> ClassResult<()> {
+        // Ignoring errors as the channels may already have been deleted by hand
+        self.text_channel.delete(cache_http.http()).await.ok();
+        self.voice_channel.delete(cache_http.http()).await.ok();
+
+        Self::get_collection().await
+            .delete_one(doc! { "text_channel": self.text_channel.to_string() }, None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn find(class: &Class, name: &str) -> ClassResult<Option<Team>> {
+        Ok(
+            Self::get_collection().await
+                .find_one(doc! { "role": class.role.to_string(), "name": name.trim() }, None)
+                .await?
+        )
+    }
+
+    async fn list(role: RoleId) -> ClassResult<Vec<Team>> {
+        Ok(
+            Self::get_collection().await
+                .find(doc! { "role": role.to_string() }, None)
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static TEAMS: OnceCell<Collection<Team>> = OnceCell::const_new();
+
+        TEAMS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("teams")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Event bus subscriber disbanding a class's teams when the class itself is deleted.
+pub(crate) async fn cleanup(ctx: SContext, event: BotEvent) {
+    let class = match event {
+        BotEvent::ClassDeleted { class, channels_deleted: true, .. } => class,
+        _ => return,
+    };
+
+    let result = match Team::list(class.role).await {
+        Ok(teams) => futures::future::try_join_all(teams.iter().map(|t| t.disband(&ctx))).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log_error!("Error cleaning up teams for class {}: {:?}", class.name, e);
+    }
+}
+
+#[poise::command(
+    slash_command,
+    subcommands("TeamCommand::create", "TeamCommand::list", "TeamCommand::disband")
+)]
+pub(crate) async fn team(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct TeamCommand;
+impl TeamCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_bot_permissions = "MANAGE_CHANNELS",
+    )]
+    #[allow(clippy::too_many_arguments, clippy::vec_init_then_push)]
+    async fn create(
+        ctx: Context<'_>,
+        class: Role,
+        name: String,
+        member1: Option<User>,
+        member2: Option<User>,
+        member3: Option<User>,
+        member4: Option<User>,
+        member5: Option<User>,
+        member6: Option<User>,
+        member7: Option<User>,
+        member8: Option<User>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let mut class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        // Members of the class can start one team each, and its staff as many as they need
+        let author = ctx.author_member().await.ok_or(ClassError::NoServer)?;
+        let staff = is_class_staff(ctx, &class).await;
+        if !staff && !author.roles.contains(&class.role) {
+            Err(ClassError::NotInClass)?;
+        }
+        let teams = Team::list(class.role).await?;
+        if teams.len() >= MAX_TEAMS {
+            Err(ClassError::TooManyTeams(MAX_TEAMS))?;
+        }
+        if !staff && teams.iter().any(|t| t.members.contains(&author.user.id)) {
+            Err(ClassError::AlreadyInTeam)?;
+        }
+
+        let mut members = Vec::new();
+        members.push(Some(ctx.author().id));
+        seq!(N in 1..=8 {
+            members.push(member~N.map(|u| u.id));
+        });
+        let members = members.into_iter().flatten().unique().collect::<Vec<_>>();
+
+        let team = Team::create(ctx.discord(), &mut class, &name, members).await?;
+
+        ctx.say(format!("Created team \"{}\": {} {}", team.name, team.text_channel.mention(), team.voice_channel.mention())).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn list(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let teams = Team::list(class.role).await?;
+
+        if teams.is_empty() {
+            ctx.say(format!("There are no teams for \"{}\".", class.name)).await?;
+        } else {
+            ctx.say(format!(
+                "Teams for \"{}\":\n{}",
+                class.name,
+                teams.iter()
+                    .map(|t| format!("**{}**: {}", t.name, t.members.iter().map(|u| u.mention()).join(", ")))
+                    .join("\n"),
+            )).await?;
+        }
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn disband(ctx: Context<'_>, class: Role, name: String) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        let team = Team::find(&class, &name).await?.ok_or(ClassError::InvalidTeam)?;
+
+        if !team.members.contains(&ctx.author().id) && !is_manager(ctx).await {
+            Err(ClassError::MissingPermissions)?;
+        }
+
+        team.disband(ctx.discord()).await?;
+
+        ctx.say(format!("Disbanded team \"{}\".", team.name)).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! Temporary access to commands a member couldn't otherwise run, on top of the server's policies.
+//! Grants are revoked by the scheduler once they expire.
+
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, UserId};
+use tokio::sync::OnceCell;
+
+use crate::events::{self, BotEvent};
+use crate::scoped::GuildScoped;
+use crate::{get_conn, ClassResult, ENV};
+
+/// The longest a temporary grant can last.
+pub(crate) const MAX_DURATION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Commands that change who can run what. Temporary access to them could be used to give out
+/// more temporary access, or to make it permanent.
+const PERMISSION_COMMANDS: [&str; 4] = [
+    "admin grant-temp",
+    "config policy",
+    "class managers",
+    "class edit staff",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct TempGrant {
+    server_id: GuildId,
+    pub(crate) user: UserId,
+    /// A command's full name or a group of commands, as in a policy.
+    pub(crate) command: String,
+    granted_by: UserId,
+    pub(crate) expires_at: DateTime,
+}
+
+impl TempGrant {
+    /// Let a member run a command, or a group of commands, until the given time.
+    pub(crate) async fn give(
+        server_id: GuildId,
+        user: UserId,
+        command: &str,
+        granted_by: UserId,
+        expires_at: DateTime,
+    ) -> ClassResult<()> {
+        let grant = Self { server_id, user, command: command.to_string(), granted_by, expires_at };
+        Self::scoped(server_id).await.insert_one(&grant, None).await?;
+
+        events::publish(BotEvent::AccessGranted {
+            server_id,
+            user_id: user,
+            granted_by,
+            command: grant.command,
+            expires_at: expires_at.timestamp_millis() / 1000,
+        });
+
+        Ok(())
+    }
+
+    /// A member's grants in a server that haven't expired yet.
+    pub(crate) async fn active(server_id: GuildId, user: UserId) -> ClassResult<Vec<Self>> {
+        Self::scoped(server_id).await
+            .find(doc! { "user": user.to_string(), "expires_at": { "$gt": DateTime::now() } }, None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static TEMP_GRANTS: OnceCell<Collection<TempGrant>> = OnceCell::const_new();
+
+        TEMP_GRANTS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("temp_grants")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Whether a grant for `granted`, a command or a group of commands, lets a member run `command`.
+pub(crate) fn covers(granted: &str, command: &str) -> bool {
+    command == granted || command.strip_prefix(granted).is_some_and(|rest| rest.starts_with(' '))
+}
+
+/// Whether a command can be granted temporarily. Anything that includes or is part of a command
+/// changing permissions can't, so a grant can't be extended or made permanent by the member
+/// holding it.
+pub(crate) fn grantable(command: &str) -> bool {
+    !PERMISSION_COMMANDS.iter().any(|p| covers(command, p) || covers(p, command))
+}
+
+/// Revoke expired grants, recording each in the audit log.
+pub(crate) async fn tick() -> ClassResult<()> {
+    let expired = TempGrant::get_collection().await
+        .find(doc! { "expires_at": { "$lte": DateTime::now() } }, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    for grant in expired {
+        let deleted = TempGrant::scoped(grant.server_id).await
+            .delete_one(
+                doc! { "user": grant.user.to_string(), "command": &grant.command, "expires_at": grant.expires_at },
+                None,
+            )
+            .await?;
+        // Another instance may have revoked it first
+        if deleted.deleted_count > 0 {
+            events::publish(BotEvent::AccessRevoked {
+                server_id: grant.server_id,
+                user_id: grant.user,
+                command: grant.command,
+            });
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::model::channel::ChannelType;
+use serenity::model::guild::Guild;
+use serenity::model::id::GuildId;
+
+use crate::classes::{Class, NewChannel, Server};
+use crate::departments::DepartmentTheme;
+use crate::jobs::JobStatus;
+use crate::visibility::Visibility;
+use crate::{lookup, ClassError, ClassResult};
+
+/// Bumped whenever the template format changes in a way older bots can't read.
+const TEMPLATE_VERSION: u32 = 1;
+/// How long to wait between classes when applying a template, to stay clear of Discord's rate
+/// limits on creating roles and channels.
+const CLASS_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ClassTemplate {
+    name: String,
+    description: Option<String>,
+    tags: Vec<String>,
+    visibility: Visibility,
+    channels: Vec<NewChannel>,
+}
+
+/// A server's class catalog and settings, without any members or IDs, for setting up another
+/// server the same way.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ServerTemplate {
+    version: u32,
+    welcome_template: Option<String>,
+    icebreaker_prompts: Vec<String>,
+    department_themes: Vec<DepartmentTheme>,
+    classes: Vec<ClassTemplate>,
+}
+
+impl ServerTemplate {
+    pub(crate) async fn export(guild: &Guild) -> ClassResult<ServerTemplate> {
+        let server = Server::get_or_create(guild.id).await?;
+
+        let classes = Class::list(guild.id).await?
+            .into_iter()
+            .map(|class| {
+                let mut channels = class.text_channels.iter()
+                    .chain(&class.voice_channels)
+                    .filter_map(|c| guild.channels.get(c)?.clone().guild())
+                    .collect::<Vec<_>>();
+                channels.sort_by_key(|c| (c.kind == ChannelType::Voice, c.position));
+
+                ClassTemplate {
+                    name: class.name,
+                    description: class.description,
+                    tags: class.tags,
+                    visibility: class.visibility,
+                    channels: channels.into_iter()
+                        .map(|c| NewChannel { name: c.name, kind: c.kind, topic: c.topic })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            version: TEMPLATE_VERSION,
+            welcome_template: server.welcome_template,
+            icebreaker_prompts: server.icebreaker_prompts,
+            department_themes: server.department_themes,
+            classes,
+        })
+    }
+
+    pub(crate) fn parse(data: &[u8]) -> ClassResult<ServerTemplate> {
+        let template = serde_json::from_slice::<Self>(data).map_err(|_| ClassError::InvalidTemplate)?;
+        if template.version > TEMPLATE_VERSION {
+            return Err(ClassError::InvalidTemplate);
+        }
+        Ok(template)
+    }
+
+    pub(crate) fn class_count(&self) -> usize {
+        self.classes.len()
+    }
+
+    /// Set up the server's settings, then create each class that doesn't exist yet, one at a
+    /// time. Returns the names of the classes that were created.
+    pub(crate) async fn apply(self, ctx: &SContext, server_id: GuildId, status: &JobStatus) -> ClassResult<Vec<String>> {
+        let mut server = Server::get_or_create(server_id).await?;
+        if server.refrole.is_none() {
+            return Err(ClassError::NoRefrole);
+        }
+
+        if self.welcome_template.is_some() {
+            server.set_welcome(!server.welcome_dm_disabled, self.welcome_template).await?;
+        }
+        for prompt in &self.icebreaker_prompts {
+            match server.add_icebreaker_prompt(prompt).await {
+                Ok(()) | Err(ClassError::PromptExists) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        for theme in self.department_themes {
+            server.set_department_theme(theme).await?;
+        }
+
+        let total = self.classes.len();
+        let mut created = Vec::new();
+        for (i, template) in self.classes.into_iter().enumerate() {
+            status.progress(ctx, &format!("Created {} classes, {} of {} checked.", created.len(), i, total)).await?;
+            if Class::class_exists(server_id, &template.name).await? {
+                continue;
+            }
+
+            let guild = lookup::guild(ctx, server_id).await?;
+            let mut class = Class::create_with_channels(
+                ctx,
+                &guild,
+                &template.name,
+                template.visibility,
+                &template.channels,
+            ).await?;
+            class.set_description(template.description).await?;
+            class.set_tags(template.tags.iter().map(|t| t.as_str())).await?;
+            created.push(class.name);
+
+            tokio::time::sleep(CLASS_DELAY).await;
+        }
+
+        Ok(created)
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context as SContext;
+use serenity::http::CacheHttp;
+use serenity::model::id::{GuildId, RoleId};
+use tokio::sync::OnceCell;
+
+use crate::classes::{Class, Server};
+use crate::jobs::{Job, JobKind, JobStatus};
+use crate::history::{self, EnrollmentMechanism};
+use crate::scheduler::parse_time;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, ClassError, ClassResult, Context, Error, ENV};
+
+/// How many members have their roles removed at once during a rollover.
+const BATCH_SIZE: usize = 10;
+/// How long to wait between batches, to stay well clear of Discord's rate limits.
+const BATCH_DELAY: Duration = Duration::from_secs(2);
+
+/// An academic term. Enrollments are stamped with the server's active term, and when a term ends
+/// class roles can be stripped from everyone so members re-enroll for the next one.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Term {
+    server_id: GuildId,
+    pub(crate) name: String,
+    ends_at: DateTime,
+    expire_roles: bool,
+    closed: bool,
+}
+
+impl Term {
+    pub(crate) async fn active(server_id: GuildId) -> ClassResult<Option<Term>> {
+        Self::scoped(server_id).await
+            .find_one(doc! { "closed": false }, None)
+            .await
+    }
+
+    /// Remove every class role from every member of the server, in rate-limited batches. Returns
+    /// how many members had roles removed. Members who already lost their roles are skipped, so a
+    /// rollover interrupted partway through can be run again.
+    pub(crate) async fn expire_roles(ctx: &SContext, server_id: GuildId, status: &JobStatus) -> ClassResult<usize> {
+        let class_roles = Class::list(server_id).await?
+            .into_iter()
+            .map(|c| c.role)
+            .collect::<HashSet<_>>();
+        let members = ctx.cache
+            .guild_field(server_id, |g| g.members.values().cloned().collect::<Vec<_>>())
+            .ok_or(ClassError::NotCached)?;
+
+        let enrolled = members.into_iter()
+            .map(|m| {
+                let roles = m.roles.iter()
+                    .filter(|r| class_roles.contains(r))
+                    .copied()
+                    .collect::<Vec<RoleId>>();
+                (m, roles)
+            })
+            .filter(|(_, roles)| !roles.is_empty())
+            .collect::<Vec<_>>();
+
+        for (i, batch) in enrolled.chunks(BATCH_SIZE).enumerate() {
+            for (member, roles) in batch {
+                let mut member = member.clone();
+                member.remove_roles(ctx.http(), roles).await?;
+
+                history::enrolled(
+                    server_id,
+                    member.user.id,
+                    ctx.cache.current_user_id(),
+                    EnrollmentMechanism::TermExpiry,
+                    Vec::new(),
+                    roles.clone(),
+                ).await;
+            }
+            status.progress(ctx, &format!("Removed roles from {} of {} members.", i * BATCH_SIZE + batch.len(), enrolled.len())).await?;
+            tokio::time::sleep(BATCH_DELAY).await;
+        }
+
+        Ok(enrolled.len())
+    }
+
+    /// Close the term, queueing its rollover as a background job as it can take a while.
+    async fn close(&self, ctx: &SContext) -> ClassResult<()> {
+        if self.expire_roles {
+            let log_channel = Server::get_or_create(self.server_id).await?.log_channel;
+            Job::enqueue(ctx, self.server_id, None, JobKind::Rollover { term: self.name.clone() }, log_channel).await?;
+        }
+
+        Self::scoped(self.server_id).await
+            .update_one(
+                doc! { "name": &self.name },
+                doc! { "$set": { "closed": true } },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static TERMS: OnceCell<Collection<Term>> = OnceCell::const_new();
+
+        TERMS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("terms")
+            })
+            .await
+            .clone()
+    }
+}
+
+/// Close every term that has ended, running its rollover.
+pub(crate) async fn tick(ctx: &SContext) -> ClassResult<()> {
+    let ended = Term::get_collection().await
+        .find(doc! { "closed": false, "ends_at": { "$lte": DateTime::now() } }, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    for term in ended {
+        term.close(ctx).await?;
+    }
+
+    Ok(())
+}
+
+#[poise::command(
+    slash_command,
+    subcommands("TermCommand::start", "TermCommand::end", "TermCommand::info")
+)]
+pub(crate) async fn term(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct TermCommand;
+impl TermCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn start(
+        ctx: Context<'_>,
+        name: String,
+        ends: String,
+        expire_roles: Option<bool>,
+    ) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server_id = ctx.guild_id().ok_or(ClassError::NoServer)?;
+        let ends_at = parse_time(&ends)
+            .filter(|t| *t > Utc::now())
+            .ok_or(ClassError::InvalidTime)?;
+        if Term::active(server_id).await?.is_some() {
+            Err(ClassError::TermActive)?;
+        }
+
+        let term = Term {
+            server_id,
+            name: name.trim().to_string(),
+            ends_at: DateTime::from_millis(ends_at.timestamp_millis()),
+            expire_roles: expire_roles.unwrap_or(false),
+            closed: false,
+        };
+        Term::get_collection().await.insert_one(&term, None).await?;
+
+        ctx.say(format!(
+            "Started term \"{}\", ending <t:{}:f>.{}",
+            term.name,
+            ends_at.timestamp(),
+            if term.expire_roles { " Class roles will be removed from all members when it ends." } else { "" },
+        )).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+        required_permissions = "MANAGE_GUILD",
+    )]
+    async fn end(ctx: Context<'_>) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let term = Term::active(ctx.guild_id().ok_or(ClassError::NoServer)?).await?
+            .ok_or(ClassError::NoTerm)?;
+
+        // Leave the rollover to the scheduler, as removing roles from every member can take a while
+        Term::scoped(term.server_id).await
+            .update_one(
+                doc! { "name": &term.name },
+                doc! { "$set": { "ends_at": DateTime::now() } },
+                None,
+            )
+            .await?;
+
+        ctx.say(format!("Term \"{}\" will be closed within a minute.", term.name)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn info(ctx: Context<'_>) -> Result<(), Error> {
+        let term = Term::active(ctx.guild_id().ok_or(ClassError::NoServer)?).await?
+            .ok_or(ClassError::NoTerm)?;
+
+        ctx.say(format!(
+            "The current term is \"{}\", ending <t:{}:f>.",
+            term.name,
+            term.ends_at.timestamp_millis() / 1000,
+        )).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+use serenity::model::id::UserId;
+
+use super::harness::{role_id, server_id, with_database};
+use crate::announcements::digest_messages;
+use crate::notifications::{announcement_recipients, set_announcement_mode, AnnouncementMode};
+
+#[test]
+fn digests_are_split_to_fit_messages() {
+    let lines = (0..30).map(|i| format!("{}: {}", i, "x".repeat(150))).collect::<Vec<_>>();
+    let messages = digest_messages(lines);
+
+    assert!(messages.len() > 1);
+    assert!(messages.iter().all(|m| m.len() <= 2000));
+    assert!(messages[0].starts_with("Announcements"));
+    assert!(messages.last().unwrap().contains("29: "));
+}
+
+#[test]
+fn announcements_follow_each_members_choice() {
+    with_database(async {
+        let (server, role) = (server_id(), role_id());
+        let (pinged, digested, silenced, muted_then_digest) = (UserId(1), UserId(2), UserId(3), UserId(4));
+
+        set_announcement_mode(server, digested, role, AnnouncementMode::Digest).await.unwrap();
+        set_announcement_mode(server, silenced, role, AnnouncementMode::Silent).await.unwrap();
+        set_announcement_mode(server, muted_then_digest, role, AnnouncementMode::Silent).await.unwrap();
+        set_announcement_mode(server, muted_then_digest, role, AnnouncementMode::Digest).await.unwrap();
+
+        let (immediate, digest) = announcement_recipients(role, [pinged, digested, silenced, muted_then_digest])
+            .await
+            .unwrap();
+        assert_eq!(immediate, vec![pinged]);
+        assert_eq!(digest, vec![digested, muted_then_digest]);
+    });
+}
@@ -0,0 +1,32 @@
+use serenity::model::id::{GuildId, RoleId};
+
+use super::harness::class;
+use crate::badges::{badged_nickname, strip_badges, StaffBadge};
+
+#[test]
+fn badges_go_after_the_name() {
+    let badge = StaffBadge { role: RoleId(1), label: "TA".to_string() };
+    let badges = [badge.text(&class(GuildId(1), "CS 341"))];
+    assert_eq!(badges[0], "[TA CS341]");
+    assert_eq!(badged_nickname("Alice", &badges), "Alice [TA CS341]");
+    assert_eq!(strip_badges("Alice [TA CS341]", &badges), "Alice");
+}
+
+#[test]
+fn badges_respect_the_nickname_limit() {
+    let badges = ["[Instructor CS341]".to_string(), "[TA CS225]".to_string()];
+    // Only the second badge fits
+    let nickname = badged_nickname("Alexandra Smith", &badges);
+    assert_eq!(nickname, "Alexandra Smith [TA CS225]");
+    assert!(nickname.chars().count() <= 32);
+
+    let long = "A".repeat(30);
+    assert_eq!(badged_nickname(&long, &badges), long);
+}
+
+#[test]
+fn stripping_only_removes_known_badges() {
+    let badges = ["[TA CS341]".to_string(), "[TA CS225]".to_string()];
+    assert_eq!(strip_badges("Bob [TA CS225] [TA CS341]", &badges), "Bob");
+    assert_eq!(strip_badges("Bob [he/him]", &badges), "Bob [he/him]");
+}
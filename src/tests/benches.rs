@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use criterion::{BenchmarkId, Criterion};
+use itertools::Itertools;
+use serenity::model::id::{GuildId, RoleId};
+
+use super::harness::{class, server};
+use crate::classes::Class;
+use crate::{class_menu_options, class_menu_rows, menu_changes};
+
+/// Class counts from a small server up to a whole university's catalog.
+const SIZES: [usize; 3] = [10, 100, 1000];
+const DEPARTMENTS: [&str; 5] = ["CS", "MATH", "PHYS", "ECE", "STAT"];
+
+fn criterion() -> Criterion {
+    Criterion::default()
+        .warm_up_time(Duration::from_millis(500))
+        .measurement_time(Duration::from_secs(2))
+}
+
+/// A catalog of classes spread across departments, in no particular order.
+fn catalog(size: usize) -> Vec<Class> {
+    (0..size)
+        .map(|i| Class {
+            role: RoleId(i as u64 + 1),
+            description: Some(format!("Class number {} in the catalog", i)),
+            ..class(GuildId(1), &format!("{} {}", DEPARTMENTS[i % DEPARTMENTS.len()], (i * 7919) % 1000 + 100))
+        })
+        .collect()
+}
+
+/// A member holding every tenth class, with a few more favorited.
+fn member(classes: &[Class]) -> (HashSet<RoleId>, Vec<RoleId>) {
+    let held = classes.iter().step_by(10).map(|c| c.role).collect();
+    let favorites = classes.iter().skip(5).step_by(20).map(|c| c.role).collect();
+    (held, favorites)
+}
+
+#[test]
+#[ignore]
+fn menu_construction() {
+    let mut c = criterion();
+    let mut group = c.benchmark_group("menu_construction");
+    let server = server(GuildId(1));
+
+    for size in SIZES {
+        let classes = catalog(size);
+        let (held, favorites) = member(&classes);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &classes, |b, classes| {
+            b.iter(|| class_menu_rows(class_menu_options(classes, &held, &favorites, None, None), &server))
+        });
+    }
+
+    group.finish();
+}
+
+#[test]
+#[ignore]
+fn class_list_sorting() {
+    let mut c = criterion();
+    let mut group = c.benchmark_group("class_list_sorting");
+
+    for size in SIZES {
+        let classes = catalog(size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &classes, |b, classes| {
+            b.iter(|| classes.iter()
+                .sorted_by(|c1, c2| human_sort::compare(&c1.name, &c2.name))
+                .map(|c| c.role)
+                .collect::<Vec<_>>()
+            )
+        });
+    }
+
+    group.finish();
+}
+
+#[test]
+#[ignore]
+fn role_diffing() {
+    let mut c = criterion();
+    let mut group = c.benchmark_group("role_diffing");
+
+    for size in SIZES {
+        let classes = catalog(size);
+        let (held, _) = member(&classes);
+        let menu = classes.iter().map(|c| c.role).collect::<HashSet<_>>();
+        // Keep the held classes and pick up as many new ones
+        let selected = classes.iter().step_by(5).map(|c| c.role).collect::<HashSet<_>>();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &menu, |b, menu| {
+            b.iter(|| menu_changes(menu, &held, &selected))
+        });
+    }
+
+    group.finish();
+}
@@ -0,0 +1,65 @@
+use chrono::Utc;
+use mongodb::bson::DateTime;
+
+use super::harness::{server_id, with_database};
+use crate::calendar::{on_break, parse_break_time, CalendarBreak};
+use crate::classes::Server;
+use crate::{scheduler, ClassError};
+
+fn utc(time: &str) -> chrono::DateTime<Utc> {
+    scheduler::parse_time(time).unwrap()
+}
+
+fn millis(time: &str) -> DateTime {
+    DateTime::from_millis(utc(time).timestamp_millis())
+}
+
+#[test]
+fn break_dates_cover_whole_days() {
+    let starts = parse_break_time("2024-12-23", false).unwrap();
+    let ends = parse_break_time("2025-01-03", true).unwrap();
+    assert_eq!(starts, utc("2024-12-23 00:00"));
+    assert_eq!(ends, utc("2025-01-04 00:00"));
+
+    let calendar_break = CalendarBreak {
+        name: "Winter break".to_string(),
+        starts: DateTime::from_millis(starts.timestamp_millis()),
+        ends: DateTime::from_millis(ends.timestamp_millis()),
+    };
+    assert!(calendar_break.contains(millis("2025-01-03 23:59")));
+    assert!(!calendar_break.contains(millis("2025-01-04 00:00")));
+    assert!(!calendar_break.contains(millis("2024-12-22 23:59")));
+}
+
+#[test]
+fn break_times_fall_back_to_scheduler_times() {
+    assert_eq!(
+        parse_break_time("2024-12-23 12:30", true),
+        Some(utc("2024-12-23 12:30")),
+    );
+    assert_eq!(parse_break_time("not a date", false), None);
+}
+
+#[test]
+fn breaks_are_stored_per_server() {
+    with_database(async {
+        let id = server_id();
+        let mut server = Server::get_or_create(id).await.unwrap();
+        let now = Utc::now().timestamp_millis();
+        let current = CalendarBreak {
+            name: "Reading week".to_string(),
+            starts: DateTime::from_millis(now - 60_000),
+            ends: DateTime::from_millis(now + 60_000),
+        };
+
+        assert!(!on_break(id).await.unwrap());
+        server.add_break(current.clone()).await.unwrap();
+        assert!(matches!(server.add_break(current).await, Err(ClassError::BreakExists)));
+        assert!(on_break(id).await.unwrap());
+        assert!(!on_break(server_id()).await.unwrap());
+
+        server.remove_break("reading week").await.unwrap();
+        assert!(!on_break(id).await.unwrap());
+        assert!(matches!(server.remove_break("Reading week").await, Err(ClassError::InvalidBreak)));
+    });
+}
@@ -0,0 +1,58 @@
+use serde_json::json;
+use serenity::model::id::GuildId;
+
+use crate::courses::{course_for, parse_courses, title_drifted, CatalogSource};
+use crate::ClassError;
+
+fn source() -> CatalogSource {
+    CatalogSource {
+        url: "https://catalog.example.edu/courses.json".to_string(),
+        courses_path: "data.courses".to_string(),
+        code_field: "code".to_string(),
+        title_field: "info.title".to_string(),
+        description_field: Some("info.description".to_string()),
+        department_field: None,
+    }
+}
+
+#[test]
+fn catalogs_are_parsed_from_configured_fields() {
+    let response = json!({ "data": { "courses": [
+        { "code": "CS 341", "info": { "title": "Systems Programming", "description": "Processes and memory" } },
+        { "code": "CS 34", "info": { "title": "Intro" } },
+        { "code": "MATH 221", "info": { "title": "Calculus I" } },
+        { "code": "", "info": { "title": "No code" } },
+        { "info": { "title": "Missing code" } },
+    ] } });
+
+    let courses = parse_courses(GuildId(1), &source(), &response).unwrap();
+    assert_eq!(courses.len(), 3);
+    assert_eq!(courses[0].title, "Systems Programming");
+    assert_eq!(courses[0].description.as_deref(), Some("Processes and memory"));
+    assert_eq!(courses[2].department, "MATH");
+
+    assert!(matches!(
+        parse_courses(GuildId(1), &source(), &json!({ "courses": [] })),
+        Err(ClassError::InvalidCatalog),
+    ));
+}
+
+#[test]
+fn classes_are_matched_by_course_code() {
+    let response = json!({ "data": { "courses": [
+        { "code": "CS 341", "info": { "title": "Systems Programming" } },
+        { "code": "CS 34", "info": { "title": "Intro" } },
+    ] } });
+    let courses = parse_courses(GuildId(1), &source(), &response).unwrap();
+
+    assert_eq!(course_for("CS 341", &courses).map(|c| c.code.as_str()), Some("CS 341"));
+    assert_eq!(course_for("cs341 - Systems", &courses).map(|c| c.code.as_str()), Some("CS 341"));
+    assert_eq!(course_for("CS 34", &courses).map(|c| c.code.as_str()), Some("CS 34"));
+    assert!(course_for("CS 3410", &courses).is_none());
+    assert!(course_for("MATH 221", &courses).is_none());
+
+    let course = &courses[0];
+    assert!(!title_drifted("CS 341", course));
+    assert!(!title_drifted("CS 341: Systems Programming", course));
+    assert!(title_drifted("CS 341 Systems Prog", course));
+}
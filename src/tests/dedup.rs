@@ -0,0 +1,21 @@
+use serenity::model::id::ChannelId;
+
+use crate::helpthreads::possibly_related;
+
+#[test]
+fn related_questions_are_ranked_and_limited() {
+    let earlier = [
+        (ChannelId(1), "Segfault in malloc lab\nMy mm_malloc segfaults when the heap grows".to_string()),
+        (ChannelId(2), "Question about the midterm room".to_string()),
+        (ChannelId(3), "malloc lab segfaults\nmm_malloc segfaults when the heap grows past one page".to_string()),
+        (ChannelId(4), "Malloc lab heap question\nHow big should the heap start".to_string()),
+    ];
+
+    let related = possibly_related("Segfault in malloc lab\nmm_malloc segfaults when heap grows", &earlier, 0.5);
+    assert_eq!(related.first(), Some(&ChannelId(1)));
+    assert!(related.contains(&ChannelId(3)));
+    assert!(!related.contains(&ChannelId(2)));
+    assert!(related.len() <= 3);
+
+    assert!(possibly_related("Where is office hours today?", &earlier, 0.5).is_empty());
+}
@@ -0,0 +1,74 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serenity::http::{Http, HttpBuilder};
+
+/// A stand-in for Discord's API that records each request, answering with an empty success
+/// unless the path contains one of the `fail` strings or the request is rate limited.
+pub(super) struct MockDiscord {
+    pub(super) http: Http,
+    requests: Arc<Mutex<Vec<(Method, String)>>>,
+}
+
+impl MockDiscord {
+    pub(super) async fn start(fail: &[String]) -> Self {
+        Self::serve(fail, 0).await
+    }
+
+    /// Answer the first `times` requests with a 429 that has no `retry-after`.
+    pub(super) async fn rate_limited(times: usize) -> Self {
+        Self::serve(&[], times).await
+    }
+
+    async fn serve(fail: &[String], rate_limited: usize) -> Self {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let fail = Arc::new(fail.to_vec());
+        let rate_limited = Arc::new(AtomicUsize::new(rate_limited));
+
+        let recorded = requests.clone();
+        let make_service = make_service_fn(move |_| {
+            let recorded = recorded.clone();
+            let fail = fail.clone();
+            let rate_limited = rate_limited.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let path = request.uri().path().to_string();
+                    recorded.lock().unwrap().push((request.method().clone(), path.clone()));
+                    let limited = rate_limited.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+                    let (status, body) = if limited.is_ok() {
+                        (StatusCode::TOO_MANY_REQUESTS, r#"{"code": 0, "message": "You are being rate limited."}"#)
+                    } else if fail.iter().any(|f| path.contains(f.as_str())) {
+                        (StatusCode::FORBIDDEN, r#"{"code": 50013, "message": "Missing Permissions"}"#)
+                    } else {
+                        (StatusCode::NO_CONTENT, "")
+                    };
+                    let body = if status == StatusCode::NO_CONTENT { Body::empty() } else { Body::from(body) };
+                    async move {
+                        Ok::<_, Infallible>(Response::builder().status(status).body(body).unwrap())
+                    }
+                }))
+            }
+        });
+
+        let server = hyper::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        let http = HttpBuilder::new("test")
+            .proxy(format!("http://{}", address))
+            .unwrap()
+            .ratelimiter_disabled(true)
+            .build();
+
+        Self { http, requests }
+    }
+
+    /// The method and path of every request made so far.
+    pub(super) fn requests(&self) -> Vec<(Method, String)> {
+        self.requests.lock().unwrap().clone()
+    }
+}
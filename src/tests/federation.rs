@@ -0,0 +1,12 @@
+use super::harness::{server, server_id};
+
+#[test]
+fn hubs_must_allow_members() {
+    let (hub, member) = (server_id(), server_id());
+    let mut hub_server = server(hub);
+    assert!(!hub_server.allows_federation(member));
+
+    hub_server.federation_members.push(member);
+    assert!(hub_server.allows_federation(member));
+    assert!(!hub_server.allows_federation(server_id()));
+}
@@ -0,0 +1,23 @@
+use hyper::Body;
+
+use super::harness::run;
+use crate::grader::{read_limited, RelayError, MAX_BODY};
+
+#[test]
+fn bodies_are_capped() {
+    run(async {
+        let body = read_limited(Body::from(vec![b'a'; MAX_BODY]), MAX_BODY).await;
+        assert!(matches!(body, Ok(b) if b.len() == MAX_BODY));
+
+        // Streamed in chunks, as a body without a Content-Length would be
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..=MAX_BODY / 1024 {
+                if sender.send_data(vec![b'a'; 1024].into()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        assert!(matches!(read_limited(body, MAX_BODY).await, Err(RelayError::TooLarge)));
+    });
+}
@@ -0,0 +1,15 @@
+use serenity::model::id::UserId;
+
+use crate::grants::parse_users;
+
+#[test]
+fn user_ids_are_read_from_any_column() {
+    let csv = "name,discord_id\r\nAda,123\r\n\"Lovelace, Ada\",<@!456>\r\nGrace,123\n\nAlan;789";
+
+    assert_eq!(parse_users(csv), vec![UserId(123), UserId(456), UserId(789)]);
+}
+
+#[test]
+fn files_without_user_ids_are_empty() {
+    assert!(parse_users("name,email\nAda,ada@example.com\n").is_empty());
+}
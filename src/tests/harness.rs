@@ -0,0 +1,140 @@
+use std::future::Future;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use lazy_static::lazy_static;
+use mongodb::bson::doc;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use testcontainers::core::{IntoContainerPort, WaitFor};
+use testcontainers::runners::AsyncRunner;
+use testcontainers::{ContainerAsync, GenericImage};
+use tokio::runtime::Runtime;
+use tokio::sync::OnceCell;
+
+use crate::classes::{Class, Server};
+use crate::migrations;
+use crate::visibility::Visibility;
+
+lazy_static! {
+    /// Shared by every test, as the Mongo client and cached collections outlive any one test.
+    static ref RUNTIME: Runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+}
+
+static MONGO: OnceCell<Option<ContainerAsync<GenericImage>>> = OnceCell::const_new();
+static DATABASE: OnceCell<bool> = OnceCell::const_new();
+
+/// Run a test that doesn't need the database.
+pub(super) fn run(test: impl Future<Output = ()>) {
+    RUNTIME.block_on(test);
+}
+
+/// Run a test against a fresh, migrated database shared by all tests, or skip it if no database
+/// is available. Tests should only touch servers and classes made with `server_id` and
+/// `role_id`, so they can run at the same time.
+pub(super) fn with_database(test: impl Future<Output = ()>) {
+    RUNTIME.block_on(async {
+        if *DATABASE.get_or_init(setup).await {
+            test.await;
+        } else {
+            eprintln!("Skipping: no MongoDB available. Set TEST_MONGODB_URI or start Docker.");
+        }
+    });
+}
+
+async fn setup() -> bool {
+    let uri = match std::env::var("TEST_MONGODB_URI") {
+        Ok(uri) => uri,
+        Err(_) => match start_mongo().await {
+            Some(uri) => uri,
+            None => return false,
+        },
+    };
+
+    // The environment has to be in place before anything reads `ENV`
+    std::env::set_var("DEV_MODE", "false");
+    std::env::set_var("BOT_TOKEN", "test");
+    std::env::set_var("GUILD_ID", "1");
+    std::env::set_var("MONGODB_NAME", format!("cs_discord_test_{}", rand::random::<u32>()));
+    std::env::set_var("MONGODB_USER", "test");
+    std::env::set_var("MONGODB_PASSWORD", "test");
+    std::env::set_var("MONGODB_URI", uri);
+    std::env::set_var("SECRET_KEY", BASE64.encode(rand::random::<[u8; 32]>()));
+
+    migrations::run().await.expect("Failed to migrate the test database");
+    true
+}
+
+async fn start_mongo() -> Option<String> {
+    let container = MONGO
+        .get_or_init(|| async {
+            GenericImage::new("mongo", "7")
+                .with_exposed_port(27017.tcp())
+                .with_wait_for(WaitFor::message_on_stdout("Waiting for connections"))
+                .start()
+                .await
+                .map_err(|e| eprintln!("Could not start a MongoDB container: {}", e))
+                .ok()
+        })
+        .await
+        .as_ref()?;
+
+    let host = container.get_host().await.ok()?;
+    let port = container.get_host_port_ipv4(27017).await.ok()?;
+    Some(format!("mongodb://{}:{}", host, port))
+}
+
+/// A server ID no other test uses.
+pub(super) fn server_id() -> GuildId {
+    GuildId(rand::random::<u64>() >> 1)
+}
+
+/// A role or channel ID no other test uses.
+pub(super) fn role_id() -> RoleId {
+    RoleId(rand::random::<u64>() >> 1)
+}
+
+pub(super) fn channel_id() -> ChannelId {
+    ChannelId(rand::random::<u64>() >> 1)
+}
+
+/// A class as `Class::track` would store it, without touching Discord.
+pub(super) fn class(server_id: GuildId, name: &str) -> Class {
+    Class {
+        server_id,
+        name: name.to_string(),
+        short_name: name.split_whitespace().collect::<String>().to_lowercase(),
+        role: role_id(),
+        categories: vec![channel_id()],
+        text_channels: vec![channel_id()],
+        voice_channels: vec![channel_id()],
+        staff_role: None,
+        tags: Vec::new(),
+        archive_messages: false,
+        mentor_role: None,
+        description: None,
+        webhook: None,
+        grader_token: None,
+        grader_dm_template: None,
+        grader_summary_template: None,
+        assignment_board: None,
+        canvas: None,
+        visibility: Visibility::default(),
+        emoji: None,
+        badges: Vec::new(),
+        managers: Vec::new(),
+        manager_roles: Vec::new(),
+        version: 0,
+    }
+}
+
+/// A server with default settings, as `Server::get_or_create` would first store it.
+pub(super) fn server(server_id: GuildId) -> Server {
+    mongodb::bson::from_document(doc! {
+        "server_id": server_id.to_string(),
+        "admin_roles": [],
+        "refrole": null,
+    }).unwrap()
+}
@@ -0,0 +1,23 @@
+use crate::help::{topic, TOPICS};
+use crate::ClassError;
+
+#[test]
+fn refrole_errors_link_to_their_fix() {
+    for error in [ClassError::NoRefrole, ClassError::InvalidRefrole] {
+        let topic = error.remediation().expect("refrole errors should have a fix");
+        assert_eq!(topic.id, "refrole");
+        assert!(topic.body.contains("/config refrole set"));
+    }
+    assert!(ClassError::InvalidClass.remediation().is_none());
+}
+
+#[test]
+fn help_topics_fit_in_discord() {
+    for t in &TOPICS {
+        assert_eq!(topic(t.id).map(|f| f.title), Some(t.title));
+        // Button labels can be at most 80 characters, and custom IDs 100
+        assert!(format!("How to fix: {}", t.title).len() <= 80);
+        assert!(format!("help:{}", t.id).len() <= 100);
+        assert!(t.body.len() <= 4096);
+    }
+}
@@ -0,0 +1,40 @@
+use std::collections::HashSet;
+
+fn valid_name(name: &str) -> bool {
+    (1..=32).contains(&name.chars().count())
+        && name.chars().all(|c| c == '-' || c == '_' || c.is_alphanumeric())
+        && name.to_lowercase() == name
+}
+
+fn check(command: &poise::Command<crate::Data, crate::Error>) {
+    for name in command.name_localizations.values() {
+        assert!(valid_name(name), "invalid localized name {:?} for {}", name, command.name);
+    }
+    for parameter in &command.parameters {
+        let description = parameter.description.as_deref()
+            .unwrap_or_else(|| panic!("{} {} has no description", command.qualified_name, parameter.name));
+        assert!(description.chars().count() <= 100);
+        for description in parameter.description_localizations.values() {
+            assert!(description.chars().count() <= 100);
+        }
+        for name in parameter.name_localizations.values() {
+            assert!(valid_name(name), "invalid localized name {:?} for {}", name, parameter.name);
+        }
+    }
+    // Parameters of the same command can't share a name in any language
+    let locales = command.parameters.iter().flat_map(|p| p.name_localizations.keys()).collect::<HashSet<_>>();
+    for locale in locales {
+        let names = command.parameters.iter()
+            .map(|p| p.name_localizations.get(locale).unwrap_or(&p.name))
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), names.iter().collect::<HashSet<_>>().len(), "{} in {}", command.qualified_name, locale);
+    }
+    command.subcommands.iter().for_each(check);
+}
+
+#[test]
+fn slash_command_parameters_are_described() {
+    for command in crate::commands().iter().filter(|c| c.slash_action.is_some() || !c.subcommands.is_empty()) {
+        check(command);
+    }
+}
@@ -0,0 +1,22 @@
+use mongodb::bson;
+use serenity::model::id::{RoleId, UserId};
+
+use crate::jobs::JobKind;
+use crate::ordering::CategoryOrder;
+
+#[test]
+fn job_kinds_survive_storage() {
+    let kinds = [
+        JobKind::OrderCategories { by: CategoryOrder::Department },
+        JobKind::TransferRole { class: RoleId(1), role: RoleId(2), migrate_members: true },
+        JobKind::Rollover { term: "Fall 2024".to_string() },
+        JobKind::GrantRole { role: RoleId(3), users: vec![UserId(4), UserId(5)], granted_by: UserId(6) },
+    ];
+
+    for kind in kinds {
+        let stored = bson::to_document(&kind).unwrap();
+        let loaded = bson::from_document::<JobKind>(stored).unwrap();
+        assert_eq!(loaded.describe(), kind.describe());
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", kind));
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use super::harness::run;
+use crate::latency::{measure, metrics, Breakdown, DiscordTimer, SLOW};
+
+#[test]
+fn slow_paths_are_broken_down() {
+    let fast = Breakdown { total: SLOW - Duration::from_millis(1), mongo: Duration::ZERO, discord: Duration::ZERO };
+    assert!(fast.slow_path("class list").is_none());
+
+    let slow = Breakdown {
+        total: Duration::from_millis(2500),
+        mongo: Duration::from_millis(1200),
+        discord: Duration::from_millis(800),
+    };
+    let line = serde_json::to_value(slow.slow_path("class list").unwrap()).unwrap();
+    assert_eq!(line, serde_json::json!({
+        "event": "slow_interaction",
+        "interaction": "class list",
+        "total_ms": 2500,
+        "mongo_ms": 1200,
+        "discord_ms": 800,
+        "other_ms": 500,
+    }));
+
+    // Concurrent work can add up to more than the total
+    let concurrent = Breakdown { mongo: Duration::from_millis(2000), ..slow };
+    assert_eq!(serde_json::to_value(concurrent.slow_path("x").unwrap()).unwrap()["other_ms"], 0);
+}
+
+#[test]
+fn tasks_are_timed() {
+    run(async {
+        let before = metrics().timed;
+        tokio::spawn(measure("test", async {})).await.unwrap();
+        assert!(metrics().timed > before);
+    });
+}
+
+#[test]
+fn other_tracing_stays_enabled() {
+    tracing::subscriber::with_default(DiscordTimer, || {
+        assert!(tracing::enabled!(tracing::Level::INFO));
+        assert!(!tracing::info_span!("not_a_request").is_disabled());
+    });
+}
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use serde_json::json;
+use serenity::cache::Cache;
+use serenity::model::channel::{Channel, GuildChannel};
+use serenity::model::guild::PartialGuild;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+
+use super::discord::MockDiscord;
+use super::harness::run;
+use crate::lookup::{self, guild_from_partial};
+
+#[test]
+fn uncached_channels_are_fetched_once() {
+    run(async {
+        let discord = MockDiscord::start(&["/channels/".to_string()]).await;
+        let cache = Arc::new(Cache::new());
+
+        assert!(lookup::channel((&cache, &discord.http), ChannelId(5)).await.unwrap().is_none());
+        assert!(lookup::channel((&cache, &discord.http), ChannelId(5)).await.unwrap().is_none());
+        assert_eq!(discord.requests().len(), 1);
+        assert!(discord.requests()[0].1.ends_with("/channels/5"));
+
+        lookup::channel((&cache, &discord.http), ChannelId(6)).await.unwrap();
+        assert_eq!(discord.requests().len(), 2);
+    });
+}
+
+#[test]
+fn fetched_guilds_become_full_guilds() {
+    let partial = serde_json::from_value::<PartialGuild>(json!({
+        "id": "1",
+        "name": "CS",
+        "owner_id": "2",
+        "afk_channel_id": null,
+        "afk_timeout": 300,
+        "default_message_notifications": 0,
+        "emojis": [],
+        "features": [],
+        "icon": null,
+        "mfa_level": 0,
+        "roles": [{
+            "id": "3",
+            "name": "CS 101",
+            "color": 0,
+            "hoist": false,
+            "managed": false,
+            "mentionable": false,
+            "permissions": "0",
+            "position": 1,
+        }],
+        "splash": null,
+        "discovery_splash": null,
+        "system_channel_id": null,
+        "system_channel_flags": 0,
+        "rules_channel_id": null,
+        "public_updates_channel_id": null,
+        "verification_level": 0,
+        "description": null,
+        "premium_subscription_count": 0,
+        "banner": null,
+        "vanity_url_code": null,
+        "nsfw_level": 0,
+        "stickers": [],
+    })).unwrap();
+    let channel = |id: &str, kind: u8, parent: Option<&str>| serde_json::from_value::<GuildChannel>(json!({
+        "id": id,
+        "guild_id": "1",
+        "type": kind,
+        "name": "cs-101",
+        "position": 0,
+        "permission_overwrites": [],
+        "parent_id": parent,
+    })).unwrap();
+
+    let guild = guild_from_partial(partial, vec![channel("4", 4, None), channel("5", 0, Some("4"))]).unwrap();
+    assert_eq!(guild.id, GuildId(1));
+    assert_eq!(guild.roles[&RoleId(3)].name, "CS 101");
+    assert!(matches!(guild.channels[&ChannelId(4)], Channel::Category(_)));
+    assert!(matches!(&guild.channels[&ChannelId(5)], Channel::Guild(c) if c.parent_id == Some(ChannelId(4))));
+    assert!(guild.members.is_empty());
+}
@@ -0,0 +1,43 @@
+use serenity::model::id::UserId;
+use serenity::model::permissions::Permissions;
+
+use super::harness::{class, role_id, server_id, with_database};
+use crate::classes::Class;
+use crate::may_manage_class;
+
+#[test]
+fn managers_are_members_or_roles() {
+    let mut class = class(server_id(), "Systems Programming");
+    let (manager, ta_role) = (UserId(1), role_id());
+    class.managers = vec![manager];
+    class.manager_roles = vec![ta_role];
+
+    assert!(class.is_managed_by(manager, &[]));
+    assert!(class.is_managed_by(UserId(2), &[role_id(), ta_role]));
+    assert!(!class.is_managed_by(UserId(2), &[role_id()]));
+}
+
+#[test]
+fn managers_are_stored() {
+    with_database(async {
+        let mut class = class(server_id(), "Compilers").add_to_db().await.unwrap();
+        let role = role_id();
+        class.set_managers(vec![UserId(7)], vec![role]).await.unwrap();
+
+        let stored = Class::find_by_role(class.role).await.unwrap().unwrap();
+        assert_eq!(stored.managers, vec![UserId(7)]);
+        assert_eq!(stored.manager_roles, vec![role]);
+    });
+}
+
+#[test]
+fn class_staff_are_not_managers() {
+    let mut class = class(server_id(), "Operating Systems");
+    let (staff_role, manager) = (role_id(), UserId(1));
+    class.staff_role = Some(staff_role);
+    class.managers = vec![manager];
+
+    assert!(!may_manage_class(&class, Permissions::empty(), UserId(2), &[staff_role]));
+    assert!(may_manage_class(&class, Permissions::empty(), manager, &[]));
+    assert!(may_manage_class(&class, Permissions::MANAGE_GUILD, UserId(2), &[]));
+}
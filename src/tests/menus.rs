@@ -0,0 +1,213 @@
+use std::collections::HashSet;
+
+use hyper::Method;
+use proptest::prelude::*;
+use proptest::sample::subsequence;
+use serenity::model::id::{GuildId, RoleId, UserId};
+
+use super::discord::MockDiscord;
+use super::harness::{class, run};
+use crate::classes::{emoji_name, Class};
+use crate::departments::department_of;
+use crate::{class_menu_options, menu_changes, parse_class_button_id, rolequeue, MENU_OPTION_LIMIT};
+
+fn roles(ids: &[u64]) -> HashSet<RoleId> {
+    ids.iter().map(|id| RoleId(*id)).collect()
+}
+
+#[test]
+fn class_emoji_names_are_valid() {
+    assert_eq!(emoji_name("cs341"), "cs341");
+    assert_eq!(emoji_name("cs-341/2"), "cs3412");
+    assert_eq!(emoji_name("c"), "class_c");
+    assert_eq!(emoji_name(&"a".repeat(40)).len(), 32);
+}
+
+#[test]
+fn menu_changes_only_touch_classes_in_the_menu() {
+    let menu = roles(&[1, 2, 3]);
+    let member = roles(&[2, 3, 10]);
+    // 10 is a class from another menu, and 20 isn't in this menu so can't be picked from it
+    let selected = roles(&[1, 3, 20]);
+
+    let (add, remove) = menu_changes(&menu, &member, &selected);
+    assert_eq!(add, vec![RoleId(1)]);
+    assert_eq!(remove, vec![RoleId(2)]);
+}
+
+#[test]
+fn menu_changes_with_nothing_changed() {
+    let menu = roles(&[1, 2]);
+    let member = roles(&[1]);
+
+    assert_eq!(menu_changes(&menu, &member, &roles(&[1])), (vec![], vec![]));
+}
+
+#[test]
+fn menu_changes_clearing_a_menu() {
+    let menu = roles(&[1, 2, 3]);
+    let member = roles(&[1, 3]);
+
+    let (add, remove) = menu_changes(&menu, &member, &roles(&[]));
+    assert!(add.is_empty());
+    assert_eq!(remove, vec![RoleId(1), RoleId(3)]);
+}
+
+#[test]
+fn class_button_ids() {
+    assert_eq!(parse_class_button_id("class_menu_button_2_7"), Some((2, Some(7))));
+    assert_eq!(parse_class_button_id("class_menu_button_2"), Some((2, None)));
+    assert_eq!(parse_class_button_id("class_menu_button"), None);
+    assert_eq!(parse_class_button_id("class_menu_button_x_7"), None);
+}
+
+#[test]
+fn role_changes_are_sent_to_discord() {
+    run(async {
+        let discord = MockDiscord::start(&[]).await;
+
+        let applied = rolequeue::apply(
+            &discord.http, GuildId(1), UserId(2), &[RoleId(3)], &[RoleId(4)], "Class menu",
+        ).await;
+
+        assert!(applied.error.is_none());
+        assert_eq!(applied.added, vec![RoleId(3)]);
+        assert_eq!(applied.removed, vec![RoleId(4)]);
+        assert_eq!(discord.requests(), vec![
+            (Method::PUT, "/api/v10/guilds/1/members/2/roles/3".to_string()),
+            (Method::DELETE, "/api/v10/guilds/1/members/2/roles/4".to_string()),
+        ]);
+    });
+}
+
+#[test]
+fn role_changes_stop_at_the_first_failure() {
+    run(async {
+        let discord = MockDiscord::start(&["/roles/4".to_string()]).await;
+
+        let applied = rolequeue::apply(
+            &discord.http, GuildId(1), UserId(2), &[RoleId(3), RoleId(4), RoleId(5)], &[RoleId(6)], "Class menu",
+        ).await;
+
+        assert!(applied.error.is_some());
+        assert_eq!(applied.added, vec![RoleId(3)]);
+        assert!(applied.removed.is_empty());
+        assert_eq!(discord.requests().len(), 2);
+    });
+}
+
+#[test]
+fn rate_limited_role_changes_are_retried() {
+    run(async {
+        let discord = MockDiscord::rate_limited(1).await;
+
+        let applied = rolequeue::apply(&discord.http, GuildId(1), UserId(2), &[RoleId(3)], &[], "Class menu").await;
+
+        assert!(applied.error.is_none());
+        assert_eq!(applied.added, vec![RoleId(3)]);
+        assert_eq!(discord.requests(), vec![
+            (Method::PUT, "/api/v10/guilds/1/members/2/roles/3".to_string()),
+            (Method::PUT, "/api/v10/guilds/1/members/2/roles/3".to_string()),
+        ]);
+    });
+}
+
+/// Up to a few menus' worth of classes with distinct roles, some tagged "core".
+fn classes() -> impl Strategy<Value = Vec<Class>> {
+    prop::collection::vec(("[A-Z]{2,4} [0-9]{3}", any::<bool>()), 0..100).prop_map(|classes| {
+        classes.into_iter()
+            .enumerate()
+            .map(|(i, (name, core))| Class {
+                role: RoleId(i as u64 + 1),
+                tags: if core { vec!["core".to_string()] } else { Vec::new() },
+                ..class(GuildId(1), &name)
+            })
+            .collect()
+    })
+}
+
+/// Classes, along with some of their roles held by the member and some favorited.
+fn menu_inputs() -> impl Strategy<Value = (Vec<Class>, HashSet<RoleId>, Vec<RoleId>)> {
+    classes().prop_flat_map(|classes| {
+        let roles = classes.iter().map(|c| c.role).collect::<Vec<_>>();
+        let count = roles.len();
+        (
+            Just(classes),
+            subsequence(roles.clone(), 0..=count).prop_map(|r| r.into_iter().collect()),
+            subsequence(roles, 0..=count),
+        )
+    })
+}
+
+proptest! {
+    #[test]
+    fn class_menus_are_within_discords_limit((classes, member, favorites) in menu_inputs()) {
+        let menus = class_menu_options(&classes, &member, &favorites, None, None);
+
+        prop_assert!(menus.iter().all(|m| !m.is_empty() && m.len() <= MENU_OPTION_LIMIT));
+        prop_assert_eq!(menus.len(), classes.len().div_ceil(MENU_OPTION_LIMIT));
+    }
+
+    #[test]
+    fn class_menus_show_every_matching_class_once(
+        (classes, member, favorites) in menu_inputs(),
+        tagged in any::<bool>(),
+        department in prop::option::of("[A-Z]{2}"),
+    ) {
+        let tag = tagged.then_some("core");
+        let menus = class_menu_options(&classes, &member, &favorites, tag, department.as_deref());
+
+        let mut shown = menus.iter().flatten().map(|o| o.class.role).collect::<Vec<_>>();
+        shown.sort();
+        let expected = classes.iter()
+            .filter(|c| !tagged || c.tags.iter().any(|t| t == "core"))
+            .filter(|c| department.as_ref().map(|d| department_of(c) == *d).unwrap_or(true))
+            .map(|c| c.role)
+            .collect::<Vec<_>>();
+        prop_assert_eq!(shown, expected);
+    }
+
+    #[test]
+    fn class_menus_select_exactly_the_held_classes((classes, member, favorites) in menu_inputs()) {
+        let menus = class_menu_options(&classes, &member, &favorites, None, None);
+
+        for option in menus.iter().flatten() {
+            prop_assert_eq!(option.selected, member.contains(&option.class.role));
+        }
+    }
+
+    #[test]
+    fn class_menus_put_held_and_favorite_classes_first((classes, member, favorites) in menu_inputs()) {
+        let menus = class_menu_options(&classes, &member, &favorites, None, None);
+
+        let pinned = menus.iter()
+            .flatten()
+            .map(|o| member.contains(&o.class.role) || favorites.contains(&o.class.role))
+            .collect::<Vec<_>>();
+        prop_assert!(pinned.windows(2).all(|w| w[0] || !w[1]));
+    }
+
+    #[test]
+    fn menu_changes_never_touch_other_roles(
+        menu in prop::collection::hash_set(1..50u64, 0..25),
+        member in prop::collection::hash_set(1..100u64, 0..40),
+        selected in prop::collection::hash_set(1..100u64, 0..25),
+    ) {
+        let (menu, member, selected) = (
+            menu.into_iter().map(RoleId).collect::<HashSet<_>>(),
+            member.into_iter().map(RoleId).collect::<HashSet<_>>(),
+            selected.into_iter().map(RoleId).collect::<HashSet<_>>(),
+        );
+        let (add, remove) = menu_changes(&menu, &member, &selected);
+
+        // Only menu roles change, roles are only added if missing and only removed if held
+        prop_assert!(add.iter().chain(&remove).all(|r| menu.contains(r)));
+        prop_assert!(add.iter().all(|r| !member.contains(r) && selected.contains(r)));
+        prop_assert!(remove.iter().all(|r| member.contains(r) && !selected.contains(r)));
+
+        // Afterwards, the member holds exactly the selected menu roles, and everything else as before
+        let after = &(&member - &remove.iter().copied().collect()) | &add.iter().copied().collect();
+        prop_assert_eq!(&after & &menu, &selected & &menu);
+        prop_assert_eq!(&after - &menu, &member - &menu);
+    }
+}
@@ -0,0 +1,54 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::Collection;
+
+use super::harness::{channel_id, class, server_id, with_database};
+use crate::classes::{Class, Server};
+use crate::{get_conn, migrations, ENV};
+
+async fn collection(name: &str) -> Collection<Document> {
+    get_conn().await.database(&ENV.mongodb_name).collection(name)
+}
+
+#[test]
+fn migrations_are_applied_once() {
+    with_database(async {
+        migrations::run().await.unwrap();
+
+        let applied = collection("migrations").await
+            .find(None, None).await.unwrap()
+            .try_collect::<Vec<_>>().await.unwrap();
+        let mut names = applied.iter()
+            .map(|m| m.get_str("name").unwrap())
+            .collect::<Vec<_>>();
+        let count = names.len();
+        names.dedup();
+        assert_eq!(names.len(), count);
+        assert!(names.contains(&"class_categories"));
+    });
+}
+
+#[test]
+fn class_categories_migration_converts_old_classes() {
+    with_database(async {
+        let id = server_id();
+        Server::get_or_create(id).await.unwrap();
+        let category = channel_id();
+
+        // Store a class the way it was stored before it could have several categories
+        let old = class(id, "Algorithms");
+        let mut document = mongodb::bson::to_document(&old).unwrap();
+        document.remove("categories");
+        document.insert("category", category.to_string());
+        collection("classes").await.insert_one(document, None).await.unwrap();
+
+        collection("migrations").await
+            .delete_one(doc! { "name": "class_categories" }, None)
+            .await
+            .unwrap();
+        migrations::run().await.unwrap();
+
+        let migrated = Class::find_by_role(old.role).await.unwrap().unwrap();
+        assert_eq!(migrated.categories, vec![category]);
+    });
+}
@@ -0,0 +1,38 @@
+//! Integration tests. Storage tests need MongoDB: set `TEST_MONGODB_URI` to use a running server,
+//! or have Docker running to start a throwaway one. They are skipped if neither is available.
+//! Discord is replaced by a local server that records the requests the bot makes. Menu building
+//! and role diffing are also covered by property tests.
+//!
+//! The benchmarks in `benches` are ignored by default. Run them with
+//! `cargo test --release benches -- --ignored --nocapture`.
+
+mod announcements;
+mod badges;
+mod benches;
+mod calendar;
+mod courses;
+mod dedup;
+mod discord;
+mod federation;
+mod grader;
+mod grants;
+mod harness;
+mod help;
+mod i18n;
+mod jobs;
+mod latency;
+mod lookup;
+mod managers;
+mod menus;
+mod migrations;
+mod ordering;
+mod pins;
+mod policy;
+mod redact;
+mod scoping;
+mod secrets;
+mod storage;
+mod suggestions;
+mod tempgrants;
+mod versions;
+mod webhooks;
@@ -0,0 +1,35 @@
+use serenity::model::id::{ChannelId, GuildId};
+
+use super::harness::class;
+use crate::ordering::{category_order, CategoryOrder};
+
+#[test]
+fn categories_follow_the_chosen_order() {
+    let classes = ["MATH 221", "CS 341", "CS 1200", "Study hall", "BIO 101"]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let mut class = class(GuildId(1), name);
+            class.categories = vec![ChannelId(i as u64 + 1)];
+            class
+        })
+        .collect::<Vec<_>>();
+    let order = |by| category_order(&classes, by).into_iter().map(|c| c.0).collect::<Vec<_>>();
+
+    assert_eq!(order(CategoryOrder::Alphabetical), vec![5, 3, 2, 1, 4]);
+    assert_eq!(order(CategoryOrder::Department), vec![5, 2, 3, 1, 4]);
+    assert_eq!(order(CategoryOrder::CourseNumber), vec![5, 1, 2, 3, 4]);
+}
+
+#[test]
+fn overflow_categories_stay_with_their_class() {
+    let mut big = class(GuildId(1), "CS 341");
+    big.categories = vec![ChannelId(10), ChannelId(11)];
+    let mut small = class(GuildId(1), "CS 225");
+    small.categories = vec![ChannelId(20)];
+
+    assert_eq!(
+        category_order(&[big, small], CategoryOrder::CourseNumber),
+        vec![ChannelId(20), ChannelId(10), ChannelId(11)],
+    );
+}
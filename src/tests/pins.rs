@@ -0,0 +1,29 @@
+use serenity::model::id::{ChannelId, GuildId, MessageId};
+
+use crate::pins::{index_text, parse_message_link};
+
+#[test]
+fn message_links_are_parsed() {
+    let ids = Some((GuildId(1), ChannelId(22), MessageId(333)));
+    assert_eq!(parse_message_link("https://discord.com/channels/1/22/333"), ids);
+    assert_eq!(parse_message_link("<https://ptb.discord.com/channels/1/22/333>"), ids);
+    assert_eq!(parse_message_link("https://discordapp.com/channels/1/22/333"), ids);
+    assert_eq!(parse_message_link("https://discord.com/channels/1/22"), None);
+    assert_eq!(parse_message_link("https://discord.com/channels/1/22/333/4"), None);
+    assert_eq!(parse_message_link("https://discord.com/channels/@me/22/333"), None);
+    assert_eq!(parse_message_link("not a link"), None);
+}
+
+#[test]
+fn index_fits_in_an_embed() {
+    let pins = (0..500)
+        .map(|i| (MessageId(i), format!("Lecture {} slides and recording", i)))
+        .collect::<Vec<_>>();
+    let text = index_text(GuildId(1), ChannelId(2), &pins);
+    assert!(text.chars().count() <= 4096);
+    assert!(text.starts_with("• [Lecture 0 slides and recording](https://discord.com/channels/1/2/0)"));
+    assert!(text.ends_with("more"));
+
+    let text = index_text(GuildId(1), ChannelId(2), &pins[..2]);
+    assert_eq!(text.lines().count(), 2);
+}
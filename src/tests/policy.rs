@@ -0,0 +1,46 @@
+use serenity::model::id::RoleId;
+use serenity::model::permissions::Permissions;
+
+use crate::commands;
+use crate::policy::{allowed, command_names, policy_for, take_required_permissions, CommandPolicy};
+
+#[test]
+fn required_permissions_are_taken_from_commands() {
+    let mut commands = commands();
+    let required = take_required_permissions(&mut commands);
+
+    assert_eq!(required.get("config policy set"), Some(&Permissions::MANAGE_GUILD));
+    assert!(!required.contains_key("class list"));
+    fn cleared(command: &poise::Command<crate::Data, crate::Error>) -> bool {
+        command.required_permissions.is_empty() && command.subcommands.iter().all(cleared)
+    }
+    assert!(commands.iter().all(cleared));
+
+    let names = command_names(&commands);
+    assert!(names.contains(&"class edit".to_string()));
+    assert!(names.contains(&"class edit tag".to_string()));
+}
+
+#[test]
+fn closest_policy_applies() {
+    let policies = [
+        CommandPolicy { command: "class".to_string(), roles: vec![RoleId(1)] },
+        CommandPolicy { command: "class edit tag".to_string(), roles: vec![RoleId(2)] },
+    ];
+
+    assert_eq!(policy_for(&policies, "class edit tag"), Some(&policies[1]));
+    assert_eq!(policy_for(&policies, "class edit description"), Some(&policies[0]));
+    assert_eq!(policy_for(&policies, "config dedup set"), None);
+}
+
+#[test]
+fn policies_replace_default_permissions() {
+    let policy = CommandPolicy { command: "class create".to_string(), roles: vec![RoleId(1)] };
+    let none = Permissions::empty();
+
+    assert!(!allowed(None, Permissions::MANAGE_GUILD, none, &[RoleId(1)]));
+    assert!(allowed(Some(&policy), Permissions::MANAGE_GUILD, none, &[RoleId(1)]));
+    assert!(!allowed(Some(&policy), none, none, &[RoleId(2)]));
+    assert!(allowed(Some(&policy), none, Permissions::MANAGE_GUILD, &[]));
+    assert!(allowed(None, none, none, &[]));
+}
@@ -0,0 +1,159 @@
+use std::fs;
+use std::path::Path;
+
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+
+use super::harness::{server_id, with_database};
+use crate::scoped::GuildScoped;
+use crate::{get_conn, ClassError, ENV};
+
+/// Files allowed to name the `server_id` field themselves, and why.
+const ALLOWED: &[(&str, &str)] = &[
+    ("scoped.rs", "adds the server to every query"),
+    ("migrations.rs", "defines the indexes on it"),
+    ("audit.rs", "records errors that may not have a server"),
+];
+
+/// Functions allowed to use a collection without `GuildScoped`, by file. Only lookups by a Discord
+/// ID, which can't belong to another server, writes of documents carrying their own server, and
+/// background jobs over every server belong here.
+const CROSS_SERVER: &[(&str, &[&str])] = &[
+    ("announcements.rs", &["announce", "tick"]),
+    ("archive.rs", &[
+        "archive_message", "deleted", "message_delete", "message_delete_bulk", "message_update", "search",
+        "tick",
+    ]),
+    ("assignments.rs", &["create", "delete", "find", "tick"]),
+    ("automod.rs", &["apply", "list", "remove"]),
+    ("classes.rs", &[
+        "add_to_db", "find_by_category", "find_by_grader_token", "find_by_role", "find_by_text_channel",
+        "find_by_voice_channel", "remove_from_db", "replace",
+    ]),
+    ("countdowns.rs", &["disable", "enable", "tick"]),
+    ("email.rs", &["tick"]),
+    ("escalation.rs", &["escalate", "exists"]),
+    ("faq.rs", &["add", "list", "remove"]),
+    ("federation.rs", &["find_allowed", "mirror", "unmirror"]),
+        ("hands.rs", &["lower", "next", "refresh", "update"]),
+    ("helpthreads.rs", &["hint_related", "solved", "stats", "track_message"]),
+    ("history.rs", &["log"]),
+    ("icebreakers.rs", &["disable", "enable", "post", "tick"]),
+    ("invites.rs", &["create", "set_uses"]),
+    ("jobs.rs", &["cancel", "claim", "enqueue", "progress", "update"]),
+    ("migrations.rs", &["run"]),
+    ("modmail.rs", &["close", "find_open_by_thread", "open", "record"]),
+    ("notifications.rs", &["announcement_recipients", "unmuted"]),
+    ("peerreview.rs", &["list", "optin", "optout", "pair"]),
+    ("pins.rs", &["list", "refresh_index", "remove"]),
+    ("recordings.rs", &["for_session"]),
+    ("requests.rs", &["claim", "release"]),
+    ("sessions.rs", &[
+        "close", "create", "find", "find_by_recording_notice", "in_voice_channel", "remind", "save_links",
+        "skip_reminder", "toggle_rsvp",
+    ]),
+    ("snippets.rs", &["get", "list", "save"]),
+    ("teams.rs", &["create", "disband", "find", "list"]),
+    ("tempgrants.rs", &["tick"]),
+    ("terms.rs", &["start", "tick"]),
+    ("trash.rs", &["capture", "tick"]),
+    ("tutors.rs", &["list", "register", "unregister"]),
+    ("users.rs", &["get", "set_hide_badges", "toggle_favorite"]),
+    ("voice.rs", &["leaderboard"]),
+];
+
+#[test]
+fn queries_cannot_reach_other_servers() {
+    with_database(async {
+        let collection = get_conn().await
+            .database(&ENV.mongodb_name)
+            .collection::<Document>("scoping_test");
+        let (ours, theirs) = (server_id(), server_id());
+        let scoped = GuildScoped::new(ours, collection.clone());
+
+        scoped.insert_one(doc! { "server_id": ours.to_string(), "name": "a" }, None).await.unwrap();
+        collection.insert_one(doc! { "server_id": theirs.to_string(), "name": "a" }, None).await.unwrap();
+        assert!(matches!(
+            scoped.insert_one(doc! { "server_id": theirs.to_string(), "name": "b" }, None).await,
+            Err(ClassError::CrossGuildWrite),
+        ));
+        assert!(matches!(
+            scoped.insert_one(doc! { "name": "b" }, None).await,
+            Err(ClassError::CrossGuildWrite),
+        ));
+
+        // Naming another server in the filter doesn't widen the query
+        let found = scoped.find(doc! { "server_id": theirs.to_string() }, None).await.unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].get_str("server_id").unwrap(), ours.to_string());
+
+        assert_eq!(scoped.delete_many(None, None).await.unwrap().deleted_count, 1);
+        assert_eq!(GuildScoped::new(theirs, collection).count_documents(None, None).await.unwrap(), 1);
+    });
+}
+
+#[test]
+fn server_filters_go_through_guild_scoped() {
+    let mut offenders = Vec::new();
+    check_dir(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src")), &mut offenders);
+    assert!(offenders.is_empty(), "filtering by server_id outside GuildScoped:\n{}", offenders.join("\n"));
+}
+
+fn check_dir(dir: &Path, offenders: &mut Vec<String>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if path.is_dir() {
+            if name != "tests" {
+                check_dir(&path, offenders);
+            }
+        } else if name.ends_with(".rs") && !ALLOWED.iter().any(|(file, _)| *file == name) {
+            let source = fs::read_to_string(&path).unwrap();
+            offenders.extend(
+                source.lines()
+                    .enumerate()
+                    .filter(|(_, line)| line.contains("\"server_id\""))
+                    .map(|(i, _)| format!("{}:{}", path.display(), i + 1))
+            );
+        }
+    }
+}
+
+#[test]
+fn unscoped_collections_are_allowlisted() {
+    let mut offenders = Vec::new();
+    check_collections(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src")), &mut offenders);
+    assert!(offenders.is_empty(), "collection used outside GuildScoped:\n{}", offenders.join("\n"));
+}
+
+fn check_collections(dir: &Path, offenders: &mut Vec<String>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if path.is_dir() {
+            if name != "tests" {
+                check_collections(&path, offenders);
+            }
+        } else if name.ends_with(".rs") {
+            let allowed = CROSS_SERVER.iter()
+                .find(|(file, _)| *file == name)
+                .map_or(&[][..], |(_, functions)| *functions);
+            let source = fs::read_to_string(&path).unwrap();
+            let mut function = "";
+            for (i, line) in source.lines().enumerate() {
+                if let Some((_, rest)) = line.split_once("fn ") {
+                    function = rest.split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap();
+                }
+                if line.contains("get_collection().await")
+                    && !["scoped", "get_collection"].contains(&function)
+                    && !allowed.contains(&function)
+                {
+                    offenders.push(format!("{}:{} in {}", path.display(), i + 1, function));
+                }
+            }
+        }
+    }
+}
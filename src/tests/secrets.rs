@@ -0,0 +1,64 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use crate::secrets::Keyring;
+use crate::ClassError;
+
+fn key() -> String {
+    BASE64.encode(rand::random::<[u8; 32]>())
+}
+
+#[test]
+fn sealed_secrets_open() {
+    let keyring = Keyring::new("1", &key(), "").unwrap();
+
+    let sealed = keyring.seal("https://discord.com/api/webhooks/1/token").unwrap();
+    assert!(!sealed.contains("token"));
+    assert_eq!(keyring.open(&sealed).unwrap(), "https://discord.com/api/webhooks/1/token");
+    // Each secret gets its own data key and nonce
+    assert_ne!(keyring.seal("https://discord.com/api/webhooks/1/token").unwrap(), sealed);
+}
+
+#[test]
+fn secrets_need_the_right_key() {
+    let sealed = Keyring::new("1", &key(), "").unwrap().seal("secret").unwrap();
+
+    let other = Keyring::new("1", &key(), "").unwrap();
+    assert!(matches!(other.open(&sealed), Err(ClassError::InvalidSecret)));
+    assert!(matches!(other.open("v2:1:garbage:garbage"), Err(ClassError::InvalidSecret)));
+    assert!(matches!(Keyring::new("1", "not a key", ""), Err(ClassError::NoSecretKey)));
+}
+
+#[test]
+fn rotated_secrets_use_the_new_key() {
+    let (old_key, new_key) = (key(), key());
+    let old = Keyring::new("1", &old_key, "").unwrap();
+    let sealed = old.seal("secret").unwrap();
+
+    let rotated = Keyring::new("2", &new_key, &format!("1:{}", old_key)).unwrap();
+    assert_eq!(rotated.open(&sealed).unwrap(), "secret");
+    let rewrapped = rotated.rewrap(&sealed).unwrap().unwrap();
+    assert!(rewrapped.starts_with("v2:2:"));
+    assert_eq!(rotated.rewrap(&rewrapped).unwrap(), None);
+
+    // Once rotated, the old key is no longer needed
+    let new_only = Keyring::new("2", &new_key, "").unwrap();
+    assert_eq!(new_only.open(&rewrapped).unwrap(), "secret");
+    assert!(new_only.open(&sealed).is_err());
+}
+
+#[test]
+fn secrets_sealed_before_data_keys_still_open() {
+    use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+
+    let key_bytes = rand::random::<[u8; 32]>();
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key_bytes).unwrap());
+    let nonce = rand::random::<[u8; 12]>();
+    let mut data = b"secret".to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data).unwrap();
+    let legacy = BASE64.encode([nonce.as_slice(), &data].concat());
+
+    let keyring = Keyring::new("1", &BASE64.encode(key_bytes), "").unwrap();
+    assert_eq!(keyring.open(&legacy).unwrap(), "secret");
+    assert!(keyring.rewrap(&legacy).unwrap().unwrap().starts_with("v2:1:"));
+}
@@ -0,0 +1,200 @@
+use serenity::model::channel::ChannelType;
+
+use super::harness::{channel_id, class, role_id, server_id, with_database};
+use crate::classes::{Class, Server};
+use crate::departments::DepartmentTheme;
+use crate::ClassError;
+
+#[test]
+fn server_is_created_once() {
+    with_database(async {
+        let id = server_id();
+        let mut server = Server::get_or_create(id).await.unwrap();
+        assert_eq!(server.menu_generation, 0);
+
+        server.set_escalation_hours(Some(12)).await.unwrap();
+        let again = Server::get_or_create(id).await.unwrap();
+        assert_eq!(again.escalation_hours, Some(12));
+    });
+}
+
+#[test]
+fn server_settings_are_stored() {
+    with_database(async {
+        let id = server_id();
+        let mut server = Server::get_or_create(id).await.unwrap();
+        let channel = channel_id();
+
+        server.set_staff_channel(channel).await.unwrap();
+        server.set_log_channel(Some(channel), true).await.unwrap();
+        server.set_alert_channel(Some(channel)).await.unwrap();
+        server.set_archive_retention_days(Some(30)).await.unwrap();
+        server.set_auto_track_channels(true).await.unwrap();
+        server.set_welcome(false, Some("Hi {user}".to_string())).await.unwrap();
+
+        let stored = Server::get_or_create(id).await.unwrap();
+        assert_eq!(stored.staff_channel, Some(channel));
+        assert_eq!(stored.log_channel, Some(channel));
+        assert!(stored.log_manual_changes);
+        assert_eq!(stored.alert_channel, Some(channel));
+        assert_eq!(stored.archive_retention_days, Some(30));
+        assert!(stored.auto_track_channels);
+        assert!(stored.welcome_dm_disabled);
+        assert_eq!(stored.welcome_template.as_deref(), Some("Hi {user}"));
+    });
+}
+
+#[test]
+fn server_webhooks() {
+    with_database(async {
+        let mut server = Server::get_or_create(server_id()).await.unwrap();
+
+        assert!(matches!(server.add_webhook("not a url").await, Err(ClassError::InvalidUrl)));
+        assert!(matches!(server.add_webhook("http://example.com/hook").await, Err(ClassError::InsecureWebhook)));
+        server.add_webhook("https://example.com/hook").await.unwrap();
+        assert!(matches!(
+            server.add_webhook("https://example.com/hook").await,
+            Err(ClassError::WebhookExists),
+        ));
+        assert!(matches!(
+            server.remove_webhook("https://example.com/other").await,
+            Err(ClassError::InvalidWebhook),
+        ));
+        server.remove_webhook("https://example.com/hook").await.unwrap();
+        assert!(server.webhooks.is_empty());
+    });
+}
+
+#[test]
+fn server_icebreaker_prompts() {
+    with_database(async {
+        let id = server_id();
+        let mut server = Server::get_or_create(id).await.unwrap();
+
+        server.add_icebreaker_prompt("First?").await.unwrap();
+        server.add_icebreaker_prompt("Second?").await.unwrap();
+        assert!(matches!(server.add_icebreaker_prompt("First?").await, Err(ClassError::PromptExists)));
+        assert!(matches!(server.remove_icebreaker_prompt(0).await, Err(ClassError::InvalidPrompt)));
+        assert_eq!(server.remove_icebreaker_prompt(1).await.unwrap(), "First?");
+
+        let stored = Server::get_or_create(id).await.unwrap();
+        assert_eq!(stored.icebreaker_prompts, vec!["Second?".to_string()]);
+    });
+}
+
+#[test]
+fn server_department_themes() {
+    with_database(async {
+        let mut server = Server::get_or_create(server_id()).await.unwrap();
+        let theme = |emoji: Option<&str>| DepartmentTheme {
+            department: "CS".to_string(),
+            emoji: emoji.map(|e| e.to_string()),
+            colour: None,
+        };
+
+        server.set_department_theme(theme(Some("💻"))).await.unwrap();
+        assert_eq!(server.department_theme("CS").unwrap().emoji.as_deref(), Some("💻"));
+        // A theme with nothing set is removed
+        server.set_department_theme(theme(None)).await.unwrap();
+        assert!(server.department_theme("CS").is_none());
+    });
+}
+
+#[test]
+fn adding_a_class_bumps_the_menu_generation() {
+    with_database(async {
+        let id = server_id();
+        Server::get_or_create(id).await.unwrap();
+
+        class(id, "Data Structures").add_to_db().await.unwrap();
+        assert_eq!(Server::get_or_create(id).await.unwrap().menu_generation, 1);
+    });
+}
+
+#[test]
+fn classes_can_be_found() {
+    with_database(async {
+        let id = server_id();
+        Server::get_or_create(id).await.unwrap();
+        let class = class(id, "Operating Systems").add_to_db().await.unwrap();
+
+        let by_name = Class::find_by_name(id, "Operating Systems").await.unwrap().unwrap();
+        assert_eq!(by_name.role, class.role);
+        assert!(Class::class_exists(id, "Operating Systems").await.unwrap());
+        assert!(!Class::class_exists(server_id(), "Operating Systems").await.unwrap());
+
+        assert_eq!(Class::find_by_role(class.role).await.unwrap().unwrap().name, class.name);
+        assert_eq!(Class::find_by_category(class.category()).await.unwrap().unwrap().role, class.role);
+        assert_eq!(Class::find_by_text_channel(class.text_channels[0]).await.unwrap().unwrap().role, class.role);
+        assert_eq!(Class::find_by_voice_channel(class.voice_channels[0]).await.unwrap().unwrap().role, class.role);
+        assert!(Class::find_by_role(role_id()).await.unwrap().is_none());
+
+        let listed = Class::list(id).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].role, class.role);
+    });
+}
+
+#[test]
+fn class_settings_are_stored() {
+    with_database(async {
+        let id = server_id();
+        Server::get_or_create(id).await.unwrap();
+        let mut class = class(id, "Compilers").add_to_db().await.unwrap();
+        let staff = role_id();
+
+        class.set_staff_role(Some(staff)).await.unwrap();
+        class.set_tags([" Systems ", "systems", "", "Theory"]).await.unwrap();
+        class.set_description(Some("Parsing and code generation".to_string())).await.unwrap();
+        class.set_archive_messages(true).await.unwrap();
+        class.rename("  Advanced Compilers ").await.unwrap();
+
+        let stored = Class::find_by_role(class.role).await.unwrap().unwrap();
+        assert_eq!(stored.staff_role, Some(staff));
+        assert_eq!(stored.tags, vec!["systems".to_string(), "theory".to_string()]);
+        assert_eq!(stored.description.as_deref(), Some("Parsing and code generation"));
+        assert!(stored.archive_messages);
+        assert_eq!(stored.name, "Advanced Compilers");
+        assert_eq!(stored.short_name, "advancedcompilers");
+    });
+}
+
+#[test]
+fn class_channels_are_tracked() {
+    with_database(async {
+        let id = server_id();
+        Server::get_or_create(id).await.unwrap();
+        let mut class = class(id, "Networks").add_to_db().await.unwrap();
+        let text = channel_id();
+        let voice = channel_id();
+
+        assert!(class.add_channel(text, ChannelType::Text).await.unwrap());
+        assert!(!class.add_channel(text, ChannelType::Text).await.unwrap());
+        assert!(class.add_channel(voice, ChannelType::Stage).await.unwrap());
+        assert!(!class.add_channel(channel_id(), ChannelType::Category).await.unwrap());
+
+        let stored = Class::find_by_role(class.role).await.unwrap().unwrap();
+        assert!(stored.text_channels.contains(&text));
+        assert!(stored.voice_channels.contains(&voice));
+        assert_eq!(stored.all_channels().len(), 5);
+
+        assert!(class.remove_channel(text).await.unwrap());
+        assert!(!class.remove_channel(text).await.unwrap());
+        assert!(Class::find_by_text_channel(text).await.unwrap().is_none());
+    });
+}
+
+#[test]
+fn untracking_a_class_removes_it() {
+    with_database(async {
+        let id = server_id();
+        Server::get_or_create(id).await.unwrap();
+        let class = class(id, "Databases").add_to_db().await.unwrap();
+        let role = class.role;
+
+        assert_eq!(class.clone().untrack().await.unwrap().as_deref(), Some("Databases"));
+        assert!(Class::find_by_role(role).await.unwrap().is_none());
+        assert_eq!(class.untrack().await.unwrap(), None);
+        assert_eq!(Server::get_or_create(id).await.unwrap().menu_generation, 3);
+    });
+}
@@ -0,0 +1,19 @@
+use futures::future::join_all;
+
+use super::harness::{server_id, with_database};
+use crate::suggestions::SuggestionCounter;
+
+#[test]
+fn suggestion_numbers_are_unique() {
+    with_database(async {
+        let id = server_id();
+        let mut numbers = join_all((0..20).map(|_| SuggestionCounter::next(id))).await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        numbers.sort_unstable();
+        assert_eq!(numbers, (1..=20).collect::<Vec<_>>());
+
+        assert_eq!(SuggestionCounter::next(server_id()).await.unwrap(), 1);
+    });
+}
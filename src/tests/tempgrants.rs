@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use mongodb::bson::DateTime;
+use serenity::model::id::UserId;
+
+use super::harness::{server_id, with_database};
+use crate::scheduler::parse_duration;
+use crate::tempgrants::{covers, grantable, tick, TempGrant};
+
+#[test]
+fn durations_parse() {
+    assert_eq!(parse_duration("3d"), Some(Duration::from_secs(3 * 24 * 60 * 60)));
+    assert_eq!(parse_duration("1w 2d"), Some(Duration::from_secs(9 * 24 * 60 * 60)));
+    assert_eq!(parse_duration("1H30m"), Some(Duration::from_secs(90 * 60)));
+    assert_eq!(parse_duration(""), None);
+    assert_eq!(parse_duration("0m"), None);
+    assert_eq!(parse_duration("3"), None);
+    assert_eq!(parse_duration("d"), None);
+    assert_eq!(parse_duration("2 days"), None);
+    assert_eq!(parse_duration("99999999999999999999w"), None);
+}
+
+#[test]
+fn grants_cover_groups() {
+    assert!(covers("class", "class edit tag"));
+    assert!(covers("class edit tag", "class edit tag"));
+    assert!(!covers("class edit tag", "class edit"));
+    assert!(!covers("class", "classes"));
+
+    assert!(grantable("class cleanup"));
+    assert!(!grantable("admin"));
+    assert!(!grantable("admin grant-temp"));
+    assert!(grantable("admin grant"));
+
+    // Nor anything that could turn temporary access into permanent access
+    assert!(!grantable("config"));
+    assert!(!grantable("config policy"));
+    assert!(!grantable("config policy set"));
+    assert!(!grantable("class managers add"));
+    assert!(!grantable("class edit staff"));
+    assert!(!grantable("class edit"));
+    assert!(grantable("class edit description"));
+    assert!(grantable("config welcome set"));
+}
+
+#[test]
+fn expired_grants_are_revoked() {
+    with_database(async {
+        let id = server_id();
+        let (user, admin) = (UserId(1), UserId(2));
+        let now = DateTime::now().timestamp_millis();
+
+        TempGrant::give(id, user, "class", admin, DateTime::from_millis(now + 60_000)).await.unwrap();
+        TempGrant::give(id, user, "config", admin, DateTime::from_millis(now - 1)).await.unwrap();
+        let active = TempGrant::active(id, user).await.unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].command, "class");
+
+        tick().await.unwrap();
+        assert_eq!(TempGrant::active(id, user).await.unwrap().len(), 1);
+        assert!(TempGrant::active(server_id(), user).await.unwrap().is_empty());
+    });
+}
@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::Path;
+
+use mongodb::bson::{doc, Document};
+
+use super::harness::{channel_id, server, server_id, with_database};
+use crate::classes::{changed_fields, merge_concurrent, Server};
+use crate::{get_conn, ClassError, ENV};
+
+#[test]
+fn concurrent_changes_to_different_fields_merge() {
+    let base = server(server_id());
+    let mut ours = base.clone();
+    ours.staff_channel = Some(channel_id());
+    let mut theirs = base.clone();
+    theirs.alert_channel = Some(channel_id());
+    theirs.version = 1;
+
+    let merged = merge_concurrent(&base, &ours, &theirs).unwrap();
+    assert_eq!(merged.staff_channel, ours.staff_channel);
+    assert_eq!(merged.alert_channel, theirs.alert_channel);
+    assert_eq!(merged.version, 1);
+
+    theirs.staff_channel = Some(channel_id());
+    assert!(matches!(merge_concurrent(&base, &ours, &theirs), Err(ClassError::EditConflict)));
+    theirs.staff_channel = ours.staff_channel;
+    assert!(merge_concurrent(&base, &ours, &theirs).is_ok());
+}
+
+#[test]
+fn stale_server_writes_keep_newer_changes() {
+    with_database(async {
+        let id = server_id();
+        let mut first = Server::get_or_create(id).await.unwrap();
+        let mut second = first.clone();
+        let mut third = first.clone();
+
+        first.set_staff_channel(channel_id()).await.unwrap();
+        Server::bump_menu_generation(id).await.unwrap();
+        second.set_alert_channel(Some(channel_id())).await.unwrap();
+
+        let stored = Server::get_or_create(id).await.unwrap();
+        assert_eq!(stored.staff_channel, first.staff_channel);
+        assert_eq!(stored.alert_channel, second.alert_channel);
+        assert_eq!(stored.menu_generation, 1);
+        assert_eq!(stored.version, 3);
+
+        assert!(matches!(third.set_staff_channel(channel_id()).await, Err(ClassError::EditConflict)));
+        assert_eq!(Server::get_or_create(id).await.unwrap().staff_channel, first.staff_channel);
+    });
+}
+
+#[test]
+fn only_changed_fields_are_written() {
+    let old = server(server_id());
+    let mut new = old.clone();
+    new.staff_channel = Some(channel_id());
+    new.version = 4;
+
+    let changed = changed_fields(&old, &new).unwrap();
+    assert_eq!(changed, doc! { "staff_channel": new.staff_channel.unwrap().to_string() });
+    assert!(changed_fields(&old, &old).unwrap().is_empty());
+}
+
+#[test]
+fn unknown_server_fields_survive_writes() {
+    with_database(async {
+        let id = server_id();
+        let mut server = Server::get_or_create(id).await.unwrap();
+        let servers = get_conn().await
+            .database(&ENV.mongodb_name)
+            .collection::<Document>("servers");
+        // As if written by a newer version of the bot
+        servers
+            .update_one(doc! { "server_id": id.to_string() }, doc! { "$set": { "future_setting": true } }, None)
+            .await
+            .unwrap();
+
+        server.set_escalation_hours(Some(6)).await.unwrap();
+
+        let stored = servers.find_one(doc! { "server_id": id.to_string() }, None).await.unwrap().unwrap();
+        assert_eq!(stored.get_bool("future_setting"), Ok(true));
+        assert_eq!(Server::get_or_create(id).await.unwrap().escalation_hours, Some(6));
+    });
+}
+
+#[test]
+fn documents_are_never_replaced_whole() {
+    let mut offenders = Vec::new();
+    check_dir(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src")), &mut offenders);
+    assert!(offenders.is_empty(), "replacing whole documents drops unknown fields:\n{}", offenders.join("\n"));
+}
+
+fn check_dir(dir: &Path, offenders: &mut Vec<String>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            if !path.ends_with("tests") {
+                check_dir(&path, offenders);
+            }
+        } else if path.extension().is_some_and(|e| e == "rs") {
+            let source = fs::read_to_string(&path).unwrap();
+            offenders.extend(
+                source.lines()
+                    .enumerate()
+                    .filter(|(_, line)| line.contains("replace_one(") || line.contains("find_one_and_replace("))
+                    .map(|(i, _)| format!("{}:{}", path.display(), i + 1))
+            );
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use serenity::model::id::{ChannelId, UserId};
+
+use super::harness::{class, server_id};
+use crate::events::BotEvent;
+use crate::webhooks::WebhookEvent;
+
+#[test]
+fn only_class_events_reach_webhooks() {
+    let id = server_id();
+    let mut class = class(id, "CS 101");
+    class.webhook = Some("sealed".to_string());
+    class.grader_token = Some("hash".to_string());
+    class.managers = vec![UserId(1)];
+
+    let payload = WebhookEvent::from_event(&BotEvent::ClassCreated { server_id: id, class: class.clone() }).unwrap();
+    let payload = serde_json::to_value(payload).unwrap();
+    assert_eq!(payload["event"], "class_created");
+    assert_eq!(payload["class"]["name"], "CS 101");
+    assert_eq!(payload["class"]["role"], class.role.to_string());
+    for secret in ["webhook", "grader_token", "canvas", "managers"] {
+        assert!(payload["class"].get(secret).is_none(), "{} was sent", secret);
+    }
+
+    assert!(WebhookEvent::from_event(&BotEvent::ConfigChanged { server_id: id, setting: "x".to_string() }).is_none());
+    assert!(WebhookEvent::from_event(&BotEvent::MessagePosted {
+        server_id: id,
+        user_id: UserId(1),
+        channel: ChannelId(2),
+        message: 3.into(),
+        command: "say".to_string(),
+    }).is_none());
+    assert!(WebhookEvent::from_event(&BotEvent::AccessRevoked {
+        server_id: id,
+        user_id: UserId(1),
+        command: "class".to_string(),
+    }).is_none());
+}
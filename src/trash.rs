@@ -0,0 +1,255 @@
+use chrono::{Duration, Utc};
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::{FindOneOptions, FindOptions};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::http::CacheHttp;
+use serenity::model::channel::{Channel, ChannelType};
+use serenity::model::guild::Guild;
+use serenity::model::id::GuildId;
+use tokio::sync::OnceCell;
+
+use crate::classes::{Class, Server};
+use crate::scoped::GuildScoped;
+use crate::{discord_name, get_conn, ClassError, ClassResult, ENV};
+
+/// How long a deleted class can be restored for.
+const RETENTION_DAYS: i64 = 30;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TrashedPin {
+    author: String,
+    content: String,
+    attachments: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TrashedChannel {
+    name: String,
+    kind: ChannelType,
+    topic: Option<String>,
+    /// Which of the class's categories the channel was in.
+    category: usize,
+    pins: Vec<TrashedPin>,
+}
+
+/// Everything needed to rebuild a deleted class's role and channels. Membership is not kept, so
+/// restored classes start out empty.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct TrashedClass {
+    server_id: GuildId,
+    pub(crate) class: Class,
+    role_colour: u32,
+    category_names: Vec<String>,
+    channels: Vec<TrashedChannel>,
+    pub(crate) deleted_at: DateTime,
+}
+
+impl TrashedClass {
+    /// Snapshot a class's structure and pinned messages before it is deleted.
+    pub(crate) async fn capture(cache_http: impl CacheHttp, class: &Class, guild: &Guild) -> ClassResult<()> {
+        let category_names = class.categories.iter()
+            .map(|c| match guild.channels.get(c) {
+                Some(Channel::Category(category)) => category.name.clone(),
+                _ => class.name.clone(),
+            })
+            .collect();
+
+        let mut channels = guild.channels.values()
+            .filter_map(|c| c.clone().guild())
+            .filter(|c| class.text_channels.contains(&c.id) || class.voice_channels.contains(&c.id))
+            .collect::<Vec<_>>();
+        channels.sort_by_key(|c| (c.kind == ChannelType::Voice, c.position));
+
+        let mut trashed_channels = Vec::new();
+        for channel in channels {
+            let pins = if channel.kind == ChannelType::Text {
+                channel.pins(cache_http.http()).await?
+                    .into_iter()
+                    .rev()
+                    .map(|m| TrashedPin {
+                        author: m.author.tag(),
+                        content: m.content,
+                        attachments: m.attachments.into_iter().map(|a| a.url).collect(),
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+            trashed_channels.push(TrashedChannel {
+                name: channel.name,
+                kind: channel.kind,
+                topic: channel.topic,
+                category: channel.parent_id
+                    .and_then(|p| class.categories.iter().position(|c| *c == p))
+                    .unwrap_or(0),
+                pins,
+            });
+        }
+
+        Self::get_collection().await
+            .insert_one(Self {
+                server_id: class.server_id,
+                class: class.clone(),
+                role_colour: guild.roles.get(&class.role).map(|r| r.colour.0).unwrap_or(0),
+                category_names,
+                channels: trashed_channels,
+                deleted_at: DateTime::now(),
+            }, None)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Recreate the most recently deleted class with the given name, reposting its pinned messages.
+    pub(crate) async fn restore(cache_http: impl CacheHttp, guild: &Guild, name: &str) -> ClassResult<Class> {
+        let name = name.trim();
+        let trashed = Self::scoped(guild.id).await
+            .find_one(
+                doc! {
+                    "class.name": name,
+                    "deleted_at": { "$gte": cutoff() },
+                },
+                FindOneOptions::builder().sort(doc! { "deleted_at": -1 }).build(),
+            )
+            .await?
+            .ok_or(ClassError::InvalidTrashedClass)?;
+
+        if Class::class_exists(guild.id, name).await? {
+            return Err(ClassError::ClassExists);
+        }
+        let refrole = Server::get_or_create(guild.id).await?.refrole.ok_or(ClassError::NoRefrole)?;
+        let position = guild.roles.get(&refrole).ok_or(ClassError::InvalidRefrole)?.position as u8;
+
+        let http = cache_http.http();
+        let role = guild
+            .create_role(http, |r| r
+                .name(discord_name(&trashed.class.name))
+                .colour(trashed.role_colour.into())
+                .mentionable(true)
+                .position(position)
+            )
+            .await?;
+
+        let mut categories = Vec::new();
+        for category_name in &trashed.category_names {
+            let category = guild
+                .create_channel(http, |c| c
+                    .name(discord_name(category_name))
+                    .kind(ChannelType::Category)
+                    .permissions(trashed.class.visibility.overwrites(guild.id, role.id))
+                )
+                .await?;
+            categories.push(category.id);
+        }
+
+        let mut text_channels = Vec::new();
+        let mut voice_channels = Vec::new();
+        for trashed_channel in &trashed.channels {
+            let category = categories.get(trashed_channel.category).or(categories.first()).copied();
+            let channel = guild
+                .create_channel(http, |c| {
+                    c.name(discord_name(&trashed_channel.name)).kind(trashed_channel.kind);
+                    if let Some(category) = category {
+                        c.category(category);
+                    }
+                    if let Some(topic) = &trashed_channel.topic {
+                        c.topic(topic);
+                    }
+                    c
+                })
+                .await?;
+
+            for pin in &trashed_channel.pins {
+                let mut content = format!("**{}**: {}", pin.author, pin.content);
+                for attachment in &pin.attachments {
+                    content += &format!("\n{}", attachment);
+                }
+                let message = channel.say(http, content).await?;
+                message.pin(http).await?;
+            }
+
+            if trashed_channel.kind == ChannelType::Voice {
+                voice_channels.push(channel.id);
+            } else {
+                text_channels.push(channel.id);
+            }
+        }
+
+        let class = Class {
+            role: role.id,
+            categories,
+            text_channels,
+            voice_channels,
+            staff_role: trashed.class.staff_role.filter(|r| guild.roles.contains_key(r)),
+            mentor_role: trashed.class.mentor_role.filter(|r| guild.roles.contains_key(r)),
+            // The webhook was deleted along with the channel it posted to
+            webhook: None,
+            assignment_board: None,
+            // The emoji was deleted along with the class
+            emoji: None,
+            ..trashed.class.clone()
+        }.add_to_db().await?;
+
+        Self::scoped(guild.id).await
+            .delete_one(
+                doc! { "class.role": trashed.class.role.to_string() },
+                None,
+            )
+            .await?;
+
+        Ok(class)
+    }
+
+    /// The classes in a server that can still be restored, most recently deleted first.
+    pub(crate) async fn list(server_id: GuildId) -> ClassResult<Vec<TrashedClass>> {
+        Ok(
+            Self::scoped(server_id).await
+                .find(
+                    doc! { "deleted_at": { "$gte": cutoff() } },
+                    FindOptions::builder().sort(doc! { "deleted_at": -1 }).build(),
+                )
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    /// When this class will be removed from the trash for good.
+    pub(crate) fn expires_at(&self) -> i64 {
+        self.deleted_at.timestamp_millis() / 1000 + Duration::days(RETENTION_DAYS).num_seconds()
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static TRASH: OnceCell<Collection<TrashedClass>> = OnceCell::const_new();
+
+        TRASH
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("class_trash")
+            })
+            .await
+            .clone()
+    }
+}
+
+fn cutoff() -> DateTime {
+    DateTime::from_millis((Utc::now() - Duration::days(RETENTION_DAYS)).timestamp_millis())
+}
+
+/// Empty deleted classes out of the trash once they can no longer be restored.
+pub(crate) async fn tick() -> ClassResult<()> {
+    TrashedClass::get_collection().await
+        .delete_many(doc! { "deleted_at": { "$lt": cutoff() } }, None)
+        .await?;
+
+    Ok(())
+}
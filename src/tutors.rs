@@ -0,0 +1,163 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::Collection;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use serenity::http::CacheHttp;
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::prelude::Mentionable;
+use tokio::sync::OnceCell;
+
+use crate::classes::{Class, Server};
+use crate::history::EnrollmentEvent;
+use crate::{get_conn, ClassError, ClassResult, Context, Error, ENV};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Tutor {
+    server_id: GuildId,
+    role: RoleId,
+    user: UserId,
+    registered_at: DateTime,
+}
+
+impl Tutor {
+    async fn register(class: &Class, user: UserId) -> ClassResult<()> {
+        if !EnrollmentEvent::has_completed(class.server_id, user, class.role).await? {
+            return Err(ClassError::NotCompleted);
+        }
+
+        let collection = Self::get_collection().await;
+        let filter = doc! { "role": class.role.to_string(), "user": user.to_string() };
+        if collection.find_one(filter, None).await?.is_some() {
+            return Err(ClassError::TutorExists);
+        }
+
+        collection
+            .insert_one(
+                Self {
+                    server_id: class.server_id,
+                    role: class.role,
+                    user,
+                    registered_at: DateTime::now(),
+                },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn unregister(class: &Class, user: UserId) -> ClassResult<bool> {
+        Ok(
+            Self::get_collection().await
+                .delete_one(doc! { "role": class.role.to_string(), "user": user.to_string() }, None)
+                .await?
+                .deleted_count > 0
+        )
+    }
+
+    async fn list(class: &Class) -> ClassResult<Vec<Tutor>> {
+        Ok(
+            Self::get_collection().await
+                .find(doc! { "role": class.role.to_string() }, None)
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static TUTORS: OnceCell<Collection<Tutor>> = OnceCell::const_new();
+
+        TUTORS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("tutors")
+            })
+            .await
+            .clone()
+    }
+}
+
+#[poise::command(
+    slash_command,
+    subcommands("TutorCommand::register", "TutorCommand::unregister", "TutorCommand::find")
+)]
+pub(crate) async fn tutor(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+struct TutorCommand;
+impl TutorCommand {
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn register(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        Tutor::register(&class, ctx.author().id).await?;
+
+        ctx.say(format!("You are now registered as a tutor for \"{}\". Thank you!", class.name)).await?;
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn unregister(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+        if Tutor::unregister(&class, ctx.author().id).await? {
+            ctx.say(format!("You are no longer a tutor for \"{}\".", class.name)).await?;
+        } else {
+            ctx.say(format!("You are not registered as a tutor for \"{}\".", class.name)).await?;
+        }
+
+        Ok(())
+    }
+
+    #[poise::command(
+        slash_command,
+        ephemeral,
+    )]
+    async fn find(ctx: Context<'_>, class: Role) -> Result<(), Error> {
+        ctx.defer_ephemeral().await?;
+
+        let server = Server::get_or_create(ctx.guild_id().ok_or(ClassError::NoServer)?).await?;
+        let channel = server.tutoring_channel.ok_or(ClassError::NoTutoringChannel)?;
+        let class = Class::find_by_role(class.id).await?.ok_or(ClassError::InvalidClass)?;
+
+        let student = ctx.author();
+        let tutor = Tutor::list(&class).await?
+            .into_iter()
+            .filter(|t| t.user != student.id)
+            .collect::<Vec<_>>()
+            .choose(&mut rand::thread_rng())
+            .map(|t| t.user)
+            .ok_or(ClassError::NoTutors)?;
+
+        let http = ctx.discord().http();
+        let thread = channel
+            .create_private_thread(http, |t| t.name(format!("Tutoring: {} — {}", class.short_name, student.name)))
+            .await?;
+        thread.id.add_thread_member(http, student.id).await?;
+        thread.id.add_thread_member(http, tutor).await?;
+        thread.say(http, format!(
+            "{}, meet your tutor for {}, {}! Use this thread to work through your questions.",
+            student.mention(),
+            class.name,
+            tutor.mention(),
+        )).await?;
+
+        ctx.say(format!("Matched you with a tutor in {}.", thread.mention())).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use mongodb::bson::doc;
+use mongodb::options::UpdateOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{RoleId, UserId};
+use tokio::sync::OnceCell;
+
+use crate::{get_conn, ClassResult, ENV};
+
+/// Per-user preferences, shared across servers.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct UserProfile {
+    user_id: UserId,
+    #[serde(default)]
+    pub(crate) favorites: Vec<RoleId>,
+    /// Whether the user opted out of staff badges in their nickname.
+    #[serde(default)]
+    pub(crate) hide_badges: bool,
+}
+
+impl UserProfile {
+    pub(crate) async fn get(user_id: UserId) -> ClassResult<UserProfile> {
+        Ok(
+            Self::get_collection().await
+                .find_one(doc! { "user_id": user_id.to_string() }, None)
+                .await?
+                .unwrap_or(Self { user_id, ..Default::default() })
+        )
+    }
+
+    /// Add or remove a class from the user's favorites. Returns whether it is now a favorite.
+    pub(crate) async fn toggle_favorite(user_id: UserId, role: RoleId) -> ClassResult<bool> {
+        let favorite = !Self::get(user_id).await?.favorites.contains(&role);
+        let update = if favorite {
+            doc! { "$addToSet": { "favorites": role.to_string() } }
+        } else {
+            doc! { "$pull": { "favorites": role.to_string() } }
+        };
+
+        Self::get_collection().await
+            .update_one(
+                doc! { "user_id": user_id.to_string() },
+                update,
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        Ok(favorite)
+    }
+
+    pub(crate) async fn set_hide_badges(user_id: UserId, hide: bool) -> ClassResult<()> {
+        Self::get_collection().await
+            .update_one(
+                doc! { "user_id": user_id.to_string() },
+                doc! { "$set": { "hide_badges": hide } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static USERS: OnceCell<Collection<UserProfile>> = OnceCell::const_new();
+
+        USERS
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("users")
+            })
+            .await
+            .clone()
+    }
+}
@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use serenity::http::CacheHttp;
+use serenity::model::channel::{PermissionOverwrite, PermissionOverwriteType};
+use serenity::model::id::{GuildId, RoleId};
+use serenity::model::Permissions;
+
+use crate::classes::Class;
+use crate::ClassResult;
+
+/// Who can see and post in a class's channels.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, poise::ChoiceParameter)]
+pub(crate) enum Visibility {
+    /// Only members of the class can see its channels
+    #[default]
+    #[name = "Private"]
+    Private,
+    /// Everyone can read the class's channels, but only members can post
+    #[name = "Public read"]
+    PublicRead,
+    /// Everyone can read and post in the class's channels
+    #[name = "Open"]
+    Open,
+}
+
+impl Visibility {
+    /// The overwrites for a class's categories and channels, for `@everyone` and the class role.
+    pub(crate) fn overwrites(self, server_id: GuildId, role: RoleId) -> Vec<PermissionOverwrite> {
+        let participate = Permissions::SEND_MESSAGES
+            | Permissions::SEND_MESSAGES_IN_THREADS
+            | Permissions::CREATE_PUBLIC_THREADS
+            | Permissions::ADD_REACTIONS
+            | Permissions::CONNECT;
+
+        let (everyone_allow, everyone_deny) = match self {
+            Self::Private => (Permissions::empty(), Permissions::VIEW_CHANNEL),
+            Self::PublicRead => (Permissions::VIEW_CHANNEL, participate),
+            Self::Open => (Permissions::VIEW_CHANNEL, Permissions::empty()),
+        };
+
+        vec![
+            PermissionOverwrite {
+                allow: everyone_allow,
+                deny: everyone_deny,
+                kind: PermissionOverwriteType::Role(server_id.0.into()),
+            },
+            PermissionOverwrite {
+                allow: Permissions::VIEW_CHANNEL | participate,
+                deny: Permissions::empty(),
+                kind: PermissionOverwriteType::Role(role),
+            },
+        ]
+    }
+
+    /// Replace the `@everyone` and class role overwrites on all of a class's categories and
+    /// channels, leaving any other overwrites alone.
+    pub(crate) async fn apply(self, cache_http: impl CacheHttp, class: &Class) -> ClassResult<()> {
+        let http = cache_http.http();
+        let overwrites = self.overwrites(class.server_id, class.role);
+
+        for channel in class.categories.iter().chain(&class.text_channels).chain(&class.voice_channels) {
+            for overwrite in &overwrites {
+                channel.create_permission(http, overwrite).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
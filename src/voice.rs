@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use mongodb::bson::doc;
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::Context as SContext;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::model::voice::VoiceState;
+use serenity::prelude::EventHandler;
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::classes::Class;
+use crate::redact::log_error;
+use crate::scoped::GuildScoped;
+use crate::{get_conn, ClassResult, ENV};
+
+lazy_static! {
+    /// When each member joined the voice channel they are currently in. Sessions in progress
+    /// when the bot restarts are not counted.
+    static ref SESSIONS: Mutex<HashMap<(GuildId, UserId), (ChannelId, Instant)>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct VoiceTime {
+    server_id: GuildId,
+    role: RoleId,
+    pub(crate) user: UserId,
+    seconds: i64,
+}
+
+impl VoiceTime {
+    pub(crate) fn minutes(&self) -> i64 {
+        self.seconds / 60
+    }
+
+    async fn add(class: &Class, user: UserId, seconds: i64) -> ClassResult<()> {
+        Self::scoped(class.server_id).await
+            .update_one(
+                doc! {
+                    "role": class.role.to_string(),
+                    "user": user.to_string(),
+                },
+                doc! { "$inc": { "seconds": seconds } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// The members who have spent the most time in the class's voice channels.
+    pub(crate) async fn leaderboard(class: &Class, limit: i64) -> ClassResult<Vec<VoiceTime>> {
+        Ok(
+            Self::get_collection().await
+                .find(
+                    doc! { "role": class.role.to_string() },
+                    FindOptions::builder()
+                        .sort(doc! { "seconds": -1 })
+                        .limit(limit)
+                        .build(),
+                )
+                .await?
+                .try_collect::<Vec<_>>()
+                .await?
+        )
+    }
+
+    async fn scoped(server_id: GuildId) -> GuildScoped<Self> {
+        GuildScoped::new(server_id, Self::get_collection().await)
+    }
+
+    async fn get_collection() -> Collection<Self> {
+        static VOICE_TIME: OnceCell<Collection<VoiceTime>> = OnceCell::const_new();
+
+        VOICE_TIME
+            .get_or_init(|| async {
+                get_conn()
+                    .await
+                    .database(&ENV.mongodb_name)
+                    .collection("voice_time")
+            })
+            .await
+            .clone()
+    }
+}
+
+pub(crate) struct VoiceTimeHandler;
+
+#[async_trait]
+impl EventHandler for VoiceTimeHandler {
+    async fn voice_state_update(&self, _ctx: SContext, _old: Option<VoiceState>, new: VoiceState) {
+        let server_id = if let Some(id) = new.guild_id {
+            id
+        } else {
+            return;
+        };
+
+        let finished = {
+            let mut sessions = SESSIONS.lock().await;
+            let key = (server_id, new.user_id);
+
+            match (sessions.get(&key).copied(), new.channel_id) {
+                // Mute/deafen updates don't end the session
+                (Some((old, _)), Some(channel)) if old == channel => return,
+                (old, Some(channel)) => {
+                    sessions.insert(key, (channel, Instant::now()));
+                    old
+                }
+                (old, None) => {
+                    sessions.remove(&key);
+                    old
+                }
+            }
+        };
+
+        let (channel, started) = if let Some(session) = finished {
+            session
+        } else {
+            return;
+        };
+
+        let result = match Class::find_by_voice_channel(channel).await {
+            Ok(Some(class)) => VoiceTime::add(&class, new.user_id, started.elapsed().as_secs() as i64).await,
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            log_error!("Error recording voice time: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use futures::future::join_all;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+
+use crate::classes::{Class, Server};
+use crate::events::BotEvent;
+use crate::history::EnrollmentMechanism;
+use crate::redact::log_error;
+
+/// How long a webhook endpoint has to respond before the delivery is dropped.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .expect("Error building webhook HTTP client");
+}
+
+/// The parts of a class sent to webhooks. Classes also hold secrets and staff details, which
+/// shouldn't leave the bot.
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct ClassSummary {
+    name: String,
+    role: RoleId,
+    categories: Vec<ChannelId>,
+    text_channels: Vec<ChannelId>,
+    voice_channels: Vec<ChannelId>,
+}
+
+impl From<&Class> for ClassSummary {
+    fn from(class: &Class) -> Self {
+        Self {
+            name: class.name.clone(),
+            role: class.role,
+            categories: class.categories.clone(),
+            text_channels: class.text_channels.clone(),
+            voice_channels: class.voice_channels.clone(),
+        }
+    }
+}
+
+/// The events sent to webhooks, in the same shape as the event bus's.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum WebhookEvent {
+    ClassCreated {
+        server_id: GuildId,
+        class: ClassSummary,
+    },
+    ClassDeleted {
+        server_id: GuildId,
+        class: ClassSummary,
+        channels_deleted: bool,
+    },
+    MemberEnrolled {
+        server_id: GuildId,
+        user_id: UserId,
+        actor: UserId,
+        mechanism: EnrollmentMechanism,
+        joined: Vec<RoleId>,
+        left: Vec<RoleId>,
+    },
+}
+
+impl WebhookEvent {
+    /// What to send for an event, or `None` for events that aren't meant for webhooks.
+    pub(crate) fn from_event(event: &BotEvent) -> Option<Self> {
+        match event {
+            BotEvent::ClassCreated { server_id, class } => Some(Self::ClassCreated {
+                server_id: *server_id,
+                class: class.into(),
+            }),
+            BotEvent::ClassDeleted { server_id, class, channels_deleted } => Some(Self::ClassDeleted {
+                server_id: *server_id,
+                class: class.into(),
+                channels_deleted: *channels_deleted,
+            }),
+            BotEvent::MemberEnrolled { server_id, user_id, actor, mechanism, joined, left } => Some(Self::MemberEnrolled {
+                server_id: *server_id,
+                user_id: *user_id,
+                actor: *actor,
+                mechanism: *mechanism,
+                joined: joined.clone(),
+                left: left.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Forward class lifecycle and enrollment events to every webhook configured for the server.
+/// Deliveries run in their own task, so slow endpoints don't hold up the event bus.
+pub(crate) async fn deliver(event: BotEvent) {
+    let payload = match WebhookEvent::from_event(&event) {
+        Some(p) => p,
+        None => return,
+    };
+
+    let server = match Server::get_or_create(event.server_id()).await {
+        Ok(s) => s,
+        Err(e) => {
+            log_error!("Error delivering webhooks: {:?}", e);
+            return;
+        }
+    };
+
+    let urls = match server.webhook_urls() {
+        Ok(u) => u,
+        Err(e) => {
+            log_error!("Error delivering webhooks: {:?}", e);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        let payload = &payload;
+        join_all(urls.iter().map(|url| async move {
+            let result = CLIENT.post(url)
+                .json(payload)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            if let Err(e) = result {
+                log_error!("Error delivering webhook to {}: {:?}", url, e);
+            }
+        })).await;
+    });
+}
@@ -0,0 +1,76 @@
+use serenity::async_trait;
+use serenity::client::{Context as SContext, EventHandler};
+use serenity::http::CacheHttp;
+use serenity::model::guild::Member;
+use serenity::model::id::GuildId;
+use serenity::model::user::User;
+use serenity::prelude::Mentionable;
+
+use crate::classes::Server;
+use crate::ClassResult;
+use crate::redact::log_error;
+
+/// Used when a server hasn't set its own welcome message. `{user}` and `{server}` are replaced
+/// with the new member and the server's name.
+pub(crate) const DEFAULT_TEMPLATE: &str = "Welcome to **{server}**, {user}!\n\n\
+    This server has channels for each class, which you can see once you join the class. Pick the \
+    classes you're taking from the class menu, and leave them the same way when you're done. If a \
+    class you're taking is missing, ask for it with `/class request`.";
+
+/// Fill in a welcome message template for a member.
+fn render(template: &str, user: &User, server_name: &str) -> String {
+    template
+        .replace("{user}", &user.mention().to_string())
+        .replace("{server}", server_name)
+}
+
+/// DM a member the server's welcome message, with a link to the class menu if there is one.
+pub(crate) async fn send(ctx: &SContext, server_id: GuildId, user: &User) -> ClassResult<()> {
+    let server = Server::get_or_create(server_id).await?;
+    let server_name = server_id.name(&ctx.cache).unwrap_or_else(|| "the server".to_string());
+    let content = render(
+        server.welcome_template.as_deref().unwrap_or(DEFAULT_TEMPLATE),
+        user,
+        &server_name,
+    );
+    let menu_link = server.menu_message
+        .map(|(channel, message)| message.link(channel, Some(server_id)));
+
+    user.create_dm_channel(ctx)
+        .await?
+        .send_message(ctx.http(), |m| {
+            m.content(content);
+            if let Some(link) = menu_link {
+                m.components(|c| c
+                    .create_action_row(|r| r
+                        .create_button(|b| b
+                            .url(link)
+                            .label("Choose your classes")
+                            .emoji('📝') // U+1F4DD : MEMO
+                        )
+                    )
+                );
+            }
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub(crate) struct WelcomeHandler;
+
+#[async_trait]
+impl EventHandler for WelcomeHandler {
+    async fn guild_member_addition(&self, ctx: SContext, new_member: Member) {
+        if new_member.user.bot {
+            return;
+        }
+        match Server::get_or_create(new_member.guild_id).await {
+            Ok(server) if server.welcome_dm_disabled => {}
+            // Throwing away the result as the member may have DMs disabled
+            Ok(_) => { send(&ctx, new_member.guild_id, &new_member.user).await.ok(); }
+            Err(e) => log_error!("Error sending welcome DM: {:?}", e),
+        }
+    }
+}